@@ -0,0 +1,284 @@
+//! Operate the mindless database without going through the server.
+//!
+//! Every command works on the database at `DATABASE_URL`, or `--database-url`, and prints a table
+//! or, with `--format json`, JSON.
+
+// Printing rows.
+mod output;
+// How each kind of row is printed.
+mod rows;
+
+use anyhow::{bail, Context};
+use database::connection::Connection;
+use database::instance::Instance;
+use database::migration;
+use database::stats;
+use database::task::Task;
+use database::user::User;
+use output::Format;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "mindless-admin", about = "Operate the mindless database.")]
+struct Options {
+    /// The database to operate on.
+    #[structopt(long, env = "DATABASE_URL", hide_env_values = true)]
+    database_url: String,
+
+    /// Print a table or json.
+    #[structopt(long, default_value = "table")]
+    format: Format,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// List, create or delete users.
+    Users(UserCommand),
+
+    /// List, rename or merge the tasks of a user.
+    Tasks(TaskCommand),
+
+    /// List instances or move them to another task.
+    Instances(InstanceCommand),
+
+    /// Apply any missing migrations.
+    Migrate,
+
+    /// Reclaim the space of deleted rows.
+    Vacuum,
+
+    /// Show how much every user uses the tracker.
+    Usage,
+}
+
+#[derive(StructOpt, Debug)]
+enum UserCommand {
+    List,
+
+    Create {
+        username: String,
+        name: String,
+    },
+
+    /// Delete a user along with everything belonging to them.
+    Delete {
+        username: String,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum TaskCommand {
+    List {
+        username: String,
+    },
+
+    Rename {
+        username: String,
+        task: String,
+        new_name: String,
+    },
+
+    /// Move every instance of `from` to `into` and delete `from`.
+    Merge {
+        username: String,
+        from: String,
+        into: String,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum InstanceCommand {
+    List {
+        username: String,
+        task: String,
+    },
+
+    /// Move instances to another task of the same user.
+    Reassign {
+        username: String,
+
+        /// The task to move the instances to.
+        #[structopt(long)]
+        to: String,
+
+        /// Ids of the instances to move.
+        #[structopt(required = true)]
+        ids: Vec<i64>,
+    },
+}
+
+#[tokio::main]
+async fn main() {
+    let options = Options::from_args();
+
+    if let Err(e) = run(options).await {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(options: Options) -> anyhow::Result<()> {
+    let format = options.format;
+    let connection = Connection::builder(&options.database_url)
+        .max_connections(1)
+        .connect()
+        .await
+        .context("Could not connect to the database")?;
+
+    if let Command::Migrate = options.command {
+        let applied = migration::run(&connection)
+            .await
+            .context("Could not migrate the database")?;
+        return output::print_message(format, &format!("Applied {} migrations", applied));
+    }
+
+    let version = migration::version(&connection).await?;
+    if version != migration::LATEST_VERSION {
+        bail!(
+            "The database is at version {} but the latest is {}, run `mindless-admin migrate` first",
+            version,
+            migration::LATEST_VERSION
+        );
+    }
+
+    match options.command {
+        Command::Users(command) => users(command, format, &connection).await,
+        Command::Tasks(command) => tasks(command, format, &connection).await,
+        Command::Instances(command) => instances(command, format, &connection).await,
+        Command::Migrate => unreachable!("Migrations are handled above."),
+        Command::Vacuum => {
+            connection.vacuum().await?;
+            output::print_message(format, "Vacuumed the database")
+        }
+        Command::Usage => output::print(format, &stats::usage(&connection).await?),
+    }
+}
+
+async fn users(
+    command: UserCommand,
+    format: Format,
+    connection: &Connection,
+) -> anyhow::Result<()> {
+    match command {
+        UserCommand::List => output::print(format, &User::get_users(connection).await?),
+        UserCommand::Create { username, name } => {
+            let user = User::insert(&username, &name, connection)
+                .await
+                .with_context(|| format!("Could not create \"{}\"", username))?;
+            output::print(format, &[user])
+        }
+        UserCommand::Delete { username } => {
+            get_user(&username, connection)
+                .await?
+                .delete(connection)
+                .await?;
+            output::print_message(format, &format!("Deleted \"{}\"", username))
+        }
+    }
+}
+
+async fn tasks(
+    command: TaskCommand,
+    format: Format,
+    connection: &Connection,
+) -> anyhow::Result<()> {
+    match command {
+        TaskCommand::List { username } => {
+            let user = get_user(&username, connection).await?;
+            output::print(format, &Task::get_tasks(&user, connection).await?)
+        }
+        TaskCommand::Rename {
+            username,
+            task,
+            new_name,
+        } => {
+            let user = get_user(&username, connection).await?;
+            let mut task = get_task(&user, &task, connection).await?;
+            task.rename(&new_name, connection)
+                .await
+                .with_context(|| format!("Could not rename to \"{}\"", new_name))?;
+            output::print(format, &[task])
+        }
+        TaskCommand::Merge {
+            username,
+            from,
+            into,
+        } => {
+            let user = get_user(&username, connection).await?;
+            let from = get_task(&user, &from, connection).await?;
+            let into = get_task(&user, &into, connection).await?;
+            if from == into {
+                bail!("Can't merge a task into itself");
+            }
+
+            let moved = from.merge_into(&into, connection).await?;
+            output::print_message(
+                format,
+                &format!("Moved {} instances into \"{}\"", moved, into.get_name()),
+            )
+        }
+    }
+}
+
+async fn instances(
+    command: InstanceCommand,
+    format: Format,
+    connection: &Connection,
+) -> anyhow::Result<()> {
+    match command {
+        InstanceCommand::List { username, task } => {
+            let user = get_user(&username, connection).await?;
+            let task = get_task(&user, &task, connection).await?;
+            output::print(
+                format,
+                &Instance::get_instances(task.get_id(), connection).await?,
+            )
+        }
+        InstanceCommand::Reassign { username, to, ids } => {
+            let user = get_user(&username, connection).await?;
+            let to = get_task(&user, &to, connection).await?;
+
+            let mut moved = Vec::with_capacity(ids.len());
+            for id in ids {
+                let instance = Instance::retrieve(id, connection)
+                    .await
+                    .with_context(|| format!("Could not find instance {}", id))?;
+
+                // Never move time between users.
+                let owner = Task::retrieve(instance.get_task_id(), connection).await?;
+                if owner.get_user_id() != user.get_id() {
+                    bail!("Instance {} does not belong to \"{}\"", id, username);
+                }
+
+                moved.push(instance);
+            }
+
+            // Move all of them or none.
+            Instance::reassign_all(&mut moved, to.get_id(), connection)
+                .await
+                .with_context(|| {
+                    format!("Could not move the instances to \"{}\"", to.get_name())
+                })?;
+
+            output::print(format, &moved)
+        }
+    }
+}
+
+async fn get_user(username: &str, connection: &Connection) -> anyhow::Result<User> {
+    User::get(username, connection)
+        .await
+        .with_context(|| format!("Could not find user \"{}\"", username))
+}
+
+async fn get_task(user: &User, name: &str, connection: &Connection) -> anyhow::Result<Task> {
+    let mut task = Task::new(0, user.get_id(), name.to_string());
+    task.find(connection)
+        .await
+        .with_context(|| format!("Could not find task \"{}\"", name))?;
+
+    Ok(task)
+}
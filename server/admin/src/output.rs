@@ -0,0 +1,101 @@
+//! Printing results as a table or as JSON.
+use serde::Serialize;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Table,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("\"{}\" is not one of table or json", format)),
+        }
+    }
+}
+
+/// Rows which can be printed as a table.
+pub trait Tabular: Serialize {
+    /// Column names.
+    fn headers() -> &'static [&'static str];
+
+    /// The cells of this row, one per header.
+    fn cells(&self) -> Vec<String>;
+}
+
+/// Print the rows in the format.
+pub fn print<T: Tabular>(format: Format, rows: &[T]) -> anyhow::Result<()> {
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(rows)?),
+        Format::Table => print!("{}", table(T::headers(), rows.iter().map(T::cells))),
+    }
+
+    Ok(())
+}
+
+/// Print a message, or `{"message": ...}` in JSON.
+pub fn print_message(format: Format, message: &str) -> anyhow::Result<()> {
+    match format {
+        Format::Json => println!("{}", serde_json::json!({ "message": message })),
+        Format::Table => println!("{}", message),
+    }
+
+    Ok(())
+}
+
+/// Render left aligned columns separated by two spaces.
+fn table<I: Iterator<Item = Vec<String>>>(headers: &[&str], rows: I) -> String {
+    let mut rows: Vec<Vec<String>> = rows.collect();
+    rows.insert(0, headers.iter().map(|header| header.to_string()).collect());
+
+    let mut widths = vec![0; headers.len()];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut output = String::new();
+    for row in rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        output.push_str(line.join("  ").trim_end());
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn align_columns() {
+        let rows = vec![
+            vec!["1".to_string(), "justin".to_string()],
+            vec!["12".to_string(), "al".to_string()],
+        ];
+
+        assert_eq!(
+            table(&["id", "username"], rows.into_iter()),
+            "id  username\n1   justin\n12  al\n"
+        );
+    }
+
+    #[test]
+    fn parse_format() {
+        assert_eq!("json".parse(), Ok(Format::Json));
+        assert_eq!("table".parse(), Ok(Format::Table));
+        assert!("yaml".parse::<Format>().is_err());
+    }
+}
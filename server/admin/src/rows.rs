@@ -0,0 +1,79 @@
+//! How each kind of row is printed as a table.
+use crate::output::Tabular;
+use database::instance::Instance;
+use database::stats::Usage;
+use database::task::Task;
+use database::user::User;
+
+impl Tabular for User {
+    fn headers() -> &'static [&'static str] {
+        &["id", "username", "name"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.get_id().to_string(),
+            self.get_username().to_string(),
+            self.get_name().to_string(),
+        ]
+    }
+}
+
+impl Tabular for Task {
+    fn headers() -> &'static [&'static str] {
+        &["id", "user_id", "name"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.get_id().to_string(),
+            self.get_user_id().to_string(),
+            self.get_name().to_string(),
+        ]
+    }
+}
+
+impl Tabular for Instance {
+    fn headers() -> &'static [&'static str] {
+        &["id", "task_id", "start", "end"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.get_id().to_string(),
+            self.get_task_id().to_string(),
+            self.get_start().to_string(),
+            self.get_end().to_string(),
+        ]
+    }
+}
+
+impl Tabular for Usage {
+    fn headers() -> &'static [&'static str] {
+        &[
+            "id",
+            "username",
+            "tasks",
+            "instances",
+            "tracked",
+            "last_active",
+        ]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.user_id.to_string(),
+            self.username.clone(),
+            self.tasks.to_string(),
+            self.instances.to_string(),
+            format!(
+                "{}h{:02}m",
+                self.tracked_sec / 3600,
+                self.tracked_sec % 3600 / 60
+            ),
+            self.last_active
+                .map(|time| time.to_string())
+                .unwrap_or_else(|| "never".to_string()),
+        ]
+    }
+}
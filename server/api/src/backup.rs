@@ -0,0 +1,51 @@
+use chrono::{DateTime, Duration, Utc};
+use database::backup::{self, Retention};
+use database::connection::Connection;
+use database::error::Result;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::config::BackupConfig;
+use crate::schedule::{self, Clock};
+
+fn retention(config: &BackupConfig) -> Retention {
+    Retention {
+        keep_last: config.keep_last,
+        max_age: config.max_age_days.map(Duration::days),
+    }
+}
+
+/// Take a backup now and delete the backups which fall outside of the retention rules.
+///
+/// Returns the path of the new backup.
+pub async fn take(
+    connection: &Connection,
+    config: &BackupConfig,
+    now: &DateTime<Utc>,
+) -> Result<PathBuf> {
+    let now = now.naive_utc();
+    let path = backup::snapshot(connection, &config.directory, &now).await?;
+    tracing::info!(path = %path.display(), "Took a backup");
+
+    for deleted in backup::prune(&config.directory, &retention(config), &now)? {
+        tracing::info!(path = %deleted.display(), "Deleted an old backup");
+    }
+
+    Ok(path)
+}
+
+/// Take backups periodically while the server is running.
+pub fn schedule(connection: Connection, config: BackupConfig, clock: Arc<dyn Clock>) {
+    let period = std::time::Duration::from_secs(config.interval_hours * 60 * 60);
+
+    schedule::every("backup", period, clock, move |now| {
+        let connection = connection.clone();
+        let config = config.clone();
+
+        async move {
+            if let Err(e) = take(&connection, &config, &now).await {
+                tracing::error!(%e, "Scheduled backup failed");
+            }
+        }
+    });
+}
@@ -0,0 +1,745 @@
+//! Rendering time and habit completions as svg charts to embed in wikis and dashboards.
+//!
+//! Days are local to the timezone of the user's reminder settings. Ranges are given as `from` and
+//! `to` dates, both included, and every chart can be narrowed down to a task or a habit. Charts of
+//! time can also be narrowed down to a tag.
+use chrono::{Datelike, Duration, NaiveDate};
+use chrono_tz::Tz;
+use database::habit::Habit;
+use database::instance::Instance;
+use database::store::Store;
+use database::task::Task;
+use database::user::User;
+use mindless_core::duration;
+use mindless_core::period::{daily_totals, hourly_totals, local_date, start_of_day, Period};
+use rocket::http::ContentType;
+use rocket::response::content::Content;
+use rocket::State;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tracing::instrument;
+
+use crate::digest::{timezone, week_of};
+use crate::error::{Error, Result};
+use crate::logging::RequestId;
+use crate::metrics::Metrics;
+use crate::qr::escape;
+use crate::schedule::Clock;
+use crate::tag::{passes, TagFilter};
+
+/// Charts cover at most this many days so they stay a reasonable size.
+const MAX_DAYS: i64 = 3 * 366;
+
+/// Dates in queries are within these years, so the days around them always exist.
+const MIN_YEAR: i32 = 1970;
+const MAX_YEAR: i32 = 9999;
+
+/// Side of a heatmap cell and the gap between cells.
+const CELL: usize = 11;
+const CELL_GAP: usize = 2;
+
+/// Heatmap colors from nothing to the most in the range.
+const LEVELS: [&str; 5] = ["#ebedf0", "#9be9a8", "#40c463", "#30a14e", "#216e39"];
+
+/// Width of a bar and the gap between bars.
+const BAR: usize = 12;
+const BAR_GAP: usize = 4;
+
+/// Height of the area bars are drawn in.
+const PLOT_HEIGHT: usize = 120;
+
+/// Space left of the plot for the axis labels.
+const AXIS_WIDTH: usize = 50;
+
+/// Colors of the series of a bar chart, repeating when there are more series.
+const PALETTE: [&str; 10] = [
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+    "#9c755f", "#bab0ac",
+];
+
+/// What the values of a chart are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unit {
+    Seconds,
+    Completions,
+}
+
+impl Unit {
+    fn format(self, value: i64) -> String {
+        match self {
+            Unit::Seconds => duration::format(Duration::seconds(value)),
+            Unit::Completions if value == 1 => "1 time".to_string(),
+            Unit::Completions => format!("{} times", value),
+        }
+    }
+}
+
+/// Named values of a bar chart, one per bar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub name: String,
+    pub values: Vec<i64>,
+}
+
+/// A bar of a bar chart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bar {
+    /// Label under the bar, empty to leave it out.
+    pub label: String,
+
+    /// Shown when hovering the bar.
+    pub title: String,
+}
+
+fn document(width: usize, height: usize, content: &str) -> String {
+    format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {width} {height}" "#,
+            r#"width="{width}" height="{height}" font-family="sans-serif" font-size="9">"#,
+            "{content}</svg>"
+        ),
+        width = width,
+        height = height,
+        content = content,
+    )
+}
+
+/// Which of the heatmap colors a value gets, 0 only for nothing.
+fn level(value: i64, max: i64) -> usize {
+    if value <= 0 || max <= 0 {
+        return 0;
+    }
+
+    1 + ((value * 4 - 1) / max).min(3) as usize
+}
+
+/// A calendar of the days from `from` to `to` with a column per week, darker the more was done.
+pub fn heatmap(
+    values: &BTreeMap<NaiveDate, i64>,
+    from: &NaiveDate,
+    to: &NaiveDate,
+    unit: Unit,
+) -> String {
+    let pitch = CELL + CELL_GAP;
+    let (left, top) = (30, 20);
+    let first = week_of(from);
+    let weeks = ((week_of(to) - first).num_days() / 7 + 1) as usize;
+    let max = values.values().copied().max().unwrap_or(0);
+
+    let mut content = String::new();
+    for (row, name) in &[(0, "Mon"), (2, "Wed"), (4, "Fri")] {
+        content.push_str(&format!(
+            r#"<text x="0" y="{}">{}</text>"#,
+            top + row * pitch + CELL - 2,
+            name
+        ));
+    }
+
+    let mut month = None;
+    let mut date = *from;
+    while date <= *to {
+        let column = ((date - first).num_days() / 7) as usize;
+        let row = date.weekday().num_days_from_monday() as usize;
+        let x = left + column * pitch;
+
+        // Label the column a month starts in.
+        if month != Some(date.month()) && (row == 0 || date == *from) {
+            month = Some(date.month());
+            content.push_str(&format!(
+                r#"<text x="{}" y="{}">{}</text>"#,
+                x,
+                top - 6,
+                date.format("%b")
+            ));
+        }
+
+        let value = values.get(&date).copied().unwrap_or(0);
+        content.push_str(&format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}" rx="2" fill="{}"><title>{}: {}</title></rect>"#,
+            x,
+            top + row * pitch,
+            CELL,
+            CELL,
+            LEVELS[level(value, max)],
+            date,
+            unit.format(value)
+        ));
+        date = date.succ();
+    }
+
+    // A legend under the last weeks.
+    let width = (left + weeks * pitch + 10).max(left + 10 * pitch);
+    let y = top + 7 * pitch + 6;
+    let x = width - 10 - LEVELS.len() * pitch;
+    content.push_str(&format!(
+        r#"<text x="{}" y="{}" text-anchor="end">Less</text>"#,
+        x - 4,
+        y + CELL - 2
+    ));
+    for (i, color) in LEVELS.iter().enumerate() {
+        content.push_str(&format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}" rx="2" fill="{}"/>"#,
+            x + i * pitch,
+            y,
+            CELL,
+            CELL,
+            color
+        ));
+    }
+    content.push_str(&format!(
+        r#"<text x="{}" y="{}">More</text>"#,
+        x + LEVELS.len() * pitch + 2,
+        y + CELL - 2
+    ));
+
+    document(width, y + CELL + 6, &content)
+}
+
+/// Bars with a segment per series stacked on top of each other, and a legend when the series
+/// have names.
+pub fn bar_chart(bars: &[Bar], series: &[Series], unit: Unit) -> String {
+    let pitch = BAR + BAR_GAP;
+    let top = 10;
+    let bottom = top + PLOT_HEIGHT;
+    let max = (0..bars.len())
+        .map(|i| {
+            series
+                .iter()
+                .map(|series| series.values.get(i).copied().unwrap_or(0))
+                .sum::<i64>()
+        })
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    let mut content = String::new();
+    for (value, y) in &[(0, bottom), (max / 2, top + PLOT_HEIGHT / 2), (max, top)] {
+        content.push_str(&format!(
+            concat!(
+                r##"<line x1="{left}" y1="{y}" x2="{right}" y2="{y}" stroke="#ddd"/>"##,
+                r#"<text x="{label}" y="{baseline}" text-anchor="end">{value}</text>"#
+            ),
+            left = AXIS_WIDTH,
+            right = AXIS_WIDTH + bars.len() * pitch,
+            y = y,
+            label = AXIS_WIDTH - 4,
+            baseline = y + 3,
+            value = unit.format(*value)
+        ));
+    }
+
+    for (i, bar) in bars.iter().enumerate() {
+        let x = AXIS_WIDTH + i * pitch + BAR_GAP / 2;
+        let mut y = bottom as f64;
+        for (j, series) in series.iter().enumerate() {
+            let value = series.values.get(i).copied().unwrap_or(0);
+            if value <= 0 {
+                continue;
+            }
+
+            let height = value as f64 * PLOT_HEIGHT as f64 / max as f64;
+            y -= height;
+            let title = if series.name.is_empty() {
+                bar.title.clone()
+            } else {
+                format!("{}, {}", series.name, bar.title)
+            };
+            content.push_str(&format!(
+                r#"<rect x="{}" y="{:.2}" width="{}" height="{:.2}" fill="{}"><title>{}: {}</title></rect>"#,
+                x,
+                y,
+                BAR,
+                height,
+                PALETTE[j % PALETTE.len()],
+                escape(&title),
+                unit.format(value)
+            ));
+        }
+
+        if !bar.label.is_empty() {
+            content.push_str(&format!(
+                r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+                x + BAR / 2,
+                bottom + 12,
+                escape(&bar.label)
+            ));
+        }
+    }
+
+    let mut width = AXIS_WIDTH + bars.len() * pitch + 10;
+    let mut height = bottom + 20;
+    let named: Vec<_> = series
+        .iter()
+        .filter(|series| !series.name.is_empty())
+        .collect();
+    for (j, series) in named.iter().enumerate() {
+        content.push_str(&format!(
+            r#"<rect x="{}" y="{}" width="9" height="9" fill="{}"/><text x="{}" y="{}">{}</text>"#,
+            AXIS_WIDTH,
+            height,
+            PALETTE[j % PALETTE.len()],
+            AXIS_WIDTH + 13,
+            height + 8,
+            escape(&series.name)
+        ));
+        // Roughly the width of the name so it isn't cut off.
+        width = width.max(AXIS_WIDTH + 23 + series.name.chars().count() * 6);
+        height += 13;
+    }
+
+    document(width, height + 4, &content)
+}
+
+/// Local dates from `from` to `to`, both included.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Range {
+    from: NaiveDate,
+    to: NaiveDate,
+}
+
+impl Range {
+    /// The range in the query, by default the `days` up to today.
+    fn parse(
+        from: Option<String>,
+        to: Option<String>,
+        days: i64,
+        today: NaiveDate,
+    ) -> Result<Range> {
+        let to = match to {
+            Some(to) => parse_date(&to)?,
+            None => today,
+        };
+        let from = match from {
+            Some(from) => parse_date(&from)?,
+            None => to
+                .checked_sub_signed(Duration::days(days - 1))
+                .ok_or_else(|| Error::BadRequest(format!("{} is too early", to)))?,
+        };
+
+        if from > to {
+            return Err(Error::BadRequest(format!("{} is after {}", from, to)));
+        }
+        if (to - from).num_days() >= MAX_DAYS {
+            return Err(Error::BadRequest(format!(
+                "Charts cover at most {} days",
+                MAX_DAYS
+            )));
+        }
+
+        Ok(Range { from, to })
+    }
+
+    fn period(&self, timezone: &Tz) -> Result<Period> {
+        let end = self
+            .to
+            .succ_opt()
+            .ok_or_else(|| Error::BadRequest(format!("{} is too late", self.to)))?;
+
+        Ok(Period::new(
+            start_of_day(&self.from, timezone),
+            start_of_day(&end, timezone),
+        ))
+    }
+}
+
+/// Whether a date is one which queries take, from 1970 up to 9999.
+pub(crate) fn in_range(date: &NaiveDate) -> bool {
+    (MIN_YEAR..=MAX_YEAR).contains(&date.year())
+}
+
+/// Parse a date such as `2020-10-12` in a query, from 1970 up to 9999.
+pub(crate) fn parse_date(date: &str) -> Result<NaiveDate> {
+    let parsed = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| Error::BadRequest(format!("\"{}\" is not a date like 2020-10-12", date)))?;
+    if !in_range(&parsed) {
+        return Err(Error::BadRequest(format!(
+            "\"{}\" is not between {} and {}",
+            date, MIN_YEAR, MAX_YEAR
+        )));
+    }
+
+    Ok(parsed)
+}
+
+/// The time tracked on every task of the user within the period, or only on one task, and only
+/// with a tag if there is a filter.
+async fn tracked(
+    store: &dyn Store,
+    user: &User,
+    task_id: Option<i64>,
+    filter: Option<&TagFilter>,
+    bounds: &Period,
+) -> Result<Vec<(Task, Vec<Period>)>> {
+    let tasks = match task_id {
+        Some(task_id) => {
+            let task = store.retrieve_task(task_id).await?;
+            if task.get_user_id() != user.get_id() {
+                return Err(database::error::Error::NotFound.into());
+            }
+            vec![task]
+        }
+        None => store.get_tasks(user).await?,
+    };
+
+    let mut tracked = Vec::new();
+    for task in tasks {
+        let periods = store
+            .get_instances(task.get_id())
+            .await?
+            .iter()
+            .filter(|instance| passes(filter, instance))
+            .map(Instance::get_period)
+            .filter(|period| period.overlaps(bounds))
+            .map(|period| period.intersection(bounds))
+            .collect();
+        tracked.push((task, periods));
+    }
+
+    Ok(tracked)
+}
+
+/// Completions of a habit and every habit under it on each local day.
+async fn completions(
+    store: &dyn Store,
+    user: &User,
+    habit_id: i64,
+    bounds: &Period,
+    timezone: &Tz,
+) -> Result<BTreeMap<NaiveDate, i64>> {
+    let habits = store.get_habits(user.get_id()).await?;
+    if !habits.iter().any(|habit| habit.get_id() == habit_id) {
+        return Err(database::error::Error::NotFound.into());
+    }
+    let parents: HashMap<i64, Option<i64>> = habits
+        .iter()
+        .map(|habit| (habit.get_id(), habit.get_parent_id()))
+        .collect();
+    let under = |habit: &Habit| {
+        let mut id = Some(habit.get_id());
+        while let Some(current) = id {
+            if current == habit_id {
+                return true;
+            }
+            id = parents.get(&current).copied().flatten();
+        }
+        false
+    };
+
+    let mut days = BTreeMap::new();
+    for habit in habits.iter().filter(|habit| under(*habit)) {
+        for completion in store.get_completions(habit.get_id(), bounds).await? {
+            *days
+                .entry(local_date(completion.get_created_at(), timezone))
+                .or_insert(0) += 1;
+        }
+    }
+
+    Ok(days)
+}
+
+/// The user along with their timezone and today's local date.
+pub(crate) async fn user_today(
+    store: &dyn Store,
+    clock: &dyn Clock,
+    user_id: i64,
+) -> Result<(User, Tz, NaiveDate)> {
+    let user = store.retrieve_user(user_id).await?;
+    let settings = store.retrieve_reminder_settings(user.get_id()).await?;
+    let timezone = timezone(&settings).map_err(Error::BadRequest)?;
+    let today = local_date(&clock.now().naive_utc(), &timezone);
+
+    Ok((user, timezone, today))
+}
+
+// A calendar of the time tracked each day, by default over the last year. With `habit` it shows
+// how often the habit and the habits under it were done instead.
+#[get("/mindless/api/chart/<user_id>/heatmap.svg?<from>&<to>&<task>&<habit>&<tag>")]
+#[instrument(name = "chart", skip(store, clock, metrics), fields(%request_id))]
+#[allow(clippy::too_many_arguments)]
+pub async fn heatmap_svg(
+    user_id: i64,
+    from: Option<String>,
+    to: Option<String>,
+    task: Option<i64>,
+    habit: Option<i64>,
+    tag: Option<String>,
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Content<String>> {
+    let _timer = metrics.api_request("chart", "Heatmap");
+    let store = store.inner().as_ref();
+
+    let (user, timezone, today) = user_today(store, clock.inner().as_ref(), user_id).await?;
+    let range = Range::parse(from, to, 52 * 7, today)?;
+    let bounds = range.period(&timezone)?;
+    let filter = TagFilter::parse(store, user.get_id(), tag).await?;
+
+    let svg = match (task, habit) {
+        (Some(_), Some(_)) => {
+            return Err(Error::BadRequest(
+                "Filter by either a task or a habit".to_string(),
+            ))
+        }
+        (_, Some(_)) if filter.is_some() => {
+            return Err(Error::BadRequest(
+                "Habits have no tags, only time does".to_string(),
+            ))
+        }
+        (_, Some(habit_id)) => {
+            let days = completions(store, &user, habit_id, &bounds, &timezone).await?;
+            heatmap(&days, &range.from, &range.to, Unit::Completions)
+        }
+        (task_id, None) => {
+            let periods: Vec<Period> = tracked(store, &user, task_id, filter.as_ref(), &bounds)
+                .await?
+                .into_iter()
+                .flat_map(|(_, periods)| periods)
+                .collect();
+            let days = daily_totals(&periods, &timezone)
+                .into_iter()
+                .map(|(date, duration)| (date, duration.num_seconds()))
+                .collect();
+            heatmap(&days, &range.from, &range.to, Unit::Seconds)
+        }
+    };
+
+    Ok(Content(ContentType::SVG, svg))
+}
+
+// The time tracked each week, stacked by task, by default over the last twelve weeks.
+#[get("/mindless/api/chart/<user_id>/weekly.svg?<from>&<to>&<task>&<tag>")]
+#[instrument(name = "chart", skip(store, clock, metrics), fields(%request_id))]
+#[allow(clippy::too_many_arguments)]
+pub async fn weekly_svg(
+    user_id: i64,
+    from: Option<String>,
+    to: Option<String>,
+    task: Option<i64>,
+    tag: Option<String>,
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Content<String>> {
+    let _timer = metrics.api_request("chart", "Weekly");
+    let store = store.inner().as_ref();
+
+    let (user, timezone, today) = user_today(store, clock.inner().as_ref(), user_id).await?;
+    let range = Range::parse(from, to, 12 * 7, today)?;
+    let filter = TagFilter::parse(store, user.get_id(), tag).await?;
+    let first = week_of(&range.from);
+    let weeks = ((week_of(&range.to) - first).num_days() / 7 + 1) as usize;
+
+    let bounds = range.period(&timezone)?;
+    let mut series = Vec::new();
+    for (task, periods) in tracked(store, &user, task, filter.as_ref(), &bounds).await? {
+        if periods.is_empty() {
+            continue;
+        }
+
+        let mut values = vec![0; weeks];
+        for (date, duration) in daily_totals(&periods, &timezone) {
+            values[((week_of(&date) - first).num_days() / 7) as usize] += duration.num_seconds();
+        }
+        series.push(Series {
+            name: task.get_name().to_string(),
+            values,
+        });
+    }
+    // Most time at the bottom.
+    series.sort_by_key(|series| -series.values.iter().sum::<i64>());
+
+    // Label about every month.
+    let bars: Vec<Bar> = (0..weeks)
+        .map(|week| {
+            let monday = first + Duration::weeks(week as i64);
+            Bar {
+                label: if week % 4 == 0 {
+                    monday.format("%b %-d").to_string()
+                } else {
+                    String::new()
+                },
+                title: format!("week of {}", monday),
+            }
+        })
+        .collect();
+
+    Ok(Content(
+        ContentType::SVG,
+        bar_chart(&bars, &series, Unit::Seconds),
+    ))
+}
+
+// The time tracked in each hour of the day, by default over the last 30 days.
+#[get("/mindless/api/chart/<user_id>/hours.svg?<from>&<to>&<task>&<tag>")]
+#[instrument(name = "chart", skip(store, clock, metrics), fields(%request_id))]
+#[allow(clippy::too_many_arguments)]
+pub async fn hours_svg(
+    user_id: i64,
+    from: Option<String>,
+    to: Option<String>,
+    task: Option<i64>,
+    tag: Option<String>,
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Content<String>> {
+    let _timer = metrics.api_request("chart", "Hours");
+    let store = store.inner().as_ref();
+
+    let (user, timezone, today) = user_today(store, clock.inner().as_ref(), user_id).await?;
+    let range = Range::parse(from, to, 30, today)?;
+    let filter = TagFilter::parse(store, user.get_id(), tag).await?;
+
+    let bounds = range.period(&timezone)?;
+    let periods: Vec<Period> = tracked(store, &user, task, filter.as_ref(), &bounds)
+        .await?
+        .into_iter()
+        .flat_map(|(_, periods)| periods)
+        .collect();
+    let series = Series {
+        name: String::new(),
+        values: hourly_totals(&periods, &timezone)
+            .iter()
+            .map(Duration::num_seconds)
+            .collect(),
+    };
+    let bars: Vec<Bar> = (0..24)
+        .map(|hour| Bar {
+            label: if hour % 3 == 0 {
+                hour.to_string()
+            } else {
+                String::new()
+            },
+            title: format!("{:02}:00 to {:02}:00", hour, (hour + 1) % 24),
+        })
+        .collect();
+
+    Ok(Content(
+        ContentType::SVG,
+        bar_chart(&bars, &[series], Unit::Seconds),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(2020, month, day)
+    }
+
+    #[test]
+    fn levels() {
+        assert_eq!(level(0, 0), 0);
+        assert_eq!(level(0, 100), 0);
+        assert_eq!(level(1, 100), 1);
+        assert_eq!(level(25, 100), 1);
+        assert_eq!(level(26, 100), 2);
+        assert_eq!(level(100, 100), 4);
+    }
+
+    #[test]
+    fn heatmap_has_a_cell_per_day() {
+        let mut values = BTreeMap::new();
+        values.insert(date(10, 13), 3600);
+        values.insert(date(10, 14), 1800);
+
+        // Thursday the 1st to Sunday the 18th of October.
+        let svg = heatmap(&values, &date(10, 1), &date(10, 18), Unit::Seconds);
+
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<title>").count(), 18);
+        assert!(svg.contains("<title>2020-10-13: 1h00m</title>"));
+        assert!(svg.contains(r##"fill="#216e39"><title>2020-10-13"##));
+        assert!(svg.contains(r##"fill="#40c463"><title>2020-10-14"##));
+        assert!(svg.contains(r##"fill="#ebedf0"><title>2020-10-15"##));
+        assert!(svg.contains(">Oct</text>"));
+    }
+
+    #[test]
+    fn stacked_bars() {
+        let bars = vec![
+            Bar {
+                label: "Oct 5".to_string(),
+                title: "week of 2020-10-05".to_string(),
+            },
+            Bar {
+                label: String::new(),
+                title: "week of 2020-10-12".to_string(),
+            },
+        ];
+        let series = vec![
+            Series {
+                name: "Work & Home".to_string(),
+                values: vec![3600, 7200],
+            },
+            Series {
+                name: "Read".to_string(),
+                values: vec![0, 3600],
+            },
+        ];
+        let svg = bar_chart(&bars, &series, Unit::Seconds);
+
+        // Three segments and the two swatches of the legend.
+        assert_eq!(svg.matches("<rect").count(), 5);
+        assert!(svg.contains("<title>Work &amp; Home, week of 2020-10-12: 2h00m</title>"));
+        assert!(
+            svg.contains(r#"y="10.00" width="12" height="40.00""#),
+            "{}",
+            svg
+        );
+        assert!(
+            svg.contains(">3h00m</text>"),
+            "The tallest bar sets the scale."
+        );
+        assert!(svg.contains(">Oct 5</text>"));
+        assert!(svg.contains(">Read</text>"));
+    }
+
+    #[test]
+    fn unnamed_series_have_no_legend() {
+        let bars = vec![Bar {
+            label: "0".to_string(),
+            title: "00:00 to 01:00".to_string(),
+        }];
+        let series = Series {
+            name: String::new(),
+            values: vec![2],
+        };
+        let svg = bar_chart(&bars, &[series], Unit::Completions);
+
+        assert_eq!(svg.matches("<rect").count(), 1);
+        assert!(svg.contains("<title>00:00 to 01:00: 2 times</title>"));
+    }
+
+    #[test]
+    fn ranges() {
+        let today = date(10, 19);
+
+        assert_eq!(
+            Range::parse(None, None, 30, today).expect("Valid."),
+            Range {
+                from: date(9, 20),
+                to: today
+            }
+        );
+        assert_eq!(
+            Range::parse(Some("2020-10-01".to_string()), None, 30, today).expect("Valid."),
+            Range {
+                from: date(10, 1),
+                to: today
+            }
+        );
+        assert!(Range::parse(Some("2020-10-20".to_string()), None, 30, today).is_err());
+        assert!(Range::parse(Some("October".to_string()), None, 30, today).is_err());
+        assert!(Range::parse(Some("2010-01-01".to_string()), None, 30, today).is_err());
+        // Dates out of range would overflow working out the days around them.
+        assert!(Range::parse(None, Some("+262142-12-31".to_string()), 30, today).is_err());
+        assert!(Range::parse(Some("1969-12-31".to_string()), None, 30, today).is_err());
+        assert!(parse_date("9999-12-31").is_ok());
+    }
+}
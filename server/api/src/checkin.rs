@@ -0,0 +1,435 @@
+//! Checking in with a link, e.g. printed as a QR code, marks a habit as done.
+//!
+//! A check-in token is `<id>.<signature>` where the signature covers the id, user and habit so
+//! tokens can't be guessed from their ids. Only mounted when `auth.secret` is set.
+use database::checkin::CheckinToken;
+use database::habit::Habit;
+use database::store::Store;
+use mindless_core::path::HabitPath;
+use mindless_core::period::Period;
+use rocket::http::ContentType;
+use rocket::response::content::Content;
+use rocket::State;
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::instrument;
+
+use crate::error::{Error, Result};
+use crate::logging::RequestId;
+use crate::metrics::Metrics;
+use crate::qr;
+use crate::schedule::Clock;
+use crate::signing::Signer;
+use crate::webhook::{Dispatcher, Event};
+
+/// Pixels per module of png QR codes unless asked otherwise.
+const DEFAULT_PNG_SCALE: usize = 8;
+
+/// Keep png QR codes to a sensible size.
+const MAX_PNG_SCALE: usize = 32;
+
+/// Builds the links check-in tokens are handed out as.
+#[derive(Debug, Clone)]
+pub struct Links {
+    public_url: String,
+}
+
+impl Links {
+    pub fn new(public_url: &str) -> Links {
+        Links {
+            public_url: public_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// The link which checks in with a token.
+    pub fn checkin(&self, token: &str) -> String {
+        format!("{}/mindless/api/checkin/{}", self.public_url, token)
+    }
+
+    /// Whether the server is reached over https, so cookies should only be sent over it.
+    pub fn is_https(&self) -> bool {
+        self.public_url.starts_with("https://")
+    }
+}
+
+// Type of events that you can execute on check-in tokens.
+#[derive(Deserialize, Debug)]
+pub enum Request {
+    // Issue a token for a habit.
+    Issue { user_id: i64, path: HabitPath },
+
+    // Retrieve all the tokens of a user, including revoked ones.
+    RetrieveAll { user_id: i64 },
+
+    // Revoke a token so it can no longer be used to check in.
+    Revoke { user_id: i64, id: i64 },
+}
+
+impl Request {
+    /// Name of the request variant. This is safe to log since it holds no user data.
+    pub fn variant(&self) -> &'static str {
+        match self {
+            Request::Issue { .. } => "Issue",
+            Request::RetrieveAll { .. } => "RetrieveAll",
+            Request::Revoke { .. } => "Revoke",
+        }
+    }
+}
+
+/// A token along with the string to check in with.
+#[derive(Serialize, Debug)]
+pub struct IssuedToken {
+    #[serde(flatten)]
+    checkin: CheckinToken,
+
+    token: String,
+}
+
+#[derive(Serialize, Debug)]
+pub enum Response {
+    Issue {
+        token: IssuedToken,
+    },
+
+    RetrieveAll {
+        tokens: Vec<IssuedToken>,
+    },
+
+    Revoke {
+        token: IssuedToken,
+    },
+
+    // Whether the habit was marked, false if it already was this period.
+    CheckIn {
+        habit: Habit,
+        period: Period,
+        marked: bool,
+    },
+}
+
+/// What the signature of a token covers.
+fn message(checkin: &CheckinToken) -> String {
+    format!(
+        "checkin.{}.{}.{}",
+        checkin.get_id(),
+        checkin.get_user_id(),
+        checkin.get_habit_id()
+    )
+}
+
+/// The token to check in with.
+pub fn token(signer: &Signer, checkin: &CheckinToken) -> String {
+    format!(
+        "{}.{}",
+        checkin.get_id(),
+        signer.sign(message(checkin).as_bytes())
+    )
+}
+
+fn issued(signer: &Signer, checkin: CheckinToken) -> IssuedToken {
+    IssuedToken {
+        token: token(signer, &checkin),
+        checkin,
+    }
+}
+
+/// Find the check-in token a token string was issued for.
+///
+/// Fails with `InvalidToken` whether the token is malformed, unknown or forged so they can't be
+/// told apart.
+pub async fn verify(token: &str, signer: &Signer, store: &dyn Store) -> Result<CheckinToken> {
+    let mut parts = token.splitn(2, '.');
+    let id = parts.next().and_then(|id| id.parse::<i64>().ok());
+    let signature = parts.next();
+
+    let (id, signature) = match (id, signature) {
+        (Some(id), Some(signature)) => (id, signature),
+        _ => return Err(Error::InvalidToken),
+    };
+
+    let checkin = match store.retrieve_checkin_token(id).await {
+        Ok(checkin) => checkin,
+        Err(database::error::Error::NotFound) => return Err(Error::InvalidToken),
+        Err(e) => return Err(e.into()),
+    };
+
+    if !signer.verify(message(&checkin).as_bytes(), signature) {
+        return Err(Error::InvalidToken);
+    }
+
+    Ok(checkin)
+}
+
+// Handle issuing and revoking tokens.
+#[post("/mindless/api/checkin", data = "<request>")]
+#[instrument(
+    name = "checkin",
+    skip(store, clock, signer, metrics, request),
+    fields(%request_id, request = request.variant())
+)]
+pub async fn checkin(
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    signer: State<'_, Signer>,
+    metrics: State<'_, Metrics>,
+    request: Json<Request>,
+    request_id: RequestId,
+) -> Result<Json<Response>> {
+    let _timer = metrics.api_request("checkin", request.variant());
+    let store = store.inner().as_ref();
+    let signer = signer.inner();
+
+    let response = match request.into_inner() {
+        Request::Issue { user_id, path } => {
+            let habit = store.get_habit(user_id, &path).await?;
+            let checkin = store
+                .insert_checkin_token(user_id, habit.get_id(), &clock.now().naive_utc())
+                .await?;
+
+            Response::Issue {
+                token: issued(signer, checkin),
+            }
+        }
+
+        Request::RetrieveAll { user_id } => {
+            let user = store.retrieve_user(user_id).await?;
+            let tokens = store
+                .get_checkin_tokens(user.get_id())
+                .await?
+                .into_iter()
+                .map(|checkin| issued(signer, checkin))
+                .collect();
+
+            Response::RetrieveAll { tokens }
+        }
+
+        Request::Revoke { user_id, id } => {
+            let mut checkin = store.retrieve_checkin_token(id).await?;
+            if checkin.get_user_id() != user_id {
+                return Err(database::error::Error::NotFound.into());
+            }
+            store
+                .revoke_checkin_token(&mut checkin, &clock.now().naive_utc())
+                .await?;
+
+            Response::Revoke {
+                token: issued(signer, checkin),
+            }
+        }
+    };
+
+    tracing::debug!("Handled request");
+
+    Ok(Json(response))
+}
+
+/// Mark the habit of a token as done for its current period.
+async fn check_in(
+    token: &str,
+    store: &dyn Store,
+    clock: &dyn Clock,
+    signer: &Signer,
+    dispatcher: &Dispatcher,
+) -> Result<Json<Response>> {
+    let checkin = verify(token, signer, store).await?;
+    if checkin.is_revoked() {
+        return Err(Error::RevokedToken);
+    }
+
+    let now = clock.now().naive_utc();
+    let habit = store.retrieve_habit(checkin.get_habit_id()).await?;
+    let marked = store.mark_habit(&habit, &now).await?;
+    tracing::debug!(habit_id = habit.get_id(), marked, "Checked in");
+
+    let period = habit.get_period(&now)?;
+    if marked {
+        let event = Event::HabitCompleted {
+            habit: habit.clone(),
+            period,
+        };
+        dispatcher.emit(habit.get_user_id(), event).await;
+    }
+
+    Ok(Json(Response::CheckIn {
+        period,
+        habit,
+        marked,
+    }))
+}
+
+// Scanning a QR code opens the link in a browser.
+#[get("/mindless/api/checkin/<token>")]
+#[instrument(
+    name = "check_in",
+    skip(token, store, clock, signer, dispatcher, metrics),
+    fields(%request_id)
+)]
+pub async fn check_in_get(
+    token: String,
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    signer: State<'_, Signer>,
+    dispatcher: State<'_, Dispatcher>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Json<Response>> {
+    let _timer = metrics.api_request("checkin", "CheckIn");
+
+    check_in(
+        &token,
+        store.inner().as_ref(),
+        clock.inner().as_ref(),
+        signer.inner(),
+        dispatcher.inner(),
+    )
+    .await
+}
+
+// For automations which would rather not use GET for changes.
+#[post("/mindless/api/checkin/<token>")]
+#[instrument(
+    name = "check_in",
+    skip(token, store, clock, signer, dispatcher, metrics),
+    fields(%request_id)
+)]
+pub async fn check_in_post(
+    token: String,
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    signer: State<'_, Signer>,
+    dispatcher: State<'_, Dispatcher>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Json<Response>> {
+    let _timer = metrics.api_request("checkin", "CheckIn");
+
+    check_in(
+        &token,
+        store.inner().as_ref(),
+        clock.inner().as_ref(),
+        signer.inner(),
+        dispatcher.inner(),
+    )
+    .await
+}
+
+/// The QR code of the check-in link of a token along with the habit it checks in.
+///
+/// Only a token handed out by `Issue` renders, so showing a code never issues a token and holding
+/// the token is what authorizes it.
+async fn token_code(
+    token: &str,
+    store: &dyn Store,
+    signer: &Signer,
+    links: &Links,
+) -> Result<(qr::Code, Habit)> {
+    let checkin = verify(token, signer, store).await?;
+    if checkin.is_revoked() {
+        return Err(Error::RevokedToken);
+    }
+
+    let habit = store.retrieve_habit(checkin.get_habit_id()).await?;
+    let code = qr::Code::new(&links.checkin(&self::token(signer, &checkin)))?;
+
+    Ok((code, habit))
+}
+
+/// Retrieve a habit making sure it belongs to the user.
+pub(crate) async fn user_habit(user_id: i64, habit_id: i64, store: &dyn Store) -> Result<Habit> {
+    let habit = store.retrieve_habit(habit_id).await?;
+    if habit.get_user_id() != user_id {
+        return Err(database::error::Error::NotFound.into());
+    }
+
+    Ok(habit)
+}
+
+// The QR code of a token as an svg, captioned with the name of its habit.
+#[get("/mindless/api/checkin/<token>/qr.svg")]
+#[instrument(
+    name = "checkin_qr",
+    skip(token, store, signer, links, metrics),
+    fields(%request_id)
+)]
+pub async fn qr_svg(
+    token: String,
+    store: State<'_, Arc<dyn Store>>,
+    signer: State<'_, Signer>,
+    links: State<'_, Links>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Content<String>> {
+    let _timer = metrics.api_request("checkin", "QrSvg");
+
+    let (code, habit) = token_code(
+        &token,
+        store.inner().as_ref(),
+        signer.inner(),
+        links.inner(),
+    )
+    .await?;
+
+    Ok(Content(ContentType::SVG, qr::svg(&code, habit.get_name())))
+}
+
+// The QR code of a token as a png, captioned with the name of its habit.
+#[get("/mindless/api/checkin/<token>/qr.png?<scale>")]
+#[instrument(
+    name = "checkin_qr",
+    skip(token, store, signer, links, metrics),
+    fields(%request_id)
+)]
+pub async fn qr_png(
+    token: String,
+    scale: Option<usize>,
+    store: State<'_, Arc<dyn Store>>,
+    signer: State<'_, Signer>,
+    links: State<'_, Links>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Content<Vec<u8>>> {
+    let _timer = metrics.api_request("checkin", "QrPng");
+    let scale = scale.unwrap_or(DEFAULT_PNG_SCALE).min(MAX_PNG_SCALE);
+
+    let (code, habit) = token_code(
+        &token,
+        store.inner().as_ref(),
+        signer.inner(),
+        links.inner(),
+    )
+    .await?;
+
+    Ok(Content(
+        ContentType::PNG,
+        qr::png(&code, habit.get_name(), scale)?,
+    ))
+}
+
+// A printable page with the QR code of each token, e.g. every token `RetrieveAll` lists that
+// isn't revoked, separated by commas.
+#[get("/mindless/api/checkin/sheet?<tokens>")]
+#[instrument(
+    name = "checkin_sheet",
+    skip(tokens, store, signer, links, metrics),
+    fields(%request_id)
+)]
+pub async fn sheet(
+    tokens: String,
+    store: State<'_, Arc<dyn Store>>,
+    signer: State<'_, Signer>,
+    links: State<'_, Links>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Content<String>> {
+    let _timer = metrics.api_request("checkin", "Sheet");
+    let store = store.inner().as_ref();
+
+    let mut codes = Vec::new();
+    for token in tokens.split(',').filter(|token| !token.is_empty()) {
+        let (code, habit) = token_code(token, store, signer.inner(), links.inner()).await?;
+        codes.push((code, habit.get_name().to_string()));
+    }
+
+    Ok(Content(ContentType::HTML, qr::sheet("Check-ins", &codes)))
+}
@@ -0,0 +1,902 @@
+//! Server configuration.
+//!
+//! The configuration is layered. We start with the defaults, then load the TOML file pointed to by
+//! `MINDLESS_CONFIG` (or `mindless.toml` if it exists) and finally apply the `MINDLESS_*`
+//! environment variable overrides. Everything is validated once at startup.
+//!
+//! ```toml
+//! [database]
+//! url = "sqlite:data/habits.db"
+//! pool_size = 5
+//!
+//! [database.sqlite]
+//! journal_mode = "wal"
+//! synchronous = "normal"
+//! busy_timeout_ms = 5000
+//! foreign_keys = true
+//!
+//! [server]
+//! address = "127.0.0.1"
+//! port = 8000
+//! public_url = "https://mindless.example.com"
+//! cors_origins = ["https://mindless.example.com"]
+//!
+//! [auth]
+//! secret = "at least 32 characters of randomness"
+//!
+//! [log]
+//! level = "info"
+//! format = "json"
+//!
+//! [backup]
+//! directory = "backups"
+//! interval_hours = 24
+//! keep_last = 7
+//! max_age_days = 30
+//!
+//! [webhooks]
+//! max_attempts = 5
+//! backoff_ms = 1000
+//! timeout_ms = 5000
+//! missed_check_minutes = 15
+//! delivery_retention_days = 30
+//! allowed_hosts = ["homeassistant.local"]
+//!
+//! [events]
+//! history = 256
+//! keep_alive_secs = 15
+//!
+//! [reminders]
+//! check_secs = 60
+//! max_late_minutes = 60
+//!
+//! [reminders.smtp]
+//! host = "localhost"
+//! port = 25
+//! from = "mindless@example.com"
+//!
+//! [digest]
+//! check_minutes = 15
+//!
+//! [features]
+//! metrics = true
+//! backups = true
+//! webhooks = true
+//! events = true
+//! reminders = true
+//! digest = true
+//! charts = true
+//! dashboard = true
+//! goals = true
+//! tags = true
+//! ```
+use database::connection::{JournalMode, Synchronous};
+use serde::Deserialize;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+/// Environment variable pointing at the configuration file.
+const CONFIG_PATH_ENV: &str = "MINDLESS_CONFIG";
+
+/// Configuration file used when `MINDLESS_CONFIG` is not set. It is optional.
+const DEFAULT_CONFIG_PATH: &str = "mindless.toml";
+
+/// The minimum length of the auth secret.
+const MIN_SECRET_LENGTH: usize = 32;
+
+/// Keeping the delivery log any longer is surely a mistake.
+const MAX_RETENTION_DAYS: u64 = 3650;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub server: ServerConfig,
+    pub auth: AuthConfig,
+    pub log: LogConfig,
+    pub backup: BackupConfig,
+    pub webhooks: WebhookConfig,
+    pub events: EventsConfig,
+    pub reminders: ReminderConfig,
+    pub digest: DigestConfig,
+    pub features: FeaturesConfig,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// Database uri, e.g. `sqlite:data/habits.db` or `postgres://localhost/mindless`.
+    pub url: String,
+
+    /// Maximum number of connections in the pool.
+    pub pool_size: u32,
+
+    pub sqlite: SqliteConfig,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: String::new(),
+            pool_size: 5,
+            sqlite: SqliteConfig::default(),
+        }
+    }
+}
+
+/// Never print the password in the url, e.g. of a PostgreSQL database.
+impl fmt::Debug for DatabaseConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DatabaseConfig")
+            .field("url", &redacted_url(&self.url))
+            .field("pool_size", &self.pool_size)
+            .field("sqlite", &self.sqlite)
+            .finish()
+    }
+}
+
+/// The url with any password replaced, both in the user info and in a `password` parameter.
+///
+/// E.g. `postgres://me:hunter2@db/mindless` is printed as `postgres://me:<redacted>@db/mindless`.
+fn redacted_url(url: &str) -> String {
+    let (url, query) = match url.find('?') {
+        Some(start) => (&url[..start], Some(&url[start + 1..])),
+        None => (url, None),
+    };
+
+    let mut redacted = match url.find("://") {
+        Some(scheme_end) => {
+            let (scheme, rest) = url.split_at(scheme_end + "://".len());
+            let authority_end = rest.find('/').unwrap_or(rest.len());
+            let (authority, path) = rest.split_at(authority_end);
+            match authority.rfind('@') {
+                Some(at) if authority[..at].contains(':') => {
+                    let user = authority[..at].split(':').next().unwrap_or_default();
+                    format!("{}{}:<redacted>{}{}", scheme, user, &authority[at..], path)
+                }
+                _ => url.to_string(),
+            }
+        }
+        None => url.to_string(),
+    };
+
+    if let Some(query) = query {
+        let parameters: Vec<String> = query
+            .split('&')
+            .map(|parameter| match parameter.split('=').next() {
+                Some("password") => "password=<redacted>".to_string(),
+                _ => parameter.to_string(),
+            })
+            .collect();
+        redacted.push('?');
+        redacted.push_str(&parameters.join("&"));
+    }
+
+    redacted
+}
+
+/// SQLite pragmas set on every connection. Ignored by other databases.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SqliteConfig {
+    pub journal_mode: JournalMode,
+    pub synchronous: Synchronous,
+    pub busy_timeout_ms: u64,
+    pub foreign_keys: bool,
+}
+
+impl Default for SqliteConfig {
+    fn default() -> Self {
+        SqliteConfig {
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            busy_timeout_ms: 5000,
+            foreign_keys: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address to bind to.
+    pub address: String,
+
+    pub port: u16,
+
+    /// Url the server is reached at, used in links such as check-in QR codes.
+    pub public_url: String,
+
+    /// Origins allowed to make cross origin requests. `*` allows any origin.
+    pub cors_origins: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            address: "127.0.0.1".to_string(),
+            port: 8000,
+            public_url: "http://localhost:8000".to_string(),
+            cors_origins: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Secret used to sign tokens.
+    pub secret: Option<String>,
+}
+
+/// Never print the secret.
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field("secret", &self.secret.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Compact,
+    Pretty,
+    Json,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Log filter, e.g. `info` or `endpoint=debug,database=debug`.
+    pub level: String,
+
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: "info".to_string(),
+            format: LogFormat::Compact,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    /// Directory the backups are written to.
+    pub directory: PathBuf,
+
+    /// Hours between scheduled backups.
+    pub interval_hours: u64,
+
+    /// Always keep this many of the latest backups.
+    pub keep_last: usize,
+
+    /// Delete older backups after this many days. Unset keeps only the latest backups.
+    pub max_age_days: Option<i64>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            directory: PathBuf::from("backups"),
+            interval_hours: 24,
+            keep_last: 7,
+            max_age_days: Some(30),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// Give up on delivering an event after this many attempts.
+    pub max_attempts: u32,
+
+    /// Wait this long before retrying, doubling after every attempt.
+    pub backoff_ms: u64,
+
+    /// Give up on an attempt when the webhook takes longer than this to respond.
+    pub timeout_ms: u64,
+
+    /// Minutes between looking for habits which were missed.
+    pub missed_check_minutes: u64,
+
+    /// Days attempts are kept in the delivery log.
+    pub delivery_retention_days: u64,
+
+    /// Hosts webhooks may post to even though they are on a private network or this machine,
+    /// e.g. `homeassistant.local`. Webhooks can't reach any other such host.
+    pub allowed_hosts: Vec<String>,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            max_attempts: 5,
+            backoff_ms: 1000,
+            timeout_ms: 5000,
+            missed_check_minutes: 15,
+            delivery_retention_days: 30,
+            allowed_hosts: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventsConfig {
+    /// Changes kept per user for clients resuming their stream.
+    pub history: usize,
+
+    /// Seconds between comments sent on idle streams so proxies don't close them.
+    pub keep_alive_secs: u64,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        EventsConfig {
+            history: 256,
+            keep_alive_secs: 15,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReminderConfig {
+    /// Seconds between looking for reminders which are due.
+    pub check_secs: u64,
+
+    /// Reminders which could not be delivered are retried for this long before being skipped.
+    pub max_late_minutes: i64,
+
+    /// Mail server email reminders are sent through. Unset disables email reminders.
+    pub smtp: Option<SmtpConfig>,
+}
+
+impl Default for ReminderConfig {
+    fn default() -> Self {
+        ReminderConfig {
+            check_secs: 60,
+            max_late_minutes: 60,
+            smtp: None,
+        }
+    }
+}
+
+/// A mail server relaying mail from the server without authentication, e.g. a local postfix.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmtpConfig {
+    pub host: String,
+
+    pub port: u16,
+
+    /// Address reminders are sent from.
+    pub from: String,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        SmtpConfig {
+            host: "localhost".to_string(),
+            port: 25,
+            from: "mindless@localhost".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DigestConfig {
+    /// Minutes between looking for users whose week ended.
+    pub check_minutes: u64,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        DigestConfig { check_minutes: 15 }
+    }
+}
+
+/// Optional parts of the server which can be turned off.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeaturesConfig {
+    /// Serve prometheus metrics at `/metrics`.
+    pub metrics: bool,
+
+    /// Take scheduled backups while the server is running.
+    pub backups: bool,
+
+    /// Post events to the webhooks users register.
+    pub webhooks: bool,
+
+    /// Stream changes to clients at `/mindless/api/events/<user_id>`.
+    pub events: bool,
+
+    /// Remind users of their habits.
+    pub reminders: bool,
+
+    /// Serve weekly digests and send them to users who asked for them.
+    pub digest: bool,
+
+    /// Serve svg charts at `/mindless/api/chart/<user_id>`.
+    pub charts: bool,
+
+    /// Serve the web dashboard at `/mindless/dashboard`. Needs `auth.secret` to sign sessions.
+    /// Logging in takes only a username, there is no password.
+    pub dashboard: bool,
+
+    /// Set goals and check on them at `/mindless/api/goal`.
+    pub goals: bool,
+
+    /// Create tags and tag tasks and instances at `/mindless/api/tag`.
+    pub tags: bool,
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        FeaturesConfig {
+            metrics: true,
+            backups: false,
+            webhooks: true,
+            events: true,
+            reminders: true,
+            digest: true,
+            charts: true,
+            dashboard: true,
+            goals: true,
+            tags: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The configuration file could not be read.
+    Io(PathBuf, std::io::Error),
+
+    /// The configuration file is not valid TOML or has unknown keys.
+    Parse(PathBuf, toml::de::Error),
+
+    /// A value is set but is not valid.
+    Invalid { key: String, reason: String },
+
+    /// A required value is not set.
+    Missing(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(path, e) => write!(f, "Could not read \"{}\": {}", path.display(), e),
+            Error::Parse(path, e) => write!(f, "Could not parse \"{}\": {}", path.display(), e),
+            Error::Invalid { key, reason } => write!(f, "Invalid `{}`: {}", key, reason),
+            Error::Missing(key) => write!(f, "Missing `{}`", key),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Config {
+    /// Load the configuration from the file and environment and validate it.
+    pub fn load() -> Result<Config> {
+        let mut config = match std::env::var(CONFIG_PATH_ENV) {
+            Ok(path) => Config::from_file(Path::new(&path))?,
+            Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            Err(_) => Config::default(),
+        };
+
+        config.apply_env(|key| std::env::var(key).ok())?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Parse a TOML configuration file.
+    pub fn from_file(path: &Path) -> Result<Config> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| Error::Io(path.to_path_buf(), e))?;
+
+        toml::from_str(&contents).map_err(|e| Error::Parse(path.to_path_buf(), e))
+    }
+
+    /// Override values from the environment.
+    ///
+    /// `DATABASE_URL` is still honoured since sqlx uses it to check queries at compile time.
+    fn apply_env<F: Fn(&str) -> Option<String>>(&mut self, var: F) -> Result<()> {
+        if let Some(url) = var("MINDLESS_DATABASE_URL").or_else(|| var("DATABASE_URL")) {
+            self.database.url = url;
+        }
+        if let Some(pool_size) = var("MINDLESS_DATABASE_POOL_SIZE") {
+            self.database.pool_size = parse_env("MINDLESS_DATABASE_POOL_SIZE", &pool_size)?;
+        }
+        if let Some(address) = var("MINDLESS_ADDRESS") {
+            self.server.address = address;
+        }
+        if let Some(port) = var("MINDLESS_PORT") {
+            self.server.port = parse_env("MINDLESS_PORT", &port)?;
+        }
+        if let Some(public_url) = var("MINDLESS_PUBLIC_URL") {
+            self.server.public_url = public_url;
+        }
+        if let Some(origins) = var("MINDLESS_CORS_ORIGINS") {
+            self.server.cors_origins = origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(secret) = var("MINDLESS_AUTH_SECRET") {
+            self.auth.secret = Some(secret);
+        }
+        if let Some(level) = var("MINDLESS_LOG") {
+            self.log.level = level;
+        }
+        if let Some(format) = var("MINDLESS_LOG_FORMAT") {
+            self.log.format = match format.as_str() {
+                "compact" => LogFormat::Compact,
+                "pretty" => LogFormat::Pretty,
+                "json" => LogFormat::Json,
+                _ => {
+                    return Err(Error::Invalid {
+                        key: "MINDLESS_LOG_FORMAT".to_string(),
+                        reason: format!("expected compact, pretty or json but got \"{}\"", format),
+                    })
+                }
+            };
+        }
+        if let Some(directory) = var("MINDLESS_BACKUP_DIRECTORY") {
+            self.backup.directory = PathBuf::from(directory);
+        }
+        if let Some(metrics) = var("MINDLESS_FEATURE_METRICS") {
+            self.features.metrics = parse_env("MINDLESS_FEATURE_METRICS", &metrics)?;
+        }
+        if let Some(backups) = var("MINDLESS_FEATURE_BACKUPS") {
+            self.features.backups = parse_env("MINDLESS_FEATURE_BACKUPS", &backups)?;
+        }
+        if let Some(webhooks) = var("MINDLESS_FEATURE_WEBHOOKS") {
+            self.features.webhooks = parse_env("MINDLESS_FEATURE_WEBHOOKS", &webhooks)?;
+        }
+        if let Some(events) = var("MINDLESS_FEATURE_EVENTS") {
+            self.features.events = parse_env("MINDLESS_FEATURE_EVENTS", &events)?;
+        }
+        if let Some(reminders) = var("MINDLESS_FEATURE_REMINDERS") {
+            self.features.reminders = parse_env("MINDLESS_FEATURE_REMINDERS", &reminders)?;
+        }
+        if let Some(digest) = var("MINDLESS_FEATURE_DIGEST") {
+            self.features.digest = parse_env("MINDLESS_FEATURE_DIGEST", &digest)?;
+        }
+        if let Some(charts) = var("MINDLESS_FEATURE_CHARTS") {
+            self.features.charts = parse_env("MINDLESS_FEATURE_CHARTS", &charts)?;
+        }
+        if let Some(dashboard) = var("MINDLESS_FEATURE_DASHBOARD") {
+            self.features.dashboard = parse_env("MINDLESS_FEATURE_DASHBOARD", &dashboard)?;
+        }
+        if let Some(goals) = var("MINDLESS_FEATURE_GOALS") {
+            self.features.goals = parse_env("MINDLESS_FEATURE_GOALS", &goals)?;
+        }
+        if let Some(tags) = var("MINDLESS_FEATURE_TAGS") {
+            self.features.tags = parse_env("MINDLESS_FEATURE_TAGS", &tags)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check every value makes sense.
+    fn validate(&self) -> Result<()> {
+        if self.database.url.is_empty() {
+            return Err(Error::Missing("database.url".to_string()));
+        }
+
+        let schemes = database::backend::URI_SCHEMES;
+        if !schemes
+            .iter()
+            .any(|scheme| self.database.url.starts_with(scheme))
+        {
+            return Err(Error::Invalid {
+                key: "database.url".to_string(),
+                reason: format!(
+                    "this server was built for {} databases",
+                    schemes.join(" or ")
+                ),
+            });
+        }
+
+        if self.database.pool_size == 0 {
+            return Err(Error::Invalid {
+                key: "database.pool_size".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        if self.server.address.parse::<IpAddr>().is_err() {
+            return Err(Error::Invalid {
+                key: "server.address".to_string(),
+                reason: format!("\"{}\" is not an ip address", self.server.address),
+            });
+        }
+
+        if !self.server.public_url.starts_with("http") {
+            return Err(Error::Invalid {
+                key: "server.public_url".to_string(),
+                reason: format!("\"{}\" is not an http(s) url", self.server.public_url),
+            });
+        }
+
+        if let Some(origin) = self
+            .server
+            .cors_origins
+            .iter()
+            .find(|origin| *origin != "*" && !origin.starts_with("http"))
+        {
+            return Err(Error::Invalid {
+                key: "server.cors_origins".to_string(),
+                reason: format!("\"{}\" is not an http(s) origin", origin),
+            });
+        }
+
+        if let Some(secret) = &self.auth.secret {
+            if secret.len() < MIN_SECRET_LENGTH {
+                return Err(Error::Invalid {
+                    key: "auth.secret".to_string(),
+                    reason: format!("must be at least {} characters", MIN_SECRET_LENGTH),
+                });
+            }
+        }
+
+        if cfg!(not(feature = "sqlite")) && self.features.backups {
+            return Err(Error::Invalid {
+                key: "features.backups".to_string(),
+                reason: "backups are only supported on sqlite, use pg_dump instead".to_string(),
+            });
+        }
+
+        if self.backup.interval_hours == 0 {
+            return Err(Error::Invalid {
+                key: "backup.interval_hours".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        if self.backup.keep_last == 0 {
+            return Err(Error::Invalid {
+                key: "backup.keep_last".to_string(),
+                reason: "must be at least 1, otherwise every backup is deleted".to_string(),
+            });
+        }
+
+        if let Some(max_age_days) = self.backup.max_age_days {
+            if max_age_days < 1 {
+                return Err(Error::Invalid {
+                    key: "backup.max_age_days".to_string(),
+                    reason: "must be at least 1".to_string(),
+                });
+            }
+        }
+
+        if self.webhooks.max_attempts == 0 {
+            return Err(Error::Invalid {
+                key: "webhooks.max_attempts".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        if self.webhooks.missed_check_minutes == 0 {
+            return Err(Error::Invalid {
+                key: "webhooks.missed_check_minutes".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        if !(1..=MAX_RETENTION_DAYS).contains(&self.webhooks.delivery_retention_days) {
+            return Err(Error::Invalid {
+                key: "webhooks.delivery_retention_days".to_string(),
+                reason: format!("must be between 1 and {}", MAX_RETENTION_DAYS),
+            });
+        }
+
+        if self.events.history == 0 {
+            return Err(Error::Invalid {
+                key: "events.history".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        if self.events.keep_alive_secs == 0 {
+            return Err(Error::Invalid {
+                key: "events.keep_alive_secs".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        if self.reminders.check_secs == 0 {
+            return Err(Error::Invalid {
+                key: "reminders.check_secs".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        if self.reminders.max_late_minutes < 1 {
+            return Err(Error::Invalid {
+                key: "reminders.max_late_minutes".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        if let Some(smtp) = &self.reminders.smtp {
+            if !smtp.from.contains('@') {
+                return Err(Error::Invalid {
+                    key: "reminders.smtp.from".to_string(),
+                    reason: format!("\"{}\" is not an email address", smtp.from),
+                });
+            }
+        }
+
+        if self.digest.check_minutes == 0 {
+            return Err(Error::Invalid {
+                key: "digest.check_minutes".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        if let Err(e) = self.log.level.parse::<tracing_subscriber::EnvFilter>() {
+            return Err(Error::Invalid {
+                key: "log.level".to_string(),
+                reason: e.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_env<T: std::str::FromStr>(key: &str, value: &str) -> Result<T>
+where
+    T::Err: fmt::Display,
+{
+    value.parse().map_err(|e: T::Err| Error::Invalid {
+        key: key.to_string(),
+        reason: format!("\"{}\": {}", value, e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config_with_env(toml: &str, env: &[(&str, &str)]) -> Result<Config> {
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
+        let mut config: Config = toml::from_str(toml).expect("Valid toml.");
+        config.apply_env(|key| env.get(key).cloned())?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    #[test]
+    fn file_values() {
+        let config = config_with_env(
+            r#"
+                [database]
+                url = "sqlite:data/habits.db"
+                pool_size = 2
+
+                [database.sqlite]
+                journal_mode = "delete"
+
+                [server]
+                port = 9000
+            "#,
+            &[],
+        )
+        .expect("Valid config.");
+
+        assert_eq!(config.database.url, "sqlite:data/habits.db");
+        assert_eq!(config.database.pool_size, 2);
+        assert_eq!(config.database.sqlite.journal_mode, JournalMode::Delete);
+        // Unset values keep their defaults.
+        assert_eq!(config.database.sqlite.synchronous, Synchronous::Normal);
+        assert_eq!(config.server.port, 9000);
+        assert_eq!(config.server.address, "127.0.0.1");
+    }
+
+    #[test]
+    fn environment_overrides_file() {
+        let config = config_with_env(
+            r#"
+                [database]
+                url = "sqlite:data/habits.db"
+            "#,
+            &[
+                ("DATABASE_URL", "sqlite:other.db"),
+                ("MINDLESS_PORT", "1234"),
+                ("MINDLESS_CORS_ORIGINS", "https://a.com, https://b.com"),
+                ("MINDLESS_LOG_FORMAT", "json"),
+            ],
+        )
+        .expect("Valid config.");
+
+        assert_eq!(config.database.url, "sqlite:other.db");
+        assert_eq!(config.server.port, 1234);
+        assert_eq!(
+            config.server.cors_origins,
+            vec!["https://a.com".to_string(), "https://b.com".to_string()]
+        );
+        assert_eq!(config.log.format, LogFormat::Json);
+    }
+
+    #[test]
+    fn missing_database_url() {
+        let error = config_with_env("", &[]).expect_err("Database url is required.");
+
+        assert_eq!(error.to_string(), "Missing `database.url`");
+    }
+
+    #[test]
+    fn invalid_values() {
+        let url = ("DATABASE_URL", "sqlite:data/habits.db");
+
+        assert!(config_with_env("", &[url, ("MINDLESS_PORT", "not a port")]).is_err());
+        assert!(config_with_env("", &[url, ("MINDLESS_ADDRESS", "localhost:80")]).is_err());
+        assert!(config_with_env("", &[url, ("MINDLESS_AUTH_SECRET", "short")]).is_err());
+        assert!(config_with_env("", &[url, ("MINDLESS_CORS_ORIGINS", "a.com")]).is_err());
+        assert!(config_with_env("", &[url, ("MINDLESS_PUBLIC_URL", "a.com")]).is_err());
+        assert!(config_with_env("[database]\npool_size = 0", &[url]).is_err());
+        assert!(config_with_env("[backup]\nkeep_last = 0", &[url]).is_err());
+        assert!(config_with_env("[webhooks]\nmax_attempts = 0", &[url]).is_err());
+        assert!(config_with_env("[webhooks]\ndelivery_retention_days = 0", &[url]).is_err());
+        assert!(config_with_env("[events]\nhistory = 0", &[url]).is_err());
+        assert!(config_with_env("[reminders]\ncheck_secs = 0", &[url]).is_err());
+        assert!(config_with_env("[reminders.smtp]\nfrom = \"nobody\"", &[url]).is_err());
+        assert!(config_with_env("[digest]\ncheck_minutes = 0", &[url]).is_err());
+    }
+
+    #[test]
+    fn database_passwords_are_not_printed() {
+        let config = DatabaseConfig {
+            url: "postgres://me:hunter2@db:5432/mindless?sslmode=require&password=hunter2"
+                .to_string(),
+            ..DatabaseConfig::default()
+        };
+
+        let printed = format!("{:?}", config);
+        assert!(!printed.contains("hunter2"), "{}", printed);
+        assert!(printed.contains(
+            "postgres://me:<redacted>@db:5432/mindless?sslmode=require&password=<redacted>"
+        ));
+        assert_eq!(
+            redacted_url("sqlite:data/habits.db"),
+            "sqlite:data/habits.db"
+        );
+        assert_eq!(
+            redacted_url("postgres://mindless@localhost/mindless"),
+            "postgres://mindless@localhost/mindless"
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let result: std::result::Result<Config, _> = toml::from_str("[server]\nprot = 80");
+
+        assert!(result.is_err());
+    }
+}
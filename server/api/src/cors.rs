@@ -0,0 +1,53 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Method, Status};
+use rocket::{Request, Response};
+use std::io::Cursor;
+
+/// Fairing allowing cross origin requests from the configured origins.
+pub struct Cors {
+    /// Allowed origins. `*` allows any origin.
+    origins: Vec<String>,
+}
+
+impl Cors {
+    pub fn new(origins: Vec<String>) -> Cors {
+        Cors { origins }
+    }
+
+    fn is_allowed(&self, origin: &str) -> bool {
+        self.origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == origin)
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let origin = match request.headers().get_one("Origin") {
+            Some(origin) if self.is_allowed(origin) => origin.to_string(),
+            _ => return,
+        };
+
+        response.set_header(Header::new("Access-Control-Allow-Origin", origin));
+        response.set_header(Header::new("Vary", "Origin"));
+
+        // We have no OPTIONS routes so answer pre-flight requests here.
+        if request.method() == Method::Options && response.status() == Status::NotFound {
+            response.set_status(Status::NoContent);
+            response.set_sized_body(0, Cursor::new(""));
+            response.set_header(Header::new(
+                "Access-Control-Allow-Methods",
+                "GET, POST, OPTIONS",
+            ));
+            response.set_header(Header::new("Access-Control-Allow-Headers", "Content-Type"));
+        }
+    }
+}
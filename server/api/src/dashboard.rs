@@ -0,0 +1,1523 @@
+//! A web dashboard to see and change a day or a week without the app.
+//!
+//! Pages are rendered on the server. Every change is a form which posts to the dashboard and
+//! redirects back to the page it came from, with a notice when the change was turned down. Users
+//! stay logged in with a cookie signed with `auth.secret`, so the dashboard is only mounted when
+//! it is set. Every form carries a token derived from the session, so other sites can't post
+//! forms on behalf of a logged in user.
+//!
+//! There is no password. Like the app, the dashboard trusts whoever reaches it: anyone who can
+//! reach it can log in as any user by typing their username. Only serve it where the API is
+//! trusted too, e.g. behind a VPN or a proxy which authenticates users.
+use chrono::{Duration, NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use database::error::Error as DBError;
+use database::habit::Habit;
+use database::instance::Instance;
+use database::store::Store;
+use database::task::Task;
+use database::timer::Timer;
+use database::user::User;
+use mindless_core::duration;
+use mindless_core::path::HabitPath;
+use mindless_core::period::{self, start_of_day, Period};
+use rand::RngCore;
+use rocket::http::{ContentType, Header};
+use rocket::request::{self, Form, FromRequest};
+use rocket::response::content::Content;
+use rocket::response::Redirect;
+use rocket::{Request, State};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tracing::instrument;
+
+use crate::chart::{in_range, parse_date, user_today};
+use crate::checkin::{user_habit, Links};
+use crate::digest::{timezone, week_of};
+use crate::error::{Error, Result};
+use crate::events::{Change, Changes};
+use crate::logging::RequestId;
+use crate::metrics::Metrics;
+use crate::qr::escape;
+use crate::schedule::Clock;
+use crate::signing::Signer;
+use crate::task::{start_timer, stop_timer, user_instance, user_task};
+use crate::webhook::{Dispatcher, Event};
+
+/// Where the dashboard lives.
+const BASE: &str = "/mindless/dashboard";
+
+/// Name of the session cookie.
+const COOKIE: &str = "mindless_session";
+
+/// Name of the cookie the login form is checked against, since there is no session yet.
+const LOGIN_COOKIE: &str = "mindless_login";
+
+/// Users have to log in again after this many days.
+const SESSION_DAYS: i64 = 30;
+
+/// How times are shown in and read from `datetime-local` inputs.
+const INPUT_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Browsers leave out the seconds when they are zero.
+const INPUT_FORMAT_MINUTES: &str = "%Y-%m-%dT%H:%M";
+
+/// What the signature of a session covers.
+fn message(user_id: i64, issued_at: i64) -> String {
+    format!("session.{}.{}", user_id, issued_at)
+}
+
+/// The session cookie of a user logging in at `now`, `<user_id>.<issued_at>.<signature>`.
+pub fn session(signer: &Signer, user_id: i64, now: &NaiveDateTime) -> String {
+    let issued_at = now.timestamp();
+
+    format!(
+        "{}.{}.{}",
+        user_id,
+        issued_at,
+        signer.sign(message(user_id, issued_at).as_bytes())
+    )
+}
+
+/// The user a session cookie was issued to, unless it is malformed, forged or expired.
+pub fn verify(session: &str, signer: &Signer, now: &NaiveDateTime) -> Option<i64> {
+    let mut parts = session.splitn(3, '.');
+    let user_id = parts.next()?.parse::<i64>().ok()?;
+    let issued_at = parts.next()?.parse::<i64>().ok()?;
+    let signature = parts.next()?;
+
+    if !signer.verify(message(user_id, issued_at).as_bytes(), signature) {
+        return None;
+    }
+
+    let age = now.timestamp() - issued_at;
+    if age < 0 || age > Duration::days(SESSION_DAYS).num_seconds() {
+        return None;
+    }
+
+    Some(user_id)
+}
+
+/// What the token of forms posted with a session covers.
+fn csrf_message(session: &str) -> String {
+    format!("csrf.{}", session)
+}
+
+/// What the token of the login form covers.
+fn login_message(nonce: &str) -> String {
+    format!("login.{}", nonce)
+}
+
+/// Whether two tokens are the same, compared in constant time.
+fn same_token(a: &str, b: &str) -> bool {
+    let diff = a
+        .bytes()
+        .zip(b.bytes())
+        .fold(0, |diff, (a, b)| diff | (a ^ b));
+
+    a.len() == b.len() && diff == 0
+}
+
+/// Cookies are kept off plain http when the server is reached over https.
+fn cookie_attributes(links: &Links) -> &'static str {
+    if links.is_https() {
+        "HttpOnly; SameSite=Lax; Secure"
+    } else {
+        "HttpOnly; SameSite=Lax"
+    }
+}
+
+fn set_cookie(session: &str, links: &Links) -> Header<'static> {
+    Header::new(
+        "Set-Cookie",
+        format!(
+            "{}={}; Path={}; Max-Age={}; {}",
+            COOKIE,
+            session,
+            BASE,
+            Duration::days(SESSION_DAYS).num_seconds(),
+            cookie_attributes(links)
+        ),
+    )
+}
+
+fn clear_cookie(links: &Links) -> Header<'static> {
+    Header::new(
+        "Set-Cookie",
+        format!(
+            "{}=; Path={}; Max-Age=0; {}",
+            COOKIE,
+            BASE,
+            cookie_attributes(links)
+        ),
+    )
+}
+
+/// A random value for the login form to be checked against, kept until the browser closes.
+fn set_login_cookie(nonce: &str, links: &Links) -> Header<'static> {
+    Header::new(
+        "Set-Cookie",
+        format!(
+            "{}={}; Path={}/login; {}",
+            LOGIN_COOKIE,
+            nonce,
+            BASE,
+            cookie_attributes(links)
+        ),
+    )
+}
+
+fn generate_nonce() -> String {
+    let mut nonce = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    base64::encode_config(&nonce, base64::URL_SAFE_NO_PAD)
+}
+
+/// The logged in user.
+///
+/// Requests without a valid session forward, so routes take an `Option<Session>` and send those
+/// to the login page.
+#[derive(Debug)]
+pub struct Session {
+    user: User,
+
+    /// The token forms posted with this session carry.
+    csrf: String,
+}
+
+impl Session {
+    /// Whether a form was posted from a page of this session rather than from another site.
+    fn posted_here(&self, csrf: &str) -> bool {
+        same_token(&self.csrf, csrf)
+    }
+}
+
+#[rocket::async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for Session {
+    type Error = ();
+
+    async fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let cookie = match request.cookies().get(COOKIE) {
+            Some(cookie) => cookie.value().to_string(),
+            None => return request::Outcome::Forward(()),
+        };
+        let (signer, store, clock) = match (
+            request.managed_state::<Signer>(),
+            request.managed_state::<Arc<dyn Store>>(),
+            request.managed_state::<Arc<dyn Clock>>(),
+        ) {
+            (Some(signer), Some(store), Some(clock)) => (signer, store, clock),
+            _ => return request::Outcome::Forward(()),
+        };
+        let user_id = match verify(&cookie, signer, &clock.now().naive_utc()) {
+            Some(user_id) => user_id,
+            None => return request::Outcome::Forward(()),
+        };
+
+        // The user may have been deleted since logging in.
+        match store.retrieve_user(user_id).await {
+            Ok(user) => request::Outcome::Success(Session {
+                user,
+                csrf: signer.sign(csrf_message(&cookie).as_bytes()),
+            }),
+            Err(_) => request::Outcome::Forward(()),
+        }
+    }
+}
+
+/// The value the login form is checked against, missing until the login page was shown.
+#[derive(Debug)]
+pub struct LoginNonce(String);
+
+#[rocket::async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for LoginNonce {
+    type Error = ();
+
+    async fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        match request.cookies().get(LOGIN_COOKIE) {
+            Some(cookie) => request::Outcome::Success(LoginNonce(cookie.value().to_string())),
+            None => request::Outcome::Forward(()),
+        }
+    }
+}
+
+/// A page, or where to go after a change.
+#[derive(Responder)]
+pub enum Page {
+    Html(Content<String>),
+
+    Redirect(Redirect),
+
+    // Redirect while setting or clearing the session cookie.
+    Session(Redirect, Header<'static>),
+
+    // The login page while setting the cookie its form is checked against.
+    Login(Content<String>, Header<'static>),
+}
+
+impl Page {
+    fn html(html: String) -> Page {
+        Page::Html(Content(ContentType::HTML, html))
+    }
+
+    fn login(notice: Option<Notice>) -> Page {
+        let url = match notice {
+            Some(notice) => format!("{}/login?notice={}", BASE, notice.key()),
+            None => format!("{}/login", BASE),
+        };
+
+        Page::Redirect(Redirect::to(url))
+    }
+}
+
+/// Whether a page shows a day or the week it is in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    Day,
+    Week,
+}
+
+impl View {
+    fn name(self) -> &'static str {
+        match self {
+            View::Day => "day",
+            View::Week => "week",
+        }
+    }
+}
+
+/// A page of the dashboard, which changes made on it go back to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    view: View,
+
+    /// Today when missing, so the page keeps up with the date.
+    date: Option<NaiveDate>,
+}
+
+impl Location {
+    /// The location in the query, by default today.
+    pub fn parse(view: Option<String>, date: Option<String>) -> Result<Location> {
+        let view = match view.as_deref() {
+            None | Some("day") => View::Day,
+            Some("week") => View::Week,
+            Some(view) => {
+                return Err(Error::BadRequest(format!(
+                    "\"{}\" is not day or week",
+                    view
+                )))
+            }
+        };
+        let date = match date {
+            Some(date) => Some(parse_date(&date)?),
+            None => None,
+        };
+
+        Ok(Location { view, date })
+    }
+
+    fn query(&self) -> String {
+        match self.date {
+            Some(date) => format!("view={}&date={}", self.view.name(), date),
+            None => format!("view={}", self.view.name()),
+        }
+    }
+
+    /// The url of the page, showing a notice if there is one.
+    fn url(&self, notice: Option<Notice>) -> String {
+        match notice {
+            Some(notice) => format!("{}?{}&notice={}", BASE, self.query(), notice.key()),
+            None => format!("{}?{}", BASE, self.query()),
+        }
+    }
+
+    /// The first and last local date shown.
+    fn days(&self, today: NaiveDate) -> Result<(NaiveDate, NaiveDate)> {
+        let date = self.date.unwrap_or(today);
+        match self.view {
+            View::Day => Ok((date, date)),
+            View::Week => {
+                let monday = week_of(&date);
+                let sunday = monday
+                    .checked_add_signed(Duration::days(6))
+                    .ok_or_else(|| out_of_range(&date))?;
+
+                Ok((monday, sunday))
+            }
+        }
+    }
+
+    /// The same view moved by a number of days or weeks, None past the dates queries take.
+    fn shift(&self, today: NaiveDate, steps: i64) -> Option<Location> {
+        let step = match self.view {
+            View::Day => Duration::days(steps),
+            View::Week => Duration::weeks(steps),
+        };
+        let date = self
+            .date
+            .unwrap_or(today)
+            .checked_add_signed(step)
+            .filter(in_range)?;
+
+        Some(Location {
+            view: self.view,
+            date: Some(date),
+        })
+    }
+}
+
+fn out_of_range(date: &NaiveDate) -> Error {
+    Error::BadRequest(format!("The days around {} can't be shown", date))
+}
+
+/// Why a change was turned down, shown on the page it goes back to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Notice {
+    UnknownUser,
+    TimerRunning,
+    TimerStopped,
+    InvalidPeriod,
+    InstanceExists,
+    InvalidHabit,
+    MissingParent,
+    HabitExists,
+    FormExpired,
+}
+
+const NOTICES: [Notice; 9] = [
+    Notice::UnknownUser,
+    Notice::TimerRunning,
+    Notice::TimerStopped,
+    Notice::InvalidPeriod,
+    Notice::InstanceExists,
+    Notice::InvalidHabit,
+    Notice::MissingParent,
+    Notice::HabitExists,
+    Notice::FormExpired,
+];
+
+impl Notice {
+    /// How the notice is passed in the query.
+    fn key(self) -> &'static str {
+        match self {
+            Notice::UnknownUser => "unknown-user",
+            Notice::TimerRunning => "timer-running",
+            Notice::TimerStopped => "timer-stopped",
+            Notice::InvalidPeriod => "invalid-period",
+            Notice::InstanceExists => "instance-exists",
+            Notice::InvalidHabit => "invalid-habit",
+            Notice::MissingParent => "missing-parent",
+            Notice::HabitExists => "habit-exists",
+            Notice::FormExpired => "form-expired",
+        }
+    }
+
+    /// Unknown notices are left out rather than failing the page.
+    fn parse(key: Option<String>) -> Option<Notice> {
+        let key = key?;
+        NOTICES.iter().copied().find(|notice| notice.key() == key)
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            Notice::UnknownUser => "There is no user with that username.",
+            Notice::TimerRunning => "That task is already being timed.",
+            Notice::TimerStopped => "That task is not being timed.",
+            Notice::InvalidPeriod => "Times look like 2020-10-12T09:30 and end after they start.",
+            Notice::InstanceExists => "That task already has time recorded for exactly then.",
+            Notice::InvalidHabit => "Habits look like Health/Exercise and repeat like 1d or 12h.",
+            Notice::MissingParent => "Add the habit it goes under first.",
+            Notice::HabitExists => "That habit already exists.",
+            Notice::FormExpired => "The page was out of date, please try again.",
+        }
+    }
+}
+
+/// A task along with the time tracked on the days shown and its timer if it is running.
+#[derive(Debug)]
+struct TaskRow {
+    task: Task,
+    tracked: Duration,
+    timer: Option<Timer>,
+}
+
+/// Time recorded on the days shown along with the name of its task.
+#[derive(Debug)]
+struct InstanceRow {
+    task: String,
+    instance: Instance,
+}
+
+/// A habit along with how deep it is in the tree and whether it is done for its current period.
+#[derive(Debug)]
+struct HabitRow {
+    habit: Habit,
+    depth: usize,
+    done: bool,
+}
+
+/// Everything on a page of the dashboard.
+#[derive(Debug)]
+struct Overview {
+    user: User,
+    timezone: Tz,
+    today: NaiveDate,
+    first: NaiveDate,
+    last: NaiveDate,
+    location: Location,
+    notice: Option<Notice>,
+
+    /// The token forms on the page carry.
+    csrf: String,
+
+    tasks: Vec<TaskRow>,
+    instances: Vec<InstanceRow>,
+    habits: Vec<HabitRow>,
+}
+
+/// Habits in outline order, each followed by the habits under it, along with how deep they are.
+fn outline(habits: Vec<Habit>) -> Vec<(Habit, usize)> {
+    let ids: HashSet<i64> = habits.iter().map(Habit::get_id).collect();
+    let mut children: HashMap<Option<i64>, Vec<Habit>> = HashMap::new();
+    for habit in habits {
+        // Show habits whose parent is missing at the top rather than not at all.
+        let parent_id = habit.get_parent_id().filter(|id| ids.contains(id));
+        children.entry(parent_id).or_default().push(habit);
+    }
+
+    let mut stack: Vec<(Habit, usize)> = children
+        .remove(&None)
+        .unwrap_or_default()
+        .into_iter()
+        .rev()
+        .map(|habit| (habit, 0))
+        .collect();
+    let mut outline = Vec::new();
+    while let Some((habit, depth)) = stack.pop() {
+        if let Some(under) = children.remove(&Some(habit.get_id())) {
+            stack.extend(under.into_iter().rev().map(|child| (child, depth + 1)));
+        }
+        outline.push((habit, depth));
+    }
+
+    outline
+}
+
+async fn overview(
+    store: &dyn Store,
+    clock: &dyn Clock,
+    session: &Session,
+    location: Location,
+    notice: Option<Notice>,
+) -> Result<Overview> {
+    let (user, timezone, today) = user_today(store, clock, session.user.get_id()).await?;
+    let (first, last) = location.days(today)?;
+    let after = last.succ_opt().ok_or_else(|| out_of_range(&last))?;
+    let bounds = Period::new(
+        start_of_day(&first, &timezone),
+        start_of_day(&after, &timezone),
+    );
+
+    let mut timers: HashMap<i64, Timer> = store
+        .get_timers(user.get_id())
+        .await?
+        .into_iter()
+        .map(|timer| (timer.get_task_id(), timer))
+        .collect();
+    let mut tasks = Vec::new();
+    let mut instances = Vec::new();
+    for task in store.get_tasks(&user).await? {
+        let mut tracked = Duration::zero();
+        for instance in store.get_instances(task.get_id()).await? {
+            let period = instance.get_period();
+            if period.overlaps(&bounds) {
+                tracked = tracked + period.intersection(&bounds).duration();
+                instances.push(InstanceRow {
+                    task: task.get_name().to_string(),
+                    instance,
+                });
+            }
+        }
+        tasks.push(TaskRow {
+            timer: timers.remove(&task.get_id()),
+            task,
+            tracked,
+        });
+    }
+    instances.sort_by_key(|row| (*row.instance.get_start(), row.instance.get_id()));
+
+    let now = clock.now().naive_utc();
+    let mut habits = Vec::new();
+    for (habit, depth) in outline(store.get_habits(user.get_id()).await?) {
+        let done = !store
+            .get_completions(habit.get_id(), &habit.get_period(&now)?)
+            .await?
+            .is_empty();
+        habits.push(HabitRow { habit, depth, done });
+    }
+
+    Ok(Overview {
+        user,
+        timezone,
+        today,
+        first,
+        last,
+        location,
+        notice,
+        csrf: session.csrf.clone(),
+        tasks,
+        instances,
+        habits,
+    })
+}
+
+/// The hidden input with the token every form carries.
+fn csrf_input(csrf: &str) -> String {
+    format!(
+        r#"<input type="hidden" name="csrf" value="{}">"#,
+        escape(csrf)
+    )
+}
+
+/// A form posting to the dashboard with a single button.
+fn button(action: &str, label: &str, csrf: &str) -> String {
+    format!(
+        r#"<form method="post" action="{}">{}<button>{}</button></form>"#,
+        escape(action),
+        csrf_input(csrf),
+        escape(label)
+    )
+}
+
+fn document(title: &str, header: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 50em; margin: 2em auto; padding: 0 1em; }}
+header {{ display: flex; gap: 1em; align-items: center; }}
+form {{ display: inline; }}
+table {{ border-collapse: collapse; width: 100%; }}
+th, td {{ text-align: left; padding: 0.25em 0.5em; border-bottom: 1px solid #ddd; }}
+.notice {{ background: #fff3cd; padding: 0.5em; }}
+</style>
+</head>
+<body>
+{header}<h1>{title}</h1>
+{body}</body>
+</html>
+"#,
+        title = escape(title),
+        header = header,
+        body = body,
+    )
+}
+
+/// The login page. There is no password, see the module documentation.
+fn login_page(notice: Option<Notice>, csrf: &str) -> String {
+    let mut body = String::new();
+    if let Some(notice) = notice {
+        body.push_str(&format!(
+            "<p class=\"notice\">{}</p>\n",
+            escape(notice.message())
+        ));
+    }
+    body.push_str(&format!(
+        concat!(
+            "<form method=\"post\" action=\"{}/login\">\n",
+            "{}\n",
+            "<label>Username <input name=\"username\" required autofocus></label>\n",
+            "<button>Log in</button>\n</form>\n"
+        ),
+        BASE,
+        csrf_input(csrf)
+    ));
+
+    document("Log in", "", &body)
+}
+
+/// The page with the tasks, time and habits of the days shown.
+fn render(overview: &Overview) -> String {
+    let location = &overview.location;
+    let query = location.query();
+    let (first, last) = (overview.first, overview.last);
+    let csrf = &overview.csrf;
+    let local = |time: &NaiveDateTime| overview.timezone.from_utc_datetime(time).naive_local();
+    // There is nothing to go to past the dates queries take.
+    let link = |steps: i64, label: &str| match location.shift(overview.today, steps) {
+        Some(shifted) => format!("<a href=\"{}\">{}</a>\n", escape(&shifted.url(None)), label),
+        None => String::new(),
+    };
+
+    let header = format!(
+        concat!(
+            "<header><strong>{name}</strong>\n",
+            "<a href=\"{base}?view=day\">Today</a>\n",
+            "<a href=\"{base}?view=week\">This week</a>\n",
+            "{previous}",
+            "{next}",
+            "{logout}</header>\n"
+        ),
+        name = escape(overview.user.get_name()),
+        base = BASE,
+        previous = link(-1, "Previous"),
+        next = link(1, "Next"),
+        logout = button(&format!("{}/logout", BASE), "Log out", csrf),
+    );
+    let title = match location.view {
+        View::Day => first.format("%A %-d %B %Y").to_string(),
+        View::Week => format!("{} to {}", first.format("%-d %B"), last.format("%-d %B %Y")),
+    };
+
+    let mut body = String::new();
+    if let Some(notice) = overview.notice {
+        body.push_str(&format!(
+            "<p class=\"notice\">{}</p>\n",
+            escape(notice.message())
+        ));
+    }
+
+    body.push_str("<h2>Tasks</h2>\n");
+    if overview.tasks.is_empty() {
+        body.push_str("<p>No tasks yet, add them in the app.</p>\n");
+    } else {
+        body.push_str("<table>\n<tr><th>Task</th><th>Time</th><th>Timer</th></tr>\n");
+        for row in &overview.tasks {
+            let timer = match &row.timer {
+                Some(timer) => format!(
+                    "Since {} {}",
+                    local(timer.get_started_at()).format("%H:%M"),
+                    button(
+                        &format!("{}/timer/{}/stop?{}", BASE, row.task.get_id(), query),
+                        "Stop",
+                        csrf
+                    )
+                ),
+                None => button(
+                    &format!("{}/timer/{}/start?{}", BASE, row.task.get_id(), query),
+                    "Start",
+                    csrf,
+                ),
+            };
+            body.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape(row.task.get_name()),
+                duration::format(row.tracked),
+                timer
+            ));
+        }
+        body.push_str("</table>\n");
+    }
+
+    body.push_str("<h2>Time</h2>\n");
+    if overview.instances.is_empty() {
+        body.push_str("<p>No time recorded.</p>\n");
+    } else {
+        body.push_str("<table>\n<tr><th>Task</th><th>Period</th><th>Time</th></tr>\n");
+        for row in &overview.instances {
+            let id = row.instance.get_id();
+            body.push_str(&format!(
+                concat!(
+                    "<tr><td>{task}</td><td>",
+                    "<form method=\"post\" action=\"{edit}\">{csrf}",
+                    "<input type=\"datetime-local\" step=\"1\" name=\"start\" value=\"{start}\" required> ",
+                    "<input type=\"datetime-local\" step=\"1\" name=\"end\" value=\"{end}\" required> ",
+                    "<button>Save</button> ",
+                    "<button formaction=\"{delete}\">Delete</button>",
+                    "</form></td><td>{time}</td></tr>\n"
+                ),
+                task = escape(&row.task),
+                edit = escape(&format!("{}/instance/{}/edit?{}", BASE, id, query)),
+                csrf = csrf_input(csrf),
+                start = local(row.instance.get_start()).format(INPUT_FORMAT),
+                end = local(row.instance.get_end()).format(INPUT_FORMAT),
+                delete = escape(&format!("{}/instance/{}/delete?{}", BASE, id, query)),
+                time = duration::format(row.instance.get_period().duration()),
+            ));
+        }
+        body.push_str("</table>\n");
+    }
+
+    body.push_str("<h2>Habits</h2>\n");
+    if !overview.habits.is_empty() {
+        body.push_str("<table>\n<tr><th>Habit</th><th>Done</th><th></th></tr>\n");
+        for row in &overview.habits {
+            let id = row.habit.get_id();
+            let mark = if row.done {
+                button(
+                    &format!("{}/habit/{}/unmark?{}", BASE, id, query),
+                    "Unmark",
+                    csrf,
+                )
+            } else {
+                button(
+                    &format!("{}/habit/{}/mark?{}", BASE, id, query),
+                    "Mark",
+                    csrf,
+                )
+            };
+            body.push_str(&format!(
+                "<tr><td style=\"padding-left: {}em\">{}</td><td>{}</td><td>{} {}</td></tr>\n",
+                0.5 + 1.5 * row.depth as f64,
+                escape(row.habit.get_name()),
+                if row.done { "Yes" } else { "No" },
+                mark,
+                button(
+                    &format!("{}/habit/{}/delete?{}", BASE, id, query),
+                    "Delete",
+                    csrf
+                )
+            ));
+        }
+        body.push_str("</table>\n");
+    }
+    body.push_str(&format!(
+        concat!(
+            "<form method=\"post\" action=\"{}\">\n",
+            "{}\n",
+            "<input name=\"path\" placeholder=\"Health/Exercise\" required>\n",
+            "<input name=\"repeat\" placeholder=\"1d\">\n",
+            "<button>Add habit</button>\n</form>\n"
+        ),
+        escape(&format!("{}/habit?{}", BASE, query)),
+        csrf_input(csrf)
+    ));
+
+    document(&title, &header, &body)
+}
+
+/// A local time from a `datetime-local` input.
+fn parse_time(time: &str, timezone: &Tz) -> Option<NaiveDateTime> {
+    let local = NaiveDateTime::parse_from_str(time, INPUT_FORMAT)
+        .or_else(|_| NaiveDateTime::parse_from_str(time, INPUT_FORMAT_MINUTES))
+        .ok()?;
+
+    // Times skipped when the clocks go forward don't exist.
+    let time = timezone.from_local_datetime(&local).earliest()?;
+
+    Some(time.naive_utc())
+}
+
+/// Go back to the page a change was made on, with a notice if it was turned down.
+fn back(location: Location, notice: Option<Notice>) -> Result<Page> {
+    Ok(Page::Redirect(Redirect::to(location.url(notice))))
+}
+
+#[derive(FromForm)]
+pub struct Login {
+    username: String,
+    csrf: String,
+}
+
+/// A form with nothing but the token, e.g. a single button.
+#[derive(FromForm)]
+pub struct Csrf {
+    csrf: String,
+}
+
+#[derive(FromForm)]
+pub struct EditInstance {
+    start: String,
+    end: String,
+    csrf: String,
+}
+
+#[derive(FromForm)]
+pub struct NewHabit {
+    path: String,
+
+    /// How often the habit repeats, e.g. `1d`. Empty if it doesn't.
+    repeat: Option<String>,
+
+    csrf: String,
+}
+
+// Shows a fresh login form every time, along with the cookie it is checked against.
+#[get("/mindless/dashboard/login?<notice>")]
+pub async fn login_get(
+    session: Option<Session>,
+    notice: Option<String>,
+    signer: State<'_, Signer>,
+    links: State<'_, Links>,
+) -> Page {
+    if session.is_some() {
+        return Page::Redirect(Redirect::to(BASE));
+    }
+
+    let nonce = generate_nonce();
+    let csrf = signer.sign(login_message(&nonce).as_bytes());
+
+    Page::Login(
+        Content(ContentType::HTML, login_page(Notice::parse(notice), &csrf)),
+        set_login_cookie(&nonce, links.inner()),
+    )
+}
+
+// Logs in with nothing but a username, see the module documentation.
+#[post("/mindless/dashboard/login", data = "<form>")]
+#[instrument(
+    name = "dashboard",
+    skip(nonce, form, store, clock, signer, links, metrics),
+    fields(%request_id)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn login_post(
+    nonce: Option<LoginNonce>,
+    form: Form<Login>,
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    signer: State<'_, Signer>,
+    links: State<'_, Links>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Page> {
+    let _timer = metrics.api_request("dashboard", "Login");
+
+    // Other sites can't read the cookie, so they can't log a user in as someone else.
+    match nonce {
+        Some(LoginNonce(nonce)) if signer.verify(login_message(&nonce).as_bytes(), &form.csrf) => {}
+        _ => return Ok(Page::login(Some(Notice::FormExpired))),
+    }
+
+    let user = match store.get_user(form.username.trim()).await {
+        Ok(user) => user,
+        Err(DBError::NotFound) => return Ok(Page::login(Some(Notice::UnknownUser))),
+        Err(e) => return Err(e.into()),
+    };
+    let session = session(signer.inner(), user.get_id(), &clock.now().naive_utc());
+
+    Ok(Page::Session(
+        Redirect::to(BASE),
+        set_cookie(&session, links.inner()),
+    ))
+}
+
+#[post("/mindless/dashboard/logout", data = "<form>")]
+pub async fn logout(
+    session: Option<Session>,
+    form: Form<Csrf>,
+    links: State<'_, Links>,
+) -> Result<Page> {
+    match session {
+        Some(session) if !session.posted_here(&form.csrf) => {
+            back(Location::parse(None, None)?, Some(Notice::FormExpired))
+        }
+        _ => Ok(Page::Session(
+            Redirect::to(format!("{}/login", BASE)),
+            clear_cookie(links.inner()),
+        )),
+    }
+}
+
+// The tasks, time and habits of a day, or of the week it is in with `view=week`.
+#[get("/mindless/dashboard?<view>&<date>&<notice>")]
+#[instrument(name = "dashboard", skip(session, store, clock, metrics), fields(%request_id))]
+#[allow(clippy::too_many_arguments)]
+pub async fn dashboard(
+    session: Option<Session>,
+    view: Option<String>,
+    date: Option<String>,
+    notice: Option<String>,
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Page> {
+    let _timer = metrics.api_request("dashboard", "Page");
+    let session = match session {
+        Some(session) => session,
+        None => return Ok(Page::login(None)),
+    };
+
+    let location = Location::parse(view, date)?;
+    let overview = overview(
+        store.inner().as_ref(),
+        clock.inner().as_ref(),
+        &session,
+        location,
+        Notice::parse(notice),
+    )
+    .await?;
+
+    Ok(Page::html(render(&overview)))
+}
+
+#[post(
+    "/mindless/dashboard/timer/<task_id>/start?<view>&<date>",
+    data = "<form>"
+)]
+#[instrument(
+    name = "dashboard",
+    skip(session, form, store, clock, dispatcher, changes, metrics),
+    fields(%request_id)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn timer_start(
+    session: Option<Session>,
+    task_id: i64,
+    view: Option<String>,
+    date: Option<String>,
+    form: Form<Csrf>,
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    dispatcher: State<'_, Dispatcher>,
+    changes: State<'_, Changes>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Page> {
+    let _timer = metrics.api_request("dashboard", "StartTimer");
+    let session = match session {
+        Some(session) => session,
+        None => return Ok(Page::login(None)),
+    };
+    let location = Location::parse(view, date)?;
+    if !session.posted_here(&form.csrf) {
+        return back(location, Some(Notice::FormExpired));
+    }
+
+    let started = start_timer(
+        session.user.get_id(),
+        task_id,
+        store.inner().as_ref(),
+        clock.inner().as_ref(),
+        dispatcher.inner(),
+        changes.inner(),
+    )
+    .await;
+
+    match started {
+        Ok(_) => back(location, None),
+        Err(Error::Database(DBError::AlreadyExists)) => back(location, Some(Notice::TimerRunning)),
+        Err(e) => Err(e),
+    }
+}
+
+#[post(
+    "/mindless/dashboard/timer/<task_id>/stop?<view>&<date>",
+    data = "<form>"
+)]
+#[instrument(
+    name = "dashboard",
+    skip(session, form, store, clock, dispatcher, changes, metrics),
+    fields(%request_id)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn timer_stop(
+    session: Option<Session>,
+    task_id: i64,
+    view: Option<String>,
+    date: Option<String>,
+    form: Form<Csrf>,
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    dispatcher: State<'_, Dispatcher>,
+    changes: State<'_, Changes>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Page> {
+    let _timer = metrics.api_request("dashboard", "StopTimer");
+    let session = match session {
+        Some(session) => session,
+        None => return Ok(Page::login(None)),
+    };
+    let location = Location::parse(view, date)?;
+    if !session.posted_here(&form.csrf) {
+        return back(location, Some(Notice::FormExpired));
+    }
+    let store = store.inner().as_ref();
+
+    // Tasks of other users are not found rather than not being timed.
+    let user_id = session.user.get_id();
+    let task = user_task(user_id, task_id, store).await?;
+    let stopped = stop_timer(
+        user_id,
+        task.get_id(),
+        store,
+        clock.inner().as_ref(),
+        dispatcher.inner(),
+        changes.inner(),
+    )
+    .await;
+
+    match stopped {
+        Ok(_) => back(location, None),
+        // The timer was stopped already, e.g. in the app.
+        Err(Error::Database(DBError::NotFound)) => back(location, Some(Notice::TimerStopped)),
+        Err(e) => Err(e),
+    }
+}
+
+#[post(
+    "/mindless/dashboard/instance/<instance_id>/edit?<view>&<date>",
+    data = "<form>"
+)]
+#[instrument(
+    name = "dashboard",
+    skip(session, form, store, changes, metrics),
+    fields(%request_id)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn instance_edit(
+    session: Option<Session>,
+    instance_id: i64,
+    view: Option<String>,
+    date: Option<String>,
+    form: Form<EditInstance>,
+    store: State<'_, Arc<dyn Store>>,
+    changes: State<'_, Changes>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Page> {
+    let _timer = metrics.api_request("dashboard", "EditInstance");
+    let session = match session {
+        Some(session) => session,
+        None => return Ok(Page::login(None)),
+    };
+    let location = Location::parse(view, date)?;
+    if !session.posted_here(&form.csrf) {
+        return back(location, Some(Notice::FormExpired));
+    }
+    let store = store.inner().as_ref();
+
+    let user_id = session.user.get_id();
+    let mut instance = user_instance(user_id, instance_id, store).await?;
+    let settings = store.retrieve_reminder_settings(user_id).await?;
+    let timezone = timezone(&settings).map_err(Error::BadRequest)?;
+    let period = match (
+        parse_time(&form.start, &timezone),
+        parse_time(&form.end, &timezone),
+    ) {
+        (Some(start), Some(end)) if start < end => Period::new(start, end),
+        _ => return back(location, Some(Notice::InvalidPeriod)),
+    };
+
+    match store
+        .update_instance(&mut instance, &period.start, &period.end)
+        .await
+    {
+        Ok(()) => {}
+        Err(DBError::AlreadyExists) => return back(location, Some(Notice::InstanceExists)),
+        Err(e) => return Err(e.into()),
+    }
+    changes.publish(user_id, Change::InstanceUpdated { instance });
+
+    back(location, None)
+}
+
+#[post(
+    "/mindless/dashboard/instance/<instance_id>/delete?<view>&<date>",
+    data = "<form>"
+)]
+#[instrument(
+    name = "dashboard",
+    skip(session, form, store, changes, metrics),
+    fields(%request_id)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn instance_delete(
+    session: Option<Session>,
+    instance_id: i64,
+    view: Option<String>,
+    date: Option<String>,
+    form: Form<Csrf>,
+    store: State<'_, Arc<dyn Store>>,
+    changes: State<'_, Changes>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Page> {
+    let _timer = metrics.api_request("dashboard", "DeleteInstance");
+    let session = match session {
+        Some(session) => session,
+        None => return Ok(Page::login(None)),
+    };
+    let location = Location::parse(view, date)?;
+    if !session.posted_here(&form.csrf) {
+        return back(location, Some(Notice::FormExpired));
+    }
+    let store = store.inner().as_ref();
+
+    let user_id = session.user.get_id();
+    let instance = user_instance(user_id, instance_id, store).await?;
+    store.delete_instance(instance.clone()).await?;
+    changes.publish(user_id, Change::InstanceDeleted { instance });
+
+    back(location, None)
+}
+
+#[post("/mindless/dashboard/habit?<view>&<date>", data = "<form>")]
+#[instrument(
+    name = "dashboard",
+    skip(session, form, store, clock, metrics),
+    fields(%request_id)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn habit_create(
+    session: Option<Session>,
+    view: Option<String>,
+    date: Option<String>,
+    form: Form<NewHabit>,
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Page> {
+    let _timer = metrics.api_request("dashboard", "CreateHabit");
+    let session = match session {
+        Some(session) => session,
+        None => return Ok(Page::login(None)),
+    };
+    let location = Location::parse(view, date)?;
+    if !session.posted_here(&form.csrf) {
+        return back(location, Some(Notice::FormExpired));
+    }
+    let store = store.inner().as_ref();
+
+    let repeat = match form.repeat.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(repeat) => duration::parse(repeat).map(|repeat| Some(repeat.num_seconds())),
+    };
+    let (path, repeat_period_sec) = match (form.path.parse::<HabitPath>(), repeat) {
+        (Ok(path), Ok(repeat)) if period::check_repeat(repeat).is_ok() => (path, repeat),
+        _ => return back(location, Some(Notice::InvalidHabit)),
+    };
+
+    let user_id = session.user.get_id();
+    let parent_id = match path.parent() {
+        Some(parent) => match store.get_habit(user_id, &parent).await {
+            Ok(parent) => Some(parent.get_id()),
+            Err(DBError::NotFound) => return back(location, Some(Notice::MissingParent)),
+            Err(e) => return Err(e.into()),
+        },
+        None => None,
+    };
+    let inserted = store
+        .insert_habit(
+            user_id,
+            parent_id,
+            path.name(),
+            &clock.now().naive_utc(),
+            repeat_period_sec,
+        )
+        .await;
+
+    match inserted {
+        Ok(_) => back(location, None),
+        Err(DBError::AlreadyExists) => back(location, Some(Notice::HabitExists)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[post(
+    "/mindless/dashboard/habit/<habit_id>/mark?<view>&<date>",
+    data = "<form>"
+)]
+#[instrument(
+    name = "dashboard",
+    skip(session, form, store, clock, dispatcher, metrics),
+    fields(%request_id)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn habit_mark(
+    session: Option<Session>,
+    habit_id: i64,
+    view: Option<String>,
+    date: Option<String>,
+    form: Form<Csrf>,
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    dispatcher: State<'_, Dispatcher>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Page> {
+    let _timer = metrics.api_request("dashboard", "MarkHabit");
+    let session = match session {
+        Some(session) => session,
+        None => return Ok(Page::login(None)),
+    };
+    let location = Location::parse(view, date)?;
+    if !session.posted_here(&form.csrf) {
+        return back(location, Some(Notice::FormExpired));
+    }
+    let store = store.inner().as_ref();
+
+    let now = clock.now().naive_utc();
+    let habit = user_habit(session.user.get_id(), habit_id, store).await?;
+    if store.mark_habit(&habit, &now).await? {
+        let event = Event::HabitCompleted {
+            period: habit.get_period(&now)?,
+            habit: habit.clone(),
+        };
+        dispatcher.emit(habit.get_user_id(), event).await;
+    }
+
+    back(location, None)
+}
+
+#[post(
+    "/mindless/dashboard/habit/<habit_id>/unmark?<view>&<date>",
+    data = "<form>"
+)]
+#[instrument(
+    name = "dashboard",
+    skip(session, form, store, clock, metrics),
+    fields(%request_id)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn habit_unmark(
+    session: Option<Session>,
+    habit_id: i64,
+    view: Option<String>,
+    date: Option<String>,
+    form: Form<Csrf>,
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Page> {
+    let _timer = metrics.api_request("dashboard", "UnmarkHabit");
+    let session = match session {
+        Some(session) => session,
+        None => return Ok(Page::login(None)),
+    };
+    let location = Location::parse(view, date)?;
+    if !session.posted_here(&form.csrf) {
+        return back(location, Some(Notice::FormExpired));
+    }
+    let store = store.inner().as_ref();
+
+    let habit = user_habit(session.user.get_id(), habit_id, store).await?;
+    store.unmark_habit(&habit, &clock.now().naive_utc()).await?;
+
+    back(location, None)
+}
+
+// Deletes the habits under it too.
+#[post(
+    "/mindless/dashboard/habit/<habit_id>/delete?<view>&<date>",
+    data = "<form>"
+)]
+#[instrument(name = "dashboard", skip(session, form, store, metrics), fields(%request_id))]
+#[allow(clippy::too_many_arguments)]
+pub async fn habit_delete(
+    session: Option<Session>,
+    habit_id: i64,
+    view: Option<String>,
+    date: Option<String>,
+    form: Form<Csrf>,
+    store: State<'_, Arc<dyn Store>>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Page> {
+    let _timer = metrics.api_request("dashboard", "DeleteHabit");
+    let session = match session {
+        Some(session) => session,
+        None => return Ok(Page::login(None)),
+    };
+    let location = Location::parse(view, date)?;
+    if !session.posted_here(&form.csrf) {
+        return back(location, Some(Notice::FormExpired));
+    }
+    let store = store.inner().as_ref();
+
+    let habit = user_habit(session.user.get_id(), habit_id, store).await?;
+    store.delete_habit(habit).await?;
+
+    back(location, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    fn time(timestamp: i64) -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(timestamp, 0)
+    }
+
+    fn habit(id: i64, parent_id: Option<i64>, name: &str) -> Habit {
+        Habit::new(id, parent_id, 1, name.to_string(), time(0), None)
+    }
+
+    #[test]
+    fn sessions_expire() {
+        let signer = Signer::new(SECRET);
+        let session = session(&signer, 7, &time(1000));
+
+        assert_eq!(verify(&session, &signer, &time(1000)), Some(7));
+        let expiry = 1000 + Duration::days(SESSION_DAYS).num_seconds();
+        assert_eq!(verify(&session, &signer, &time(expiry)), Some(7));
+        assert_eq!(verify(&session, &signer, &time(expiry + 1)), None);
+        assert_eq!(verify(&session, &signer, &time(999)), None);
+    }
+
+    #[test]
+    fn sessions_cannot_be_forged() {
+        let signer = Signer::new(SECRET);
+        let session = session(&signer, 7, &time(1000));
+        let signature = session.rsplit('.').next().expect("Has a signature.");
+
+        assert_eq!(
+            verify(&format!("8.1000.{}", signature), &signer, &time(1000)),
+            None
+        );
+        assert_eq!(verify("7.1000", &signer, &time(1000)), None);
+        assert_eq!(verify("", &signer, &time(1000)), None);
+        assert_eq!(
+            verify(
+                &session,
+                &Signer::new("another secret at least 32 chars"),
+                &time(1000)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn locations() {
+        let today = NaiveDate::from_ymd(2020, 10, 14);
+        let location = Location::parse(None, None).expect("Defaults to today.");
+        assert_eq!(location.days(today).expect("In range."), (today, today));
+        assert_eq!(location.url(None), "/mindless/dashboard?view=day");
+
+        let week = Location::parse(Some("week".to_string()), Some("2020-10-14".to_string()))
+            .expect("Is valid.");
+        let monday = NaiveDate::from_ymd(2020, 10, 12);
+        assert_eq!(
+            week.days(today).expect("In range."),
+            (monday, monday + Duration::days(6))
+        );
+        assert_eq!(
+            week.shift(today, -1)
+                .expect("In range.")
+                .url(Some(Notice::HabitExists)),
+            "/mindless/dashboard?view=week&date=2020-10-07&notice=habit-exists"
+        );
+
+        // There is nothing before 1970 or after 9999.
+        let first = Location::parse(None, Some("1970-01-01".to_string())).expect("Is valid.");
+        assert_eq!(first.shift(today, -1), None);
+        assert!(first.shift(today, 1).is_some());
+        let last = Location::parse(Some("week".to_string()), Some("9999-12-31".to_string()))
+            .expect("Is valid.");
+        assert_eq!(last.shift(today, 1), None);
+        assert!(last.days(today).is_ok());
+
+        assert!(Location::parse(Some("month".to_string()), None).is_err());
+        assert!(Location::parse(None, Some("yesterday".to_string())).is_err());
+    }
+
+    #[test]
+    fn tokens_are_compared_whole() {
+        assert!(same_token("abc", "abc"));
+        assert!(!same_token("abc", "abd"));
+        assert!(!same_token("abc", "ab"));
+        assert!(!same_token("", "abc"));
+    }
+
+    #[test]
+    fn notices_round_trip() {
+        for notice in NOTICES.iter() {
+            assert_eq!(Notice::parse(Some(notice.key().to_string())), Some(*notice));
+        }
+        assert_eq!(Notice::parse(Some("<script>".to_string())), None);
+    }
+
+    #[test]
+    fn outline_puts_children_under_their_parent() {
+        let habits = vec![
+            habit(1, None, "Health"),
+            habit(2, None, "Reading"),
+            habit(3, Some(1), "Exercise"),
+            habit(4, Some(3), "Running"),
+            habit(5, Some(9), "Orphan"),
+        ];
+        let outline: Vec<(i64, usize)> = outline(habits)
+            .iter()
+            .map(|(habit, depth)| (habit.get_id(), *depth))
+            .collect();
+
+        assert_eq!(outline, vec![(1, 0), (3, 1), (4, 2), (2, 0), (5, 0)]);
+    }
+
+    #[test]
+    fn local_times_from_inputs() {
+        let sydney: Tz = "Australia/Sydney".parse().expect("Is a timezone.");
+
+        assert_eq!(
+            parse_time("2020-10-12T09:30", &sydney),
+            Some(NaiveDate::from_ymd(2020, 10, 11).and_hms(22, 30, 0))
+        );
+        assert_eq!(
+            parse_time("2020-10-12T09:30:15", &Tz::UTC),
+            Some(NaiveDate::from_ymd(2020, 10, 12).and_hms(9, 30, 15))
+        );
+        // Clocks went forward from 02:00 to 03:00.
+        assert_eq!(parse_time("2020-10-04T02:30", &sydney), None);
+        assert_eq!(parse_time("09:30", &Tz::UTC), None);
+    }
+
+    #[test]
+    fn render_escapes_and_offers_timers() {
+        let user = User::new(1, "justin".to_string(), "<Justin>".to_string());
+        let today = NaiveDate::from_ymd(2020, 10, 14);
+        let running = Task::new(2, 1, "Deep <Work>".to_string());
+        let idle = Task::new(3, 1, "Exercise".to_string());
+        let overview = Overview {
+            user,
+            timezone: Tz::UTC,
+            today,
+            first: today,
+            last: today,
+            location: Location::parse(None, None).expect("Defaults to today."),
+            notice: Some(Notice::TimerRunning),
+            csrf: "token".to_string(),
+            tasks: vec![
+                TaskRow {
+                    timer: Some(Timer::new(4, 2, today.and_hms(9, 0, 0))),
+                    task: running,
+                    tracked: Duration::minutes(90),
+                },
+                TaskRow {
+                    task: idle,
+                    tracked: Duration::zero(),
+                    timer: None,
+                },
+            ],
+            instances: vec![InstanceRow {
+                task: "Exercise".to_string(),
+                instance: Instance::new(5, 3, today.and_hms(7, 0, 0), today.and_hms(7, 45, 0)),
+            }],
+            habits: vec![HabitRow {
+                habit: habit(6, None, "Health"),
+                depth: 0,
+                done: true,
+            }],
+        };
+        let html = render(&overview);
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Wednesday 14 October 2020</title>"));
+        assert!(html.contains("&lt;Justin&gt;"));
+        assert!(html.contains("Deep &lt;Work&gt;"));
+        assert!(!html.contains("<Work>"));
+        assert!(html.contains(Notice::TimerRunning.message()));
+        assert!(html.contains("Since 09:00"));
+        assert!(html.contains(r#"action="/mindless/dashboard/timer/2/stop?view=day""#));
+        assert!(html.contains(r#"action="/mindless/dashboard/timer/3/start?view=day""#));
+        assert!(html.contains(r#"value="2020-10-14T07:00:00""#));
+        assert!(html.contains(r#"formaction="/mindless/dashboard/instance/5/delete?view=day""#));
+        assert!(html.contains(r#"action="/mindless/dashboard/habit/6/unmark?view=day""#));
+        // Every form carries the token.
+        assert_eq!(
+            html.matches("<form ").count(),
+            html.matches(r#"<input type="hidden" name="csrf" value="token">"#)
+                .count()
+        );
+    }
+}
@@ -0,0 +1,1093 @@
+//! Weekly digests of what a user did, compared to the week before.
+//!
+//! Weeks start on Monday in the timezone of the user's reminder settings. Digests are served as
+//! json, Markdown or html and sent every Monday to users who picked a digest channel.
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use database::habit::Habit;
+use database::instance::Instance;
+use database::reminder::ReminderSettings;
+use database::store::Store;
+use database::user::User;
+use mindless_core::duration;
+use mindless_core::period::{daily_totals, local_date, merge, start_of_day, streak, Period};
+use rocket::http::ContentType;
+use rocket::response::content::Content;
+use rocket::State;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use tracing::instrument;
+
+use crate::chart::parse_date;
+use crate::config::DigestConfig;
+use crate::error::{Error, Result};
+use crate::goal;
+use crate::logging::RequestId;
+use crate::metrics::Metrics;
+use crate::qr::escape;
+use crate::reminder::Engine;
+use crate::schedule::{self, Clock};
+use crate::tag::{passes, TagFilter};
+
+/// Time spent on a task.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TaskTime {
+    pub task_id: i64,
+    pub name: String,
+    pub seconds: i64,
+    pub previous_seconds: i64,
+}
+
+/// How often a habit and everything under it was done.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HabitSummary {
+    pub habit_id: i64,
+
+    /// E.g. `Health/Exercise`.
+    pub path: String,
+
+    /// Completions of the habit and the habits under it.
+    pub completions: usize,
+    pub previous_completions: usize,
+
+    /// Consecutive days the habit itself was done up to the end of the week.
+    pub streak: u32,
+
+    /// The streak at the end of the week before.
+    pub previous_streak: u32,
+}
+
+/// How a goal did in its periods which ended in the week.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GoalSummary {
+    pub goal_id: i64,
+
+    /// E.g. `At least 5h00m of Deep Work per week`.
+    pub description: String,
+
+    /// Periods which ended in the week and how many of them met the goal.
+    pub periods: usize,
+    pub met: usize,
+}
+
+/// Time tracked on a local day.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Day {
+    pub date: NaiveDate,
+    pub seconds: i64,
+}
+
+/// A week of a user.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Digest {
+    pub user_id: i64,
+
+    /// The Monday the week starts on.
+    pub week: NaiveDate,
+
+    /// IANA timezone the days are in.
+    pub timezone: String,
+
+    /// Time tracked without counting overlapping instances twice.
+    pub seconds: i64,
+    pub previous_seconds: i64,
+
+    /// Tasks with time in either week, most time first.
+    pub tasks: Vec<TaskTime>,
+
+    /// Every habit, parents before their children.
+    pub habits: Vec<HabitSummary>,
+
+    /// Goals with a period which ended in the week, e.g. a monthly goal only in the week its
+    /// month ends. Oldest first.
+    pub goals: Vec<GoalSummary>,
+
+    /// Every day of the week from Monday.
+    pub days: Vec<Day>,
+
+    /// The days with the most and least time. None if nothing was tracked.
+    pub best_day: Option<Day>,
+    pub worst_day: Option<Day>,
+}
+
+/// The Monday of the week a date is in.
+pub fn week_of(date: &NaiveDate) -> NaiveDate {
+    *date - Duration::days(i64::from(date.weekday().num_days_from_monday()))
+}
+
+/// The week starting on a local Monday.
+fn week_period(monday: &NaiveDate, timezone: &Tz) -> Period {
+    Period::new(
+        start_of_day(monday, timezone),
+        start_of_day(&(*monday + Duration::days(7)), timezone),
+    )
+}
+
+/// Time spent in periods without counting overlapping time twice.
+fn total_seconds(periods: &[Period]) -> i64 {
+    merge(periods)
+        .iter()
+        .map(|period| period.duration().num_seconds())
+        .sum()
+}
+
+/// The parts of periods within bounds.
+fn within(periods: &[Period], bounds: &Period) -> Vec<Period> {
+    periods
+        .iter()
+        .map(|period| period.intersection(bounds))
+        .filter(|period| !period.is_empty())
+        .collect()
+}
+
+/// The names of a habit and its parents from the top, joined like a `HabitPath`.
+pub(crate) fn habit_path(habit: &Habit, habits: &HashMap<i64, &Habit>) -> String {
+    let mut names = vec![habit.get_name()];
+    let mut parent_id = habit.get_parent_id();
+    while let Some(parent) = parent_id.and_then(|id| habits.get(&id)) {
+        names.push(parent.get_name());
+        parent_id = parent.get_parent_id();
+    }
+    names.reverse();
+
+    names.join("/")
+}
+
+/// Put together the digest of the week containing `date`. With a filter only time with the tag
+/// counts, habits and goals are the same.
+pub async fn generate(
+    store: &dyn Store,
+    user: &User,
+    date: &NaiveDate,
+    timezone: &Tz,
+    filter: Option<&TagFilter>,
+) -> Result<Digest> {
+    let week = week_of(date);
+    let current = week_period(&week, timezone);
+    let previous = week_period(&(week - Duration::days(7)), timezone);
+
+    let mut tasks = Vec::new();
+    let mut tracked = Vec::new();
+    let mut previously_tracked = Vec::new();
+    for task in store.get_tasks(user).await? {
+        let instances: Vec<Period> = store
+            .get_instances(task.get_id())
+            .await?
+            .iter()
+            .filter(|instance| passes(filter, instance))
+            .map(Instance::get_period)
+            .collect();
+        let this_week = within(&instances, &current);
+        let week_before = within(&instances, &previous);
+        if this_week.is_empty() && week_before.is_empty() {
+            continue;
+        }
+
+        tasks.push(TaskTime {
+            task_id: task.get_id(),
+            name: task.get_name().to_string(),
+            seconds: total_seconds(&this_week),
+            previous_seconds: total_seconds(&week_before),
+        });
+        tracked.extend(this_week);
+        previously_tracked.extend(week_before);
+    }
+    tasks.sort_by(|a, b| b.seconds.cmp(&a.seconds).then_with(|| a.name.cmp(&b.name)));
+
+    let totals = daily_totals(&tracked, timezone);
+    let days: Vec<Day> = (0..7)
+        .map(|offset| {
+            let date = week + Duration::days(offset);
+            Day {
+                date,
+                seconds: totals.get(&date).map_or(0, Duration::num_seconds),
+            }
+        })
+        .collect();
+    let seconds = total_seconds(&tracked);
+    // Ties go to the earlier day.
+    let best_day = days
+        .iter()
+        .rev()
+        .max_by_key(|day| day.seconds)
+        .filter(|_| seconds > 0)
+        .cloned();
+    let worst_day = days
+        .iter()
+        .min_by_key(|day| day.seconds)
+        .filter(|_| seconds > 0)
+        .cloned();
+
+    let habits = store.get_habits(user.get_id()).await?;
+    let by_id: HashMap<i64, &Habit> = habits.iter().map(|habit| (habit.get_id(), habit)).collect();
+    let last_day = week + Duration::days(6);
+    let mut counts: HashMap<i64, (usize, usize)> = HashMap::new();
+    let mut streaks = HashMap::new();
+    for habit in &habits {
+        let completions: Vec<NaiveDateTime> = store
+            .get_completions(
+                habit.get_id(),
+                &Period::new(*habit.get_created_at(), current.end),
+            )
+            .await?
+            .iter()
+            .map(|completion| *completion.get_created_at())
+            .collect();
+        let this_week = completions
+            .iter()
+            .filter(|time| current.start <= **time)
+            .count();
+        let week_before = completions
+            .iter()
+            .filter(|time| previous.start <= **time && **time < previous.end)
+            .count();
+
+        // Completions count towards every habit above.
+        let mut id = Some(habit.get_id());
+        while let Some(current_id) = id {
+            let count = counts.entry(current_id).or_default();
+            count.0 += this_week;
+            count.1 += week_before;
+            id = by_id
+                .get(&current_id)
+                .and_then(|habit| habit.get_parent_id());
+        }
+
+        let days: BTreeSet<NaiveDate> = completions
+            .iter()
+            .map(|time| local_date(time, timezone))
+            .collect();
+        streaks.insert(
+            habit.get_id(),
+            (
+                streak(&days, &last_day),
+                streak(&days, &(last_day - Duration::days(7))),
+            ),
+        );
+    }
+
+    let habits = habits
+        .iter()
+        .map(|habit| {
+            let (completions, previous_completions) = counts[&habit.get_id()];
+            let (streak, previous_streak) = streaks[&habit.get_id()];
+            HabitSummary {
+                habit_id: habit.get_id(),
+                path: habit_path(habit, &by_id),
+                completions,
+                previous_completions,
+                streak,
+                previous_streak,
+            }
+        })
+        .collect();
+
+    let mut goals = Vec::new();
+    for goal in store.get_goals(user.get_id()).await? {
+        let cadence = goal
+            .get_target()
+            .map_err(|error| Error::BadRequest(error.to_string()))?
+            .cadence;
+        let starts: Vec<NaiveDate> = cadence
+            .starts(&week, &last_day)
+            .into_iter()
+            .filter(|start| {
+                cadence
+                    .next(start)
+                    .map_or(false, |next| next.pred() <= last_day)
+            })
+            .collect();
+        if starts.is_empty() {
+            continue;
+        }
+
+        let goal_id = goal.get_id();
+        let progress = goal::progress(store, goal, &starts, timezone).await?;
+        // Goals show up once their first period has ended.
+        if progress.periods.is_empty() {
+            continue;
+        }
+        goals.push(GoalSummary {
+            goal_id,
+            description: progress.description,
+            periods: progress.periods.len(),
+            met: progress.periods.iter().filter(|period| period.met).count(),
+        });
+    }
+
+    Ok(Digest {
+        user_id: user.get_id(),
+        week,
+        timezone: timezone.name().to_string(),
+        seconds,
+        previous_seconds: total_seconds(&previously_tracked),
+        tasks,
+        habits,
+        goals,
+        days,
+        best_day,
+        worst_day,
+    })
+}
+
+fn format_seconds(seconds: i64) -> String {
+    duration::format(Duration::seconds(seconds))
+}
+
+/// How the week compares to the week before, e.g. `1h05m more than the week before`.
+fn comparison(seconds: i64, previous_seconds: i64) -> String {
+    let difference = seconds - previous_seconds;
+    if difference > 0 {
+        format!("{} more than the week before", format_seconds(difference))
+    } else if difference < 0 {
+        format!("{} less than the week before", format_seconds(-difference))
+    } else {
+        "the same as the week before".to_string()
+    }
+}
+
+fn format_day(day: &Day) -> String {
+    format!(
+        "{} ({})",
+        day.date.format("%A %Y-%m-%d"),
+        format_seconds(day.seconds)
+    )
+}
+
+/// Escape text to put in a Markdown table cell.
+fn escape_cell(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+impl Digest {
+    /// A title such as `Week of 2020-10-12`.
+    pub fn title(&self) -> String {
+        format!("Week of {}", self.week)
+    }
+
+    /// A sentence on the time tracked.
+    fn summary(&self) -> String {
+        format!(
+            "Tracked {}, {}.",
+            format_seconds(self.seconds),
+            comparison(self.seconds, self.previous_seconds)
+        )
+    }
+
+    /// A sentence on the best and worst days, if anything was tracked.
+    fn days_summary(&self) -> Option<String> {
+        match (&self.best_day, &self.worst_day) {
+            (Some(best), Some(worst)) => Some(format!(
+                "Best day: {}. Worst day: {}.",
+                format_day(best),
+                format_day(worst)
+            )),
+            _ => None,
+        }
+    }
+
+    fn streak(habit: &HabitSummary) -> String {
+        format!("{} days (was {})", habit.streak, habit.previous_streak)
+    }
+
+    fn met(goal: &GoalSummary) -> String {
+        format!("{} of {}", goal.met, goal.periods)
+    }
+
+    pub fn markdown(&self) -> String {
+        let mut markdown = format!("# {}\n\n{}\n", self.title(), self.summary());
+
+        if !self.tasks.is_empty() {
+            markdown
+                .push_str("\n## Tasks\n\n| Task | Time | Week before |\n| --- | ---: | ---: |\n");
+            for task in &self.tasks {
+                markdown.push_str(&format!(
+                    "| {} | {} | {} |\n",
+                    escape_cell(&task.name),
+                    format_seconds(task.seconds),
+                    format_seconds(task.previous_seconds)
+                ));
+            }
+        }
+
+        markdown.push_str("\n## Days\n\n");
+        if let Some(days) = self.days_summary() {
+            markdown.push_str(&days);
+            markdown.push_str("\n\n");
+        }
+        markdown.push_str("| Day | Time |\n| --- | ---: |\n");
+        for day in &self.days {
+            markdown.push_str(&format!(
+                "| {} | {} |\n",
+                day.date.format("%A"),
+                format_seconds(day.seconds)
+            ));
+        }
+
+        if !self.habits.is_empty() {
+            markdown.push_str(
+                "\n## Habits\n\n| Habit | Done | Week before | Streak |\n| --- | ---: | ---: | --- |\n",
+            );
+            for habit in &self.habits {
+                markdown.push_str(&format!(
+                    "| {} | {} | {} | {} |\n",
+                    escape_cell(&habit.path),
+                    habit.completions,
+                    habit.previous_completions,
+                    Digest::streak(habit)
+                ));
+            }
+        }
+
+        if !self.goals.is_empty() {
+            markdown.push_str("\n## Goals\n\n| Goal | Met |\n| --- | ---: |\n");
+            for goal in &self.goals {
+                markdown.push_str(&format!(
+                    "| {} | {} |\n",
+                    escape_cell(&goal.description),
+                    Digest::met(goal)
+                ));
+            }
+        }
+
+        markdown
+    }
+
+    pub fn html(&self) -> String {
+        let mut body = format!(
+            "<h1>{}</h1>\n<p>{}</p>\n",
+            escape(&self.title()),
+            escape(&self.summary())
+        );
+
+        if !self.tasks.is_empty() {
+            body.push_str(
+                "<h2>Tasks</h2>\n<table>\n<tr><th>Task</th><th>Time</th><th>Week before</th></tr>\n",
+            );
+            for task in &self.tasks {
+                body.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    escape(&task.name),
+                    format_seconds(task.seconds),
+                    format_seconds(task.previous_seconds)
+                ));
+            }
+            body.push_str("</table>\n");
+        }
+
+        body.push_str("<h2>Days</h2>\n");
+        if let Some(days) = self.days_summary() {
+            body.push_str(&format!("<p>{}</p>\n", escape(&days)));
+        }
+        body.push_str("<table>\n<tr><th>Day</th><th>Time</th></tr>\n");
+        for day in &self.days {
+            body.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td></tr>\n",
+                day.date.format("%A"),
+                format_seconds(day.seconds)
+            ));
+        }
+        body.push_str("</table>\n");
+
+        if !self.habits.is_empty() {
+            body.push_str(concat!(
+                "<h2>Habits</h2>\n<table>\n",
+                "<tr><th>Habit</th><th>Done</th><th>Week before</th><th>Streak</th></tr>\n"
+            ));
+            for habit in &self.habits {
+                body.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    escape(&habit.path),
+                    habit.completions,
+                    habit.previous_completions,
+                    Digest::streak(habit)
+                ));
+            }
+            body.push_str("</table>\n");
+        }
+
+        if !self.goals.is_empty() {
+            body.push_str("<h2>Goals</h2>\n<table>\n<tr><th>Goal</th><th>Met</th></tr>\n");
+            for goal in &self.goals {
+                body.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td></tr>\n",
+                    escape(&goal.description),
+                    Digest::met(goal)
+                ));
+            }
+            body.push_str("</table>\n");
+        }
+
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 40em; margin: 2em auto; }}
+table {{ border-collapse: collapse; }}
+th, td {{ padding: 0.2em 1em 0.2em 0; text-align: left; }}
+</style>
+</head>
+<body>
+{body}</body>
+</html>
+"#,
+            title = escape(&self.title()),
+            body = body,
+        )
+    }
+}
+
+/// The user's timezone from their reminder settings.
+pub(crate) fn timezone(settings: &ReminderSettings) -> std::result::Result<Tz, String> {
+    settings.get_timezone().parse()
+}
+
+/// At most this many of the weeks a user wasn't sent are sent at once, e.g. after their digests
+/// failed for a while. Older weeks are skipped.
+const MAX_UNSENT_WEEKS: i64 = 4;
+
+/// The Monday of the last full week at `now` in `timezone`.
+pub(crate) fn last_full_week(now: &NaiveDateTime, timezone: &Tz) -> NaiveDate {
+    week_of(&local_date(now, timezone)) - Duration::days(7)
+}
+
+/// The digests of the full weeks a user wasn't sent yet, oldest first and no more than
+/// `MAX_UNSENT_WEEKS`. A user who was never sent one only gets the last full week.
+async fn unsent(
+    store: &dyn Store,
+    settings: &ReminderSettings,
+    now: &NaiveDateTime,
+) -> Result<Vec<Digest>> {
+    let timezone = timezone(settings).map_err(Error::BadRequest)?;
+    let last = last_full_week(now, &timezone);
+    let oldest = last - Duration::days(7 * (MAX_UNSENT_WEEKS - 1));
+    let mut week = match store.retrieve_digest_week(settings.get_user_id()).await? {
+        Some(sent) => (sent + Duration::days(7)).max(oldest),
+        None => last,
+    };
+
+    let user = store.retrieve_user(settings.get_user_id()).await?;
+    let mut digests = Vec::new();
+    while week <= last {
+        digests.push(generate(store, &user, &week, &timezone, None).await?);
+        week = week + Duration::days(7);
+    }
+
+    Ok(digests)
+}
+
+/// The digests of every user with a digest channel which weren't sent yet, along with their
+/// settings. Users whose digests can't be put together are logged and skipped.
+pub async fn ended(
+    store: &dyn Store,
+    now: &NaiveDateTime,
+) -> Result<Vec<(ReminderSettings, Vec<Digest>)>> {
+    let mut ended = Vec::new();
+    for settings in store.get_all_reminder_settings().await? {
+        if settings.get_digest_channel().is_none() {
+            continue;
+        }
+
+        match unsent(store, &settings, now).await {
+            Ok(digests) if digests.is_empty() => {}
+            Ok(digests) => ended.push((settings, digests)),
+            Err(e) => {
+                let user_id = settings.get_user_id();
+                tracing::error!(?e, user_id, "Putting together digests failed");
+            }
+        }
+    }
+
+    Ok(ended)
+}
+
+/// Send the digests which weren't sent yet, recording the week of each one sent, and return how
+/// many were sent. A user's digests stop at the first which fails so it is sent again next time.
+pub async fn send_ended(store: &dyn Store, engine: &Engine, now: &NaiveDateTime) -> Result<usize> {
+    let mut sent = 0;
+    for (settings, digests) in ended(store, now).await? {
+        for digest in digests {
+            if let Err(e) = engine.send_digest(&settings, &digest).await {
+                tracing::warn!(?e, user_id = digest.user_id, "Sending digest failed");
+                break;
+            }
+            if let Err(e) = store.update_digest_week(digest.user_id, &digest.week).await {
+                tracing::error!(?e, user_id = digest.user_id, "Recording digest failed");
+                break;
+            }
+            sent += 1;
+        }
+    }
+
+    Ok(sent)
+}
+
+/// Send the digests of weeks as they end while the server is running.
+pub fn schedule(
+    store: Arc<dyn Store>,
+    engine: Engine,
+    config: DigestConfig,
+    clock: Arc<dyn Clock>,
+) {
+    let period = std::time::Duration::from_secs(config.check_minutes * 60);
+
+    schedule::every("digests", period, clock, move |now| {
+        let store = store.clone();
+        let engine = engine.clone();
+
+        async move {
+            match send_ended(store.as_ref(), &engine, &now.naive_utc()).await {
+                Ok(sent) => tracing::debug!(sent, "Sent digests"),
+                Err(e) => tracing::error!(?e, "Sending digests failed"),
+            }
+        }
+    });
+}
+
+// The digest of a week as json, markdown or html. The week is any date in it, by default the
+// last full week. With `tag` only time with the tag counts.
+#[get("/mindless/api/digest/<user_id>?<week>&<format>&<tag>")]
+#[instrument(name = "digest", skip(store, clock, metrics), fields(%request_id))]
+#[allow(clippy::too_many_arguments)]
+pub async fn digest(
+    user_id: i64,
+    week: Option<String>,
+    format: Option<String>,
+    tag: Option<String>,
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Content<String>> {
+    let _timer = metrics.api_request("digest", "Digest");
+    let store = store.inner().as_ref();
+
+    let user = store.retrieve_user(user_id).await?;
+    let settings = store.retrieve_reminder_settings(user.get_id()).await?;
+    let timezone = timezone(&settings).map_err(Error::BadRequest)?;
+    let date = match week {
+        Some(week) => parse_date(&week)?,
+        None => local_date(&clock.now().naive_utc(), &timezone) - Duration::days(7),
+    };
+
+    let filter = TagFilter::parse(store, user.get_id(), tag).await?;
+
+    let digest = generate(store, &user, &date, &timezone, filter.as_ref()).await?;
+    match format.as_deref().unwrap_or("json") {
+        "json" => Ok(Content(
+            ContentType::JSON,
+            serde_json::to_string(&digest).expect("Digests serialize."),
+        )),
+        "markdown" => Ok(Content(
+            ContentType::new("text", "markdown"),
+            digest.markdown(),
+        )),
+        "html" => Ok(Content(ContentType::HTML, digest.html())),
+        format => Err(Error::BadRequest(format!(
+            "\"{}\" is not one of json, markdown, html",
+            format
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ReminderConfig;
+    use crate::reminder::{Due, Notifier};
+    use database::memory::MemoryStore;
+    use mindless_core::goal::{Cadence, Comparison, Target, Unit};
+    use std::sync::Mutex;
+
+    fn time(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2020, 10, day).and_hms(hour, 0, 0)
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(2020, 10, day)
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        assert_eq!(week_of(&date(12)), date(12));
+        assert_eq!(week_of(&date(18)), date(12));
+        assert_eq!(week_of(&date(19)), date(19));
+    }
+
+    /// A user who worked on the 13th and 14th of October and the week before, with a habit done
+    /// every day but the 15th. They aim to work at least an hour a day and every month.
+    async fn history() -> (Arc<dyn Store>, User) {
+        let store: Arc<dyn Store> = Arc::new(MemoryStore::default());
+        let user = store
+            .insert_user("username", "name")
+            .await
+            .expect("Should successfully insert.");
+        let work = store
+            .insert_task(user.get_id(), "Work | Home")
+            .await
+            .expect("Should successfully insert.");
+        let read = store
+            .insert_task(user.get_id(), "Read")
+            .await
+            .expect("Should successfully insert.");
+        for (task, start, end) in &[
+            (&work, time(13, 9), time(13, 12)),
+            // Overlapping time is counted once.
+            (&work, time(13, 11), time(13, 13)),
+            (&work, time(14, 9), time(14, 10)),
+            (&read, time(14, 20), time(14, 21)),
+            (&work, time(6, 9), time(6, 10)),
+        ] {
+            store
+                .insert_instance(task.get_id(), start, end)
+                .await
+                .expect("Should successfully insert.");
+        }
+
+        let health = store
+            .insert_habit(user.get_id(), None, "Health", &time(1, 0), None)
+            .await
+            .expect("Should successfully insert.");
+        let stretch = store
+            .insert_habit(
+                user.get_id(),
+                Some(health.get_id()),
+                "Stretch",
+                &time(1, 0),
+                Some(86400),
+            )
+            .await
+            .expect("Should successfully insert.");
+        for day in (9..=18).filter(|day| *day != 15) {
+            store
+                .mark_habit(&stretch, &time(day, 7))
+                .await
+                .expect("Can mark.");
+        }
+
+        for cadence in &[Cadence::Day, Cadence::Month] {
+            let target = Target {
+                comparison: Comparison::AtLeast,
+                amount: 3600,
+                unit: Unit::Seconds,
+                cadence: *cadence,
+            };
+            store
+                .insert_goal(
+                    user.get_id(),
+                    Some(work.get_id()),
+                    None,
+                    &target,
+                    &time(1, 0),
+                )
+                .await
+                .expect("Should successfully insert.");
+        }
+
+        (store, user)
+    }
+
+    #[tokio::test]
+    async fn digest_of_a_week() {
+        let (store, user) = history().await;
+        let digest = generate(store.as_ref(), &user, &date(15), &Tz::UTC, None)
+            .await
+            .expect("Can generate.");
+
+        assert_eq!(digest.week, date(12));
+        assert_eq!(digest.seconds, 6 * 3600);
+        assert_eq!(digest.previous_seconds, 3600);
+
+        let tasks: Vec<(&str, i64, i64)> = digest
+            .tasks
+            .iter()
+            .map(|task| (task.name.as_str(), task.seconds, task.previous_seconds))
+            .collect();
+        assert_eq!(
+            tasks,
+            vec![("Work | Home", 5 * 3600, 3600), ("Read", 3600, 0)]
+        );
+
+        let days: Vec<i64> = digest.days.iter().map(|day| day.seconds / 3600).collect();
+        assert_eq!(days, vec![0, 4, 2, 0, 0, 0, 0]);
+        assert_eq!(digest.best_day.map(|day| day.date), Some(date(13)));
+        assert_eq!(digest.worst_day.map(|day| day.date), Some(date(12)));
+
+        let habits: Vec<(&str, usize, usize, u32, u32)> = digest
+            .habits
+            .iter()
+            .map(|habit| {
+                (
+                    habit.path.as_str(),
+                    habit.completions,
+                    habit.previous_completions,
+                    habit.streak,
+                    habit.previous_streak,
+                )
+            })
+            .collect();
+        assert_eq!(
+            habits,
+            vec![("Health", 6, 3, 0, 0), ("Health/Stretch", 6, 3, 3, 3)]
+        );
+
+        // October doesn't end in the week.
+        let goals: Vec<(&str, usize, usize)> = digest
+            .goals
+            .iter()
+            .map(|goal| (goal.description.as_str(), goal.periods, goal.met))
+            .collect();
+        assert_eq!(goals, vec![("At least 1h00m of Work | Home per day", 7, 2)]);
+
+        // The goals were set on Thursday the 1st, so that week only has the days since and
+        // September doesn't count.
+        let digest = generate(store.as_ref(), &user, &date(1), &Tz::UTC, None)
+            .await
+            .expect("Can generate.");
+        let periods: Vec<usize> = digest.goals.iter().map(|goal| goal.periods).collect();
+        assert_eq!(periods, vec![4]);
+        let digest = generate(
+            store.as_ref(),
+            &user,
+            &(date(1) - Duration::days(7)),
+            &Tz::UTC,
+            None,
+        )
+        .await
+        .expect("Can generate.");
+        assert!(digest.goals.is_empty());
+    }
+
+    #[tokio::test]
+    async fn digests_filtered_by_tag() {
+        let (store, user) = history().await;
+        let read = store
+            .get_tasks(&user)
+            .await
+            .expect("Can list.")
+            .into_iter()
+            .find(|task| task.get_name() == "Read")
+            .expect("Task exists.");
+        let tag = "reading".parse().expect("Is a valid tag.");
+        let reading = store
+            .insert_tag(user.get_id(), &tag)
+            .await
+            .expect("Should successfully insert.");
+        store
+            .tag_task(&reading, read.get_id())
+            .await
+            .expect("Can tag.");
+
+        let filter = TagFilter::new(store.as_ref(), user.get_id(), tag)
+            .await
+            .expect("Tag exists.");
+        let digest = generate(store.as_ref(), &user, &date(15), &Tz::UTC, Some(&filter))
+            .await
+            .expect("Can generate.");
+        assert_eq!(digest.seconds, 3600);
+        assert_eq!(digest.previous_seconds, 0);
+        let tasks: Vec<&str> = digest.tasks.iter().map(|task| task.name.as_str()).collect();
+        assert_eq!(tasks, vec!["Read"]);
+        // Habits are not tagged.
+        assert_eq!(digest.habits.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rendered_digests() {
+        let (store, user) = history().await;
+        let digest = generate(store.as_ref(), &user, &date(12), &Tz::UTC, None)
+            .await
+            .expect("Can generate.");
+
+        let markdown = digest.markdown();
+        assert!(markdown.starts_with("# Week of 2020-10-12\n\nTracked 6h00m, 5h00m more than"));
+        assert!(markdown.contains("| Work \\| Home | 5h00m | 1h00m |"));
+        assert!(markdown.contains("Best day: Tuesday 2020-10-13 (4h00m)."));
+        assert!(markdown.contains("| Health/Stretch | 6 | 3 | 3 days (was 3) |"));
+        assert!(markdown.contains("| At least 1h00m of Work \\| Home per day | 2 of 7 |"));
+
+        let html = digest.html();
+        assert!(html.contains("<title>Week of 2020-10-12</title>"));
+        assert!(html.contains("<td>Work | Home</td>"));
+        assert!(html.contains("<td>At least 1h00m of Work | Home per day</td><td>2 of 7</td>"));
+    }
+
+    /// Records the weeks of digests, failing while `fail` is set.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        weeks: Arc<Mutex<Vec<NaiveDate>>>,
+        fail: Arc<Mutex<bool>>,
+    }
+
+    #[rocket::async_trait]
+    impl Notifier for Recorder {
+        fn channel(&self) -> &'static str {
+            "test"
+        }
+
+        async fn notify(&self, _due: &Due) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn delivers_digests(&self) -> bool {
+            true
+        }
+
+        async fn send_digest(
+            &self,
+            _settings: &ReminderSettings,
+            digest: &Digest,
+        ) -> anyhow::Result<()> {
+            if *self.fail.lock().unwrap() {
+                anyhow::bail!("Unreachable");
+            }
+            self.weeks.lock().unwrap().push(digest.week);
+
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn digests_are_sent_once_weeks_end() {
+        let (store, user) = history().await;
+        let mut settings = ReminderSettings::new(user.get_id());
+        settings.set_timezone("Australia/Sydney".to_string());
+        store
+            .update_reminder_settings(&settings)
+            .await
+            .expect("Can save.");
+
+        // Monday starts at 13:00 UTC on Sunday in Sydney.
+        assert!(
+            ended(store.as_ref(), &time(18, 13))
+                .await
+                .expect("Can check.")
+                .is_empty(),
+            "Nobody wants a digest."
+        );
+
+        settings.set_digest_channel(Some("test".to_string()));
+        store
+            .update_reminder_settings(&settings)
+            .await
+            .expect("Can save.");
+        let digests = ended(store.as_ref(), &time(18, 12))
+            .await
+            .expect("Can check.");
+        assert_eq!(digests.len(), 1);
+        assert_eq!(digests[0].0, settings);
+        assert_eq!(digests[0].1.len(), 1, "Only the last full week.");
+        assert_eq!(digests[0].1[0].week, date(5));
+        assert_eq!(digests[0].1[0].timezone, "Australia/Sydney");
+
+        let digests = ended(store.as_ref(), &time(18, 13))
+            .await
+            .expect("Can check.");
+        assert_eq!(digests[0].1[0].week, date(12));
+
+        // Weeks are sent until one is recorded, whenever the check runs.
+        store
+            .update_digest_week(user.get_id(), &date(12))
+            .await
+            .expect("Can save.");
+        assert!(ended(store.as_ref(), &time(25, 12))
+            .await
+            .expect("Can check.")
+            .is_empty());
+        let digests = ended(
+            store.as_ref(),
+            &NaiveDate::from_ymd(2020, 11, 2).and_hms(0, 0, 0),
+        )
+        .await
+        .expect("Can check.");
+        let weeks: Vec<NaiveDate> = digests[0].1.iter().map(|digest| digest.week).collect();
+        assert_eq!(weeks, vec![date(19), date(26)]);
+
+        // Only the last few weeks are caught up on.
+        store
+            .update_digest_week(user.get_id(), &NaiveDate::from_ymd(1970, 1, 5))
+            .await
+            .expect("Can save.");
+        let digests = ended(
+            store.as_ref(),
+            &NaiveDate::from_ymd(2020, 11, 2).and_hms(0, 0, 0),
+        )
+        .await
+        .expect("Can check.");
+        let weeks: Vec<NaiveDate> = digests[0].1.iter().map(|digest| digest.week).collect();
+        assert_eq!(weeks, vec![date(5), date(12), date(19), date(26)]);
+    }
+
+    #[tokio::test]
+    async fn broken_settings_do_not_hold_back_others() {
+        let (store, user) = history().await;
+        let other = store
+            .insert_user("other", "Other")
+            .await
+            .expect("Should successfully insert.");
+        let mut settings = ReminderSettings::new(other.get_id());
+        settings.set_timezone("Nowhere/Special".to_string());
+        settings.set_digest_channel(Some("test".to_string()));
+        store
+            .update_reminder_settings(&settings)
+            .await
+            .expect("Can save.");
+        let mut settings = ReminderSettings::new(user.get_id());
+        settings.set_digest_channel(Some("test".to_string()));
+        store
+            .update_reminder_settings(&settings)
+            .await
+            .expect("Can save.");
+
+        let digests = ended(store.as_ref(), &time(19, 0))
+            .await
+            .expect("Can check.");
+        assert_eq!(digests.len(), 1);
+        assert_eq!(digests[0].0, settings);
+    }
+
+    #[tokio::test]
+    async fn failed_digests_are_sent_again() {
+        let (store, user) = history().await;
+        let mut settings = ReminderSettings::new(user.get_id());
+        settings.set_digest_channel(Some("test".to_string()));
+        store
+            .update_reminder_settings(&settings)
+            .await
+            .expect("Can save.");
+        let recorder = Recorder::default();
+        let engine = Engine::new(
+            store.clone(),
+            vec![Box::new(recorder.clone())],
+            &ReminderConfig::default(),
+        );
+
+        *recorder.fail.lock().unwrap() = true;
+        let sent = send_ended(store.as_ref(), &engine, &time(19, 0))
+            .await
+            .expect("Can send.");
+        assert_eq!(sent, 0);
+        assert_eq!(
+            store
+                .retrieve_digest_week(user.get_id())
+                .await
+                .expect("Can retrieve."),
+            None
+        );
+
+        *recorder.fail.lock().unwrap() = false;
+        let sent = send_ended(store.as_ref(), &engine, &time(19, 0))
+            .await
+            .expect("Can send.");
+        assert_eq!(sent, 1);
+        let sent = send_ended(store.as_ref(), &engine, &time(19, 1))
+            .await
+            .expect("Can send.");
+        assert_eq!(sent, 0, "Each week is sent once.");
+        assert_eq!(*recorder.weeks.lock().unwrap(), vec![date(12)]);
+    }
+}
@@ -1,5 +1,8 @@
+use crate::logging::RequestId;
+use crate::metrics::Metrics;
 use database::error::Error as DBError;
 use rocket::http::{ContentType, Status};
+use rocket::response::status::Custom;
 use rocket::response::{self, Responder, Response};
 use rocket::Request;
 use std::convert::From;
@@ -12,32 +15,82 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug)]
 pub enum Error {
     Database(DBError),
+
+    /// A token which we did not issue, e.g. a mistyped check-in link.
+    InvalidToken,
+
+    /// A token which was revoked.
+    RevokedToken,
+
+    /// An image such as a QR code could not be rendered.
+    Render(crate::qr::Error),
+
+    /// The request is well formed but makes no sense, e.g. a webhook url which isn't http.
+    BadRequest(String),
 }
 
 /// Error responder!
 impl<'r> Responder<'r, 'static> for Error {
     fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
-        let response = match &self {
-            Error::Database(error) => match &error {
-                DBError::UnknownSql(_) => {
-                    let body = format!("Error: {:#?}\nRequest: {:#?}", error, request,);
-
-                    Response::build()
-                        .sized_body(body.len(), Cursor::new(body))
-                        .status(Status::InternalServerError)
-                        .header(ContentType::Plain)
-                        .ok()
+        let request_id = RequestId::of(request);
+
+        match &self {
+            Error::Database(error) => {
+                if let Some(metrics) = request.managed_state::<Metrics>() {
+                    metrics.database_error(error);
+                }
+
+                match &error {
+                    DBError::UnknownSql(_) | DBError::Io(_) => {
+                        tracing::error!(%request_id, %error, "Database request failed");
+
+                        // Don't leak the internal error to the client, the request id is enough
+                        // to find it in the logs.
+                        let body = format!("Internal server error. Request id: {}", request_id);
+
+                        Response::build()
+                            .sized_body(body.len(), Cursor::new(body))
+                            .status(Status::InternalServerError)
+                            .header(ContentType::Plain)
+                            .ok()
+                    }
+                    _ => {
+                        tracing::warn!(%request_id, %error, "Database request rejected");
+
+                        // The app reads `error` from successful responses only, e.g. to tell an
+                        // unknown user from a failed login.
+                        json!({"error": error.to_string()}).respond_to(request)
+                    }
                 }
-                _ => json!({"error": error.to_string()}).respond_to(request),
-            },
-        };
+            }
+            Error::InvalidToken => {
+                tracing::warn!(%request_id, "Invalid token");
+
+                // Don't tell whether the token exists.
+                Custom(Status::NotFound, json!({"error": "InvalidToken"})).respond_to(request)
+            }
+            Error::RevokedToken => {
+                tracing::warn!(%request_id, "Revoked token");
+
+                Custom(Status::Gone, json!({"error": "RevokedToken"})).respond_to(request)
+            }
+            Error::BadRequest(reason) => {
+                tracing::warn!(%request_id, %reason, "Bad request");
 
-        println!(
-            "Responding with error: {:#?}\nfrom request: {:#?}",
-            &response, &request
-        );
+                Custom(Status::BadRequest, json!({ "error": reason })).respond_to(request)
+            }
+            Error::Render(error) => {
+                tracing::error!(%request_id, %error, "Rendering failed");
 
-        response
+                let body = format!("Internal server error. Request id: {}", request_id);
+
+                Response::build()
+                    .sized_body(body.len(), Cursor::new(body))
+                    .status(Status::InternalServerError)
+                    .header(ContentType::Plain)
+                    .ok()
+            }
+        }
     }
 }
 
@@ -46,3 +99,9 @@ impl From<DBError> for Error {
         Error::Database(error)
     }
 }
+
+impl From<crate::qr::Error> for Error {
+    fn from(error: crate::qr::Error) -> Self {
+        Error::Render(error)
+    }
+}
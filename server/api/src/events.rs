@@ -0,0 +1,495 @@
+//! Streaming changes to the clients of a user with server-sent events.
+//!
+//! Clients open `GET /mindless/api/events/<user_id>` and receive every change to the user's tasks,
+//! instances and timers as it happens. Each event has the id `<stream>-<sequence>` where the
+//! sequence counts up per user and the stream changes whenever the server restarts.
+//!
+//! Reconnecting with the last id, in the `Last-Event-ID` header browsers send or the `since`
+//! query, replays the changes missed in between. When they can't be replayed, because more than
+//! `events.history` changes happened or the server restarted, a `Reset` event tells the client to
+//! reload everything with `RetrieveAll`.
+use database::instance::Instance;
+use database::store::Store;
+use database::task::Task;
+use database::timer::Timer;
+use rand::Rng;
+use rocket::http::ContentType;
+use rocket::request::{self, FromRequest};
+use rocket::response::{self, Responder, Response};
+use rocket::{Request, State};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::io::Cursor;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::io::AsyncRead;
+use tokio::stream::StreamExt;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::Instant;
+use tracing::instrument;
+
+use crate::config::EventsConfig;
+use crate::error::Result;
+use crate::logging::RequestId;
+
+/// Changes which haven't been sent to a slow client yet before it has to catch up by reconnecting.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// A change to the data of a user.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub enum Change {
+    TaskCreated { task: Task },
+
+    TaskRenamed { task: Task },
+
+    InstanceInserted { instance: Instance },
+
+    // The start or end of an instance changed.
+    InstanceUpdated { instance: Instance },
+
+    InstanceDeleted { instance: Instance },
+
+    TimerStarted { timer: Timer },
+
+    // The timer was removed and its time recorded as the instance.
+    TimerStopped { timer: Timer, instance: Instance },
+}
+
+impl Change {
+    /// Name of the change variant, used as the event name.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Change::TaskCreated { .. } => "TaskCreated",
+            Change::TaskRenamed { .. } => "TaskRenamed",
+            Change::InstanceInserted { .. } => "InstanceInserted",
+            Change::InstanceUpdated { .. } => "InstanceUpdated",
+            Change::InstanceDeleted { .. } => "InstanceDeleted",
+            Change::TimerStarted { .. } => "TimerStarted",
+            Change::TimerStopped { .. } => "TimerStopped",
+        }
+    }
+}
+
+/// A change along with its place in the changes of the user.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub sequence: u64,
+    pub user_id: i64,
+    pub change: Change,
+}
+
+/// The recent changes of a user.
+#[derive(Default)]
+struct Log {
+    /// Sequence number of the latest change, zero before the first one.
+    sequence: u64,
+
+    entries: VecDeque<Arc<Entry>>,
+}
+
+struct Inner {
+    /// Identifies this run of the server so ids from before a restart are recognized.
+    stream: u32,
+    history: usize,
+    keep_alive: Duration,
+    logs: Mutex<HashMap<i64, Log>>,
+    sender: broadcast::Sender<Arc<Entry>>,
+}
+
+/// Where a new client starts.
+#[derive(Debug)]
+pub struct Subscription {
+    /// Sequence number of the latest change before subscribing.
+    pub sequence: u64,
+
+    /// The changes the client missed, oldest first.
+    pub missed: Vec<Arc<Entry>>,
+
+    /// The client missed changes which are gone and has to reload everything.
+    pub reset: bool,
+
+    /// Every change published after subscribing, of every user.
+    pub receiver: broadcast::Receiver<Arc<Entry>>,
+}
+
+/// Publishes changes to the streams of their user and keeps the recent ones for clients resuming.
+///
+/// Handlers publish changes whether or not streaming is enabled, a disabled feed drops them.
+#[derive(Clone)]
+pub struct Changes {
+    inner: Option<Arc<Inner>>,
+}
+
+impl Changes {
+    pub fn new(config: &EventsConfig) -> Changes {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+
+        Changes {
+            inner: Some(Arc::new(Inner {
+                stream: rand::thread_rng().gen(),
+                history: config.history,
+                keep_alive: Duration::from_secs(config.keep_alive_secs),
+                logs: Mutex::new(HashMap::new()),
+                sender,
+            })),
+        }
+    }
+
+    /// A feed which drops every change.
+    pub fn disabled() -> Changes {
+        Changes { inner: None }
+    }
+
+    /// Add a change to the changes of a user and send it to their streams.
+    pub fn publish(&self, user_id: i64, change: Change) {
+        let inner = match &self.inner {
+            Some(inner) => inner,
+            None => return,
+        };
+
+        let mut logs = inner.lock();
+        let log = logs.entry(user_id).or_default();
+        log.sequence += 1;
+        let entry = Arc::new(Entry {
+            sequence: log.sequence,
+            user_id,
+            change,
+        });
+
+        log.entries.push_back(entry.clone());
+        if log.entries.len() > inner.history {
+            log.entries.pop_front();
+        }
+
+        // Sent while holding the lock so every stream sees the changes in order. Failing only
+        // means nobody is listening.
+        let _ = inner.sender.send(entry);
+    }
+
+    /// Start following the changes of a user, after the change with id `since` if given.
+    ///
+    /// Returns `None` if the feed is disabled.
+    pub fn subscribe(&self, user_id: i64, since: Option<&str>) -> Option<Subscription> {
+        let inner = self.inner.as_ref()?;
+
+        // Subscribing while holding the lock means every change is either missed or received.
+        let logs = inner.lock();
+        let receiver = inner.sender.subscribe();
+        let empty = VecDeque::new();
+        let (sequence, entries) = match logs.get(&user_id) {
+            Some(log) => (log.sequence, &log.entries),
+            None => (0, &empty),
+        };
+
+        let mut subscription = Subscription {
+            sequence,
+            missed: Vec::new(),
+            reset: false,
+            receiver,
+        };
+
+        if let Some(since) = since {
+            match inner.parse_id(since) {
+                Some(since) if since <= sequence => {
+                    let oldest = entries.front().map_or(sequence + 1, |entry| entry.sequence);
+                    if since + 1 < oldest {
+                        subscription.reset = true;
+                    } else {
+                        subscription.missed = entries
+                            .iter()
+                            .filter(|entry| entry.sequence > since)
+                            .cloned()
+                            .collect();
+                    }
+                }
+                _ => subscription.reset = true,
+            }
+        }
+
+        Some(subscription)
+    }
+
+    /// Time between comments sent on idle streams.
+    fn keep_alive(&self) -> Duration {
+        self.inner
+            .as_ref()
+            .map_or(Duration::from_secs(15), |inner| inner.keep_alive)
+    }
+
+    /// The event id of a change.
+    fn id(&self, sequence: u64) -> String {
+        let stream = self.inner.as_ref().map_or(0, |inner| inner.stream);
+
+        format!("{:08x}-{}", stream, sequence)
+    }
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, HashMap<i64, Log>> {
+        // The logs are never left half updated so they are still usable after a panic.
+        self.logs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Sequence number of an event id of this stream.
+    fn parse_id(&self, id: &str) -> Option<u64> {
+        let mut parts = id.splitn(2, '-');
+        let stream = u32::from_str_radix(parts.next()?, 16).ok()?;
+        let sequence = parts.next()?.parse().ok()?;
+
+        if stream == self.stream {
+            Some(sequence)
+        } else {
+            None
+        }
+    }
+}
+
+/// Format a server-sent event. The data is a single line of json.
+fn message(id: Option<&str>, event: &str, data: &str) -> String {
+    match id {
+        Some(id) => format!("id: {}\nevent: {}\ndata: {}\n\n", id, event, data),
+        None => format!("event: {}\ndata: {}\n\n", event, data),
+    }
+}
+
+fn entry_message(changes: &Changes, entry: &Entry) -> String {
+    let data = serde_json::to_string(entry).expect("Changes serialize to json.");
+
+    message(
+        Some(&changes.id(entry.sequence)),
+        entry.change.kind(),
+        &data,
+    )
+}
+
+/// The `Last-Event-ID` header browsers send when reconnecting.
+pub struct LastEventId(Option<String>);
+
+#[rocket::async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for LastEventId {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let id = request
+            .headers()
+            .get_one("Last-Event-ID")
+            .map(str::to_string);
+
+        request::Outcome::Success(LastEventId(id))
+    }
+}
+
+/// A `text/event-stream` response which lasts until the client disconnects.
+pub struct EventStream(Box<dyn AsyncRead + Send + Unpin>);
+
+impl<'r> Responder<'r, 'static> for EventStream {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .header(ContentType::new("text", "event-stream"))
+            .raw_header("Cache-Control", "no-cache")
+            .streamed_body(self.0)
+            .ok()
+    }
+}
+
+/// Stream the changes of a user.
+///
+/// Starts with a `Connected` event holding the latest sequence number, followed by the changes
+/// missed since `since` or a `Reset` if they are gone.
+#[get("/mindless/api/events/<user_id>?<since>")]
+#[instrument(name = "events", skip(store, changes, since, last_event_id), fields(%request_id))]
+pub async fn events(
+    user_id: i64,
+    since: Option<String>,
+    last_event_id: LastEventId,
+    store: State<'_, Arc<dyn Store>>,
+    changes: State<'_, Changes>,
+    request_id: RequestId,
+) -> Result<EventStream> {
+    let user = store.retrieve_user(user_id).await?;
+    let changes = changes.inner().clone();
+
+    // Browsers send the id of the latest event they saw, which is newer than the query they
+    // started with.
+    let since = last_event_id.0.or(since);
+    let mut subscription = changes
+        .subscribe(user.get_id(), since.as_deref())
+        .ok_or(database::error::Error::NotFound)?;
+
+    let head = json!({ "sequence": subscription.sequence }).to_string();
+    let mut messages = vec![message(None, "Connected", &head)];
+    if subscription.reset {
+        messages.push(message(
+            Some(&changes.id(subscription.sequence)),
+            "Reset",
+            &head,
+        ));
+    }
+    for entry in &subscription.missed {
+        messages.push(entry_message(&changes, entry));
+    }
+
+    tracing::debug!(
+        missed = subscription.missed.len(),
+        reset = subscription.reset,
+        "Streaming changes"
+    );
+
+    let keep_alive = changes.keep_alive();
+    let (mut sender, receiver) = mpsc::channel(16);
+    tokio::spawn(async move {
+        for message in messages {
+            if sender.send(message).await.is_err() {
+                return;
+            }
+        }
+
+        let mut deadline = Instant::now() + keep_alive;
+        loop {
+            let received = tokio::time::timeout_at(deadline, subscription.receiver.recv()).await;
+            let message = match received {
+                // Comments keep proxies from closing idle streams and notice disconnects.
+                Err(_) => ": keep-alive\n\n".to_string(),
+                Ok(Ok(entry)) if entry.user_id == user_id => entry_message(&changes, &entry),
+                Ok(Ok(_)) => continue,
+                // Too slow to keep up or shutting down. The client reconnects and resumes.
+                Ok(Err(_)) => return,
+            };
+
+            // Fails once the client is gone.
+            if sender.send(message).await.is_err() {
+                return;
+            }
+            deadline = Instant::now() + keep_alive;
+        }
+    });
+
+    let body = receiver.map(|message| Ok::<_, std::io::Error>(Cursor::new(message)));
+
+    Ok(EventStream(Box::new(tokio::io::stream_reader(body))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    fn changes(history: usize) -> Changes {
+        Changes::new(&EventsConfig {
+            history,
+            ..EventsConfig::default()
+        })
+    }
+
+    fn renamed(name: &str) -> Change {
+        Change::TaskRenamed {
+            task: Task::new(1, 1, name.to_string()),
+        }
+    }
+
+    fn sequences(entries: &[Arc<Entry>]) -> Vec<u64> {
+        entries.iter().map(|entry| entry.sequence).collect()
+    }
+
+    #[tokio::test]
+    async fn sequences_count_up_per_user() {
+        let changes = changes(8);
+        let mut subscription = changes.subscribe(1, None).expect("Enabled.");
+        assert_eq!(subscription.sequence, 0);
+
+        changes.publish(1, renamed("a"));
+        changes.publish(2, renamed("b"));
+        changes.publish(1, renamed("c"));
+
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let entry = subscription.receiver.recv().await.expect("Published.");
+            received.push((entry.user_id, entry.sequence));
+        }
+        assert_eq!(received, vec![(1, 1), (2, 1), (1, 2)]);
+        assert_eq!(changes.subscribe(1, None).expect("Enabled.").sequence, 2);
+    }
+
+    #[test]
+    fn resuming_replays_missed_changes() {
+        let changes = changes(8);
+        for name in &["a", "b", "c"] {
+            changes.publish(1, renamed(name));
+        }
+
+        let subscription = changes
+            .subscribe(1, Some(&changes.id(1)))
+            .expect("Enabled.");
+        assert!(!subscription.reset);
+        assert_eq!(sequences(&subscription.missed), vec![2, 3]);
+        assert_eq!(subscription.missed[1].change, renamed("c"));
+
+        let subscription = changes
+            .subscribe(1, Some(&changes.id(3)))
+            .expect("Enabled.");
+        assert!(!subscription.reset);
+        assert!(subscription.missed.is_empty());
+    }
+
+    #[test]
+    fn resuming_without_history_resets() {
+        let changes = changes(2);
+        for name in &["a", "b", "c", "d"] {
+            changes.publish(1, renamed(name));
+        }
+
+        // Only 3 and 4 are kept.
+        assert!(changes.subscribe(1, Some(&changes.id(1))).unwrap().reset);
+        let subscription = changes.subscribe(1, Some(&changes.id(2))).unwrap();
+        assert!(!subscription.reset);
+        assert_eq!(sequences(&subscription.missed), vec![3, 4]);
+
+        // Ids of another run of the server, from the future or made up.
+        let restarted = self::changes(2);
+        assert!(changes.subscribe(1, Some(&restarted.id(2))).unwrap().reset);
+        assert!(changes.subscribe(1, Some(&changes.id(5))).unwrap().reset);
+        assert!(changes.subscribe(1, Some("nonsense")).unwrap().reset);
+    }
+
+    #[test]
+    fn disabled_feeds_drop_changes() {
+        let changes = Changes::disabled();
+        changes.publish(1, renamed("a"));
+
+        assert!(changes.subscribe(1, None).is_none());
+    }
+
+    #[test]
+    fn events_are_single_lines_of_json() {
+        let changes = changes(8);
+        let instance = Instance::new(
+            3,
+            1,
+            NaiveDateTime::from_timestamp(0, 0),
+            NaiveDateTime::from_timestamp(60, 0),
+        );
+        changes.publish(
+            1,
+            Change::InstanceInserted {
+                instance: instance.clone(),
+            },
+        );
+        let subscription = changes.subscribe(1, Some(&changes.id(0))).unwrap();
+
+        let message = entry_message(&changes, &subscription.missed[0]);
+        let lines: Vec<&str> = message.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], format!("id: {}", changes.id(1)));
+        assert_eq!(lines[1], "event: InstanceInserted");
+        let data: serde_json::Value =
+            serde_json::from_str(lines[2].trim_start_matches("data: ")).expect("Data is json.");
+        assert_eq!(data["sequence"], json!(1));
+        assert_eq!(
+            data["change"]["InstanceInserted"]["instance"],
+            serde_json::to_value(&instance).unwrap()
+        );
+        assert_eq!(lines[3], "");
+    }
+}
@@ -0,0 +1,517 @@
+//! Goals for tasks and habits, e.g. at least 5h of Deep Work per week, and how they are going.
+//!
+//! Periods are in the timezone of the user's reminder settings.
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use database::error::Error as DBError;
+use database::goal::Goal;
+use database::habit::Habit;
+use database::instance::Instance;
+use database::store::Store;
+use mindless_core::goal::{bounds, count, measure, Cadence, Comparison, Progress, Target, Unit};
+use mindless_core::period::{local_date, Period};
+use rocket::State;
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::instrument;
+
+use crate::chart::{parse_date, user_today};
+use crate::checkin::user_habit;
+use crate::digest::habit_path;
+use crate::error::{Error, Result};
+use crate::logging::RequestId;
+use crate::metrics::Metrics;
+use crate::schedule::Clock;
+use crate::task::user_task;
+
+/// The most days progress is worked out over at once.
+const MAX_DAYS: i64 = 3 * 366;
+
+// Type of events that you can execute on goals.
+#[derive(Deserialize, Debug)]
+pub enum Request {
+    // Set a goal for either a task or a habit, e.g. at least 18000 seconds of a task per week.
+    // Habit goals count how often the habit and the habits under it were done.
+    Create {
+        user_id: i64,
+        task_id: Option<i64>,
+        habit_id: Option<i64>,
+        comparison: Comparison,
+        amount: i64,
+        unit: Unit,
+        cadence: Cadence,
+    },
+
+    // Retrieve all the goals of a user.
+    RetrieveAll {
+        user_id: i64,
+    },
+
+    // Delete a goal.
+    Delete {
+        user_id: i64,
+        id: i64,
+    },
+
+    // How every goal did in each of its periods from `from` to `to`, e.g. `2020-10-12`, leaving
+    // out periods before the one it was set in. Both are today by default, which is the period
+    // each goal is in now.
+    Progress {
+        user_id: i64,
+        from: Option<String>,
+        to: Option<String>,
+    },
+}
+
+impl Request {
+    /// Name of the request variant. This is safe to log since it holds no user data.
+    pub fn variant(&self) -> &'static str {
+        match self {
+            Request::Create { .. } => "Create",
+            Request::RetrieveAll { .. } => "RetrieveAll",
+            Request::Delete { .. } => "Delete",
+            Request::Progress { .. } => "Progress",
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub enum Response {
+    Create { goal: Goal },
+
+    RetrieveAll { goals: Vec<Goal> },
+
+    Delete { goal: Goal },
+
+    // Every goal of the user, oldest first.
+    Progress { goals: Vec<GoalProgress> },
+}
+
+/// How a goal did in some of its periods.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GoalProgress {
+    pub goal: Goal,
+
+    /// E.g. `At least 5h00m of Deep Work per week`.
+    pub description: String,
+
+    /// The periods in order.
+    pub periods: Vec<Progress>,
+}
+
+/// Retrieve a goal making sure it belongs to the user.
+async fn user_goal(user_id: i64, id: i64, store: &dyn Store) -> Result<Goal> {
+    let goal = store.retrieve_goal(id).await?;
+    if goal.get_user_id() != user_id {
+        return Err(DBError::NotFound.into());
+    }
+
+    Ok(goal)
+}
+
+/// Ids of a habit and the habits under it.
+fn under(habits: &[Habit], habit_id: i64) -> Vec<i64> {
+    let parents: HashMap<i64, Option<i64>> = habits
+        .iter()
+        .map(|habit| (habit.get_id(), habit.get_parent_id()))
+        .collect();
+
+    habits
+        .iter()
+        .map(Habit::get_id)
+        .filter(|id| {
+            let mut current = Some(*id);
+            while let Some(id) = current {
+                if id == habit_id {
+                    return true;
+                }
+                current = parents.get(&id).copied().flatten();
+            }
+
+            false
+        })
+        .collect()
+}
+
+/// How a goal did in the periods starting on the local days `starts`, leaving out the periods
+/// before the one it was set in.
+pub(crate) async fn progress(
+    store: &dyn Store,
+    goal: Goal,
+    starts: &[NaiveDate],
+    timezone: &Tz,
+) -> Result<GoalProgress> {
+    let target = goal
+        .get_target()
+        .map_err(|error| Error::BadRequest(error.to_string()))?;
+    let first = target
+        .cadence
+        .start(&local_date(goal.get_created_at(), timezone));
+    let (starts, periods): (Vec<NaiveDate>, Vec<Period>) = starts
+        .iter()
+        .filter(|start| **start >= first)
+        .filter_map(|start| Some((*start, bounds(target.cadence, start, timezone)?)))
+        .unzip();
+
+    let (name, actuals): (String, Vec<i64>) = match (goal.get_task_id(), goal.get_habit_id()) {
+        (Some(task_id), _) => {
+            let task = store.retrieve_task(task_id).await?;
+            let instances: Vec<Period> = store
+                .get_instances(task.get_id())
+                .await?
+                .iter()
+                .map(Instance::get_period)
+                .collect();
+            let actuals = periods
+                .iter()
+                .map(|bounds| measure(target.unit, &instances, bounds))
+                .collect();
+
+            (task.get_name().to_string(), actuals)
+        }
+        (None, Some(habit_id)) => {
+            let habits = store.get_habits(goal.get_user_id()).await?;
+            let by_id: HashMap<i64, &Habit> =
+                habits.iter().map(|habit| (habit.get_id(), habit)).collect();
+            let habit = by_id.get(&habit_id).ok_or(DBError::NotFound)?;
+
+            let mut done = Vec::new();
+            if let (Some(first), Some(last)) = (periods.first(), periods.last()) {
+                let within = Period::new(first.start, last.end);
+                for id in under(&habits, habit_id) {
+                    let completions = store.get_completions(id, &within).await?;
+                    done.extend(
+                        completions
+                            .iter()
+                            .map(|completion| *completion.get_created_at()),
+                    );
+                }
+            }
+            let actuals = periods.iter().map(|bounds| count(&done, bounds)).collect();
+
+            (habit_path(habit, &by_id), actuals)
+        }
+        (None, None) => return Err(DBError::NotFound.into()),
+    };
+
+    Ok(GoalProgress {
+        description: target.describe(&name),
+        periods: starts
+            .iter()
+            .zip(actuals)
+            .filter_map(|(start, actual)| Progress::new(&target, start, actual))
+            .collect(),
+        goal,
+    })
+}
+
+// Handle setting goals and checking on them.
+#[post("/mindless/api/goal", data = "<request>")]
+#[instrument(
+    name = "goal",
+    skip(store, clock, metrics, request),
+    fields(%request_id, request = request.variant())
+)]
+pub async fn goal(
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    metrics: State<'_, Metrics>,
+    request: Json<Request>,
+    request_id: RequestId,
+) -> Result<Json<Response>> {
+    let _timer = metrics.api_request("goal", request.variant());
+    let store = store.inner().as_ref();
+
+    let response = match request.into_inner() {
+        Request::Create {
+            user_id,
+            task_id,
+            habit_id,
+            comparison,
+            amount,
+            unit,
+            cadence,
+        } => {
+            if amount < 0 || (comparison == Comparison::AtLeast && amount == 0) {
+                return Err(Error::BadRequest(format!(
+                    "{} is not an amount for an {} goal",
+                    amount, comparison
+                )));
+            }
+            // Nothing happens more than once a second.
+            if amount > cadence.longest().num_seconds() {
+                return Err(Error::BadRequest(format!(
+                    "{} {} is more than fits in a {}",
+                    amount, unit, cadence
+                )));
+            }
+            match (task_id, habit_id) {
+                (Some(task_id), None) => {
+                    user_task(user_id, task_id, store).await?;
+                }
+                (None, Some(habit_id)) => {
+                    if unit == Unit::Seconds {
+                        return Err(Error::BadRequest(
+                            "Habit goals count times, not seconds".to_string(),
+                        ));
+                    }
+                    user_habit(user_id, habit_id, store).await?;
+                }
+                _ => {
+                    return Err(Error::BadRequest(
+                        "A goal is for either a task_id or a habit_id".to_string(),
+                    ))
+                }
+            }
+
+            let target = Target {
+                comparison,
+                amount,
+                unit,
+                cadence,
+            };
+            let goal = store
+                .insert_goal(
+                    user_id,
+                    task_id,
+                    habit_id,
+                    &target,
+                    &clock.now().naive_utc(),
+                )
+                .await?;
+
+            Response::Create { goal }
+        }
+
+        Request::RetrieveAll { user_id } => {
+            let user = store.retrieve_user(user_id).await?;
+            let goals = store.get_goals(user.get_id()).await?;
+
+            Response::RetrieveAll { goals }
+        }
+
+        Request::Delete { user_id, id } => {
+            let goal = user_goal(user_id, id, store).await?;
+            store.delete_goal(goal.clone()).await?;
+
+            Response::Delete { goal }
+        }
+
+        Request::Progress { user_id, from, to } => {
+            let (user, timezone, today) =
+                user_today(store, clock.inner().as_ref(), user_id).await?;
+            let to = match to {
+                Some(to) => parse_date(&to)?,
+                None => today,
+            };
+            let from = match from {
+                Some(from) => parse_date(&from)?,
+                None => to,
+            };
+            if from > to {
+                return Err(Error::BadRequest(format!("{} is after {}", from, to)));
+            }
+            if (to - from).num_days() >= MAX_DAYS {
+                return Err(Error::BadRequest(format!(
+                    "Progress covers at most {} days",
+                    MAX_DAYS
+                )));
+            }
+
+            let mut goals = Vec::new();
+            for goal in store.get_goals(user.get_id()).await? {
+                let target = goal
+                    .get_target()
+                    .map_err(|error| Error::BadRequest(error.to_string()))?;
+                let starts = target.cadence.starts(&from, &to);
+                goals.push(progress(store, goal, &starts, &timezone).await?);
+            }
+
+            Response::Progress { goals }
+        }
+    };
+
+    tracing::debug!("Handled request");
+
+    Ok(Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+    use database::memory::MemoryStore;
+
+    fn time(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2020, 10, day).and_hms(hour, 0, 0)
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(2020, 10, day)
+    }
+
+    #[tokio::test]
+    async fn task_and_habit_progress() {
+        let store = MemoryStore::default();
+        let user = store
+            .insert_user("username", "name")
+            .await
+            .expect("Should successfully insert.");
+        let work = store
+            .insert_task(user.get_id(), "Deep Work")
+            .await
+            .expect("Should successfully insert.");
+        for (start, end) in &[
+            (time(12, 9), time(12, 12)),
+            // Overlapping time is counted once.
+            (time(12, 11), time(12, 13)),
+            (time(19, 9), time(19, 10)),
+        ] {
+            store
+                .insert_instance(work.get_id(), start, end)
+                .await
+                .expect("Should successfully insert.");
+        }
+
+        let health = store
+            .insert_habit(user.get_id(), None, "Health", &time(1, 0), None)
+            .await
+            .expect("Should successfully insert.");
+        let run = store
+            .insert_habit(
+                user.get_id(),
+                Some(health.get_id()),
+                "Run",
+                &time(1, 0),
+                Some(86400),
+            )
+            .await
+            .expect("Should successfully insert.");
+        for day in &[12, 14, 16, 20] {
+            store
+                .mark_habit(&run, &time(*day, 7))
+                .await
+                .expect("Can mark.");
+        }
+
+        let deep_work = store
+            .insert_goal(
+                user.get_id(),
+                Some(work.get_id()),
+                None,
+                &Target {
+                    comparison: Comparison::AtLeast,
+                    amount: 4 * 3600,
+                    unit: Unit::Seconds,
+                    cadence: Cadence::Week,
+                },
+                &time(1, 0),
+            )
+            .await
+            .expect("Should successfully insert.");
+        let starts = Cadence::Week.starts(&date(12), &date(19));
+        let progress_of_work = progress(&store, deep_work, &starts, &Tz::UTC)
+            .await
+            .expect("Can work out progress.");
+        assert_eq!(
+            progress_of_work.description,
+            "At least 4h00m of Deep Work per week"
+        );
+        let periods: Vec<(NaiveDate, NaiveDate, i64, bool)> = progress_of_work
+            .periods
+            .iter()
+            .map(|period| (period.start, period.end, period.actual, period.met))
+            .collect();
+        assert_eq!(
+            periods,
+            vec![
+                (date(12), date(18), 4 * 3600, true),
+                (date(19), date(25), 3600, false)
+            ]
+        );
+
+        // Completions of the habits under a habit count towards it.
+        let exercise = store
+            .insert_goal(
+                user.get_id(),
+                None,
+                Some(health.get_id()),
+                &Target {
+                    comparison: Comparison::AtLeast,
+                    amount: 3,
+                    unit: Unit::Times,
+                    cadence: Cadence::Week,
+                },
+                &time(1, 0),
+            )
+            .await
+            .expect("Should successfully insert.");
+        let progress_of_health = progress(&store, exercise, &starts, &Tz::UTC)
+            .await
+            .expect("Can work out progress.");
+        assert_eq!(
+            progress_of_health.description,
+            "Health at least 3 times per week"
+        );
+        let actuals: Vec<(i64, bool)> = progress_of_health
+            .periods
+            .iter()
+            .map(|period| (period.actual, period.met))
+            .collect();
+        assert_eq!(actuals, vec![(3, true), (1, false)]);
+    }
+
+    #[tokio::test]
+    async fn periods_before_the_goal_are_left_out() {
+        let store = MemoryStore::default();
+        let user = store
+            .insert_user("username", "name")
+            .await
+            .expect("Should successfully insert.");
+        let work = store
+            .insert_task(user.get_id(), "Deep Work")
+            .await
+            .expect("Should successfully insert.");
+        let target = Target {
+            comparison: Comparison::AtMost,
+            amount: 3600,
+            unit: Unit::Seconds,
+            cadence: Cadence::Week,
+        };
+        // Set early on Monday in Sydney, which is still Sunday in UTC.
+        let goal = store
+            .insert_goal(
+                user.get_id(),
+                Some(work.get_id()),
+                None,
+                &target,
+                &time(18, 14),
+            )
+            .await
+            .expect("Should successfully insert.");
+
+        let sydney: Tz = "Australia/Sydney".parse().expect("Is a timezone.");
+        let starts = Cadence::Week.starts(&date(1), &date(19));
+        let progress = progress(&store, goal, &starts, &sydney)
+            .await
+            .expect("Can work out progress.");
+        let starts: Vec<NaiveDate> = progress.periods.iter().map(|period| period.start).collect();
+        assert_eq!(starts, vec![date(19)]);
+    }
+
+    #[test]
+    fn habits_under() {
+        let habits = vec![
+            Habit::new(1, None, 1, "Health".to_string(), time(1, 0), None),
+            Habit::new(2, Some(1), 1, "Run".to_string(), time(1, 0), None),
+            Habit::new(3, Some(2), 1, "Sprint".to_string(), time(1, 0), None),
+            Habit::new(4, None, 1, "Chores".to_string(), time(1, 0), None),
+        ];
+
+        assert_eq!(under(&habits, 1), vec![1, 2, 3]);
+        assert_eq!(under(&habits, 2), vec![2, 3]);
+        assert_eq!(under(&habits, 4), vec![4]);
+    }
+}
@@ -0,0 +1,168 @@
+use database::habit::Habit;
+use database::store::Store;
+use mindless_core::path::HabitPath;
+use mindless_core::period::{self, Period};
+use rocket::State;
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::instrument;
+
+use crate::error::{Error, Result};
+use crate::logging::RequestId;
+use crate::metrics::Metrics;
+use crate::schedule::Clock;
+use crate::webhook::{Dispatcher, Event};
+
+// Type of events that you can execute on a habit.
+#[derive(Deserialize, Debug)]
+pub enum Request {
+    // Create a habit under an existing parent, e.g. `Health/Exercise` under `Health`.
+    Create {
+        user_id: i64,
+        path: HabitPath,
+        repeat_period_sec: Option<i64>,
+    },
+
+    // Retrieve all the habits, parents before their children.
+    RetrieveAll {
+        user_id: i64,
+    },
+
+    // Mark a habit as done for its current period. Marking it again does nothing.
+    Mark {
+        user_id: i64,
+        path: HabitPath,
+    },
+
+    // Undo marking a habit as done for its current period.
+    Unmark {
+        user_id: i64,
+        path: HabitPath,
+    },
+}
+
+impl Request {
+    /// Name of the request variant. This is safe to log since it holds no user data.
+    pub fn variant(&self) -> &'static str {
+        match self {
+            Request::Create { .. } => "Create",
+            Request::RetrieveAll { .. } => "RetrieveAll",
+            Request::Mark { .. } => "Mark",
+            Request::Unmark { .. } => "Unmark",
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub enum Response {
+    Create {
+        habit: Habit,
+    },
+
+    RetrieveAll {
+        habits: Vec<Habit>,
+    },
+
+    // Whether the habit was marked, false if it already was.
+    Mark {
+        habit: Habit,
+        period: Period,
+        marked: bool,
+    },
+
+    // Whether the habit was unmarked, false if it wasn't marked.
+    Unmark {
+        habit: Habit,
+        period: Period,
+        unmarked: bool,
+    },
+}
+
+// Handle all interfacing with habits.
+#[post("/mindless/api/habit", data = "<request>")]
+#[instrument(
+    name = "habit",
+    skip(store, clock, dispatcher, metrics, request),
+    fields(%request_id, request = request.variant())
+)]
+pub async fn habit(
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    dispatcher: State<'_, Dispatcher>,
+    metrics: State<'_, Metrics>,
+    request: Json<Request>,
+    request_id: RequestId,
+) -> Result<Json<Response>> {
+    let _timer = metrics.api_request("habit", request.variant());
+    let store = store.inner().as_ref();
+    let now = clock.now().naive_utc();
+
+    let response = match request.into_inner() {
+        Request::Create {
+            user_id,
+            path,
+            repeat_period_sec,
+        } => {
+            period::check_repeat(repeat_period_sec)
+                .map_err(|error| Error::BadRequest(error.to_string()))?;
+            let user = store.retrieve_user(user_id).await?;
+            let parent_id = match path.parent() {
+                Some(parent) => Some(store.get_habit(user.get_id(), &parent).await?.get_id()),
+                None => None,
+            };
+            let habit = store
+                .insert_habit(
+                    user.get_id(),
+                    parent_id,
+                    path.name(),
+                    &now,
+                    repeat_period_sec,
+                )
+                .await?;
+
+            Response::Create { habit }
+        }
+
+        Request::RetrieveAll { user_id } => {
+            let user = store.retrieve_user(user_id).await?;
+            let habits = store.get_habits(user.get_id()).await?;
+
+            Response::RetrieveAll { habits }
+        }
+
+        Request::Mark { user_id, path } => {
+            let habit = store.get_habit(user_id, &path).await?;
+            let marked = store.mark_habit(&habit, &now).await?;
+            let period = habit.get_period(&now)?;
+            if marked {
+                let event = Event::HabitCompleted {
+                    habit: habit.clone(),
+                    period,
+                };
+                dispatcher.emit(habit.get_user_id(), event).await;
+            }
+
+            Response::Mark {
+                period,
+                habit,
+                marked,
+            }
+        }
+
+        Request::Unmark { user_id, path } => {
+            let habit = store.get_habit(user_id, &path).await?;
+            let unmarked = store.unmark_habit(&habit, &now).await?;
+
+            Response::Unmark {
+                period: habit.get_period(&now)?,
+                habit,
+                unmarked,
+            }
+        }
+    };
+
+    tracing::debug!("Handled request");
+
+    Ok(Json(response))
+}
@@ -0,0 +1,120 @@
+use database::connection::Connection;
+use database::migration;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket::State;
+use rocket_contrib::json::{Json, JsonValue};
+use serde::Serialize;
+use std::path::Path;
+
+/// Name of the file written to check the database directory is writable.
+const DISK_PROBE_FILE_NAME: &str = ".mindless-readyz";
+
+/// The result of a single readiness check.
+#[derive(Serialize, Debug)]
+pub struct Check {
+    ok: bool,
+
+    /// Why the check failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Check {
+    fn from_result<E: ToString>(result: std::result::Result<(), E>) -> Check {
+        match result {
+            Ok(()) => Check {
+                ok: true,
+                error: None,
+            },
+            Err(error) => Check {
+                ok: false,
+                error: Some(error.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Readiness {
+    ok: bool,
+
+    /// The pool can execute a query.
+    database: Check,
+
+    /// Every migration has been applied.
+    migrations: Check,
+
+    /// The directory holding the database is writable.
+    disk: Check,
+}
+
+/// The process is up.
+#[get("/healthz")]
+pub async fn healthz() -> JsonValue {
+    json!({ "ok": true })
+}
+
+/// The server can serve requests.
+///
+/// Responds with 503 Service Unavailable if any of the checks fail.
+#[get("/readyz")]
+pub async fn readyz(connection: State<'_, Connection>) -> Custom<Json<Readiness>> {
+    let database = Check::from_result(connection.ping().await);
+    let migrations = Check::from_result(check_migrations(&connection).await);
+    let disk = Check::from_result(match connection.get_path() {
+        Some(path) => check_writable(path),
+        // In memory databases have no disk to check.
+        None => Ok(()),
+    });
+
+    let ok = database.ok && migrations.ok && disk.ok;
+    if !ok {
+        tracing::warn!(?database, ?migrations, ?disk, "Server is not ready");
+    }
+
+    let status = if ok {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+
+    Custom(
+        status,
+        Json(Readiness {
+            ok,
+            database,
+            migrations,
+            disk,
+        }),
+    )
+}
+
+/// Check the database is at the latest schema version.
+async fn check_migrations(connection: &Connection) -> Result<(), String> {
+    let version = migration::version(connection)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if version == migration::LATEST_VERSION {
+        Ok(())
+    } else {
+        Err(format!(
+            "Database is at version {} but the latest is {}",
+            version,
+            migration::LATEST_VERSION
+        ))
+    }
+}
+
+/// Check we can create files next to the database.
+fn check_writable(database_path: &Path) -> std::io::Result<()> {
+    let directory = match database_path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+
+    let probe = directory.join(DISK_PROBE_FILE_NAME);
+    std::fs::write(&probe, b"ok")?;
+    std::fs::remove_file(&probe)
+}
@@ -0,0 +1,235 @@
+#![feature(proc_macro_hygiene)]
+#![feature(async_closure)]
+
+#[macro_use]
+extern crate rocket;
+#[macro_use]
+extern crate rocket_contrib;
+extern crate chrono;
+
+extern crate serde;
+extern crate serde_json;
+
+// Database queries.
+extern crate database;
+
+// List of common routes.
+pub mod routes;
+// User routes
+pub mod user;
+// Task routes
+pub mod task;
+// Habit routes
+pub mod habit;
+// Check-in routes, e.g. behind a QR code.
+pub mod checkin;
+// Signing tokens with the auth secret.
+pub mod signing;
+// Rendering QR codes.
+pub mod qr;
+// Webhook routes and posting events to them.
+pub mod webhook;
+// Streaming changes to clients.
+pub mod events;
+// Reminder routes and delivering reminders.
+pub mod reminder;
+// Weekly digests.
+pub mod digest;
+// Goals for tasks and habits.
+pub mod goal;
+// Tags of tasks and instances.
+pub mod tag;
+// Svg charts of time and habits.
+pub mod chart;
+// The web dashboard.
+pub mod dashboard;
+// Errors
+pub mod error;
+// Logging and request tracing.
+pub mod logging;
+// Prometheus metrics.
+pub mod metrics;
+// Health and readiness checks.
+pub mod health;
+// Server configuration.
+pub mod config;
+// Cross origin requests.
+pub mod cors;
+// Periodic background jobs.
+pub mod schedule;
+// Database backups.
+#[cfg(feature = "sqlite")]
+pub mod backup;
+
+use anyhow::Context;
+use database::connection::Connection;
+use database::store::Store;
+use schedule::Clock;
+use signing::Signer;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Connect to the configured database and bring it up to date.
+pub async fn connect(config: &config::Config) -> anyhow::Result<Connection> {
+    let sqlite = &config.database.sqlite;
+    let connection = Connection::builder(&config.database.url)
+        .max_connections(config.database.pool_size)
+        .journal_mode(sqlite.journal_mode)
+        .synchronous(sqlite.synchronous)
+        .busy_timeout(Duration::from_millis(sqlite.busy_timeout_ms))
+        .foreign_keys(sqlite.foreign_keys)
+        .connect()
+        .await
+        .context("Could not connect to the database")?;
+
+    database::migration::run(&connection)
+        .await
+        .context("Could not migrate the database")?;
+
+    Ok(connection)
+}
+
+/// Start the rocket server.
+///
+/// Separate this from main in order to use it in tests.
+pub async fn liftoff(config: &config::Config) -> anyhow::Result<rocket::Rocket> {
+    let rocket_config = rocket::Config::build(rocket::config::Environment::active()?)
+        .address(config.server.address.clone())
+        .port(config.server.port)
+        .finalize()?;
+
+    let connection = connect(config).await?;
+
+    let mut routes = routes![
+        routes::index,
+        routes::favicon,
+        user::user,
+        task::task,
+        habit::habit,
+        health::healthz,
+        health::readyz
+    ];
+
+    if config.features.webhooks {
+        routes.extend(routes![webhook::webhook]);
+    }
+
+    let changes = if config.features.events {
+        routes.extend(routes![events::events]);
+        events::Changes::new(&config.events)
+    } else {
+        events::Changes::disabled()
+    };
+
+    let metrics = metrics::Metrics::new();
+    if config.features.metrics {
+        routes.extend(routes![metrics::metrics]);
+    }
+
+    // Check-in tokens are signed so they can only be handed out with a secret.
+    let signer = config.auth.secret.as_deref().map(Signer::new);
+    if signer.is_some() {
+        routes.extend(routes![
+            checkin::checkin,
+            checkin::check_in_get,
+            checkin::check_in_post,
+            checkin::qr_svg,
+            checkin::qr_png,
+            checkin::sheet
+        ]);
+    } else {
+        tracing::info!("No auth secret is configured, check-in routes are disabled");
+    }
+
+    // Dashboard sessions are signed with the same secret.
+    if config.features.dashboard && signer.is_some() {
+        routes.extend(routes![
+            dashboard::login_get,
+            dashboard::login_post,
+            dashboard::logout,
+            dashboard::dashboard,
+            dashboard::timer_start,
+            dashboard::timer_stop,
+            dashboard::instance_edit,
+            dashboard::instance_delete,
+            dashboard::habit_create,
+            dashboard::habit_mark,
+            dashboard::habit_unmark,
+            dashboard::habit_delete
+        ]);
+    }
+
+    // Handlers go through the store, health checks and background jobs need the connection.
+    let store: Arc<dyn Store> = Arc::new(connection.clone());
+    let clock: Arc<dyn Clock> = Arc::new(schedule::SystemClock);
+    let dispatcher = if config.features.webhooks {
+        webhook::Dispatcher::new(store.clone(), clock.clone(), config.webhooks.clone())
+            .context("Could not create the webhook client")?
+    } else {
+        webhook::Dispatcher::disabled()
+    };
+
+    if config.features.digest {
+        routes.extend(routes![digest::digest]);
+    }
+    if config.features.goals {
+        routes.extend(routes![goal::goal]);
+    }
+    if config.features.tags {
+        routes.extend(routes![tag::tag]);
+    }
+    if config.features.charts {
+        routes.extend(routes![
+            chart::heatmap_svg,
+            chart::weekly_svg,
+            chart::hours_svg
+        ]);
+    }
+
+    let engine = if config.features.reminders {
+        routes.extend(routes![reminder::reminder, reminder::pending]);
+
+        let mut notifiers: Vec<Box<dyn reminder::Notifier>> =
+            vec![Box::new(reminder::PollNotifier::new(store.clone()))];
+        if config.features.webhooks {
+            notifiers.push(Box::new(reminder::WebhookNotifier::new(dispatcher.clone())));
+        }
+        if let Some(smtp) = &config.reminders.smtp {
+            notifiers.push(Box::new(reminder::EmailNotifier::new(smtp.clone())));
+        }
+
+        Some(reminder::Engine::new(
+            store.clone(),
+            notifiers,
+            &config.reminders,
+        ))
+    } else {
+        None
+    };
+
+    let mut rocket = rocket::custom(rocket_config)
+        .manage(connection)
+        .manage(store)
+        .manage(clock)
+        .manage(dispatcher)
+        .manage(changes);
+    if config.features.webhooks {
+        rocket = rocket.manage(config.webhooks.clone());
+    }
+    if let Some(engine) = engine {
+        rocket = rocket.manage(engine);
+    }
+    if let Some(signer) = signer {
+        rocket = rocket
+            .manage(signer)
+            .manage(checkin::Links::new(&config.server.public_url));
+    }
+
+    Ok(rocket
+        .mount("/", routes)
+        .manage(metrics.clone())
+        .attach(metrics)
+        .attach(logging::RequestTracing)
+        .attach(cors::Cors::new(config.server.cors_origins.clone()))
+        .register(catchers![routes::not_found]))
+}
@@ -0,0 +1,98 @@
+use crate::config::{LogConfig, LogFormat};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::{self, FromRequest};
+use rocket::{Data, Request, Response};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+
+/// Install the global tracing subscriber.
+///
+/// Closed spans are logged so every database call reports how long it took (`time.busy`) when
+/// the `database` target is enabled at debug level.
+pub fn init(config: &LogConfig) {
+    // The level has been validated when loading the configuration.
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(&config.level))
+        .with_span_events(FmtSpan::CLOSE);
+
+    match config.format {
+        LogFormat::Json => builder.json().init(),
+        LogFormat::Pretty => builder.pretty().init(),
+        LogFormat::Compact => builder.init(),
+    }
+}
+
+/// Unique id handed to every request so the log lines of a single request can be correlated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestId(u64);
+
+impl RequestId {
+    fn next() -> RequestId {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+        RequestId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Get the id of a request, assigning one if it does not have one yet.
+    pub fn of(request: &Request<'_>) -> RequestId {
+        *request.local_cache(RequestId::next)
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[rocket::async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for RequestId {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(RequestId::of(request))
+    }
+}
+
+/// Time at which we started handling a request.
+struct RequestStart(Instant);
+
+/// Fairing logging the start and end of every request along with its id and latency.
+///
+/// Only the method, path and status are logged. Bodies contain user data and are never logged.
+pub struct RequestTracing;
+
+#[rocket::async_trait]
+impl Fairing for RequestTracing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request tracing",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &Data) {
+        request.local_cache(|| RequestStart(Instant::now()));
+
+        tracing::info!(
+            request_id = %RequestId::of(request),
+            method = %request.method(),
+            path = %request.uri().path(),
+            "Received request"
+        );
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let start = request.local_cache(|| RequestStart(Instant::now()));
+
+        tracing::info!(
+            request_id = %RequestId::of(request),
+            status = response.status().code,
+            latency_ms = start.0.elapsed().as_millis() as u64,
+            "Sending response"
+        );
+    }
+}
@@ -1,52 +1,229 @@
-#![feature(proc_macro_hygiene)]
-#![feature(async_closure)]
-
-#[macro_use]
-extern crate rocket;
-#[macro_use]
-extern crate rocket_contrib;
-extern crate chrono;
-
-extern crate serde;
-extern crate serde_json;
-
-// Database queries.
-extern crate database;
-
-// List of common routes.
-mod routes;
-// User routes
-mod user;
-// Task routes
-mod task;
-// Errors
-mod error;
+use anyhow::Context;
+use database::store::Store;
+use endpoint::schedule::Clock;
+use endpoint::{config, digest, liftoff, logging, reminder, webhook};
+use std::sync::Arc;
+use structopt::StructOpt;
+
+// Only backups and seeding need the database outside of the server.
+#[cfg(feature = "sqlite")]
+use database::connection::Connection;
+#[cfg(feature = "sqlite")]
+use endpoint::backup;
+#[cfg(any(feature = "sqlite", feature = "test-util"))]
+use endpoint::connect;
+#[cfg(feature = "sqlite")]
+use std::path::PathBuf;
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "The mindless server.")]
+struct Options {
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Run the server. This is the default.
+    Serve,
+
+    /// Take a backup of the database now.
+    #[cfg(feature = "sqlite")]
+    Backup {
+        /// Write the backup here instead of the configured directory.
+        #[structopt(long)]
+        directory: Option<PathBuf>,
+    },
+
+    /// Replace the database with a backup. The server must be stopped.
+    #[cfg(feature = "sqlite")]
+    Restore {
+        /// The backup to restore.
+        backup: PathBuf,
+    },
+
+    /// Fill the database with generated users, habits, tasks and instances.
+    #[cfg(feature = "test-util")]
+    Seed {
+        /// The same seed always generates the same data.
+        #[structopt(long, default_value = "0")]
+        seed: u64,
+
+        /// Number of users to generate.
+        #[structopt(long, default_value = "3")]
+        users: usize,
+
+        /// Days of history to generate, up to today.
+        #[structopt(long, default_value = "90")]
+        days: i64,
+    },
+}
 
 #[tokio::main]
 async fn main() {
-    let database_url =
-        &std::env::var("DATABASE_URL").expect("`DATBASE_URL` environment variable must be set.");
+    let options = Options::from_args();
+
+    let config = match config::Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    logging::init(&config.log);
+    tracing::info!(?config, "Loaded configuration");
+
+    let result = match options.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(&config).await,
+        #[cfg(feature = "sqlite")]
+        Command::Backup { directory } => backup(config, directory).await,
+        #[cfg(feature = "sqlite")]
+        Command::Restore { backup } => restore(&config, &backup).await,
+        #[cfg(feature = "test-util")]
+        Command::Seed { seed, users, days } => self::seed(&config, seed, users, days).await,
+    };
+
+    if let Err(e) = result {
+        tracing::error!("{:#}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn serve(config: &config::Config) -> anyhow::Result<()> {
+    let rocket = liftoff(config)
+        .await
+        .context("Failed to start the server")?;
+
+    // Validating the configuration ensures backups are only enabled on sqlite.
+    #[cfg(feature = "sqlite")]
+    if config.features.backups {
+        let connection = rocket
+            .state::<Connection>()
+            .expect("Connection is managed by liftoff.")
+            .clone();
+
+        let clock = rocket
+            .state::<Arc<dyn Clock>>()
+            .expect("Clock is managed by liftoff.")
+            .clone();
+
+        backup::schedule(connection, config.backup.clone(), clock);
+    }
 
-    liftoff(database_url)
+    if config.features.webhooks {
+        let store = rocket
+            .state::<Arc<dyn Store>>()
+            .expect("Store is managed by liftoff.")
+            .clone();
+        let dispatcher = rocket
+            .state::<webhook::Dispatcher>()
+            .expect("Dispatcher is managed by liftoff.")
+            .clone();
+        let clock = rocket
+            .state::<Arc<dyn Clock>>()
+            .expect("Clock is managed by liftoff.")
+            .clone();
+
+        webhook::schedule(store, dispatcher, config.webhooks.clone(), clock);
+    }
+
+    if config.features.reminders {
+        let engine = rocket
+            .state::<reminder::Engine>()
+            .expect("Engine is managed by liftoff.")
+            .clone();
+        let clock = rocket
+            .state::<Arc<dyn Clock>>()
+            .expect("Clock is managed by liftoff.")
+            .clone();
+
+        reminder::schedule(engine, config.reminders.clone(), clock);
+    }
+
+    // Digests are delivered through the reminder notifiers.
+    if config.features.digest && config.features.reminders {
+        let store = rocket
+            .state::<Arc<dyn Store>>()
+            .expect("Store is managed by liftoff.")
+            .clone();
+        let engine = rocket
+            .state::<reminder::Engine>()
+            .expect("Engine is managed by liftoff.")
+            .clone();
+        let clock = rocket
+            .state::<Arc<dyn Clock>>()
+            .expect("Clock is managed by liftoff.")
+            .clone();
+
+        digest::schedule(store, engine, config.digest.clone(), clock);
+    }
+
+    rocket.launch().await.context("Server stopped")?;
+
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+async fn backup(mut config: config::Config, directory: Option<PathBuf>) -> anyhow::Result<()> {
+    if let Some(directory) = directory {
+        config.backup.directory = directory;
+    }
+
+    let connection = connect(&config).await?;
+    let path = backup::take(&connection, &config.backup, &chrono::Utc::now())
         .await
-        .launch()
+        .context("Could not take a backup")?;
+
+    println!("{}", path.display());
+
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+async fn restore(config: &config::Config, backup: &std::path::Path) -> anyhow::Result<()> {
+    let destination = database::connection::database_file(&config.database.url)
+        .context("Can only restore into a database file")?;
+
+    let version = database::backup::restore(backup, &destination)
         .await
-        .expect("Successful launch.");
+        .with_context(|| format!("Could not restore \"{}\"", backup.display()))?;
+
+    tracing::info!(
+        version,
+        destination = %destination.display(),
+        "Restored backup, missing migrations are applied when the server starts"
+    );
+
+    Ok(())
 }
 
-/// Start the rocket server.
-///
-/// Separate this from main in order to use it in tests.
-pub async fn liftoff(database_url: &str) -> rocket::Rocket {
-    rocket::ignite()
-        .manage(
-            database::connection::Connection::connect(database_url)
-                .await
-                .expect("Should connect to database."),
-        )
-        .mount(
-            "/",
-            routes![routes::index, routes::favicon, user::user, task::task],
-        )
-        .register(catchers![routes::not_found])
+#[cfg(feature = "test-util")]
+async fn seed(config: &config::Config, seed: u64, users: usize, days: i64) -> anyhow::Result<()> {
+    let connection = connect(config).await?;
+    let fixture = database::fixture::FixtureBuilder::new(seed)
+        .users(users)
+        .days(days)
+        .until(chrono::Utc::today().naive_utc())
+        .insert(&connection)
+        .await
+        .context("Could not seed the database")?;
+
+    for user in fixture.users {
+        let instances: usize = user
+            .tasks
+            .iter()
+            .map(|(_, instances)| instances.len())
+            .sum();
+        println!(
+            "{} (id {}): {} habits, {} tasks, {} instances",
+            user.user.get_username(),
+            user.user.get_id(),
+            user.habits.len(),
+            user.tasks.len(),
+            instances
+        );
+    }
+
+    Ok(())
 }
@@ -0,0 +1,253 @@
+use chrono::Utc;
+use database::connection::Connection;
+use database::error::Result;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramTimer, HistogramVec, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::ContentType;
+use rocket::response::content::Content;
+use rocket::{Data, Request, Response, State};
+use std::time::Instant;
+
+/// Prometheus metrics for the server.
+///
+/// Cloning is cheap, every clone records into the same registry.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+
+    /// Number of http requests by route and status.
+    http_requests: IntCounterVec,
+
+    /// Http latency by route.
+    http_latency: HistogramVec,
+
+    /// Number of api requests by route and request variant.
+    api_requests: IntCounterVec,
+
+    /// Api latency by route and request variant.
+    api_latency: HistogramVec,
+
+    /// Number of database errors by kind.
+    database_errors: IntCounterVec,
+
+    pool_connections: IntGauge,
+    pool_idle_connections: IntGauge,
+
+    active_timers: IntGauge,
+    instances_today: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        let http_requests = IntCounterVec::new(
+            Opts::new("mindless_http_requests_total", "Number of http requests."),
+            &["route", "status"],
+        )
+        .expect("Valid metric.");
+
+        let http_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "mindless_http_request_duration_seconds",
+                "Latency of http requests.",
+            ),
+            &["route"],
+        )
+        .expect("Valid metric.");
+
+        let api_requests = IntCounterVec::new(
+            Opts::new(
+                "mindless_api_requests_total",
+                "Number of api requests by request variant.",
+            ),
+            &["route", "variant"],
+        )
+        .expect("Valid metric.");
+
+        let api_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "mindless_api_request_duration_seconds",
+                "Latency of api requests by request variant.",
+            ),
+            &["route", "variant"],
+        )
+        .expect("Valid metric.");
+
+        let database_errors = IntCounterVec::new(
+            Opts::new(
+                "mindless_database_errors_total",
+                "Number of database errors returned to clients.",
+            ),
+            &["kind"],
+        )
+        .expect("Valid metric.");
+
+        let pool_connections = IntGauge::new(
+            "mindless_pool_connections",
+            "Number of connections currently in the pool.",
+        )
+        .expect("Valid metric.");
+
+        let pool_idle_connections = IntGauge::new(
+            "mindless_pool_idle_connections",
+            "Number of idle connections in the pool.",
+        )
+        .expect("Valid metric.");
+
+        let active_timers = IntGauge::new(
+            "mindless_active_timers",
+            "Number of timers which are currently running.",
+        )
+        .expect("Valid metric.");
+
+        let instances_today = IntGauge::new(
+            "mindless_instances_today",
+            "Number of instances which started today (UTC).",
+        )
+        .expect("Valid metric.");
+
+        let registry = Registry::new();
+        registry
+            .register(Box::new(http_requests.clone()))
+            .expect("Registered once.");
+        registry
+            .register(Box::new(http_latency.clone()))
+            .expect("Registered once.");
+        registry
+            .register(Box::new(api_requests.clone()))
+            .expect("Registered once.");
+        registry
+            .register(Box::new(api_latency.clone()))
+            .expect("Registered once.");
+        registry
+            .register(Box::new(database_errors.clone()))
+            .expect("Registered once.");
+        registry
+            .register(Box::new(pool_connections.clone()))
+            .expect("Registered once.");
+        registry
+            .register(Box::new(pool_idle_connections.clone()))
+            .expect("Registered once.");
+        registry
+            .register(Box::new(active_timers.clone()))
+            .expect("Registered once.");
+        registry
+            .register(Box::new(instances_today.clone()))
+            .expect("Registered once.");
+
+        Metrics {
+            registry,
+            http_requests,
+            http_latency,
+            api_requests,
+            api_latency,
+            database_errors,
+            pool_connections,
+            pool_idle_connections,
+            active_timers,
+            instances_today,
+        }
+    }
+
+    /// Count an api request and time it until the returned timer is dropped.
+    pub fn api_request(&self, route: &str, variant: &str) -> HistogramTimer {
+        self.api_requests.with_label_values(&[route, variant]).inc();
+        self.api_latency
+            .with_label_values(&[route, variant])
+            .start_timer()
+    }
+
+    /// Count a database error.
+    pub fn database_error(&self, error: &database::error::Error) {
+        self.database_errors
+            .with_label_values(&[error.kind()])
+            .inc();
+    }
+
+    /// Refresh the gauges which are sampled from the database.
+    ///
+    /// Gauges keep their last value if sampling fails.
+    async fn sample(&self, connection: &Connection) -> Result<()> {
+        let pool = connection.get_pool();
+        self.pool_connections.set(pool.size() as i64);
+        self.pool_idle_connections.set(pool.num_idle() as i64);
+
+        self.active_timers
+            .set(database::stats::count_active_timers(connection).await?);
+
+        let today = Utc::today().and_hms(0, 0, 0).naive_utc();
+        self.instances_today
+            .set(database::stats::count_instances_since(&today, connection).await?);
+
+        Ok(())
+    }
+
+    /// Render all metrics in the prometheus text format.
+    fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Can always encode to a vector.");
+
+        String::from_utf8(buffer).expect("Prometheus text format is utf8.")
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+/// Time at which the metrics fairing saw the request.
+struct MetricsStart(Instant);
+
+#[rocket::async_trait]
+impl Fairing for Metrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Prometheus metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &Data) {
+        request.local_cache(|| MetricsStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        // Label by the route rather than the uri so ids in paths don't blow up the cardinality.
+        let route = request
+            .route()
+            .map(|route| route.uri.path().to_string())
+            .unwrap_or_else(|| "unmatched".to_string());
+        let status = response.status().code.to_string();
+        let start = request.local_cache(|| MetricsStart(Instant::now()));
+
+        self.http_requests
+            .with_label_values(&[&route, &status])
+            .inc();
+        self.http_latency
+            .with_label_values(&[&route])
+            .observe(start.0.elapsed().as_secs_f64());
+    }
+}
+
+/// Expose the metrics to prometheus.
+#[get("/metrics")]
+pub async fn metrics(
+    metrics: State<'_, Metrics>,
+    connection: State<'_, Connection>,
+) -> Content<String> {
+    // The process and request metrics are still worth scraping when the database is down.
+    if let Err(error) = metrics.sample(&connection).await {
+        tracing::error!(%error, "Sampling the database gauges failed");
+    }
+
+    Content(
+        ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+        metrics.render(),
+    )
+}
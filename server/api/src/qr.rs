@@ -0,0 +1,327 @@
+//! Rendering check-in links as QR codes with a caption underneath.
+use font8x8::{UnicodeFonts, BASIC_FONTS, LATIN_FONTS};
+use qrcode::types::QrError;
+use qrcode::{Color, EcLevel, QrCode};
+use std::fmt;
+
+/// Blank modules around the code, as scanners expect.
+const QUIET_ZONE: usize = 4;
+
+/// Height of the caption under the code, in modules.
+const CAPTION_HEIGHT: usize = 4;
+
+/// Glyphs of the png caption font are 8 by 8 pixels.
+const GLYPH_SIZE: usize = 8;
+
+/// Longer png captions are cut short, otherwise they would make the image as wide as they like.
+const MAX_CAPTION_CHARS: usize = 24;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The data does not fit in a QR code.
+    Qr(QrError),
+
+    Png(png::EncodingError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Qr(e) => write!(f, "Could not encode the QR code: {}", e),
+            Error::Png(e) => write!(f, "Could not encode the png: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<QrError> for Error {
+    fn from(error: QrError) -> Self {
+        Error::Qr(error)
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(error: png::EncodingError) -> Self {
+        Error::Png(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The modules of a QR code, without the quiet zone.
+#[derive(Debug, Clone)]
+pub struct Code {
+    width: usize,
+    dark: Vec<bool>,
+}
+
+impl Code {
+    pub fn new(data: &str) -> Result<Code> {
+        // Medium error correction still reads with a bit of wear on a printed code.
+        let code = QrCode::with_error_correction_level(data, EcLevel::M)?;
+
+        Ok(Code {
+            width: code.width(),
+            dark: code
+                .to_colors()
+                .into_iter()
+                .map(|color| color == Color::Dark)
+                .collect(),
+        })
+    }
+
+    /// Number of modules on each side.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.dark[y * self.width + x]
+    }
+
+    /// Number of modules on each side including the quiet zone.
+    fn size(&self) -> usize {
+        self.width + 2 * QUIET_ZONE
+    }
+}
+
+/// Escape text to put in an xml or html document.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Render a code as an svg with the caption underneath. One module is one unit.
+pub fn svg(code: &Code, caption: &str) -> String {
+    let size = code.size();
+    let height = size + CAPTION_HEIGHT;
+
+    // A single path of unit squares is much smaller than a rect per module.
+    let mut path = String::new();
+    for y in 0..code.width() {
+        for x in 0..code.width() {
+            if code.is_dark(x, y) {
+                path.push_str(&format!("M{},{}h1v1h-1z", x + QUIET_ZONE, y + QUIET_ZONE));
+            }
+        }
+    }
+
+    // Squeeze long captions to fit under the code.
+    let font_size = 2;
+    let max_width = size - 2;
+    let length = if caption.chars().count() * font_size * 3 / 5 > max_width {
+        format!(
+            r#" textLength="{}" lengthAdjust="spacingAndGlyphs""#,
+            max_width
+        )
+    } else {
+        String::new()
+    };
+
+    format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {size} {height}" "#,
+            r#"shape-rendering="crispEdges">"#,
+            r##"<rect width="{size}" height="{height}" fill="#fff"/>"##,
+            r##"<path d="{path}" fill="#000"/>"##,
+            r#"<text x="{middle}" y="{baseline}" "#,
+            r#"font-family="sans-serif" font-size="{font_size}" "#,
+            r#"text-anchor="middle"{length}>{caption}</text>"#,
+            "</svg>"
+        ),
+        size = size,
+        height = height,
+        path = path,
+        middle = size as f64 / 2.0,
+        baseline = size + CAPTION_HEIGHT / 2,
+        font_size = font_size,
+        length = length,
+        caption = escape(caption),
+    )
+}
+
+/// The bitmap of a character in the caption font, `?` when it has none.
+fn glyph(c: char) -> [u8; GLYPH_SIZE] {
+    BASIC_FONTS
+        .get(c)
+        .or_else(|| LATIN_FONTS.get(c))
+        .or_else(|| BASIC_FONTS.get('?'))
+        .unwrap_or_default()
+}
+
+/// Render a code as a grayscale png with the caption underneath, `scale` pixels per module.
+///
+/// Captions longer than `MAX_CAPTION_CHARS` end with `...`.
+pub fn png(code: &Code, caption: &str, scale: usize) -> Result<Vec<u8>> {
+    let scale = scale.max(1);
+    // A caption pixel is half a module so letters stay readable next to the code.
+    let glyph_scale = (scale / 2).max(1);
+    let glyphs: Vec<[u8; GLYPH_SIZE]> = if caption.chars().count() > MAX_CAPTION_CHARS {
+        caption
+            .chars()
+            .take(MAX_CAPTION_CHARS - 3)
+            .chain("...".chars())
+            .map(glyph)
+            .collect()
+    } else {
+        caption.chars().map(glyph).collect()
+    };
+
+    let code_width = code.size() * scale;
+    let caption_width = glyphs.len() * GLYPH_SIZE * glyph_scale + 2 * QUIET_ZONE * scale;
+    let width = code_width.max(caption_width);
+    let caption_height = (CAPTION_HEIGHT * scale).max(2 * GLYPH_SIZE * glyph_scale);
+    let height = code.size() * scale + caption_height;
+
+    let mut pixels = vec![u8::MAX; width * height];
+    let mut fill = |x: usize, y: usize, side: usize| {
+        for row in y..y + side {
+            for pixel in &mut pixels[row * width + x..row * width + x + side] {
+                *pixel = 0;
+            }
+        }
+    };
+
+    let left = (width - code_width) / 2 + QUIET_ZONE * scale;
+    let top = QUIET_ZONE * scale;
+    for y in 0..code.width() {
+        for x in 0..code.width() {
+            if code.is_dark(x, y) {
+                fill(left + x * scale, top + y * scale, scale);
+            }
+        }
+    }
+
+    let left = (width - glyphs.len() * GLYPH_SIZE * glyph_scale) / 2;
+    let top = code.size() * scale + (caption_height - GLYPH_SIZE * glyph_scale) / 2;
+    for (i, glyph) in glyphs.iter().enumerate() {
+        for (y, row) in glyph.iter().enumerate() {
+            for x in 0..GLYPH_SIZE {
+                // The lowest bit is the leftmost pixel.
+                if row & (1 << x) != 0 {
+                    fill(
+                        left + (i * GLYPH_SIZE + x) * glyph_scale,
+                        top + y * glyph_scale,
+                        glyph_scale,
+                    );
+                }
+            }
+        }
+    }
+
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&pixels)?;
+    }
+
+    Ok(bytes)
+}
+
+/// A printable html page of codes, each with its caption.
+pub fn sheet(title: &str, codes: &[(Code, String)]) -> String {
+    let figures: String = codes
+        .iter()
+        .map(|(code, caption)| format!("<figure>{}</figure>\n", svg(code, caption)))
+        .collect();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+@page {{ size: A4; margin: 10mm; }}
+body {{ margin: 0; font-family: sans-serif; }}
+main {{ display: grid; grid-template-columns: repeat(3, 1fr); gap: 8mm; }}
+figure {{ margin: 0; break-inside: avoid; }}
+svg {{ width: 100%; }}
+</style>
+</head>
+<body>
+<main>
+{figures}</main>
+</body>
+</html>
+"#,
+        title = escape(title),
+        figures = figures,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "http://localhost:8000/mindless/api/checkin/1.signature";
+
+    #[test]
+    fn svg_has_the_caption() {
+        let code = Code::new(URL).expect("Url fits.");
+        let svg = svg(&code, "Cold <Shower>");
+
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(">Cold &lt;Shower&gt;</text>"));
+        assert!(!svg.contains("textLength"));
+
+        let svg = super::svg(&code, &"Long".repeat(20));
+        assert!(svg.contains("textLength"), "Long captions are squeezed.");
+    }
+
+    #[test]
+    fn png_is_wide_enough_for_the_caption() {
+        let code = Code::new(URL).expect("Url fits.");
+        let png = png(&code, "Gym", 4).expect("Can encode.");
+
+        let decoder = png::Decoder::new(png.as_slice());
+        let (info, _) = decoder.read_info().expect("Valid png.");
+        assert_eq!(info.width as usize, code.size() * 4);
+
+        let caption = "Longer than the code";
+        let png = super::png(&code, caption, 4).expect("Can encode.");
+        let (info, _) = png::Decoder::new(png.as_slice())
+            .read_info()
+            .expect("Valid png.");
+        assert!(info.width as usize > code.size() * 4);
+        assert!(info.width as usize >= caption.len() * GLYPH_SIZE * 2);
+
+        // Captions can't make the image any wider than this.
+        let caption = "Long".repeat(100);
+        let png = super::png(&code, &caption, 4).expect("Can encode.");
+        let (info, _) = png::Decoder::new(png.as_slice())
+            .read_info()
+            .expect("Valid png.");
+        assert_eq!(
+            info.width as usize,
+            MAX_CAPTION_CHARS * GLYPH_SIZE * 2 + 2 * QUIET_ZONE * 4
+        );
+    }
+
+    #[test]
+    fn sheet_has_every_code() {
+        let codes = vec![
+            (Code::new(URL).unwrap(), "Read".to_string()),
+            (Code::new(URL).unwrap(), "Run".to_string()),
+        ];
+        let sheet = sheet("Justin & co", &codes);
+
+        assert_eq!(sheet.matches("<figure>").count(), 2);
+        assert!(sheet.contains("<title>Justin &amp; co</title>"));
+    }
+}
@@ -0,0 +1,1092 @@
+//! Reminding users of their habits.
+//!
+//! Reminders go off at a local time of day in the timezone of the user's reminder settings,
+//! optionally only when the habit isn't done yet in its current period. They are held back during
+//! the user's quiet hours. Every reminder is delivered through one of the notifiers, e.g. by email
+//! or by queueing it for the app to fetch from `GET /mindless/api/reminder/pending/<user_id>`.
+//!
+//! Reminders which could not be delivered are retried every check until they are
+//! `reminders.max_late_minutes` late.
+//!
+//! Weekly digests go out through the same notifiers, for those which can deliver them.
+use chrono::{DateTime, Duration, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use database::habit::Habit;
+use database::reminder::{PendingReminder, Reminder, ReminderSettings};
+use database::store::Store;
+use mindless_core::reminder::{due, QuietHours};
+use rocket::State;
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tracing::instrument;
+
+use crate::config::{ReminderConfig, SmtpConfig};
+use crate::digest::{self, Digest};
+use crate::error::{Error, Result};
+use crate::logging::RequestId;
+use crate::metrics::Metrics;
+use crate::schedule::{self, Clock};
+use crate::webhook::{Dispatcher, Event};
+
+/// Give up on talking to the mail server after this long.
+const SMTP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// A reminder which went off.
+#[derive(Debug, Clone)]
+pub struct Due {
+    pub user_id: i64,
+
+    pub habit: Habit,
+
+    /// Its `last_due_at` is when it went off.
+    pub reminder: Reminder,
+
+    pub settings: ReminderSettings,
+}
+
+/// A way of delivering reminders, chosen per reminder by its channel.
+#[rocket::async_trait]
+pub trait Notifier: Send + Sync {
+    /// Name of the channel reminders choose this notifier by.
+    fn channel(&self) -> &'static str;
+
+    /// Deliver a reminder. Failing reminders are retried on the next check.
+    async fn notify(&self, due: &Due) -> anyhow::Result<()>;
+
+    /// Whether users can get their weekly digest through this notifier.
+    fn delivers_digests(&self) -> bool {
+        false
+    }
+
+    /// Deliver a weekly digest. Failing digests are retried on the next check.
+    async fn send_digest(
+        &self,
+        _settings: &ReminderSettings,
+        _digest: &Digest,
+    ) -> anyhow::Result<()> {
+        anyhow::bail!("The {} channel does not deliver digests", self.channel())
+    }
+}
+
+/// Queues reminders for the app to fetch.
+pub struct PollNotifier {
+    store: Arc<dyn Store>,
+}
+
+impl PollNotifier {
+    pub fn new(store: Arc<dyn Store>) -> PollNotifier {
+        PollNotifier { store }
+    }
+}
+
+#[rocket::async_trait]
+impl Notifier for PollNotifier {
+    fn channel(&self) -> &'static str {
+        "poll"
+    }
+
+    async fn notify(&self, due: &Due) -> anyhow::Result<()> {
+        let due_at = due
+            .reminder
+            .get_last_due_at()
+            .expect("Due reminders have gone off.");
+
+        match self
+            .store
+            .insert_pending_reminder(&due.reminder, due_at)
+            .await
+        {
+            // Queued by an earlier check which failed to record it.
+            Ok(_) | Err(database::error::Error::AlreadyExists) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Posts reminders and digests to the user's webhooks as `ReminderDue` and `WeeklyDigest` events.
+///
+/// Fails when none of the user's webhooks subscribe to the event, so it isn't recorded as sent.
+pub struct WebhookNotifier {
+    dispatcher: Dispatcher,
+}
+
+impl WebhookNotifier {
+    pub fn new(dispatcher: Dispatcher) -> WebhookNotifier {
+        WebhookNotifier { dispatcher }
+    }
+}
+
+#[rocket::async_trait]
+impl Notifier for WebhookNotifier {
+    fn channel(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn notify(&self, due: &Due) -> anyhow::Result<()> {
+        let event = Event::ReminderDue {
+            habit: due.habit.clone(),
+            reminder: due.reminder.clone(),
+        };
+        if self.dispatcher.emit(due.user_id, event).await == 0 {
+            anyhow::bail!("No webhook subscribes to ReminderDue");
+        }
+
+        Ok(())
+    }
+
+    fn delivers_digests(&self) -> bool {
+        true
+    }
+
+    async fn send_digest(
+        &self,
+        settings: &ReminderSettings,
+        digest: &Digest,
+    ) -> anyhow::Result<()> {
+        let event = Event::WeeklyDigest {
+            digest: digest.clone(),
+        };
+        if self.dispatcher.emit(settings.get_user_id(), event).await == 0 {
+            anyhow::bail!("No webhook subscribes to WeeklyDigest");
+        }
+
+        Ok(())
+    }
+}
+
+/// Emails reminders and digests to the address in the user's settings.
+pub struct EmailNotifier {
+    config: SmtpConfig,
+}
+
+impl EmailNotifier {
+    pub fn new(config: SmtpConfig) -> EmailNotifier {
+        EmailNotifier { config }
+    }
+
+    /// Send a plain text email to the address in the settings.
+    async fn send(
+        &self,
+        settings: &ReminderSettings,
+        subject: &str,
+        body: &str,
+        date: &DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let to = match settings.get_email() {
+            Some(to) => to,
+            None => anyhow::bail!("No email address to send to"),
+        };
+        // Names of habits end up in the subject header.
+        let subject = subject.replace(|c: char| c == '\r' || c == '\n', " ");
+
+        let message = format!(
+            "From: {}\r\nTo: {}\r\nDate: {}\r\nSubject: {}\r\n\r\n{}\r\n",
+            self.config.from,
+            to,
+            date.to_rfc2822(),
+            subject,
+            body
+        );
+
+        tokio::time::timeout(SMTP_TIMEOUT, send_mail(&self.config, to, &message))
+            .await
+            .map_err(|_| anyhow::anyhow!("The mail server timed out"))?
+    }
+}
+
+#[rocket::async_trait]
+impl Notifier for EmailNotifier {
+    fn channel(&self) -> &'static str {
+        "email"
+    }
+
+    async fn notify(&self, due: &Due) -> anyhow::Result<()> {
+        let due_at = due
+            .reminder
+            .get_last_due_at()
+            .expect("Due reminders have gone off.");
+        let name = due.habit.get_name();
+
+        self.send(
+            &due.settings,
+            &format!("Reminder: {}", name),
+            &format!("Time for {}.", name),
+            &DateTime::<Utc>::from_utc(*due_at, Utc),
+        )
+        .await
+    }
+
+    fn delivers_digests(&self) -> bool {
+        true
+    }
+
+    async fn send_digest(
+        &self,
+        settings: &ReminderSettings,
+        digest: &Digest,
+    ) -> anyhow::Result<()> {
+        self.send(settings, &digest.title(), &digest.markdown(), &Utc::now())
+            .await
+    }
+}
+
+/// Relay a message through the mail server.
+async fn send_mail(config: &SmtpConfig, to: &str, message: &str) -> anyhow::Result<()> {
+    let stream = TcpStream::connect((config.host.as_str(), config.port)).await?;
+    let mut stream = BufReader::new(stream);
+
+    expect(&mut stream, 2, "connecting").await?;
+    command(&mut stream, "HELO mindless", 2).await?;
+    command(&mut stream, &format!("MAIL FROM:<{}>", config.from), 2).await?;
+    command(&mut stream, &format!("RCPT TO:<{}>", to), 2).await?;
+    command(&mut stream, "DATA", 3).await?;
+
+    // Lines starting with a dot are escaped by doubling it, a single dot ends the message.
+    let mut data = String::new();
+    for line in message.lines() {
+        if line.starts_with('.') {
+            data.push('.');
+        }
+        data.push_str(line);
+        data.push_str("\r\n");
+    }
+    data.push_str(".\r\n");
+    stream.write_all(data.as_bytes()).await?;
+    expect(&mut stream, 2, "the message").await?;
+    command(&mut stream, "QUIT", 2).await?;
+
+    Ok(())
+}
+
+/// Send a line and check the reply is of the expected class, e.g. 2 for `250 OK`.
+async fn command(stream: &mut BufReader<TcpStream>, line: &str, class: u16) -> anyhow::Result<()> {
+    stream.write_all(line.as_bytes()).await?;
+    stream.write_all(b"\r\n").await?;
+
+    let name = line
+        .split(|c: char| c == ' ' || c == ':')
+        .next()
+        .unwrap_or(line);
+    expect(stream, class, name).await
+}
+
+async fn expect(stream: &mut BufReader<TcpStream>, class: u16, after: &str) -> anyhow::Result<()> {
+    let mut reply = String::new();
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            anyhow::bail!("The mail server closed the connection after {}", after);
+        }
+        reply.push_str(&line);
+
+        // Replies continue over lines with a dash after the code, e.g. `250-SIZE 1000`.
+        if line.as_bytes().get(3) != Some(&b'-') {
+            break;
+        }
+    }
+
+    match reply.get(..3).and_then(|code| code.parse::<u16>().ok()) {
+        Some(code) if code / 100 == class => Ok(()),
+        _ => anyhow::bail!(
+            "The mail server replied to {} with {}",
+            after,
+            reply.trim_end()
+        ),
+    }
+}
+
+struct Inner {
+    store: Arc<dyn Store>,
+    notifiers: Vec<Box<dyn Notifier>>,
+    max_late: Duration,
+}
+
+/// Delivers reminders once they are due.
+#[derive(Clone)]
+pub struct Engine {
+    inner: Arc<Inner>,
+}
+
+impl Engine {
+    pub fn new(
+        store: Arc<dyn Store>,
+        notifiers: Vec<Box<dyn Notifier>>,
+        config: &ReminderConfig,
+    ) -> Engine {
+        Engine {
+            inner: Arc::new(Inner {
+                store,
+                notifiers,
+                max_late: Duration::minutes(config.max_late_minutes),
+            }),
+        }
+    }
+
+    /// The channels reminders can be delivered through.
+    pub fn channels(&self) -> Vec<&'static str> {
+        self.inner
+            .notifiers
+            .iter()
+            .map(|notifier| notifier.channel())
+            .collect()
+    }
+
+    /// The channels weekly digests can be delivered through.
+    pub fn digest_channels(&self) -> Vec<&'static str> {
+        self.inner
+            .notifiers
+            .iter()
+            .filter(|notifier| notifier.delivers_digests())
+            .map(|notifier| notifier.channel())
+            .collect()
+    }
+
+    /// Deliver a weekly digest through the user's digest channel.
+    pub async fn send_digest(
+        &self,
+        settings: &ReminderSettings,
+        digest: &Digest,
+    ) -> anyhow::Result<()> {
+        let channel = match settings.get_digest_channel() {
+            Some(channel) => channel,
+            None => anyhow::bail!("The user doesn't want digests"),
+        };
+
+        match self
+            .inner
+            .notifiers
+            .iter()
+            .find(|notifier| notifier.delivers_digests() && notifier.channel() == channel)
+        {
+            Some(notifier) => notifier.send_digest(settings, digest).await,
+            None => anyhow::bail!("The {} digest channel is disabled", channel),
+        }
+    }
+
+    /// Deliver every reminder which went off since it was last due, returning how many were
+    /// delivered.
+    ///
+    /// Reminders which only go off when a habit isn't done are recorded as due without being
+    /// delivered when it is. A reminder which can't be checked is logged and retried next run so
+    /// it doesn't hold back the others.
+    pub async fn run(&self, now: &NaiveDateTime) -> Result<usize> {
+        let mut delivered = 0;
+
+        for reminder in self.inner.store.get_all_reminders().await? {
+            let reminder_id = reminder.get_id();
+            match self.run_reminder(reminder, now).await {
+                Ok(true) => delivered += 1,
+                Ok(false) => {}
+                Err(e) => tracing::error!(?e, reminder_id, "Checking reminder failed"),
+            }
+        }
+
+        Ok(delivered)
+    }
+
+    /// Deliver a reminder if it went off since it was last due, returning whether it was.
+    async fn run_reminder(&self, mut reminder: Reminder, now: &NaiveDateTime) -> Result<bool> {
+        let store = self.inner.store.as_ref();
+        let earliest = *now - self.inner.max_late;
+        let since = *reminder
+            .get_last_due_at()
+            .unwrap_or(reminder.get_created_at())
+            .max(&earliest);
+
+        let habit = store.retrieve_habit(reminder.get_habit_id()).await?;
+        let settings = store
+            .retrieve_reminder_settings(habit.get_user_id())
+            .await?;
+        // Timezones are checked when the settings are updated.
+        let timezone: Tz = match settings.get_timezone().parse() {
+            Ok(timezone) => timezone,
+            Err(e) => {
+                tracing::error!(%e, user_id = habit.get_user_id(), "Invalid timezone");
+                return Ok(false);
+            }
+        };
+
+        let quiet = settings.get_quiet_hours();
+        let due_at = match due(
+            &reminder.get_time_of_day(),
+            quiet.as_ref(),
+            &timezone,
+            &since,
+            now,
+        ) {
+            Some(due_at) => due_at,
+            None => return Ok(false),
+        };
+        reminder.set_last_due_at(due_at);
+
+        let done = reminder.get_unless_done()
+            && !store
+                .get_completions(habit.get_id(), &habit.get_period(&due_at)?)
+                .await?
+                .is_empty();
+        let mut delivered = false;
+        if !done {
+            let notifier = match self
+                .inner
+                .notifiers
+                .iter()
+                .find(|notifier| notifier.channel() == reminder.get_channel())
+            {
+                Some(notifier) => notifier,
+                None => {
+                    tracing::warn!(
+                        reminder_id = reminder.get_id(),
+                        channel = reminder.get_channel(),
+                        "Reminder channel is disabled"
+                    );
+                    return Ok(false);
+                }
+            };
+
+            let due = Due {
+                user_id: habit.get_user_id(),
+                habit,
+                reminder: reminder.clone(),
+                settings,
+            };
+            if let Err(e) = notifier.notify(&due).await {
+                tracing::warn!(
+                    ?e,
+                    reminder_id = reminder.get_id(),
+                    "Delivering reminder failed"
+                );
+                return Ok(false);
+            }
+            delivered = true;
+        }
+
+        store.update_reminder_last_due_at(&reminder).await?;
+
+        Ok(delivered)
+    }
+}
+
+/// Deliver reminders periodically while the server is running.
+pub fn schedule(engine: Engine, config: ReminderConfig, clock: Arc<dyn Clock>) {
+    let period = std::time::Duration::from_secs(config.check_secs);
+
+    schedule::every("reminders", period, clock, move |now| {
+        let engine = engine.clone();
+
+        async move {
+            match engine.run(&now.naive_utc()).await {
+                Ok(delivered) => tracing::debug!(delivered, "Delivered reminders"),
+                Err(e) => tracing::error!(?e, "Delivering reminders failed"),
+            }
+        }
+    });
+}
+
+/// Parse a local time of day such as `08:30`.
+fn parse_time(time: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|_| Error::BadRequest(format!("\"{}\" is not a time like 08:30", time)))
+}
+
+// Type of events that you can execute on reminders.
+#[derive(Deserialize, Debug)]
+pub enum Request {
+    // Remind of a habit every day at a local time such as `08:30`, through one of the channels.
+    // With `unless_done` the reminder only goes off if the habit isn't done in its period yet.
+    Create {
+        user_id: i64,
+        habit_id: i64,
+        time_of_day: String,
+        #[serde(default)]
+        unless_done: bool,
+        channel: String,
+    },
+
+    // Retrieve all the reminders of a user.
+    RetrieveAll {
+        user_id: i64,
+    },
+
+    // Delete a reminder along with its pending deliveries.
+    Delete {
+        user_id: i64,
+        id: i64,
+    },
+
+    // Retrieve the timezone, email address, quiet hours and digest channel of a user's reminders.
+    Settings {
+        user_id: i64,
+    },
+
+    // Replace the settings. Quiet hours are set with both a start and an end, e.g. `22:00` to
+    // `07:00`, or neither. The weekly digest is sent through `digest_channel` if set.
+    UpdateSettings {
+        user_id: i64,
+        timezone: String,
+        email: Option<String>,
+        quiet_start: Option<String>,
+        quiet_end: Option<String>,
+        digest_channel: Option<String>,
+    },
+
+    // Remove a pending reminder the app has shown.
+    Acknowledge {
+        user_id: i64,
+        id: i64,
+    },
+}
+
+impl Request {
+    /// Name of the request variant. This is safe to log since it holds no user data.
+    pub fn variant(&self) -> &'static str {
+        match self {
+            Request::Create { .. } => "Create",
+            Request::RetrieveAll { .. } => "RetrieveAll",
+            Request::Delete { .. } => "Delete",
+            Request::Settings { .. } => "Settings",
+            Request::UpdateSettings { .. } => "UpdateSettings",
+            Request::Acknowledge { .. } => "Acknowledge",
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub enum Response {
+    Create { reminder: Reminder },
+
+    RetrieveAll { reminders: Vec<Reminder> },
+
+    Delete { reminder: Reminder },
+
+    Settings { settings: ReminderSettings },
+
+    UpdateSettings { settings: ReminderSettings },
+
+    Acknowledge { pending: PendingReminder },
+}
+
+/// Retrieve a habit making sure it belongs to the user.
+async fn user_habit(user_id: i64, habit_id: i64, store: &dyn Store) -> Result<Habit> {
+    let habit = store.retrieve_habit(habit_id).await?;
+    if habit.get_user_id() != user_id {
+        return Err(database::error::Error::NotFound.into());
+    }
+
+    Ok(habit)
+}
+
+/// Retrieve a reminder making sure it belongs to the user.
+async fn user_reminder(user_id: i64, id: i64, store: &dyn Store) -> Result<Reminder> {
+    let reminder = store.retrieve_reminder(id).await?;
+    user_habit(user_id, reminder.get_habit_id(), store).await?;
+
+    Ok(reminder)
+}
+
+// Handle setting up reminders.
+#[post("/mindless/api/reminder", data = "<request>")]
+#[instrument(
+    name = "reminder",
+    skip(store, clock, engine, metrics, request),
+    fields(%request_id, request = request.variant())
+)]
+pub async fn reminder(
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    engine: State<'_, Engine>,
+    metrics: State<'_, Metrics>,
+    request: Json<Request>,
+    request_id: RequestId,
+) -> Result<Json<Response>> {
+    let _timer = metrics.api_request("reminder", request.variant());
+    let store = store.inner().as_ref();
+
+    let response = match request.into_inner() {
+        Request::Create {
+            user_id,
+            habit_id,
+            time_of_day,
+            unless_done,
+            channel,
+        } => {
+            let time_of_day = parse_time(&time_of_day)?;
+            let channels = engine.channels();
+            if !channels.contains(&channel.as_str()) {
+                return Err(Error::BadRequest(format!(
+                    "\"{}\" is not one of {}",
+                    channel,
+                    channels.join(", ")
+                )));
+            }
+
+            let habit = user_habit(user_id, habit_id, store).await?;
+            if channel == "email"
+                && store
+                    .retrieve_reminder_settings(user_id)
+                    .await?
+                    .get_email()
+                    .is_none()
+            {
+                return Err(Error::BadRequest(
+                    "Set an email address with UpdateSettings first".to_string(),
+                ));
+            }
+
+            let reminder = store
+                .insert_reminder(
+                    habit.get_id(),
+                    &time_of_day,
+                    unless_done,
+                    &channel,
+                    &clock.now().naive_utc(),
+                )
+                .await?;
+
+            Response::Create { reminder }
+        }
+
+        Request::RetrieveAll { user_id } => {
+            let user = store.retrieve_user(user_id).await?;
+            let reminders = store.get_reminders(user.get_id()).await?;
+
+            Response::RetrieveAll { reminders }
+        }
+
+        Request::Delete { user_id, id } => {
+            let reminder = user_reminder(user_id, id, store).await?;
+            store.delete_reminder(reminder.clone()).await?;
+
+            Response::Delete { reminder }
+        }
+
+        Request::Settings { user_id } => {
+            let user = store.retrieve_user(user_id).await?;
+            let settings = store.retrieve_reminder_settings(user.get_id()).await?;
+
+            Response::Settings { settings }
+        }
+
+        Request::UpdateSettings {
+            user_id,
+            timezone,
+            email,
+            quiet_start,
+            quiet_end,
+            digest_channel,
+        } => {
+            if timezone.parse::<Tz>().is_err() {
+                return Err(Error::BadRequest(format!(
+                    "\"{}\" is not a timezone like Australia/Sydney",
+                    timezone
+                )));
+            }
+            if let Some(email) = &email {
+                // It ends up in the headers of emails.
+                if !email.contains('@') || email.contains(|c: char| c.is_whitespace() || c == '>') {
+                    return Err(Error::BadRequest(format!(
+                        "\"{}\" is not an email address",
+                        email
+                    )));
+                }
+            }
+            let quiet_hours = match (quiet_start, quiet_end) {
+                (Some(start), Some(end)) => {
+                    Some(QuietHours::new(parse_time(&start)?, parse_time(&end)?))
+                }
+                (None, None) => None,
+                _ => {
+                    return Err(Error::BadRequest(
+                        "Quiet hours need both a start and an end".to_string(),
+                    ))
+                }
+            };
+
+            if let Some(channel) = &digest_channel {
+                let channels = engine.digest_channels();
+                if !channels.contains(&channel.as_str()) {
+                    return Err(Error::BadRequest(format!(
+                        "\"{}\" is not one of {}",
+                        channel,
+                        channels.join(", ")
+                    )));
+                }
+                if channel == "email" && email.is_none() {
+                    return Err(Error::BadRequest(
+                        "Digests by email need an email address".to_string(),
+                    ));
+                }
+            }
+
+            let user = store.retrieve_user(user_id).await?;
+            let previous = store.retrieve_reminder_settings(user.get_id()).await?;
+            let mut settings = ReminderSettings::new(user.get_id());
+            settings.set_timezone(timezone);
+            settings.set_email(email);
+            settings.set_quiet_hours(quiet_hours);
+            settings.set_digest_channel(digest_channel);
+            store.update_reminder_settings(&settings).await?;
+
+            // A new digest channel starts with the current week rather than catching up on the
+            // weeks before it.
+            if settings.get_digest_channel().is_some()
+                && settings.get_digest_channel() != previous.get_digest_channel()
+            {
+                let timezone = digest::timezone(&settings).map_err(Error::BadRequest)?;
+                let week = digest::last_full_week(&clock.now().naive_utc(), &timezone);
+                store.update_digest_week(user.get_id(), &week).await?;
+            }
+
+            Response::UpdateSettings { settings }
+        }
+
+        Request::Acknowledge { user_id, id } => {
+            let pending = store.retrieve_pending_reminder(id).await?;
+            user_habit(user_id, pending.get_habit_id(), store).await?;
+            store.delete_pending_reminder(pending.clone()).await?;
+
+            Response::Acknowledge { pending }
+        }
+    };
+
+    tracing::debug!("Handled request");
+
+    Ok(Json(response))
+}
+
+// Reminders queued for the app, oldest first. They stay until they are acknowledged.
+#[get("/mindless/api/reminder/pending/<user_id>")]
+#[instrument(name = "reminder_pending", skip(store, metrics), fields(%request_id))]
+pub async fn pending(
+    user_id: i64,
+    store: State<'_, Arc<dyn Store>>,
+    metrics: State<'_, Metrics>,
+    request_id: RequestId,
+) -> Result<Json<Vec<PendingReminder>>> {
+    let _timer = metrics.api_request("reminder", "Pending");
+
+    let user = store.retrieve_user(user_id).await?;
+    let pending = store.get_pending_reminders(user.get_id()).await?;
+
+    Ok(Json(pending))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use database::memory::MemoryStore;
+    use std::sync::Mutex;
+    use tokio::net::TcpListener;
+
+    fn time(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2020, 10, 19).and_hms(hour, minute, 0)
+    }
+
+    /// Records reminders, failing while `fail` is set.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        delivered: Arc<Mutex<Vec<NaiveDateTime>>>,
+        fail: Arc<Mutex<bool>>,
+    }
+
+    #[rocket::async_trait]
+    impl Notifier for Recorder {
+        fn channel(&self) -> &'static str {
+            "test"
+        }
+
+        async fn notify(&self, due: &Due) -> anyhow::Result<()> {
+            if *self.fail.lock().unwrap() {
+                anyhow::bail!("Unreachable");
+            }
+
+            let due_at = *due.reminder.get_last_due_at().expect("Gone off.");
+            self.delivered.lock().unwrap().push(due_at);
+
+            Ok(())
+        }
+    }
+
+    async fn engine(store: &Arc<dyn Store>, recorder: &Recorder) -> (Engine, Habit) {
+        let user = store
+            .insert_user("username", "name")
+            .await
+            .expect("Should successfully insert.");
+        let habit = store
+            .insert_habit(user.get_id(), None, "Stretch", &time(0, 0), Some(86400))
+            .await
+            .expect("Should successfully insert.");
+
+        let engine = Engine::new(
+            store.clone(),
+            vec![Box::new(recorder.clone())],
+            &ReminderConfig::default(),
+        );
+
+        (engine, habit)
+    }
+
+    #[tokio::test]
+    async fn reminders_go_off_once() {
+        let store: Arc<dyn Store> = Arc::new(MemoryStore::default());
+        let recorder = Recorder::default();
+        let (engine, habit) = engine(&store, &recorder).await;
+        store
+            .insert_reminder(
+                habit.get_id(),
+                &NaiveTime::from_hms(9, 0, 0),
+                false,
+                "test",
+                &time(0, 0),
+            )
+            .await
+            .expect("Should successfully insert.");
+
+        assert_eq!(engine.run(&time(8, 59)).await.expect("Can run."), 0);
+        assert_eq!(engine.run(&time(9, 0)).await.expect("Can run."), 1);
+        assert_eq!(engine.run(&time(9, 1)).await.expect("Can run."), 0);
+        assert_eq!(*recorder.delivered.lock().unwrap(), vec![time(9, 0)]);
+    }
+
+    #[tokio::test]
+    async fn failed_reminders_are_retried_until_late() {
+        let store: Arc<dyn Store> = Arc::new(MemoryStore::default());
+        let recorder = Recorder::default();
+        let (engine, habit) = engine(&store, &recorder).await;
+        for hour in &[9, 10] {
+            store
+                .insert_reminder(
+                    habit.get_id(),
+                    &NaiveTime::from_hms(*hour, 0, 0),
+                    false,
+                    "test",
+                    &time(0, 0),
+                )
+                .await
+                .expect("Should successfully insert.");
+        }
+
+        *recorder.fail.lock().unwrap() = true;
+        assert_eq!(engine.run(&time(10, 0)).await.expect("Can run."), 0);
+
+        // The 09:00 reminder is more than an hour late by now.
+        *recorder.fail.lock().unwrap() = false;
+        assert_eq!(engine.run(&time(10, 30)).await.expect("Can run."), 1);
+        assert_eq!(*recorder.delivered.lock().unwrap(), vec![time(10, 0)]);
+    }
+
+    #[tokio::test]
+    async fn broken_reminders_do_not_hold_back_others() {
+        let store: Arc<dyn Store> = Arc::new(MemoryStore::default());
+        let recorder = Recorder::default();
+        let (engine, habit) = engine(&store, &recorder).await;
+        let nine = NaiveTime::from_hms(9, 0, 0);
+        // Its habit is gone.
+        store
+            .insert_reminder(habit.get_id() + 100, &nine, false, "test", &time(0, 0))
+            .await
+            .expect("Should successfully insert.");
+        store
+            .insert_reminder(habit.get_id(), &nine, false, "test", &time(0, 0))
+            .await
+            .expect("Should successfully insert.");
+
+        assert_eq!(engine.run(&time(9, 30)).await.expect("Can run."), 1);
+        assert_eq!(*recorder.delivered.lock().unwrap(), vec![time(9, 0)]);
+    }
+
+    #[tokio::test]
+    async fn webhook_reminders_need_a_subscriber() {
+        let notifier = WebhookNotifier::new(Dispatcher::disabled());
+        let mut reminder = Reminder::new(
+            1,
+            2,
+            &NaiveTime::from_hms(9, 0, 0),
+            false,
+            "webhook".to_string(),
+            time(0, 0),
+        );
+        reminder.set_last_due_at(time(9, 0));
+        let due = Due {
+            user_id: 3,
+            habit: Habit::new(2, None, 3, "Stretch".to_string(), time(0, 0), None),
+            reminder,
+            settings: ReminderSettings::new(3),
+        };
+
+        assert!(notifier.notify(&due).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reminders_unless_done() {
+        let store: Arc<dyn Store> = Arc::new(MemoryStore::default());
+        let recorder = Recorder::default();
+        let (engine, habit) = engine(&store, &recorder).await;
+        let reminder = store
+            .insert_reminder(
+                habit.get_id(),
+                &NaiveTime::from_hms(20, 0, 0),
+                true,
+                "test",
+                &time(0, 0),
+            )
+            .await
+            .expect("Should successfully insert.");
+
+        store
+            .mark_habit(&habit, &time(12, 0))
+            .await
+            .expect("Can mark.");
+        assert_eq!(engine.run(&time(20, 0)).await.expect("Can run."), 0);
+        assert!(recorder.delivered.lock().unwrap().is_empty());
+
+        // Still recorded as due so it isn't checked again.
+        let reminder = store
+            .retrieve_reminder(reminder.get_id())
+            .await
+            .expect("Exists.");
+        assert_eq!(reminder.get_last_due_at(), Some(&time(20, 0)));
+    }
+
+    #[tokio::test]
+    async fn reminders_held_back_in_quiet_hours() {
+        let store: Arc<dyn Store> = Arc::new(MemoryStore::default());
+        let recorder = Recorder::default();
+        let (engine, habit) = engine(&store, &recorder).await;
+        store
+            .insert_reminder(
+                habit.get_id(),
+                &NaiveTime::from_hms(6, 0, 0),
+                false,
+                "test",
+                &time(0, 0),
+            )
+            .await
+            .expect("Should successfully insert.");
+
+        // Sydney is 11 hours ahead of UTC in October, 06:00 to 08:00 there is 19:00 to 21:00 UTC.
+        let mut settings = ReminderSettings::new(habit.get_user_id());
+        settings.set_timezone("Australia/Sydney".to_string());
+        settings.set_quiet_hours(Some(QuietHours::new(
+            NaiveTime::from_hms(22, 0, 0),
+            NaiveTime::from_hms(8, 0, 0),
+        )));
+        store
+            .update_reminder_settings(&settings)
+            .await
+            .expect("Can update.");
+
+        assert_eq!(engine.run(&time(20, 0)).await.expect("Can run."), 0);
+        assert_eq!(engine.run(&time(21, 0)).await.expect("Can run."), 1);
+        assert_eq!(*recorder.delivered.lock().unwrap(), vec![time(21, 0)]);
+    }
+
+    #[tokio::test]
+    async fn polled_reminders_are_queued_once() {
+        let store: Arc<dyn Store> = Arc::new(MemoryStore::default());
+        let (_, habit) = engine(&store, &Recorder::default()).await;
+        let mut reminder = store
+            .insert_reminder(
+                habit.get_id(),
+                &NaiveTime::from_hms(9, 0, 0),
+                false,
+                "poll",
+                &time(0, 0),
+            )
+            .await
+            .expect("Should successfully insert.");
+        reminder.set_last_due_at(time(9, 0));
+
+        let due = Due {
+            user_id: habit.get_user_id(),
+            habit: habit.clone(),
+            reminder,
+            settings: ReminderSettings::new(habit.get_user_id()),
+        };
+        let notifier = PollNotifier::new(store.clone());
+        notifier.notify(&due).await.expect("Can queue.");
+        notifier.notify(&due).await.expect("Already queued.");
+
+        let pending = store
+            .get_pending_reminders(habit.get_user_id())
+            .await
+            .expect("Can list.");
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].get_due_at(), &time(9, 0));
+    }
+
+    #[tokio::test]
+    async fn emails_are_relayed() {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.expect("Can bind.");
+        let port = listener.local_addr().expect("Bound.").port();
+
+        // A mail server accepting everything.
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.expect("Connected.");
+            let (read, mut write) = tokio::io::split(socket);
+            let mut lines = BufReader::new(read).lines();
+            let mut received = Vec::new();
+            let mut data = false;
+
+            write.write_all(b"220 ready\r\n").await.unwrap();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                received.push(line.clone());
+                let reply: &[u8] = match line.as_str() {
+                    "." if data => {
+                        data = false;
+                        b"250 queued\r\n"
+                    }
+                    _ if data => continue,
+                    "DATA" => {
+                        data = true;
+                        b"354 go ahead\r\n"
+                    }
+                    "QUIT" => b"221 bye\r\n",
+                    _ => b"250-hello\r\n250 ok\r\n",
+                };
+                write.write_all(reply).await.unwrap();
+            }
+
+            received
+        });
+
+        let config = SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            from: "mindless@example.com".to_string(),
+        };
+        let mut settings = ReminderSettings::new(1);
+        settings.set_email(Some("user@example.com".to_string()));
+        let mut reminder = Reminder::new(
+            1,
+            2,
+            &NaiveTime::from_hms(9, 0, 0),
+            false,
+            "email".to_string(),
+            time(0, 0),
+        );
+        reminder.set_last_due_at(time(9, 0));
+        let due = Due {
+            user_id: 1,
+            habit: Habit::new(2, None, 1, ".Stretch".to_string(), time(0, 0), None),
+            reminder,
+            settings,
+        };
+
+        EmailNotifier::new(config)
+            .notify(&due)
+            .await
+            .expect("Can send.");
+
+        let received = server.await.expect("Server finished.");
+        assert_eq!(received[0], "HELO mindless");
+        assert_eq!(received[1], "MAIL FROM:<mindless@example.com>");
+        assert_eq!(received[2], "RCPT TO:<user@example.com>");
+        assert!(received.contains(&"Subject: Reminder: .Stretch".to_string()));
+        assert!(received.contains(&"Time for .Stretch.".to_string()));
+        assert_eq!(received.last().map(String::as_str), Some("QUIT"));
+    }
+}
@@ -15,12 +15,11 @@ pub async fn index() -> &'static str {
 }
 
 /// Default and only favicon.
+///
+/// Responds with 404 if the favicon is missing.
 #[get("/mindless/favicon.ico")]
-pub async fn favicon() -> NamedFile {
-    // TODO: Don't assume this always exists.
-    NamedFile::open("static/favicon.ico")
-        .await
-        .expect("does not error")
+pub async fn favicon() -> Option<NamedFile> {
+    NamedFile::open("static/favicon.ico").await.ok()
 }
 
 #[catch(404)]
@@ -0,0 +1,43 @@
+//! Running jobs periodically in the background.
+use chrono::{DateTime, Utc};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Source of the current time.
+///
+/// Jobs take the time from a clock rather than calling `Utc::now` so tests can control it.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The real time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Run `job` every `period`, starting one period from now, until the server stops.
+///
+/// The job is handed the clock so it knows what time it is running at.
+pub fn every<F, Fut>(name: &'static str, period: Duration, clock: Arc<dyn Clock>, job: F)
+where
+    F: Fn(DateTime<Utc>) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    tracing::info!(job = name, ?period, "Scheduling job");
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+
+        loop {
+            interval.tick().await;
+
+            tracing::debug!(job = name, "Running job");
+            job(clock.now()).await;
+        }
+    });
+}
@@ -0,0 +1,78 @@
+//! Signing values handed to clients with `auth.secret` so they can't be forged.
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use std::fmt;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signs messages with HMAC-SHA256.
+#[derive(Clone)]
+pub struct Signer {
+    secret: Vec<u8>,
+}
+
+impl Signer {
+    pub fn new(secret: &str) -> Signer {
+        Signer {
+            secret: secret.as_bytes().to_vec(),
+        }
+    }
+
+    fn mac(&self, message: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_varkey(&self.secret).expect("HMAC takes keys of any size.");
+        mac.update(message);
+        mac
+    }
+
+    /// The signature of `message` as url safe base64, e.g. for use in a path.
+    pub fn sign(&self, message: &[u8]) -> String {
+        base64::encode_config(
+            self.mac(message).finalize().into_bytes(),
+            base64::URL_SAFE_NO_PAD,
+        )
+    }
+
+    /// Whether `signature` is the signature of `message`, compared in constant time.
+    pub fn verify(&self, message: &[u8], signature: &str) -> bool {
+        match base64::decode_config(signature, base64::URL_SAFE_NO_PAD) {
+            Ok(signature) => self.mac(message).verify(&signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Never print the secret.
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Signer")
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn sign_and_verify() {
+        let signer = Signer::new(SECRET);
+        let signature = signer.sign(b"message");
+
+        assert!(signer.verify(b"message", &signature));
+        assert!(!signer.verify(b"other message", &signature));
+        assert!(!signer.verify(b"message", "not base64!"));
+        assert!(!Signer::new("another secret at least 32 chars").verify(b"message", &signature));
+        // Safe to put in a url.
+        assert!(signature
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+    }
+
+    #[test]
+    fn debug_hides_the_secret() {
+        assert!(!format!("{:?}", Signer::new(SECRET)).contains(SECRET));
+    }
+}
@@ -0,0 +1,416 @@
+//! Tags which cut across tasks, e.g. `billable` or `outdoor`, and filtering by them.
+//!
+//! A tag on a task applies to all of its instances. A tag on an instance applies to that instance
+//! only, e.g. one session of a task which was billable.
+use database::error::Error as DBError;
+use database::instance::Instance;
+use database::store::Store;
+use database::tag::Tag;
+use database::task::Task;
+use mindless_core::tag::TagName;
+use rocket::State;
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::instrument;
+
+use crate::error::{Error, Result};
+use crate::logging::RequestId;
+use crate::metrics::Metrics;
+use crate::task::{user_instance, user_task};
+
+// Type of events that you can execute on tags.
+#[derive(Deserialize, Debug)]
+pub enum Request {
+    // Create a tag. Names are lowercased, e.g. `Billable` is `billable`.
+    Create {
+        user_id: i64,
+        name: TagName,
+    },
+
+    // Retrieve all the tags of a user.
+    RetrieveAll {
+        user_id: i64,
+    },
+
+    // Rename a tag everywhere it is used.
+    Rename {
+        user_id: i64,
+        id: i64,
+        name: TagName,
+    },
+
+    // Delete a tag and remove it from everything it is on.
+    Delete {
+        user_id: i64,
+        id: i64,
+    },
+
+    // Tag a task, and with it all of its instances.
+    TagTask {
+        user_id: i64,
+        id: i64,
+        task_id: i64,
+    },
+
+    UntagTask {
+        user_id: i64,
+        id: i64,
+        task_id: i64,
+    },
+
+    // Tag a single instance.
+    TagInstance {
+        user_id: i64,
+        id: i64,
+        instance_id: i64,
+    },
+
+    UntagInstance {
+        user_id: i64,
+        id: i64,
+        instance_id: i64,
+    },
+}
+
+impl Request {
+    /// Name of the request variant. This is safe to log since it holds no user data.
+    pub fn variant(&self) -> &'static str {
+        match self {
+            Request::Create { .. } => "Create",
+            Request::RetrieveAll { .. } => "RetrieveAll",
+            Request::Rename { .. } => "Rename",
+            Request::Delete { .. } => "Delete",
+            Request::TagTask { .. } => "TagTask",
+            Request::UntagTask { .. } => "UntagTask",
+            Request::TagInstance { .. } => "TagInstance",
+            Request::UntagInstance { .. } => "UntagInstance",
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub enum Response {
+    Create { tag: Tag },
+
+    // Ordered by name.
+    RetrieveAll { tags: Vec<Tag> },
+
+    Rename { tag: Tag },
+
+    Delete { tag: Tag },
+
+    // The task with its tags after the change.
+    TagTask { task: Tagged<Task> },
+
+    UntagTask { task: Tagged<Task> },
+
+    // The instance with its own tags after the change.
+    TagInstance { instance: Tagged<Instance> },
+
+    UntagInstance { instance: Tagged<Instance> },
+}
+
+/// A task or an instance along with the names of its tags, serialized as one object.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Tagged<T> {
+    #[serde(flatten)]
+    pub item: T,
+
+    /// Ordered by name.
+    pub tags: Vec<String>,
+}
+
+/// The names of the tags on the tasks and instances of a user.
+#[derive(Debug, Default)]
+pub struct Tags {
+    tasks: HashMap<i64, Vec<String>>,
+    instances: HashMap<i64, Vec<String>>,
+}
+
+impl Tags {
+    pub async fn of_user(store: &dyn Store, user_id: i64) -> Result<Tags> {
+        let mut tags = Tags::default();
+        for (task_id, tag) in store.get_task_tags(user_id).await? {
+            tags.tasks
+                .entry(task_id)
+                .or_default()
+                .push(tag.get_name().to_string());
+        }
+        for (instance_id, tag) in store.get_instance_tags(user_id).await? {
+            tags.instances
+                .entry(instance_id)
+                .or_default()
+                .push(tag.get_name().to_string());
+        }
+
+        Ok(tags)
+    }
+
+    pub fn task(&self, task: Task) -> Tagged<Task> {
+        let tags = self.tasks.get(&task.get_id()).cloned().unwrap_or_default();
+
+        Tagged { item: task, tags }
+    }
+
+    /// The instance with its own tags. The tags of its task apply too but are not repeated.
+    pub fn instance(&self, instance: Instance) -> Tagged<Instance> {
+        let tags = self
+            .instances
+            .get(&instance.get_id())
+            .cloned()
+            .unwrap_or_default();
+
+        Tagged {
+            item: instance,
+            tags,
+        }
+    }
+
+    /// Whether the instance or its task has the tag.
+    pub fn matches(&self, instance: &Instance, tag: &TagName) -> bool {
+        let has = |tags: Option<&Vec<String>>| {
+            tags.map_or(false, |tags| tags.iter().any(|name| name == tag.as_str()))
+        };
+
+        has(self.tasks.get(&instance.get_task_id())) || has(self.instances.get(&instance.get_id()))
+    }
+}
+
+/// Narrows time down to the instances with a tag, directly or through their task.
+#[derive(Debug)]
+pub struct TagFilter {
+    tag: TagName,
+    tags: Tags,
+}
+
+impl TagFilter {
+    /// Fails with `NotFound` if the user has no such tag.
+    pub async fn new(store: &dyn Store, user_id: i64, tag: TagName) -> Result<TagFilter> {
+        if !store
+            .get_tags(user_id)
+            .await?
+            .iter()
+            .any(|existing| existing.get_name() == tag.as_str())
+        {
+            return Err(DBError::NotFound.into());
+        }
+
+        Ok(TagFilter {
+            tag,
+            tags: Tags::of_user(store, user_id).await?,
+        })
+    }
+
+    /// The filter for a `tag` in a query, if there is one.
+    pub(crate) async fn parse(
+        store: &dyn Store,
+        user_id: i64,
+        tag: Option<String>,
+    ) -> Result<Option<TagFilter>> {
+        match tag {
+            Some(tag) => {
+                let tag = tag.parse().map_err(|error: mindless_core::error::Error| {
+                    Error::BadRequest(error.to_string())
+                })?;
+                Ok(Some(TagFilter::new(store, user_id, tag).await?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn name(&self) -> &TagName {
+        &self.tag
+    }
+
+    pub fn matches(&self, instance: &Instance) -> bool {
+        self.tags.matches(instance, &self.tag)
+    }
+}
+
+/// Whether there is no filter or the instance passes it.
+pub(crate) fn passes(filter: Option<&TagFilter>, instance: &Instance) -> bool {
+    filter.map_or(true, |filter| filter.matches(instance))
+}
+
+/// Retrieve a tag making sure it belongs to the user.
+async fn user_tag(user_id: i64, id: i64, store: &dyn Store) -> Result<Tag> {
+    let tag = store.retrieve_tag(id).await?;
+    if tag.get_user_id() != user_id {
+        return Err(DBError::NotFound.into());
+    }
+
+    Ok(tag)
+}
+
+// Handle tags and tagging tasks and instances.
+#[post("/mindless/api/tag", data = "<request>")]
+#[instrument(
+    name = "tag",
+    skip(store, metrics, request),
+    fields(%request_id, request = request.variant())
+)]
+pub async fn tag(
+    store: State<'_, Arc<dyn Store>>,
+    metrics: State<'_, Metrics>,
+    request: Json<Request>,
+    request_id: RequestId,
+) -> Result<Json<Response>> {
+    let _timer = metrics.api_request("tag", request.variant());
+    let store = store.inner().as_ref();
+
+    let response = match request.into_inner() {
+        Request::Create { user_id, name } => {
+            let user = store.retrieve_user(user_id).await?;
+            let tag = store.insert_tag(user.get_id(), &name).await?;
+
+            Response::Create { tag }
+        }
+
+        Request::RetrieveAll { user_id } => {
+            let user = store.retrieve_user(user_id).await?;
+            let tags = store.get_tags(user.get_id()).await?;
+
+            Response::RetrieveAll { tags }
+        }
+
+        Request::Rename { user_id, id, name } => {
+            let mut tag = user_tag(user_id, id, store).await?;
+            store.rename_tag(&mut tag, &name).await?;
+
+            Response::Rename { tag }
+        }
+
+        Request::Delete { user_id, id } => {
+            let tag = user_tag(user_id, id, store).await?;
+            store.delete_tag(tag.clone()).await?;
+
+            Response::Delete { tag }
+        }
+
+        Request::TagTask {
+            user_id,
+            id,
+            task_id,
+        } => {
+            let tag = user_tag(user_id, id, store).await?;
+            let task = user_task(user_id, task_id, store).await?;
+            store.tag_task(&tag, task.get_id()).await?;
+
+            let task = Tags::of_user(store, user_id).await?.task(task);
+            Response::TagTask { task }
+        }
+
+        Request::UntagTask {
+            user_id,
+            id,
+            task_id,
+        } => {
+            let tag = user_tag(user_id, id, store).await?;
+            let task = user_task(user_id, task_id, store).await?;
+            store.untag_task(&tag, task.get_id()).await?;
+
+            let task = Tags::of_user(store, user_id).await?.task(task);
+            Response::UntagTask { task }
+        }
+
+        Request::TagInstance {
+            user_id,
+            id,
+            instance_id,
+        } => {
+            let tag = user_tag(user_id, id, store).await?;
+            let instance = user_instance(user_id, instance_id, store).await?;
+            store.tag_instance(&tag, instance.get_id()).await?;
+
+            let instance = Tags::of_user(store, user_id).await?.instance(instance);
+            Response::TagInstance { instance }
+        }
+
+        Request::UntagInstance {
+            user_id,
+            id,
+            instance_id,
+        } => {
+            let tag = user_tag(user_id, id, store).await?;
+            let instance = user_instance(user_id, instance_id, store).await?;
+            store.untag_instance(&tag, instance.get_id()).await?;
+
+            let instance = Tags::of_user(store, user_id).await?.instance(instance);
+            Response::UntagInstance { instance }
+        }
+    };
+
+    tracing::debug!("Handled request");
+
+    Ok(Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+    use database::memory::MemoryStore;
+
+    fn name(name: &str) -> TagName {
+        name.parse().expect("Is a valid tag.")
+    }
+
+    #[tokio::test]
+    async fn tags_of_tasks_apply_to_their_instances() {
+        let store = MemoryStore::default();
+        let user = store.insert_user("justin", "Justin").await.unwrap();
+        let work = store.insert_task(user.get_id(), "Work").await.unwrap();
+        let time = |hour| NaiveDateTime::from_timestamp(hour * 3600, 0);
+        let meeting = store
+            .insert_instance(work.get_id(), &time(0), &time(1))
+            .await
+            .unwrap();
+        let review = store
+            .insert_instance(work.get_id(), &time(2), &time(3))
+            .await
+            .unwrap();
+        let billable = store
+            .insert_tag(user.get_id(), &name("billable"))
+            .await
+            .unwrap();
+        let outdoor = store
+            .insert_tag(user.get_id(), &name("outdoor"))
+            .await
+            .unwrap();
+        store.tag_task(&billable, work.get_id()).await.unwrap();
+        store.tag_instance(&outdoor, review.get_id()).await.unwrap();
+
+        let tags = Tags::of_user(&store, user.get_id()).await.unwrap();
+        assert_eq!(tags.task(work).tags, vec!["billable".to_string()]);
+        assert_eq!(tags.instance(meeting.clone()).tags, Vec::<String>::new());
+        assert!(tags.matches(&meeting, &name("billable")));
+        assert!(tags.matches(&review, &name("outdoor")));
+        assert!(!tags.matches(&meeting, &name("outdoor")));
+
+        let filter = TagFilter::new(&store, user.get_id(), name("outdoor"))
+            .await
+            .unwrap();
+        assert!(passes(Some(&filter), &review));
+        assert!(!passes(Some(&filter), &meeting));
+        assert!(passes(None, &meeting));
+        assert!(matches!(
+            TagFilter::new(&store, user.get_id(), name("unknown")).await,
+            Err(Error::Database(DBError::NotFound))
+        ));
+    }
+
+    #[test]
+    fn tagged_items_are_flat() {
+        let task = Task::new(1, 2, "Work".to_string());
+        let tagged = Tagged {
+            item: task,
+            tags: vec!["billable".to_string()],
+        };
+        assert_eq!(
+            serde_json::to_value(&tagged).unwrap(),
+            serde_json::json!({ "id": 1, "user_id": 2, "name": "Work", "tags": ["billable"] })
+        );
+    }
+}
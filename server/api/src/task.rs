@@ -1,57 +1,302 @@
-use database::connection::Connection;
-use database::user::User;
+use database::store::Store;
 use rocket::State;
 use rocket_contrib::json::Json;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tracing::instrument;
 
 use crate::error::Result;
+use crate::events::{Change, Changes};
+use crate::logging::RequestId;
+use crate::metrics::Metrics;
+use crate::schedule::Clock;
+use crate::tag::{passes, TagFilter, Tagged, Tags};
+use crate::webhook::{Dispatcher, Event};
 use database::instance::Instance;
 use database::task::Task;
+use database::timer::Timer;
+use mindless_core::tag::TagName;
 
 // Type of events that you can execute on a user.
 #[derive(Deserialize, Debug)]
 pub enum Request {
-    // Retrieve all the tasks.
-    RetrieveAll { user_id: i64 },
+    // Retrieve all the tasks. With a `tag` only the instances with the tag, directly or through
+    // their task, and the tasks which have the tag or such instances.
+    RetrieveAll {
+        user_id: i64,
+        tag: Option<TagName>,
+    },
 
     // Insert all of the tasks!
-    InsertAll { tasks: Vec<(Task, Vec<Instance>)> },
+    InsertAll {
+        tasks: Vec<(Task, Vec<Instance>)>,
+    },
+
+    // Rename a task.
+    RenameTask {
+        user_id: i64,
+        task_id: i64,
+        name: String,
+    },
+
+    // Start timing a task.
+    StartTimer {
+        user_id: i64,
+        task_id: i64,
+    },
+
+    // Stop timing a task, which records an instance of it.
+    StopTimer {
+        user_id: i64,
+        task_id: i64,
+    },
+
+    // Retrieve the running timers.
+    RetrieveTimers {
+        user_id: i64,
+    },
+}
+
+impl Request {
+    /// Name of the request variant. This is safe to log since it holds no user data.
+    pub fn variant(&self) -> &'static str {
+        match self {
+            Request::RetrieveAll { .. } => "RetrieveAll",
+            Request::InsertAll { .. } => "InsertAll",
+            Request::RenameTask { .. } => "RenameTask",
+            Request::StartTimer { .. } => "StartTimer",
+            Request::StopTimer { .. } => "StopTimer",
+            Request::RetrieveTimers { .. } => "RetrieveTimers",
+        }
+    }
 }
 
+// Tasks and instances come with the names of their tags.
 #[derive(Serialize, Debug)]
 pub enum Response {
-    RetrieveAll { tasks: Vec<(Task, Vec<Instance>)> },
+    RetrieveAll {
+        tasks: Vec<(Tagged<Task>, Vec<Tagged<Instance>>)>,
+    },
 
     // All the tasks with their task id.
-    InsertAll { tasks: Vec<(Task, Vec<Instance>)> },
+    InsertAll {
+        tasks: Vec<(Tagged<Task>, Vec<Tagged<Instance>>)>,
+    },
+
+    RenameTask {
+        task: Tagged<Task>,
+    },
+
+    StartTimer {
+        timer: Timer,
+    },
+
+    // The instance recorded by the timer.
+    StopTimer {
+        instance: Instance,
+    },
+
+    RetrieveTimers {
+        timers: Vec<Timer>,
+    },
 }
 
 // Handle all interfacing with user.
 #[post("/mindless/api/task", data = "<request>")]
+#[instrument(
+    name = "task",
+    skip(store, clock, dispatcher, changes, metrics, request),
+    fields(%request_id, request = request.variant())
+)]
 pub async fn task(
-    connection: State<'_, Connection>,
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    dispatcher: State<'_, Dispatcher>,
+    changes: State<'_, Changes>,
+    metrics: State<'_, Metrics>,
     request: Json<Request>,
+    request_id: RequestId,
 ) -> Result<Json<Response>> {
-    println!("Recieved request: {:#?}", request);
+    let _timer = metrics.api_request("task", request.variant());
+    let store = store.inner().as_ref();
+    let dispatcher = dispatcher.inner();
+    let changes = changes.inner();
 
     let return_value = match request.into_inner() {
-        Request::RetrieveAll { user_id } => retrieve_all(user_id, &connection).await?,
-        Request::InsertAll { tasks } => insert_all(tasks, &connection).await?,
+        Request::RetrieveAll { user_id, tag } => retrieve_all(user_id, tag, store).await?,
+        Request::InsertAll { tasks } => insert_all(tasks, store, dispatcher, changes).await?,
+        Request::RenameTask {
+            user_id,
+            task_id,
+            name,
+        } => {
+            let mut task = user_task(user_id, task_id, store).await?;
+            store.rename_task(&mut task, &name).await?;
+
+            let change = Change::TaskRenamed { task: task.clone() };
+            changes.publish(user_id, change);
+
+            let task = Tags::of_user(store, user_id).await?.task(task);
+            Response::RenameTask { task }
+        }
+        Request::StartTimer { user_id, task_id } => {
+            let timer = start_timer(
+                user_id,
+                task_id,
+                store,
+                clock.inner().as_ref(),
+                dispatcher,
+                changes,
+            )
+            .await?;
+
+            Response::StartTimer { timer }
+        }
+        Request::StopTimer { user_id, task_id } => {
+            let instance = stop_timer(
+                user_id,
+                task_id,
+                store,
+                clock.inner().as_ref(),
+                dispatcher,
+                changes,
+            )
+            .await?;
+
+            Response::StopTimer { instance }
+        }
+        Request::RetrieveTimers { user_id } => {
+            let user = store.retrieve_user(user_id).await?;
+            let timers = store.get_timers(user.get_id()).await?;
+
+            Response::RetrieveTimers { timers }
+        }
     };
 
-    println!("Sending response: {:#?}", return_value);
+    tracing::debug!("Handled request");
 
     Ok(Json(return_value))
 }
 
-pub async fn retrieve_all(user_id: i64, connection: &Connection) -> Result<Response> {
-    let user = User::retrieve(user_id, &connection).await?;
-    let tasks: Vec<Task> = Task::get_tasks(&user, &connection).await?;
+/// Start timing a task of the user and tell webhooks and clients.
+pub(crate) async fn start_timer(
+    user_id: i64,
+    task_id: i64,
+    store: &dyn Store,
+    clock: &dyn Clock,
+    dispatcher: &Dispatcher,
+    changes: &Changes,
+) -> Result<Timer> {
+    let task = user_task(user_id, task_id, store).await?;
+    let timer = store
+        .start_timer(task.get_id(), &clock.now().naive_utc())
+        .await?;
+
+    let event = Event::TimerStarted {
+        task,
+        timer: timer.clone(),
+    };
+    dispatcher.emit(user_id, event).await;
+    let change = Change::TimerStarted {
+        timer: timer.clone(),
+    };
+    changes.publish(user_id, change);
+
+    Ok(timer)
+}
+
+/// Stop timing a task of the user, recording the time as an instance, and tell webhooks and
+/// clients.
+pub(crate) async fn stop_timer(
+    user_id: i64,
+    task_id: i64,
+    store: &dyn Store,
+    clock: &dyn Clock,
+    dispatcher: &Dispatcher,
+    changes: &Changes,
+) -> Result<Instance> {
+    let task = user_task(user_id, task_id, store).await?;
+    let timer = store.retrieve_timer(task.get_id()).await?;
+    let instance = store
+        .stop_timer(timer.clone(), &clock.now().naive_utc())
+        .await?;
+
+    let event = Event::TimerEnded {
+        task: task.clone(),
+        instance: instance.clone(),
+    };
+    dispatcher.emit(user_id, event).await;
+    let event = Event::InstanceCreated {
+        task,
+        instance: instance.clone(),
+    };
+    dispatcher.emit(user_id, event).await;
+    let change = Change::TimerStopped {
+        timer,
+        instance: instance.clone(),
+    };
+    changes.publish(user_id, change);
+
+    Ok(instance)
+}
+
+/// Retrieve a task making sure it belongs to the user.
+pub(crate) async fn user_task(user_id: i64, task_id: i64, store: &dyn Store) -> Result<Task> {
+    let task = store.retrieve_task(task_id).await?;
+    if task.get_user_id() != user_id {
+        return Err(database::error::Error::NotFound.into());
+    }
+
+    Ok(task)
+}
+
+/// Retrieve an instance making sure it belongs to the user.
+pub(crate) async fn user_instance(
+    user_id: i64,
+    instance_id: i64,
+    store: &dyn Store,
+) -> Result<Instance> {
+    let instance = store.retrieve_instance(instance_id).await?;
+    user_task(user_id, instance.get_task_id(), store).await?;
+
+    Ok(instance)
+}
+
+pub async fn retrieve_all(
+    user_id: i64,
+    tag: Option<TagName>,
+    store: &dyn Store,
+) -> Result<Response> {
+    let user = store.retrieve_user(user_id).await?;
+    let tasks: Vec<Task> = store.get_tasks(&user).await?;
+    let filter = match tag {
+        Some(tag) => Some(TagFilter::new(store, user.get_id(), tag).await?),
+        None => None,
+    };
+    let tags = Tags::of_user(store, user.get_id()).await?;
 
     let mut result = Vec::new();
     for task in tasks {
-        let instance = Instance::get_instances(task.get_id(), &connection).await?;
-        result.push((task, instance));
+        let instances: Vec<Instance> = store
+            .get_instances(task.get_id())
+            .await?
+            .into_iter()
+            .filter(|instance| passes(filter.as_ref(), instance))
+            .collect();
+        let task = tags.task(task);
+        if let Some(filter) = &filter {
+            let tagged = task.tags.iter().any(|name| name == filter.name().as_str());
+            if instances.is_empty() && !tagged {
+                continue;
+            }
+        }
+
+        let instances = instances
+            .into_iter()
+            .map(|instance| tags.instance(instance))
+            .collect();
+        result.push((task, instances));
     }
 
     Ok(Response::RetrieveAll { tasks: result })
@@ -59,25 +304,72 @@ pub async fn retrieve_all(user_id: i64, connection: &Connection) -> Result<Respo
 
 pub async fn insert_all(
     tasks: Vec<(Task, Vec<Instance>)>,
-    connection: &Connection,
+    store: &dyn Store,
+    dispatcher: &Dispatcher,
+    changes: &Changes,
 ) -> Result<Response> {
     let mut result = Vec::new();
-    println!("{:#?}", tasks);
+    let mut checked_users = HashSet::new();
+    tracing::debug!(count = tasks.len(), "Inserting tasks");
     for data in tasks {
         // Move the task out.
         let mut task = data.0;
-        task.try_insert(&connection).await?;
+        // Uploads can be for several users, make sure each exists once.
+        if checked_users.insert(task.get_user_id()) {
+            store.retrieve_user(task.get_user_id()).await?;
+        }
+        if store.try_insert_task(&mut task).await? {
+            let change = Change::TaskCreated { task: task.clone() };
+            changes.publish(task.get_user_id(), change);
+        }
+
+        // Uploading the same instances again must not emit events again.
+        let existing: HashSet<i64> = store
+            .get_instances(task.get_id())
+            .await?
+            .iter()
+            .map(Instance::get_id)
+            .collect();
 
         let mut instances = data.1;
         for instance in instances.iter_mut() {
             instance.set_task_id(task.get_id());
         }
+        let instances = store.try_insert_instances(instances).await?;
+
+        for instance in instances
+            .iter()
+            .filter(|instance| !existing.contains(&instance.get_id()))
+        {
+            let event = Event::InstanceCreated {
+                task: task.clone(),
+                instance: instance.clone(),
+            };
+            dispatcher.emit(task.get_user_id(), event).await;
+            let change = Change::InstanceInserted {
+                instance: instance.clone(),
+            };
+            changes.publish(task.get_user_id(), change);
+        }
 
-        result.push((
-            task,
-            Instance::try_insert_all(instances, &connection).await?,
-        ));
+        result.push((task, instances));
+    }
+
+    // Uploads can be for several users.
+    let mut tagged = Vec::new();
+    let mut users: HashMap<i64, Tags> = HashMap::new();
+    for (task, instances) in result {
+        let user_id = task.get_user_id();
+        if !users.contains_key(&user_id) {
+            users.insert(user_id, Tags::of_user(store, user_id).await?);
+        }
+        let tags = &users[&user_id];
+        let instances = instances
+            .into_iter()
+            .map(|instance| tags.instance(instance))
+            .collect();
+        tagged.push((tags.task(task), instances));
     }
 
-    Ok(Response::InsertAll { tasks: result })
+    Ok(Response::InsertAll { tasks: tagged })
 }
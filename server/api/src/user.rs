@@ -1,10 +1,14 @@
-use database::connection::Connection;
+use database::store::Store;
 use database::user::User;
 use rocket::State;
 use rocket_contrib::json::Json;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::instrument;
 
 use crate::error::Result;
+use crate::logging::RequestId;
+use crate::metrics::Metrics;
 
 // Type of events that you can execute on a user.
 #[derive(Deserialize, Debug)]
@@ -22,6 +26,18 @@ pub enum Request {
     Update { user: User },
 }
 
+impl Request {
+    /// Name of the request variant. This is safe to log since it holds no user data.
+    pub fn variant(&self) -> &'static str {
+        match self {
+            Request::Create { .. } => "Create",
+            Request::Login { .. } => "Login",
+            Request::Delete { .. } => "Delete",
+            Request::Update { .. } => "Update",
+        }
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub enum Response {
     Create { user: User },
@@ -35,40 +51,44 @@ pub enum Response {
 
 // Handle all interfacing with user.
 #[post("/mindless/api/user", data = "<request>")]
+#[instrument(
+    name = "user",
+    skip(store, metrics, request),
+    fields(%request_id, request = request.variant())
+)]
 pub async fn user(
-    connection: State<'_, Connection>,
+    store: State<'_, Arc<dyn Store>>,
+    metrics: State<'_, Metrics>,
     request: Json<Request>,
+    request_id: RequestId,
 ) -> Result<Json<Response>> {
-    println!("Recieved request: {:#?}", request);
-
+    let _timer = metrics.api_request("user", request.variant());
     let response = match request.into_inner() {
         Request::Create { username, name } => {
-            let user = User::insert(&username, &name, &connection).await?;
+            let user = store.insert_user(&username, &name).await?;
             Ok(Json(Response::Create { user }))
         }
 
         Request::Login { username } => {
-            let user = User::get(&username, &connection).await?;
+            let user = store.get_user(&username).await?;
             Ok(Json(Response::Login { user }))
         }
 
         Request::Delete { id } => {
-            User::retrieve(id, &connection)
-                .await?
-                .delete(&connection)
-                .await?;
+            let user = store.retrieve_user(id).await?;
+            store.delete_user(user).await?;
 
             Ok(Json(Response::Delete))
         }
 
-        Request::Update { mut user } => {
-            user.update(&connection).await?;
+        Request::Update { user } => {
+            store.update_user(&user).await?;
 
             Ok(Json(Response::Update { user }))
         }
     };
 
-    println!("Sending response: {:#?}", response);
+    tracing::debug!("Handled request");
 
     response
 }
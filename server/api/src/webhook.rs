@@ -0,0 +1,743 @@
+//! Posting events to the webhooks users register, e.g. for home automation or chat bots.
+//!
+//! Every event is posted as json along with an `X-Mindless-Signature` header, the HMAC-SHA256 of
+//! the body keyed with the secret handed out when the webhook was registered. Failed attempts are
+//! retried with exponential backoff and every attempt is kept in the delivery log.
+use chrono::{Duration, NaiveDateTime};
+use database::habit::Habit;
+use database::instance::Instance;
+use database::reminder::Reminder;
+use database::store::Store;
+use database::task::Task;
+use database::timer::Timer;
+use database::webhook::{Delivery, Webhook};
+use mindless_core::period::Period;
+use rand::RngCore;
+use rocket::State;
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tracing::instrument;
+
+use crate::config::WebhookConfig;
+use crate::digest::Digest;
+use crate::error::{Error, Result};
+use crate::logging::RequestId;
+use crate::metrics::Metrics;
+use crate::schedule::{self, Clock};
+use crate::signing::Signer;
+
+/// Header holding the kind of event.
+pub const EVENT_HEADER: &str = "X-Mindless-Event";
+
+/// Header holding the signature of the body.
+pub const SIGNATURE_HEADER: &str = "X-Mindless-Signature";
+
+/// Every kind of event webhooks can subscribe to.
+pub const EVENTS: &[&str] = &[
+    "InstanceCreated",
+    "TimerStarted",
+    "TimerEnded",
+    "HabitCompleted",
+    "HabitMissed",
+    "ReminderDue",
+    "WeeklyDigest",
+];
+
+/// Something which happened to a user.
+#[derive(Serialize, Debug, Clone)]
+pub enum Event {
+    // Time was recorded against a task, e.g. uploaded by the cli or from a timer.
+    InstanceCreated { task: Task, instance: Instance },
+
+    TimerStarted { task: Task, timer: Timer },
+
+    TimerEnded { task: Task, instance: Instance },
+
+    // A habit was marked as done for its period.
+    HabitCompleted { habit: Habit, period: Period },
+
+    // A period of a habit ended without it being marked as done.
+    HabitMissed { habit: Habit, period: Period },
+
+    // A reminder of a habit went off, its `last_due_at` is when.
+    ReminderDue { habit: Habit, reminder: Reminder },
+
+    // A week ended, for users who get their digest by webhook.
+    WeeklyDigest { digest: Digest },
+}
+
+impl Event {
+    /// Name of the event variant, which is what webhooks subscribe to.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::InstanceCreated { .. } => "InstanceCreated",
+            Event::TimerStarted { .. } => "TimerStarted",
+            Event::TimerEnded { .. } => "TimerEnded",
+            Event::HabitCompleted { .. } => "HabitCompleted",
+            Event::HabitMissed { .. } => "HabitMissed",
+            Event::ReminderDue { .. } => "ReminderDue",
+            Event::WeeklyDigest { .. } => "WeeklyDigest",
+        }
+    }
+}
+
+/// The body posted to a webhook.
+#[derive(Serialize, Debug)]
+struct Payload<'a> {
+    webhook_id: i64,
+    user_id: i64,
+
+    /// When the event happened.
+    created_at: NaiveDateTime,
+
+    event: &'a Event,
+}
+
+struct Inner {
+    store: Arc<dyn Store>,
+    clock: Arc<dyn Clock>,
+    http: reqwest::Client,
+    config: WebhookConfig,
+}
+
+/// Posts events to webhooks in the background.
+///
+/// Handlers emit events whether or not webhooks are enabled, a disabled dispatcher drops them.
+#[derive(Clone)]
+pub struct Dispatcher {
+    inner: Option<Arc<Inner>>,
+}
+
+impl Dispatcher {
+    pub fn new(
+        store: Arc<dyn Store>,
+        clock: Arc<dyn Clock>,
+        config: WebhookConfig,
+    ) -> reqwest::Result<Dispatcher> {
+        // Following a redirect would get around the checks on where webhooks may post to.
+        let http = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_millis(config.timeout_ms))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+
+        Ok(Dispatcher {
+            inner: Some(Arc::new(Inner {
+                store,
+                clock,
+                http,
+                config,
+            })),
+        })
+    }
+
+    /// A dispatcher which drops every event.
+    pub fn disabled() -> Dispatcher {
+        Dispatcher { inner: None }
+    }
+
+    /// Post an event to the webhooks of a user which subscribe to it.
+    ///
+    /// This only waits for the webhooks to be looked up. Failing to post an event never fails the
+    /// request which caused it. Returns how many webhooks it is being posted to.
+    pub async fn emit(&self, user_id: i64, event: Event) -> usize {
+        let inner = match &self.inner {
+            Some(inner) => inner,
+            None => return 0,
+        };
+
+        let webhooks = match inner.store.get_webhooks(user_id).await {
+            Ok(webhooks) => webhooks,
+            Err(e) => {
+                tracing::error!(%e, user_id, "Could not look up webhooks");
+                return 0;
+            }
+        };
+
+        let created_at = inner.clock.now().naive_utc();
+        let mut posted = 0;
+        for webhook in webhooks {
+            if !webhook.subscribes_to(event.kind()) {
+                continue;
+            }
+
+            let payload = Payload {
+                webhook_id: webhook.get_id(),
+                user_id,
+                created_at,
+                event: &event,
+            };
+            let payload = match serde_json::to_string(&payload) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    tracing::error!(%e, "Could not serialize the event");
+                    return posted;
+                }
+            };
+
+            tokio::spawn(deliver(inner.clone(), webhook, event.kind(), payload));
+            posted += 1;
+        }
+
+        posted
+    }
+}
+
+/// Post a payload until the webhook accepts it or we run out of attempts.
+#[instrument(skip(inner, webhook, payload), fields(webhook_id = webhook.get_id()))]
+async fn deliver(inner: Arc<Inner>, webhook: Webhook, event: &'static str, payload: String) {
+    let signature = format!(
+        "sha256={}",
+        Signer::new(webhook.get_secret()).sign(payload.as_bytes())
+    );
+    let mut backoff = std::time::Duration::from_millis(inner.config.backoff_ms);
+
+    for attempt in 1..=inner.config.max_attempts {
+        let mut delivery = Delivery::new(
+            0,
+            webhook.get_id(),
+            event.to_string(),
+            payload.clone(),
+            i64::from(attempt),
+            inner.clock.now().naive_utc(),
+        );
+
+        let response = match check_url(webhook.get_url(), &inner.config).await {
+            Ok(()) => inner
+                .http
+                .post(webhook.get_url())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, event)
+                .header(SIGNATURE_HEADER, &signature)
+                .body(payload.clone())
+                .send()
+                .await
+                .map_err(|e| e.to_string()),
+            Err(reason) => Err(reason),
+        };
+        match response {
+            Ok(response) => delivery.set_status_code(response.status().as_u16()),
+            Err(error) => delivery.set_error(error),
+        }
+
+        if let Err(e) = inner.store.insert_delivery(&mut delivery).await {
+            tracing::error!(%e, "Could not log the delivery");
+        }
+
+        if delivery.is_delivered() {
+            tracing::debug!(attempt, "Delivered event");
+            return;
+        }
+
+        tracing::warn!(
+            attempt,
+            error = ?delivery.get_error(),
+            "Delivering event failed"
+        );
+        if attempt < inner.config.max_attempts {
+            tokio::time::delay_for(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    tracing::error!("Giving up on delivering event");
+}
+
+/// Every period of a repeating habit which ended in `(since, now]`, newest first, as long as it
+/// ended after the habit was created.
+fn ended_periods(
+    habit: &Habit,
+    since: &NaiveDateTime,
+    now: &NaiveDateTime,
+) -> database::error::Result<Vec<Period>> {
+    let mut ended = Vec::new();
+    let mut end = habit.get_period(now)?.start;
+    while end > *since && end > *habit.get_created_at() {
+        let period = habit.get_period(&(end - Duration::seconds(1)))?;
+        end = period.start;
+        ended.push(period);
+    }
+
+    Ok(ended)
+}
+
+/// The `HabitMissed` events of every period which ended in `(since, now]` without being marked,
+/// oldest first, along with the user they belong to.
+///
+/// Only users with a webhook subscribed to missed habits are checked.
+pub async fn missed(
+    store: &dyn Store,
+    since: &NaiveDateTime,
+    now: &NaiveDateTime,
+) -> Result<Vec<(i64, Event)>> {
+    let mut users: Vec<i64> = store
+        .get_all_webhooks()
+        .await?
+        .iter()
+        .filter(|webhook| webhook.subscribes_to("HabitMissed"))
+        .map(Webhook::get_user_id)
+        .collect();
+    users.sort_unstable();
+    users.dedup();
+
+    let mut events = Vec::new();
+    for user_id in users {
+        for habit in store.get_habits(user_id).await? {
+            // Habits which don't repeat have a single period which never ends.
+            if habit.get_repeat_period_sec().is_none() {
+                continue;
+            }
+
+            let ended = match ended_periods(&habit, since, now) {
+                Ok(ended) => ended,
+                Err(error) => {
+                    // Don't let one broken habit hold back everyone else's.
+                    tracing::error!(%error, habit_id = habit.get_id(), "Habit has no periods");
+                    continue;
+                }
+            };
+            let span = match (ended.last(), ended.first()) {
+                (Some(oldest), Some(newest)) => Period::new(oldest.start, newest.end),
+                _ => continue,
+            };
+
+            // Periods can be as short as a second, so look up the completions of all of them at
+            // once.
+            let completions = store.get_completions(habit.get_id(), &span).await?;
+            for period in ended.into_iter().rev() {
+                let completed = completions.iter().any(|completion| {
+                    period.start <= *completion.get_created_at()
+                        && *completion.get_created_at() < period.end
+                });
+                if !completed {
+                    events.push((
+                        user_id,
+                        Event::HabitMissed {
+                            habit: habit.clone(),
+                            period,
+                        },
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+/// Look for missed habits periodically while the server is running, and prune the delivery log
+/// while at it.
+pub fn schedule(
+    store: Arc<dyn Store>,
+    dispatcher: Dispatcher,
+    config: WebhookConfig,
+    clock: Arc<dyn Clock>,
+) {
+    let minutes = config.missed_check_minutes as i64;
+    let retention = Duration::days(config.delivery_retention_days as i64);
+    let period = std::time::Duration::from_secs(config.missed_check_minutes * 60);
+
+    schedule::every("missed_habits", period, clock, move |now| {
+        let store = store.clone();
+        let dispatcher = dispatcher.clone();
+
+        async move {
+            let now = now.naive_utc();
+            let since = now - Duration::minutes(minutes);
+            match missed(store.as_ref(), &since, &now).await {
+                Ok(events) => {
+                    for (user_id, event) in events {
+                        dispatcher.emit(user_id, event).await;
+                    }
+                }
+                Err(e) => tracing::error!(?e, "Looking for missed habits failed"),
+            }
+
+            match store.delete_deliveries_before(&(now - retention)).await {
+                Ok(count) => tracing::debug!(count, "Pruned the delivery log"),
+                Err(e) => tracing::error!(?e, "Pruning the delivery log failed"),
+            }
+        }
+    });
+}
+
+/// Whether an address is on the internet rather than on this machine or a private network.
+fn is_public(address: &IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => {
+            let octets = address.octets();
+            !(address.is_private()
+                || address.is_loopback()
+                || address.is_link_local()
+                || address.is_unspecified()
+                || address.is_broadcast()
+                || octets[0] == 0
+                // Carrier grade NAT.
+                || (octets[0] == 100 && (64..128).contains(&octets[1])))
+        }
+        IpAddr::V6(address) => {
+            let first = address.segments()[0];
+            if address.is_loopback() || address.is_unspecified() {
+                false
+            } else if let Some(mapped) = address.to_ipv4() {
+                is_public(&IpAddr::V4(mapped))
+            } else {
+                // Unique local and link local addresses.
+                first & 0xfe00 != 0xfc00 && first & 0xffc0 != 0xfe80
+            }
+        }
+    }
+}
+
+/// Make sure webhooks post over http(s) and can't be used to reach the server's own network.
+///
+/// Unless the host is in `webhooks.allowed_hosts`, every address it resolves to must be public.
+/// Checked when a webhook is registered and again before every delivery, since where a host
+/// points can change. Returns why a url is rejected.
+async fn check_url(url: &str, config: &WebhookConfig) -> std::result::Result<(), String> {
+    let invalid = |reason: &str| format!("\"{}\" {}", url, reason);
+
+    let parsed = reqwest::Url::parse(url).map_err(|_| invalid("is not an http(s) url"))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(invalid("is not an http(s) url"));
+    }
+    let host = match parsed.host_str() {
+        Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+        None => return Err(invalid("is not an http(s) url")),
+    };
+    if config
+        .allowed_hosts
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(host))
+    {
+        return Ok(());
+    }
+
+    let addresses: Vec<IpAddr> = match host.parse() {
+        Ok(address) => vec![address],
+        Err(_) => {
+            let port = parsed.port_or_known_default().unwrap_or(80);
+            tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|_| invalid("does not resolve"))?
+                .map(|address| address.ip())
+                .collect()
+        }
+    };
+    if addresses.is_empty() {
+        return Err(invalid("does not resolve"));
+    }
+    if !addresses.iter().all(is_public) {
+        return Err(invalid(
+            "is on a private network, add its host to webhooks.allowed_hosts to allow it",
+        ));
+    }
+
+    Ok(())
+}
+
+/// A random secret to sign payloads with.
+fn generate_secret() -> String {
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+
+    base64::encode_config(&secret, base64::URL_SAFE_NO_PAD)
+}
+
+// Type of events that you can execute on webhooks.
+#[derive(Deserialize, Debug)]
+pub enum Request {
+    // Register a url for some kinds of events, or every event if none are given.
+    Register {
+        user_id: i64,
+        url: String,
+        events: Vec<String>,
+    },
+
+    // Retrieve all the webhooks of a user.
+    RetrieveAll {
+        user_id: i64,
+    },
+
+    // Delete a webhook along with its delivery log.
+    Delete {
+        user_id: i64,
+        id: i64,
+    },
+
+    // Retrieve the delivery log of a webhook.
+    Deliveries {
+        user_id: i64,
+        id: i64,
+    },
+}
+
+impl Request {
+    /// Name of the request variant. This is safe to log since it holds no user data.
+    pub fn variant(&self) -> &'static str {
+        match self {
+            Request::Register { .. } => "Register",
+            Request::RetrieveAll { .. } => "RetrieveAll",
+            Request::Delete { .. } => "Delete",
+            Request::Deliveries { .. } => "Deliveries",
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub enum Response {
+    // The secret is only ever shown here.
+    Register { webhook: Webhook, secret: String },
+
+    RetrieveAll { webhooks: Vec<Webhook> },
+
+    Delete { webhook: Webhook },
+
+    Deliveries { deliveries: Vec<Delivery> },
+}
+
+/// Retrieve a webhook making sure it belongs to the user.
+async fn user_webhook(user_id: i64, id: i64, store: &dyn Store) -> Result<Webhook> {
+    let webhook = store.retrieve_webhook(id).await?;
+    if webhook.get_user_id() != user_id {
+        return Err(database::error::Error::NotFound.into());
+    }
+
+    Ok(webhook)
+}
+
+// Handle registering webhooks and reading their delivery logs.
+#[post("/mindless/api/webhook", data = "<request>")]
+#[instrument(
+    name = "webhook",
+    skip(store, clock, config, metrics, request),
+    fields(%request_id, request = request.variant())
+)]
+pub async fn webhook(
+    store: State<'_, Arc<dyn Store>>,
+    clock: State<'_, Arc<dyn Clock>>,
+    config: State<'_, WebhookConfig>,
+    metrics: State<'_, Metrics>,
+    request: Json<Request>,
+    request_id: RequestId,
+) -> Result<Json<Response>> {
+    let _timer = metrics.api_request("webhook", request.variant());
+    let store = store.inner().as_ref();
+
+    let response = match request.into_inner() {
+        Request::Register {
+            user_id,
+            url,
+            events,
+        } => {
+            check_url(&url, config.inner())
+                .await
+                .map_err(Error::BadRequest)?;
+            if let Some(event) = events
+                .iter()
+                .find(|event| !EVENTS.contains(&event.as_str()))
+            {
+                return Err(Error::BadRequest(format!(
+                    "\"{}\" is not one of {}",
+                    event,
+                    EVENTS.join(", ")
+                )));
+            }
+
+            let user = store.retrieve_user(user_id).await?;
+            let secret = generate_secret();
+            let webhook = store
+                .insert_webhook(
+                    user.get_id(),
+                    &url,
+                    &secret,
+                    &events,
+                    &clock.now().naive_utc(),
+                )
+                .await?;
+
+            Response::Register { webhook, secret }
+        }
+
+        Request::RetrieveAll { user_id } => {
+            let user = store.retrieve_user(user_id).await?;
+            let webhooks = store.get_webhooks(user.get_id()).await?;
+
+            Response::RetrieveAll { webhooks }
+        }
+
+        Request::Delete { user_id, id } => {
+            let webhook = user_webhook(user_id, id, store).await?;
+            store.delete_webhook(webhook.clone()).await?;
+
+            Response::Delete { webhook }
+        }
+
+        Request::Deliveries { user_id, id } => {
+            let webhook = user_webhook(user_id, id, store).await?;
+            let deliveries = store.get_deliveries(webhook.get_id()).await?;
+
+            Response::Deliveries { deliveries }
+        }
+    };
+
+    tracing::debug!("Handled request");
+
+    Ok(Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::memory::MemoryStore;
+    use database::store::{HabitStore, UserStore, WebhookStore};
+
+    fn time(hour: i64) -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(hour * 60 * 60, 0)
+    }
+
+    #[test]
+    fn every_event_can_be_subscribed_to() {
+        let task = Task::new(1, 1, "Exercise".to_string());
+        let instance = Instance::new(2, 1, time(0), time(1));
+        let habit = Habit::new(3, None, 1, "Read".to_string(), time(0), Some(3600));
+        let period = Period::new(time(0), time(1));
+
+        let events = vec![
+            Event::InstanceCreated {
+                task: task.clone(),
+                instance: instance.clone(),
+            },
+            Event::TimerStarted {
+                task: task.clone(),
+                timer: Timer::new(4, 1, time(0)),
+            },
+            Event::TimerEnded { task, instance },
+            Event::HabitCompleted {
+                habit: habit.clone(),
+                period: period.clone(),
+            },
+            Event::HabitMissed {
+                habit: habit.clone(),
+                period,
+            },
+            Event::ReminderDue {
+                reminder: Reminder::new(
+                    5,
+                    3,
+                    &time(0).time(),
+                    false,
+                    "webhook".to_string(),
+                    time(0),
+                ),
+                habit,
+            },
+            Event::WeeklyDigest {
+                digest: Digest {
+                    user_id: 1,
+                    week: time(0).date(),
+                    timezone: "UTC".to_string(),
+                    seconds: 0,
+                    previous_seconds: 0,
+                    tasks: Vec::new(),
+                    habits: Vec::new(),
+                    goals: Vec::new(),
+                    days: Vec::new(),
+                    best_day: None,
+                    worst_day: None,
+                },
+            },
+        ];
+
+        let kinds: Vec<&str> = events.iter().map(Event::kind).collect();
+        assert_eq!(kinds, EVENTS);
+        for event in &events {
+            let json = serde_json::to_value(event).expect("Can serialize.");
+            assert!(json.get(event.kind()).is_some(), "Tagged by kind: {}", json);
+        }
+    }
+
+    #[tokio::test]
+    async fn missed_habits() {
+        let store = MemoryStore::default();
+        let user = store
+            .insert_user("username", "name")
+            .await
+            .expect("Should successfully insert.");
+        let hourly = store
+            .insert_habit(user.get_id(), None, "Stretch", &time(0), Some(3600))
+            .await
+            .expect("Should successfully insert.");
+        store
+            .insert_habit(user.get_id(), None, "Someday", &time(0), None)
+            .await
+            .expect("Should successfully insert.");
+        store
+            .mark_habit(&hourly, &time(1))
+            .await
+            .expect("Can mark.");
+
+        assert!(
+            missed(&store, &time(2), &time(3))
+                .await
+                .expect("Can check.")
+                .is_empty(),
+            "Nobody subscribed."
+        );
+
+        store
+            .insert_webhook(
+                user.get_id(),
+                "http://localhost/hook",
+                "secret",
+                &["HabitMissed".to_string()],
+                &time(0),
+            )
+            .await
+            .expect("Should successfully insert.");
+
+        // The first period ended before the last check.
+        assert!(missed(&store, &time(1), &time(1))
+            .await
+            .expect("Can check.")
+            .is_empty());
+        // The second period was marked.
+        assert!(missed(&store, &time(1), &time(2))
+            .await
+            .expect("Can check.")
+            .is_empty());
+
+        let events = missed(&store, &time(2), &time(3))
+            .await
+            .expect("Can check.");
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            (user_id, Event::HabitMissed { habit, period }) => {
+                assert_eq!(*user_id, user.get_id());
+                assert_eq!(habit, &hourly);
+                assert_eq!(period, &Period::new(time(2), time(3)));
+            }
+            (_, event) => panic!("Unexpected event {:?}", event),
+        }
+
+        // Every period since the last check is missed, not only the latest.
+        let periods: Vec<Period> = missed(&store, &time(0), &time(4))
+            .await
+            .expect("Can check.")
+            .into_iter()
+            .map(|(_, event)| match event {
+                Event::HabitMissed { period, .. } => period,
+                event => panic!("Unexpected event {:?}", event),
+            })
+            .collect();
+        assert_eq!(
+            periods,
+            vec![
+                Period::new(time(0), time(1)),
+                Period::new(time(2), time(3)),
+                Period::new(time(3), time(4)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{assert_rejected, client, client_with, create_user, post};
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::{json, Value};
+
+/// Record exercise on the 1st and 2nd of September 2020 and return the task id.
+async fn exercise(client: &Client, user_id: i64) -> i64 {
+    let (status, json) = post(
+        client,
+        "/mindless/api/task",
+        json!({ "InsertAll": { "tasks": [[
+            { "id": 0, "user_id": user_id, "name": "Exercise" },
+            [
+                { "id": 0, "task_id": 0, "start": "2020-09-01T10:00:00", "end": "2020-09-01T11:00:00" },
+                { "id": 0, "task_id": 0, "start": "2020-09-02T10:00:00", "end": "2020-09-02T10:30:00" }
+            ]
+        ]] } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+
+    json["InsertAll"]["tasks"][0][0]["id"]
+        .as_i64()
+        .expect("Inserted task has an id.")
+}
+
+async fn chart(client: &Client, uri: String) -> (Status, Option<ContentType>, String) {
+    let response = client.get(uri).dispatch().await;
+    let status = response.status();
+    let content_type = response.content_type();
+    let body = response.into_string().await.unwrap_or_default();
+
+    (status, content_type, body)
+}
+
+/// Assert a chart was rejected because something it is of doesn't exist.
+async fn assert_not_found(client: &Client, uri: String) {
+    let (status, _, body) = chart(client, uri).await;
+    let json: Value = serde_json::from_str(&body).expect("Rejections are json.");
+    assert_rejected(status, &json, "NotFound");
+}
+
+#[tokio::test]
+async fn charts_of_tracked_time() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let task_id = exercise(&client, user_id).await;
+    let range = "from=2020-08-01&to=2020-09-30";
+
+    let uri = format!("/mindless/api/chart/{}/heatmap.svg?{}", user_id, range);
+    let (status, content_type, svg) = chart(&client, uri).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(content_type, Some(ContentType::SVG));
+    assert!(svg.contains("<title>2020-09-01: 1h00m</title>"), "{}", svg);
+    assert!(svg.contains("<title>2020-09-02: 0h30m</title>"), "{}", svg);
+
+    let uri = format!(
+        "/mindless/api/chart/{}/weekly.svg?{}&task={}",
+        user_id, range, task_id
+    );
+    let (status, _, svg) = chart(&client, uri).await;
+    assert_eq!(status, Status::Ok);
+    assert!(
+        svg.contains("<title>Exercise, week of 2020-08-31: 1h30m</title>"),
+        "{}",
+        svg
+    );
+
+    let uri = format!("/mindless/api/chart/{}/hours.svg?{}", user_id, range);
+    let (status, _, svg) = chart(&client, uri).await;
+    assert_eq!(status, Status::Ok);
+    assert!(
+        svg.contains("<title>10:00 to 11:00: 1h30m</title>"),
+        "{}",
+        svg
+    );
+}
+
+#[tokio::test]
+async fn habit_heatmap() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    for path in &["Health", "Health/Gym"] {
+        let (status, _) = post(
+            &client,
+            "/mindless/api/habit",
+            json!({ "Create": { "user_id": user_id, "path": path, "repeat_period_sec": 86400 } }),
+        )
+        .await;
+        assert_eq!(status, Status::Ok);
+    }
+    let (status, json) = post(
+        &client,
+        "/mindless/api/habit",
+        json!({ "Mark": { "user_id": user_id, "path": "Health/Gym" } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    let health_id = json["Mark"]["habit"]["parent_id"]
+        .as_i64()
+        .expect("Gym is under health.");
+
+    // Marked today, which is in the last year.
+    let uri = format!(
+        "/mindless/api/chart/{}/heatmap.svg?habit={}",
+        user_id, health_id
+    );
+    let (status, _, svg) = chart(&client, uri).await;
+    assert_eq!(status, Status::Ok);
+    assert!(svg.contains(": 1 time</title>"), "{}", svg);
+}
+
+#[tokio::test]
+async fn invalid_charts() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let other_id = create_user(&client, "other", "Other").await;
+    let task_id = exercise(&client, other_id).await;
+
+    for (query, expected) in &[
+        ("from=yesterday", Status::BadRequest),
+        ("from=2020-09-30&to=2020-09-01", Status::BadRequest),
+        ("from=2000-01-01&to=2020-09-01", Status::BadRequest),
+        ("task=1&habit=1", Status::BadRequest),
+        ("to=%2B262142-12-31", Status::BadRequest),
+        ("from=0001-01-01&to=0001-12-31", Status::BadRequest),
+    ] {
+        let uri = format!("/mindless/api/chart/{}/heatmap.svg?{}", user_id, query);
+        assert_eq!(chart(&client, uri).await.0, *expected, "{}", query);
+    }
+
+    let uri = format!("/mindless/api/chart/{}/heatmap.svg?habit=42", user_id);
+    assert_not_found(&client, uri).await;
+    let uri = format!(
+        "/mindless/api/chart/{}/weekly.svg?task={}",
+        user_id, task_id
+    );
+    assert_not_found(&client, uri).await;
+    let uri = "/mindless/api/chart/42/hours.svg".to_string();
+    assert_not_found(&client, uri).await;
+}
+
+#[tokio::test]
+async fn charts_can_be_disabled() {
+    let client = client_with(|config| config.features.charts = false).await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let uri = format!("/mindless/api/chart/{}/heatmap.svg", user_id);
+    assert_eq!(chart(&client, uri).await.0, Status::NotFound);
+}
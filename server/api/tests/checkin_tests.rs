@@ -0,0 +1,231 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{assert_rejected, client, client_with, create_user, post};
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::{json, Value};
+
+const CHECKIN_URI: &str = "/mindless/api/checkin";
+
+const SECRET: &str = "a test secret which is long enough";
+
+async fn client_with_secret() -> Client {
+    client_with(|config| config.auth.secret = Some(SECRET.to_string())).await
+}
+
+/// Create a daily habit and issue a check-in token for it.
+async fn issue_token(client: &Client, user_id: i64, path: &str) -> Value {
+    let (status, json) = post(
+        client,
+        "/mindless/api/habit",
+        json!({ "Create": { "user_id": user_id, "path": path, "repeat_period_sec": 24 * 60 * 60 } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+
+    let (status, json) = post(
+        client,
+        CHECKIN_URI,
+        json!({ "Issue": { "user_id": user_id, "path": path } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+
+    json["Issue"]["token"].clone()
+}
+
+/// Scan a token and return the status along with the json response.
+async fn scan(client: &Client, token: &str) -> (Status, Value) {
+    let response = client
+        .get(format!("{}/{}", CHECKIN_URI, token))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let body = response.into_string().await.expect("Response has a body.");
+
+    (
+        status,
+        serde_json::from_str(&body).expect("Response is json."),
+    )
+}
+
+#[tokio::test]
+async fn check_in_marks_once_per_period() {
+    let client = client_with_secret().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let issued = issue_token(&client, user_id, "Cold Shower").await;
+    let token = issued["token"].as_str().expect("Token is a string.");
+
+    let (status, json) = scan(&client, token).await;
+    assert_eq!(status, Status::Ok, "{}", json);
+    assert_eq!(json["CheckIn"]["habit"]["id"], issued["habit_id"]);
+    assert_eq!(json["CheckIn"]["marked"], json!(true));
+
+    let (status, json) = scan(&client, token).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json["CheckIn"]["marked"], json!(false));
+
+    // Automations can POST instead.
+    let (status, json) = post(&client, &format!("{}/{}", CHECKIN_URI, token), json!(null)).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json["CheckIn"]["marked"], json!(false));
+}
+
+#[tokio::test]
+async fn tampered_tokens_are_rejected() {
+    let client = client_with_secret().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let first = issue_token(&client, user_id, "Gym").await;
+    let second = issue_token(&client, user_id, "Read").await;
+
+    // Reusing the signature of another token.
+    let first_signature = first["token"]
+        .as_str()
+        .unwrap()
+        .splitn(2, '.')
+        .nth(1)
+        .unwrap();
+    let forged = format!("{}.{}", second["id"], first_signature);
+
+    for token in &[forged.as_str(), "nonsense", "1", "999.signature"] {
+        let (status, json) = scan(&client, token).await;
+        assert_eq!(status, Status::NotFound, "{}", token);
+        assert_eq!(json, json!({ "error": "InvalidToken" }));
+    }
+}
+
+#[tokio::test]
+async fn revoked_tokens_are_gone() {
+    let client = client_with_secret().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let other_id = create_user(&client, "other", "Other").await;
+    let issued = issue_token(&client, user_id, "Journal").await;
+    let token = issued["token"].as_str().unwrap();
+
+    // Only the owner can revoke a token.
+    let (status, json) = post(
+        &client,
+        CHECKIN_URI,
+        json!({ "Revoke": { "user_id": other_id, "id": issued["id"] } }),
+    )
+    .await;
+    assert_rejected(status, &json, "NotFound");
+
+    let (status, json) = post(
+        &client,
+        CHECKIN_URI,
+        json!({ "Revoke": { "user_id": user_id, "id": issued["id"] } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert!(json["Revoke"]["token"]["revoked_at"].is_string());
+
+    let (status, json) = scan(&client, token).await;
+    assert_eq!(status, Status::Gone);
+    assert_eq!(json, json!({ "error": "RevokedToken" }));
+
+    let (status, json) = post(
+        &client,
+        CHECKIN_URI,
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json["RetrieveAll"]["tokens"][0]["token"], json!(token));
+}
+
+#[tokio::test]
+async fn check_in_requires_a_secret() {
+    let client = client().await;
+
+    let response = client
+        .get(format!("{}/1.signature", CHECKIN_URI))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[tokio::test]
+async fn qr_codes_of_tokens() {
+    let client = client_with_secret().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let issued = issue_token(&client, user_id, "Cold Shower").await;
+    let token = issued["token"].as_str().expect("Token is a string.");
+    let qr_uri = format!("{}/{}/qr", CHECKIN_URI, token);
+
+    let response = client.get(format!("{}.svg", qr_uri)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::SVG));
+    let svg = response.into_string().await.expect("Response has a body.");
+    assert!(svg.contains(">Cold Shower</text>"));
+
+    let response = client
+        .get(format!("{}.png?scale=2", qr_uri))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::PNG));
+    let png = response.into_bytes().await.expect("Response has a body.");
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+
+    // Only issued tokens render.
+    let forged = format!("{}.forged", issued["id"]);
+    let response = client
+        .get(format!("{}/{}/qr.svg", CHECKIN_URI, forged))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let (status, _) = post(
+        &client,
+        CHECKIN_URI,
+        json!({ "Revoke": { "user_id": user_id, "id": issued["id"] } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    let response = client.get(format!("{}.svg", qr_uri)).dispatch().await;
+    assert_eq!(response.status(), Status::Gone);
+}
+
+#[tokio::test]
+async fn sheet_has_a_code_per_token() {
+    let client = client_with_secret().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let read = issue_token(&client, user_id, "Read").await;
+    let run = issue_token(&client, user_id, "Run").await;
+    let (status, _) = post(
+        &client,
+        "/mindless/api/habit",
+        json!({ "Create": { "user_id": user_id, "path": "Walk", "repeat_period_sec": null } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+
+    let response = client
+        .get(format!(
+            "{}/sheet?tokens={},{}",
+            CHECKIN_URI,
+            read["token"].as_str().expect("Token is a string."),
+            run["token"].as_str().expect("Token is a string.")
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::HTML));
+    let sheet = response.into_string().await.expect("Response has a body.");
+    assert_eq!(sheet.matches("<figure>").count(), 2);
+    assert!(sheet.contains(">Read</text>"));
+    assert!(sheet.contains(">Run</text>"));
+
+    // Printing never issues tokens.
+    let (_, json) = post(
+        &client,
+        CHECKIN_URI,
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(json["RetrieveAll"]["tokens"].as_array().unwrap().len(), 2);
+}
@@ -0,0 +1,72 @@
+//! Shared setup for the HTTP tests.
+use endpoint::config::Config;
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::Value;
+
+/// A client for a server backed by a fresh in memory database with every migration applied.
+pub async fn client() -> Client {
+    client_with(|_| ()).await
+}
+
+/// Like `client` with changes to the configuration, e.g. setting a secret.
+pub async fn client_with(configure: impl FnOnce(&mut Config)) -> Client {
+    let config = config_with(configure);
+    let rocket = endpoint::liftoff(&config)
+        .await
+        .expect("Server should start.");
+
+    Client::new(rocket).await.expect("Valid rocket instance.")
+}
+
+/// The configuration of a server backed by a fresh in memory database, with changes.
+pub fn config_with(configure: impl FnOnce(&mut Config)) -> Config {
+    let mut config = Config::default();
+    config.database.url = "sqlite::memory:".to_string();
+    // Every connection to `sqlite::memory:` opens a different database.
+    config.database.pool_size = 1;
+    configure(&mut config);
+
+    config
+}
+
+/// Post a json body and return the status along with the json response.
+pub async fn post(client: &Client, uri: &str, body: Value) -> (Status, Value) {
+    let response = client
+        .post(uri.to_string())
+        .header(ContentType::JSON)
+        .body(body.to_string())
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let body = response.into_string().await.expect("Response has a body.");
+    let json = serde_json::from_str(&body)
+        .unwrap_or_else(|e| panic!("Response is not json ({}): {}", e, body));
+
+    (status, json)
+}
+
+/// Assert that a request was rejected. Rejections keep the 200 status the app expects and name
+/// the reason in `error`.
+#[allow(dead_code)]
+pub fn assert_rejected(status: Status, json: &Value, error: &str) {
+    assert_eq!(status, Status::Ok, "{}", json);
+    assert_eq!(json, &serde_json::json!({ "error": error }));
+}
+
+/// Create a user and return its id.
+#[allow(dead_code)]
+pub async fn create_user(client: &Client, username: &str, name: &str) -> i64 {
+    let (status, json) = post(
+        client,
+        "/mindless/api/user",
+        serde_json::json!({ "Create": { "username": username, "name": name } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+
+    json["Create"]["user"]["id"]
+        .as_i64()
+        .expect("Created user has an id.")
+}
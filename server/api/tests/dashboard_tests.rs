@@ -0,0 +1,573 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{assert_rejected, client, client_with, create_user, post};
+use rocket::http::{ContentType, Cookie, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use serde_json::{json, Value};
+
+const DASHBOARD_URI: &str = "/mindless/dashboard";
+
+const SECRET: &str = "a test secret which is long enough";
+
+async fn client_with_secret() -> Client {
+    client_with(|config| config.auth.secret = Some(SECRET.to_string())).await
+}
+
+fn location(response: &LocalResponse<'_>) -> String {
+    response
+        .headers()
+        .get_one("Location")
+        .expect("Redirects.")
+        .to_string()
+}
+
+/// The name and value of the cookie a response sets.
+fn set_cookie(response: &LocalResponse<'_>) -> Cookie<'static> {
+    let set_cookie = response
+        .headers()
+        .get_one("Set-Cookie")
+        .expect("Sets a cookie.");
+    assert!(set_cookie.contains("HttpOnly"), "{}", set_cookie);
+    let (name, value) = set_cookie
+        .split(';')
+        .next()
+        .and_then(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            Some((parts.next()?, parts.next()?))
+        })
+        .expect("Cookie has a value.");
+
+    Cookie::new(name.to_string(), value.to_string())
+}
+
+/// The token the forms on a page carry.
+fn csrf(html: &str) -> String {
+    let input = r#"name="csrf" value=""#;
+    let start = html.find(input).expect("Forms carry a token.") + input.len();
+
+    html[start..]
+        .split('"')
+        .next()
+        .expect("Token is quoted.")
+        .to_string()
+}
+
+/// A logged in user along with the token their forms carry.
+struct Session {
+    cookie: Cookie<'static>,
+    csrf: String,
+}
+
+/// Show the login page and return the cookie and token its form is checked against.
+async fn login_form(client: &Client) -> (Cookie<'static>, String) {
+    let response = client
+        .get(format!("{}/login", DASHBOARD_URI))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let cookie = set_cookie(&response);
+    let html = response.into_string().await.expect("Response has a body.");
+
+    (cookie, csrf(&html))
+}
+
+/// Log in and return the session.
+async fn login(client: &Client, username: &str) -> Session {
+    let (nonce, token) = login_form(client).await;
+    let response = client
+        .post(format!("{}/login", DASHBOARD_URI))
+        .cookie(nonce)
+        .header(ContentType::Form)
+        .body(format!("username={}&csrf={}", username, token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(location(&response), DASHBOARD_URI);
+    let cookie = set_cookie(&response);
+
+    let response = client
+        .get(DASHBOARD_URI)
+        .cookie(cookie.clone())
+        .dispatch()
+        .await;
+    let html = response.into_string().await.expect("Response has a body.");
+
+    Session {
+        cookie,
+        csrf: csrf(&html),
+    }
+}
+
+/// Get a dashboard page and return its status along with the html.
+async fn page(client: &Client, session: &Session, query: &str) -> (Status, String) {
+    let response = client
+        .get(format!("{}?{}", DASHBOARD_URI, query))
+        .cookie(session.cookie.clone())
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let body = response.into_string().await.expect("Response has a body.");
+
+    (status, body)
+}
+
+/// Submit a form on the dashboard along with its token and return where it goes back to.
+async fn submit(client: &Client, session: &Session, uri: &str, form: &str) -> String {
+    let body = match form {
+        "" => format!("csrf={}", session.csrf),
+        form => format!("{}&csrf={}", form, session.csrf),
+    };
+    let response = client
+        .post(format!("{}{}", DASHBOARD_URI, uri))
+        .cookie(session.cookie.clone())
+        .header(ContentType::Form)
+        .body(body)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther, "{}", uri);
+
+    location(&response)
+}
+
+/// Upload a task with an instance and return the task along with the instance.
+async fn insert_task(client: &Client, user_id: i64, name: &str) -> (Value, Value) {
+    let (status, json) = post(
+        client,
+        "/mindless/api/task",
+        json!({ "InsertAll": { "tasks": [[
+            { "id": 0, "user_id": user_id, "name": name },
+            [{ "id": 0, "task_id": 0, "start": "2020-10-12T09:00:00", "end": "2020-10-12T10:30:00" }]
+        ]] } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+
+    let task = &json["InsertAll"]["tasks"][0];
+    (task[0].clone(), task[1][0].clone())
+}
+
+#[tokio::test]
+async fn dashboard_needs_a_secret() {
+    let client = client().await;
+
+    let response = client.get(DASHBOARD_URI).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[tokio::test]
+async fn dashboard_needs_a_session() {
+    let client = client_with_secret().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let response = client.get(DASHBOARD_URI).dispatch().await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(location(&response), "/mindless/dashboard/login");
+
+    // A session for another user signed without the secret.
+    let forged = Cookie::new("mindless_session", format!("{}.0.c2lnbmF0dXJl", user_id));
+    let response = client.get(DASHBOARD_URI).cookie(forged).dispatch().await;
+    assert_eq!(response.status(), Status::SeeOther);
+
+    let response = client
+        .post(format!("{}/habit", DASHBOARD_URI))
+        .header(ContentType::Form)
+        .body("path=Health&csrf=forged")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(location(&response), "/mindless/dashboard/login");
+
+    let response = client
+        .get(format!("{}/login", DASHBOARD_URI))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::HTML));
+    assert!(!set_cookie(&response).value().is_empty());
+}
+
+#[tokio::test]
+async fn login_with_unknown_username() {
+    let client = client_with_secret().await;
+    let (nonce, token) = login_form(&client).await;
+
+    let response = client
+        .post(format!("{}/login", DASHBOARD_URI))
+        .cookie(nonce)
+        .header(ContentType::Form)
+        .body(format!("username=nobody&csrf={}", token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(
+        location(&response),
+        "/mindless/dashboard/login?notice=unknown-user"
+    );
+    assert!(response.headers().get_one("Set-Cookie").is_none());
+}
+
+#[tokio::test]
+async fn show_a_week() {
+    let client = client_with_secret().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    insert_task(&client, user_id, "Deep <Work>").await;
+    let session = login(&client, "justin").await;
+
+    let (status, html) = page(&client, &session, "view=week&date=2020-10-14").await;
+    assert_eq!(status, Status::Ok);
+    assert!(html.contains("<title>12 October to 18 October 2020</title>"));
+    assert!(html.contains("Justin"));
+    assert!(html.contains("Deep &lt;Work&gt;"));
+    assert!(html.contains("1h30m"));
+    assert!(html.contains(r#"value="2020-10-12T09:00:00""#));
+
+    // Nothing was recorded the week before.
+    let (_, html) = page(&client, &session, "view=week&date=2020-10-05").await;
+    assert!(html.contains("0h00m"));
+    assert!(html.contains("No time recorded."));
+
+    let (status, _) = page(&client, &session, "view=month").await;
+    assert_eq!(status, Status::BadRequest);
+}
+
+#[tokio::test]
+async fn start_and_stop_timers() {
+    let client = client_with_secret().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let (task, _) = insert_task(&client, user_id, "Exercise").await;
+    let task_id = task["id"].as_i64().expect("Task has an id.");
+    let session = login(&client, "justin").await;
+
+    let start = format!("/timer/{}/start?view=week", task_id);
+    assert_eq!(
+        submit(&client, &session, &start, "").await,
+        "/mindless/dashboard?view=week"
+    );
+    let (_, json) = post(
+        &client,
+        "/mindless/api/task",
+        json!({ "RetrieveTimers": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(
+        json["RetrieveTimers"]["timers"][0]["task_id"],
+        json!(task_id)
+    );
+    let (_, html) = page(&client, &session, "").await;
+    assert!(html.contains(&format!("/timer/{}/stop?view=day", task_id)));
+
+    assert_eq!(
+        submit(&client, &session, &start, "").await,
+        "/mindless/dashboard?view=week&notice=timer-running"
+    );
+
+    let stop = format!("/timer/{}/stop?view=week", task_id);
+    assert_eq!(
+        submit(&client, &session, &stop, "").await,
+        "/mindless/dashboard?view=week"
+    );
+    assert_eq!(
+        submit(&client, &session, &stop, "").await,
+        "/mindless/dashboard?view=week&notice=timer-stopped"
+    );
+}
+
+#[tokio::test]
+async fn edit_and_delete_instances() {
+    let client = client_with_secret().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let (task, instance) = insert_task(&client, user_id, "Exercise").await;
+    let instance_id = instance["id"].as_i64().expect("Instance has an id.");
+    let session = login(&client, "justin").await;
+
+    let edit = format!("/instance/{}/edit?view=day&date=2020-10-12", instance_id);
+    assert_eq!(
+        submit(
+            &client,
+            &session,
+            &edit,
+            "start=2020-10-12T08:15&end=2020-10-12T09:45:30"
+        )
+        .await,
+        "/mindless/dashboard?view=day&date=2020-10-12"
+    );
+    assert_eq!(
+        submit(
+            &client,
+            &session,
+            &edit,
+            "start=2020-10-12T10:00&end=2020-10-12T09:00"
+        )
+        .await,
+        "/mindless/dashboard?view=day&date=2020-10-12&notice=invalid-period"
+    );
+
+    let (_, json) = post(
+        &client,
+        "/mindless/api/task",
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(
+        json["RetrieveAll"]["tasks"][0][1],
+        json!([{
+            "id": instance_id,
+            "task_id": task["id"],
+            "start": "2020-10-12T08:15:00",
+            "end": "2020-10-12T09:45:30"
+        }])
+    );
+
+    let delete = format!("/instance/{}/delete?view=day&date=2020-10-12", instance_id);
+    submit(&client, &session, &delete, "").await;
+    let (_, json) = post(
+        &client,
+        "/mindless/api/task",
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(json["RetrieveAll"]["tasks"][0][1], json!([]));
+}
+
+#[tokio::test]
+async fn instances_of_other_users_are_not_found() {
+    let client = client_with_secret().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let (_, instance) = insert_task(&client, user_id, "Exercise").await;
+    create_user(&client, "other", "Other").await;
+    let session = login(&client, "other").await;
+
+    let response = client
+        .post(format!(
+            "{}/instance/{}/delete",
+            DASHBOARD_URI,
+            instance["id"].as_i64().expect("Instance has an id.")
+        ))
+        .cookie(session.cookie)
+        .header(ContentType::Form)
+        .body(format!("csrf={}", session.csrf))
+        .dispatch()
+        .await;
+    let status = response.status();
+    let body = response.into_string().await.expect("Response has a body.");
+    let json: Value = serde_json::from_str(&body).expect("Rejections are json.");
+    assert_rejected(status, &json, "NotFound");
+}
+
+#[tokio::test]
+async fn manage_habits() {
+    let client = client_with_secret().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let session = login(&client, "justin").await;
+
+    let back = "/mindless/dashboard?view=day";
+    assert_eq!(
+        submit(&client, &session, "/habit?view=day", "path=Health").await,
+        back
+    );
+    assert_eq!(
+        submit(
+            &client,
+            &session,
+            "/habit?view=day",
+            "path=Health%2FExercise&repeat=1d"
+        )
+        .await,
+        back
+    );
+    assert_eq!(
+        submit(&client, &session, "/habit?view=day", "path=Health").await,
+        "/mindless/dashboard?view=day&notice=habit-exists"
+    );
+    assert_eq!(
+        submit(&client, &session, "/habit?view=day", "path=Sleep%2FNap").await,
+        "/mindless/dashboard?view=day&notice=missing-parent"
+    );
+    for repeat in &["often", "9999999999999d", "400d"] {
+        assert_eq!(
+            submit(
+                &client,
+                &session,
+                "/habit?view=day",
+                &format!("path=Reading&repeat={}", repeat)
+            )
+            .await,
+            "/mindless/dashboard?view=day&notice=invalid-habit",
+            "{}",
+            repeat
+        );
+    }
+
+    let (_, json) = post(
+        &client,
+        "/mindless/api/habit",
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    let habits = json["RetrieveAll"]["habits"].clone();
+    assert_eq!(habits[1]["name"], json!("Exercise"));
+    assert_eq!(habits[1]["repeat_period_sec"], json!(24 * 60 * 60));
+    let health = habits[0]["id"].as_i64().expect("Habit has an id.");
+    let exercise = habits[1]["id"].as_i64().expect("Habit has an id.");
+
+    submit(
+        &client,
+        &session,
+        &format!("/habit/{}/mark?view=day", exercise),
+        "",
+    )
+    .await;
+    let (_, html) = page(&client, &session, "view=day").await;
+    assert!(html.contains(&format!("/habit/{}/unmark?view=day", exercise)));
+
+    submit(
+        &client,
+        &session,
+        &format!("/habit/{}/unmark?view=day", exercise),
+        "",
+    )
+    .await;
+    let (_, html) = page(&client, &session, "view=day").await;
+    assert!(html.contains(&format!("/habit/{}/mark?view=day", exercise)));
+
+    // Deleting a habit deletes the habits under it.
+    submit(
+        &client,
+        &session,
+        &format!("/habit/{}/delete?view=day", health),
+        "",
+    )
+    .await;
+    let (_, json) = post(
+        &client,
+        "/mindless/api/habit",
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(json, json!({ "RetrieveAll": { "habits": [] } }));
+}
+
+#[tokio::test]
+async fn logout_clears_the_session() {
+    let client = client_with_secret().await;
+    create_user(&client, "justin", "Justin").await;
+    let session = login(&client, "justin").await;
+
+    let response = client
+        .post(format!("{}/logout", DASHBOARD_URI))
+        .cookie(session.cookie)
+        .header(ContentType::Form)
+        .body(format!("csrf={}", session.csrf))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(location(&response), "/mindless/dashboard/login");
+    let set_cookie = response
+        .headers()
+        .get_one("Set-Cookie")
+        .expect("Clears the session cookie.");
+    assert!(set_cookie.starts_with("mindless_session=;"));
+    assert!(set_cookie.contains("Max-Age=0"));
+}
+
+#[tokio::test]
+async fn forms_need_their_token() {
+    let client = client_with_secret().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    create_user(&client, "other", "Other").await;
+
+    // Logging in from another site, which can't read the login cookie.
+    let (_, token) = login_form(&client).await;
+    let response = client
+        .post(format!("{}/login", DASHBOARD_URI))
+        .header(ContentType::Form)
+        .body(format!("username=justin&csrf={}", token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(
+        location(&response),
+        "/mindless/dashboard/login?notice=form-expired"
+    );
+    assert!(response.headers().get_one("Set-Cookie").is_none());
+
+    let session = login(&client, "justin").await;
+    let other = login(&client, "other").await;
+    assert_ne!(session.csrf, other.csrf);
+    for csrf in &["forged".to_string(), other.csrf] {
+        let forged = Session {
+            cookie: session.cookie.clone(),
+            csrf: csrf.clone(),
+        };
+        assert_eq!(
+            submit(&client, &forged, "/habit?view=day", "path=Health").await,
+            "/mindless/dashboard?view=day&notice=form-expired"
+        );
+    }
+    let (_, json) = post(
+        &client,
+        "/mindless/api/habit",
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(json, json!({ "RetrieveAll": { "habits": [] } }));
+
+    // Logging out from another site keeps the session.
+    let forged = Session {
+        cookie: session.cookie.clone(),
+        csrf: "forged".to_string(),
+    };
+    assert_eq!(
+        submit(&client, &forged, "/logout", "").await,
+        "/mindless/dashboard?view=day&notice=form-expired"
+    );
+}
+
+#[tokio::test]
+async fn cookies_are_secure_over_https() {
+    let client = client_with(|config| {
+        config.auth.secret = Some(SECRET.to_string());
+        config.server.public_url = "https://mindless.example.com".to_string();
+    })
+    .await;
+    create_user(&client, "justin", "Justin").await;
+
+    let response = client
+        .get(format!("{}/login", DASHBOARD_URI))
+        .dispatch()
+        .await;
+    let set_login_cookie = response
+        .headers()
+        .get_one("Set-Cookie")
+        .expect("Sets the login cookie.");
+    assert!(set_login_cookie.contains("Secure"), "{}", set_login_cookie);
+
+    let session = login(&client, "justin").await;
+    let response = client
+        .post(format!("{}/logout", DASHBOARD_URI))
+        .cookie(session.cookie)
+        .header(ContentType::Form)
+        .body(format!("csrf={}", session.csrf))
+        .dispatch()
+        .await;
+    let cleared = response
+        .headers()
+        .get_one("Set-Cookie")
+        .expect("Clears the session cookie.");
+    assert!(cleared.contains("Secure"), "{}", cleared);
+
+    // Plain http is fine without https.
+    let client = client_with_secret().await;
+    let response = client
+        .get(format!("{}/login", DASHBOARD_URI))
+        .dispatch()
+        .await;
+    let set_login_cookie = response
+        .headers()
+        .get_one("Set-Cookie")
+        .expect("Sets the login cookie.");
+    assert!(!set_login_cookie.contains("Secure"), "{}", set_login_cookie);
+}
@@ -0,0 +1,149 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{assert_rejected, client, client_with, create_user, post};
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::{json, Value};
+
+/// Record an hour and a half of exercise on the Tuesday and Wednesday of the week of 2020-08-31.
+async fn exercise(client: &Client, user_id: i64) {
+    let (status, _) = post(
+        client,
+        "/mindless/api/task",
+        json!({ "InsertAll": { "tasks": [[
+            { "id": 0, "user_id": user_id, "name": "Exercise" },
+            [
+                { "id": 0, "task_id": 0, "start": "2020-09-01T10:00:00", "end": "2020-09-01T11:00:00" },
+                { "id": 0, "task_id": 0, "start": "2020-09-02T10:00:00", "end": "2020-09-02T10:30:00" }
+            ]
+        ]] } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+}
+
+async fn digest(client: &Client, uri: String) -> (Status, Option<ContentType>, String) {
+    let response = client.get(uri).dispatch().await;
+    let status = response.status();
+    let content_type = response.content_type();
+    let body = response.into_string().await.unwrap_or_default();
+
+    (status, content_type, body)
+}
+
+#[tokio::test]
+async fn digest_formats() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    exercise(&client, user_id).await;
+
+    let uri = |format: &str| {
+        format!(
+            "/mindless/api/digest/{}?week=2020-09-02&format={}",
+            user_id, format
+        )
+    };
+
+    let (status, content_type, body) = digest(&client, uri("json")).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(content_type, Some(ContentType::JSON));
+    let json: Value = serde_json::from_str(&body).expect("Digest is json.");
+    assert_eq!(json["week"], json!("2020-08-31"));
+    assert_eq!(json["seconds"], json!(90 * 60));
+    assert_eq!(json["tasks"][0]["name"], json!("Exercise"));
+    assert_eq!(json["best_day"]["date"], json!("2020-09-01"));
+
+    let (status, content_type, body) = digest(&client, uri("markdown")).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(content_type, Some(ContentType::new("text", "markdown")));
+    assert!(body.starts_with("# Week of 2020-08-31\n"), "{}", body);
+    assert!(body.contains("| Exercise | 1h30m | 0h00m |"), "{}", body);
+
+    let (status, content_type, body) = digest(&client, uri("html")).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(content_type, Some(ContentType::HTML));
+    assert!(body.contains("<h1>Week of 2020-08-31</h1>"), "{}", body);
+
+    let (status, _, body) = digest(&client, uri("pdf")).await;
+    assert_eq!(status, Status::BadRequest);
+    assert!(
+        body.contains("\\\"pdf\\\" is not one of json, markdown, html"),
+        "{}",
+        body
+    );
+}
+
+#[tokio::test]
+async fn invalid_digests() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    for week in &["last", "%2B262142-12-31", "1969-12-31"] {
+        let uri = format!("/mindless/api/digest/{}?week={}", user_id, week);
+        assert_eq!(digest(&client, uri).await.0, Status::BadRequest, "{}", week);
+    }
+    let (status, _, body) = digest(&client, "/mindless/api/digest/42".to_string()).await;
+    let json: Value = serde_json::from_str(&body).expect("Rejections are json.");
+    assert_rejected(status, &json, "NotFound");
+}
+
+#[tokio::test]
+async fn digest_channels() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let update = |digest_channel: &str| {
+        json!({ "UpdateSettings": {
+            "user_id": user_id,
+            "timezone": "UTC",
+            "digest_channel": digest_channel
+        } })
+    };
+
+    // Email is only offered with a mail server, and the app can fetch digests itself.
+    let (status, json) = post(&client, "/mindless/api/reminder", update("email")).await;
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(json["error"], json!("\"email\" is not one of webhook"));
+    let (status, _) = post(&client, "/mindless/api/reminder", update("poll")).await;
+    assert_eq!(status, Status::BadRequest);
+
+    let (status, json) = post(&client, "/mindless/api/reminder", update("webhook")).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        json["UpdateSettings"]["settings"]["digest_channel"],
+        json!("webhook")
+    );
+}
+
+#[tokio::test]
+async fn email_digests_need_an_address() {
+    let client = client_with(|config| config.reminders.smtp = Some(Default::default())).await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let (status, json) = post(
+        &client,
+        "/mindless/api/reminder",
+        json!({ "UpdateSettings": {
+            "user_id": user_id,
+            "timezone": "UTC",
+            "digest_channel": "email"
+        } }),
+    )
+    .await;
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(
+        json["error"],
+        json!("Digests by email need an email address")
+    );
+}
+
+#[tokio::test]
+async fn digests_can_be_disabled() {
+    let client = client_with(|config| config.features.digest = false).await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let uri = format!("/mindless/api/digest/{}", user_id);
+    assert_eq!(digest(&client, uri).await.0, Status::NotFound);
+}
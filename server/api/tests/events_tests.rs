@@ -0,0 +1,218 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{assert_rejected, client, client_with, config_with, create_user};
+use rocket::http::Status;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// A server listening on a free port, since event streams never end and the local client only
+/// reads whole responses. Returns the url it is at.
+async fn serve() -> String {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("A port is free.")
+        .port();
+    let config = config_with(|config| config.server.port = port);
+    let rocket = endpoint::liftoff(&config)
+        .await
+        .expect("Server should start.");
+    tokio::spawn(rocket.launch());
+
+    for _ in 0..100 {
+        if tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .is_ok()
+        {
+            return format!("http://127.0.0.1:{}", port);
+        }
+        tokio::time::delay_for(Duration::from_millis(20)).await;
+    }
+
+    panic!("Server never listened on {}", port);
+}
+
+async fn post(url: &str, uri: &str, body: Value) -> Value {
+    let response = reqwest::Client::new()
+        .post(&format!("{}{}", url, uri))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Server responds.");
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let body = response.text().await.expect("Response has a body.");
+
+    serde_json::from_str(&body).expect("Response is json.")
+}
+
+async fn create_task(url: &str, user_id: i64, name: &str) {
+    post(
+        url,
+        "/mindless/api/task",
+        json!({ "InsertAll": { "tasks": [[{ "id": 0, "user_id": user_id, "name": name }, []]] } }),
+    )
+    .await;
+}
+
+/// A server-sent event.
+#[derive(Debug)]
+struct Event {
+    id: Option<String>,
+    event: String,
+    data: Value,
+}
+
+/// An open stream of events, read as they arrive.
+struct Stream {
+    response: reqwest::Response,
+    buffer: String,
+}
+
+impl Stream {
+    async fn open(url: &str, user_id: i64, last_event_id: Option<&str>) -> Stream {
+        let mut request =
+            reqwest::Client::new().get(&format!("{}/mindless/api/events/{}", url, user_id));
+        if let Some(id) = last_event_id {
+            request = request.header("Last-Event-ID", id);
+        }
+        let response = request.send().await.expect("Server responds.");
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+        Stream {
+            response,
+            buffer: String::new(),
+        }
+    }
+
+    /// The next event, skipping keep alive comments.
+    async fn next(&mut self) -> Event {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let message: String = self.buffer.drain(..end + 2).collect();
+                let mut event = Event {
+                    id: None,
+                    event: String::new(),
+                    data: Value::Null,
+                };
+                for line in message.lines() {
+                    if let Some(id) = line.strip_prefix("id: ") {
+                        event.id = Some(id.to_string());
+                    } else if let Some(name) = line.strip_prefix("event: ") {
+                        event.event = name.to_string();
+                    } else if let Some(data) = line.strip_prefix("data: ") {
+                        event.data = serde_json::from_str(data).expect("Data is json.");
+                    }
+                }
+                if !event.event.is_empty() {
+                    return event;
+                }
+                continue;
+            }
+
+            let chunk = tokio::time::timeout(Duration::from_secs(5), self.response.chunk())
+                .await
+                .expect("An event arrives in time.")
+                .expect("Stream can be read.")
+                .expect("Stream is still open.");
+            self.buffer.push_str(&String::from_utf8_lossy(&chunk));
+        }
+    }
+}
+
+#[tokio::test]
+async fn changes_are_streamed() {
+    let url = serve().await;
+    let user = post(
+        &url,
+        "/mindless/api/user",
+        json!({ "Create": { "username": "justin", "name": "Justin" } }),
+    )
+    .await;
+    let user_id = user["Create"]["user"]["id"]
+        .as_i64()
+        .expect("User has an id.");
+
+    let mut stream = Stream::open(&url, user_id, None).await;
+    let connected = stream.next().await;
+    assert_eq!(connected.event, "Connected");
+    assert_eq!(connected.data, json!({ "sequence": 0 }));
+
+    create_task(&url, user_id, "Deep Work").await;
+    let created = stream.next().await;
+    assert_eq!(created.event, "TaskCreated");
+    assert!(created.id.is_some());
+    assert_eq!(created.data["sequence"], json!(1));
+    assert_eq!(created.data["user_id"], json!(user_id));
+    assert_eq!(
+        created.data["change"]["TaskCreated"]["task"]["name"],
+        json!("Deep Work")
+    );
+}
+
+#[tokio::test]
+async fn reconnecting_resumes() {
+    let url = serve().await;
+    let user = post(
+        &url,
+        "/mindless/api/user",
+        json!({ "Create": { "username": "justin", "name": "Justin" } }),
+    )
+    .await;
+    let user_id = user["Create"]["user"]["id"]
+        .as_i64()
+        .expect("User has an id.");
+
+    let mut stream = Stream::open(&url, user_id, None).await;
+    stream.next().await;
+    create_task(&url, user_id, "Deep Work").await;
+    let seen = stream.next().await.id.expect("Changes have ids.");
+    drop(stream);
+
+    // Missed while disconnected.
+    create_task(&url, user_id, "Exercise").await;
+    create_task(&url, user_id, "Reading").await;
+
+    let mut stream = Stream::open(&url, user_id, Some(&seen)).await;
+    assert_eq!(stream.next().await.data, json!({ "sequence": 3 }));
+    for name in &["Exercise", "Reading"] {
+        let missed = stream.next().await;
+        assert_eq!(missed.event, "TaskCreated");
+        assert_eq!(
+            missed.data["change"]["TaskCreated"]["task"]["name"],
+            json!(name)
+        );
+    }
+    drop(stream);
+
+    // An id from another run of the server can't be resumed from.
+    let mut stream = Stream::open(&url, user_id, Some("00000000-1")).await;
+    stream.next().await;
+    let reset = stream.next().await;
+    assert_eq!(reset.event, "Reset");
+    assert_eq!(reset.data, json!({ "sequence": 3 }));
+}
+
+#[tokio::test]
+async fn events_of_unknown_user() {
+    let client = client().await;
+
+    let response = client.get("/mindless/api/events/42").dispatch().await;
+    let status = response.status();
+    let body = response.into_string().await.expect("Response has a body.");
+    let json = serde_json::from_str(&body).expect("Response is json.");
+    assert_rejected(status, &json, "NotFound");
+}
+
+#[tokio::test]
+async fn events_can_be_disabled() {
+    let client = client_with(|config| config.features.events = false).await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let response = client
+        .get(format!("/mindless/api/events/{}", user_id))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
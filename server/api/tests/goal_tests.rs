@@ -0,0 +1,332 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use chrono::{Datelike, Duration, NaiveDate, Utc, Weekday};
+use common::{assert_rejected, client, client_with, create_user, post};
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::{json, Value};
+
+const GOAL_URI: &str = "/mindless/api/goal";
+
+/// Today in UTC, the timezone of users without settings. Goals only count from the period they
+/// were set in.
+fn today() -> NaiveDate {
+    Utc::today().naive_utc()
+}
+
+/// Insert a task with an hour on `day` and half an hour the day after.
+async fn create_task(client: &Client, user_id: i64, day: NaiveDate) -> i64 {
+    let at = |day: NaiveDate, time: &str| format!("{}T{}", day, time);
+    let next = day.succ();
+    let (status, json) = post(
+        client,
+        "/mindless/api/task",
+        json!({ "InsertAll": { "tasks": [[
+            { "id": 0, "user_id": user_id, "name": "Deep Work" },
+            [
+                { "id": 0, "task_id": 0, "start": at(day, "10:00:00"), "end": at(day, "11:00:00") },
+                { "id": 0, "task_id": 0, "start": at(next, "10:00:00"), "end": at(next, "10:30:00") }
+            ]
+        ]] } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+
+    json["InsertAll"]["tasks"][0][0]["id"]
+        .as_i64()
+        .expect("Task has an id.")
+}
+
+async fn create_habit(client: &Client, user_id: i64, path: &str) -> i64 {
+    let (status, json) = post(
+        client,
+        "/mindless/api/habit",
+        json!({ "Create": { "user_id": user_id, "path": path, "repeat_period_sec": 24 * 60 * 60 } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+
+    json["Create"]["habit"]["id"]
+        .as_i64()
+        .expect("Created habit has an id.")
+}
+
+async fn progress(
+    client: &Client,
+    user_id: i64,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> (Status, Value) {
+    let range = json!({ "user_id": user_id, "from": from.to_string(), "to": to.to_string() });
+    post(client, GOAL_URI, json!({ "Progress": range })).await
+}
+
+async fn create_goal(client: &Client, goal: Value) -> (Status, Value) {
+    post(client, GOAL_URI, json!({ "Create": goal })).await
+}
+
+#[tokio::test]
+async fn create_and_delete_goals() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let task_id = create_task(&client, user_id, today()).await;
+
+    let (status, json) = create_goal(
+        &client,
+        json!({
+            "user_id": user_id,
+            "task_id": task_id,
+            "comparison": "at_least",
+            "amount": 3600,
+            "unit": "seconds",
+            "cadence": "day"
+        }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+    let goal = &json["Create"]["goal"];
+    assert_eq!(goal["task_id"], json!(task_id));
+    assert_eq!(goal["habit_id"], Value::Null);
+    assert_eq!(goal["comparison"], json!("at_least"));
+    assert_eq!(goal["cadence"], json!("day"));
+
+    let (status, json) = post(
+        &client,
+        GOAL_URI,
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json["RetrieveAll"]["goals"], json!([goal]));
+
+    let delete = json!({ "Delete": { "user_id": user_id, "id": goal["id"] } });
+    let (status, _) = post(&client, GOAL_URI, delete.clone()).await;
+    assert_eq!(status, Status::Ok);
+    let (status, json) = post(&client, GOAL_URI, delete).await;
+    assert_rejected(status, &json, "NotFound");
+}
+
+#[tokio::test]
+async fn progress_over_a_range() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let task_id = create_task(&client, user_id, today()).await;
+
+    let (status, _) = create_goal(
+        &client,
+        json!({
+            "user_id": user_id,
+            "task_id": task_id,
+            "comparison": "at_least",
+            "amount": 3600,
+            "unit": "seconds",
+            "cadence": "day"
+        }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    let (status, _) = create_goal(
+        &client,
+        json!({
+            "user_id": user_id,
+            "task_id": task_id,
+            "comparison": "at_most",
+            "amount": 1,
+            "unit": "times",
+            "cadence": "week"
+        }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+
+    let today = today();
+    let (status, json) = progress(&client, user_id, today, today + Duration::days(2)).await;
+    assert_eq!(status, Status::Ok, "{}", json);
+    let goals = &json["Progress"]["goals"];
+
+    assert_eq!(
+        goals[0]["description"],
+        json!("At least 1h00m of Deep Work per day")
+    );
+    let daily: Vec<(Value, Value)> = goals[0]["periods"]
+        .as_array()
+        .expect("Periods are a list.")
+        .iter()
+        .map(|period| (period["actual"].clone(), period["met"].clone()))
+        .collect();
+    assert_eq!(
+        daily,
+        vec![
+            (json!(3600), json!(true)),
+            (json!(1800), json!(false)),
+            (json!(0), json!(false))
+        ]
+    );
+
+    // The week from this Monday, which only has tomorrow's work as well when today isn't Sunday.
+    let monday = today - Duration::days(today.weekday().num_days_from_monday().into());
+    let actual = if today.weekday() == Weekday::Sun {
+        1
+    } else {
+        2
+    };
+    assert_eq!(
+        goals[1]["description"],
+        json!("Deep Work at most once per week")
+    );
+    assert_eq!(
+        goals[1]["periods"][0],
+        json!({
+            "start": monday.to_string(),
+            "end": (monday + Duration::days(6)).to_string(),
+            "actual": actual,
+            "met": actual <= 1
+        })
+    );
+
+    // Both goals were set today, so nothing before this week is measured.
+    let (status, json) = progress(
+        &client,
+        user_id,
+        monday - Duration::days(14),
+        monday - Duration::days(1),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+    assert_eq!(json["Progress"]["goals"][0]["periods"], json!([]));
+    assert_eq!(json["Progress"]["goals"][1]["periods"], json!([]));
+
+    for (from, to) in &[
+        ("2020-09-03", "2020-09-01"),
+        ("2020-09-01", "+262142-12-31"),
+        ("1969-12-31", "2020-09-01"),
+    ] {
+        let (status, _) = post(
+            &client,
+            GOAL_URI,
+            json!({ "Progress": { "user_id": user_id, "from": from, "to": to } }),
+        )
+        .await;
+        assert_eq!(status, Status::BadRequest, "{} to {}", from, to);
+    }
+}
+
+#[tokio::test]
+async fn habit_goals() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let habit_id = create_habit(&client, user_id, "Run").await;
+
+    let goal = json!({
+        "user_id": user_id,
+        "habit_id": habit_id,
+        "comparison": "at_least",
+        "amount": 3,
+        "unit": "times",
+        "cadence": "week"
+    });
+    let (status, json) = create_goal(&client, goal.clone()).await;
+    assert_eq!(status, Status::Ok, "{}", json);
+
+    let (status, json) = post(
+        &client,
+        "/mindless/api/habit",
+        json!({ "Mark": { "user_id": user_id, "path": "Run" } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+
+    // The current week by default.
+    let (status, json) = post(
+        &client,
+        GOAL_URI,
+        json!({ "Progress": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+    let progress = &json["Progress"]["goals"][0];
+    assert_eq!(
+        progress["description"],
+        json!("Run at least 3 times per week")
+    );
+    assert_eq!(progress["periods"][0]["actual"], json!(1));
+    assert_eq!(progress["periods"][0]["met"], json!(false));
+
+    let mut seconds = goal;
+    seconds["unit"] = json!("seconds");
+    let (status, _) = create_goal(&client, seconds).await;
+    assert_eq!(status, Status::BadRequest);
+}
+
+#[tokio::test]
+async fn invalid_goals() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let other_id = create_user(&client, "other", "Other").await;
+    let task_id = create_task(&client, user_id, today()).await;
+    let habit_id = create_habit(&client, user_id, "Run").await;
+
+    let goal = json!({
+        "user_id": user_id,
+        "task_id": task_id,
+        "comparison": "at_least",
+        "amount": 3600,
+        "unit": "seconds",
+        "cadence": "week"
+    });
+
+    let mut both = goal.clone();
+    both["habit_id"] = json!(habit_id);
+    assert_eq!(create_goal(&client, both).await.0, Status::BadRequest);
+
+    let mut neither = goal.clone();
+    neither["task_id"] = Value::Null;
+    assert_eq!(create_goal(&client, neither).await.0, Status::BadRequest);
+
+    let mut nothing = goal.clone();
+    nothing["amount"] = json!(0);
+    assert_eq!(create_goal(&client, nothing).await.0, Status::BadRequest);
+
+    for (amount, cadence) in &[(7 * 24 * 3600 + 1, "week"), (i64::MAX, "month")] {
+        let mut too_much = goal.clone();
+        too_much["amount"] = json!(amount);
+        too_much["cadence"] = json!(cadence);
+        assert_eq!(
+            create_goal(&client, too_much).await.0,
+            Status::BadRequest,
+            "{} per {}",
+            amount,
+            cadence
+        );
+    }
+
+    let mut other = goal.clone();
+    other["user_id"] = json!(other_id);
+    let (status, json) = create_goal(&client, other).await;
+    assert_rejected(status, &json, "NotFound");
+
+    let (_, json) = create_goal(&client, goal).await;
+    let (status, json) = post(
+        &client,
+        GOAL_URI,
+        json!({ "Delete": { "user_id": other_id, "id": json["Create"]["goal"]["id"] } }),
+    )
+    .await;
+    assert_rejected(status, &json, "NotFound");
+}
+
+#[tokio::test]
+async fn goals_can_be_disabled() {
+    let client = client_with(|config| config.features.goals = false).await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let response = client
+        .post(GOAL_URI)
+        .header(ContentType::JSON)
+        .body(json!({ "RetrieveAll": { "user_id": user_id } }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
@@ -0,0 +1,140 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{assert_rejected, client, create_user, post};
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use serde_json::{json, Value};
+
+const HABIT_URI: &str = "/mindless/api/habit";
+
+const DAY_SEC: i64 = 24 * 60 * 60;
+
+/// Create a habit and return it.
+async fn create_habit(
+    client: &Client,
+    user_id: i64,
+    path: &str,
+    repeat_period_sec: Option<i64>,
+) -> Value {
+    let (status, json) = post(
+        client,
+        HABIT_URI,
+        json!({ "Create": { "user_id": user_id, "path": path, "repeat_period_sec": repeat_period_sec } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+
+    json["Create"]["habit"].clone()
+}
+
+#[tokio::test]
+async fn create_and_retrieve_habits() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let health = create_habit(&client, user_id, "Health", None).await;
+    let shower = create_habit(&client, user_id, "Health/Cold Shower", Some(DAY_SEC)).await;
+    assert_eq!(shower["parent_id"], health["id"]);
+    assert_eq!(shower["name"], json!("Cold Shower"));
+    assert_eq!(shower["repeat_period_sec"], json!(DAY_SEC));
+
+    let (status, json) = post(
+        &client,
+        HABIT_URI,
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        json,
+        json!({ "RetrieveAll": { "habits": [health, shower] } })
+    );
+}
+
+#[tokio::test]
+async fn create_habit_requires_parent() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let (status, json) = post(
+        &client,
+        HABIT_URI,
+        json!({ "Create": { "user_id": user_id, "path": "Health/Exercise", "repeat_period_sec": null } }),
+    )
+    .await;
+    assert_rejected(status, &json, "NotFound");
+
+    create_habit(&client, user_id, "Health", None).await;
+    let (status, json) = post(
+        &client,
+        HABIT_URI,
+        json!({ "Create": { "user_id": user_id, "path": "Health", "repeat_period_sec": null } }),
+    )
+    .await;
+    assert_rejected(status, &json, "AlreadyExists");
+}
+
+#[tokio::test]
+async fn mark_and_unmark_are_idempotent() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    create_habit(&client, user_id, "Health", None).await;
+    let gym = create_habit(&client, user_id, "Health/Gym", Some(DAY_SEC)).await;
+
+    let request = |action: &str| json!({ action: { "user_id": user_id, "path": "Health/Gym" } });
+
+    let (status, json) = post(&client, HABIT_URI, request("Mark")).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json["Mark"]["habit"], gym);
+    assert_eq!(json["Mark"]["marked"], json!(true));
+    // A day long period starting when the habit was created.
+    assert_eq!(json["Mark"]["period"]["start"], gym["created_at"]);
+
+    let (_, json) = post(&client, HABIT_URI, request("Mark")).await;
+    assert_eq!(json["Mark"]["marked"], json!(false));
+
+    let (status, json) = post(&client, HABIT_URI, request("Unmark")).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json["Unmark"]["unmarked"], json!(true));
+
+    let (_, json) = post(&client, HABIT_URI, request("Unmark")).await;
+    assert_eq!(json["Unmark"]["unmarked"], json!(false));
+
+    let (_, json) = post(&client, HABIT_URI, request("Mark")).await;
+    assert_eq!(json["Mark"]["marked"], json!(true));
+}
+
+#[tokio::test]
+async fn mark_unknown_habit() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let (status, json) = post(
+        &client,
+        HABIT_URI,
+        json!({ "Mark": { "user_id": user_id, "path": "Health/Gym" } }),
+    )
+    .await;
+    assert_rejected(status, &json, "NotFound");
+}
+
+#[tokio::test]
+async fn repeat_periods_are_bounded() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    for repeat in &[0, -DAY_SEC, 367 * DAY_SEC, i64::MAX] {
+        let (status, _) = post(
+            &client,
+            HABIT_URI,
+            json!({ "Create": { "user_id": user_id, "path": "Health", "repeat_period_sec": repeat } }),
+        )
+        .await;
+        assert_eq!(status, Status::BadRequest, "{}", repeat);
+    }
+
+    let year = create_habit(&client, user_id, "Health", Some(366 * DAY_SEC)).await;
+    assert_eq!(year["repeat_period_sec"], json!(366 * DAY_SEC));
+}
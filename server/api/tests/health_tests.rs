@@ -0,0 +1,30 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::client;
+use rocket::http::Status;
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn healthz() {
+    let client = client().await;
+
+    let response = client.get("/healthz").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let body = response.into_string().await.expect("Response has a body.");
+    let json: Value = serde_json::from_str(&body).expect("Response is json.");
+    assert_eq!(json, json!({ "ok": true }));
+}
+
+#[tokio::test]
+async fn readyz_after_migrations() {
+    let client = client().await;
+
+    let response = client.get("/readyz").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let body = response.into_string().await.expect("Response has a body.");
+    let json: Value = serde_json::from_str(&body).expect("Response is json.");
+    assert_eq!(json["ok"], json!(true));
+    assert_eq!(json["migrations"], json!({ "ok": true }));
+}
@@ -0,0 +1,257 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{assert_rejected, client, client_with, create_user, post};
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use serde_json::{json, Value};
+
+const REMINDER_URI: &str = "/mindless/api/reminder";
+
+async fn create_habit(client: &Client, user_id: i64, path: &str) -> i64 {
+    let (status, json) = post(
+        client,
+        "/mindless/api/habit",
+        json!({ "Create": { "user_id": user_id, "path": path, "repeat_period_sec": 24 * 60 * 60 } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+
+    json["Create"]["habit"]["id"]
+        .as_i64()
+        .expect("Created habit has an id.")
+}
+
+async fn create_reminder(client: &Client, user_id: i64, habit_id: i64, channel: &str) -> Value {
+    post(
+        client,
+        REMINDER_URI,
+        json!({ "Create": {
+            "user_id": user_id,
+            "habit_id": habit_id,
+            "time_of_day": "08:30",
+            "unless_done": true,
+            "channel": channel
+        } }),
+    )
+    .await
+    .1
+}
+
+async fn pending(client: &Client, user_id: i64) -> (Status, Option<Value>) {
+    let response = client
+        .get(format!("/mindless/api/reminder/pending/{}", user_id))
+        .dispatch()
+        .await;
+    let status = response.status();
+    let body = response
+        .into_string()
+        .await
+        .and_then(|body| serde_json::from_str(&body).ok());
+
+    (status, body)
+}
+
+#[tokio::test]
+async fn create_and_delete_reminders() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let habit_id = create_habit(&client, user_id, "Gym").await;
+
+    let json = create_reminder(&client, user_id, habit_id, "poll").await;
+    let reminder = &json["Create"]["reminder"];
+    assert_eq!(reminder["habit_id"], json!(habit_id));
+    assert_eq!(reminder["time_of_day"], json!("08:30"));
+    assert_eq!(reminder["unless_done"], json!(true));
+    assert_eq!(reminder["last_due_at"], Value::Null);
+
+    let (status, json) = post(
+        &client,
+        REMINDER_URI,
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(&json["RetrieveAll"]["reminders"][0], reminder);
+
+    // Nothing went off yet.
+    assert_eq!(
+        pending(&client, user_id).await,
+        (Status::Ok, Some(json!([])))
+    );
+
+    let delete = json!({ "Delete": { "user_id": user_id, "id": reminder["id"] } });
+    let (status, _) = post(&client, REMINDER_URI, delete.clone()).await;
+    assert_eq!(status, Status::Ok);
+    let (status, json) = post(&client, REMINDER_URI, delete).await;
+    assert_rejected(status, &json, "NotFound");
+}
+
+#[tokio::test]
+async fn reminders_of_other_users() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let other_id = create_user(&client, "other", "Other").await;
+    let habit_id = create_habit(&client, user_id, "Gym").await;
+
+    let (status, json) = post(
+        &client,
+        REMINDER_URI,
+        json!({ "Create": {
+            "user_id": other_id,
+            "habit_id": habit_id,
+            "time_of_day": "08:30",
+            "channel": "poll"
+        } }),
+    )
+    .await;
+    assert_rejected(status, &json, "NotFound");
+
+    let created = create_reminder(&client, user_id, habit_id, "poll").await;
+    let (status, json) = post(
+        &client,
+        REMINDER_URI,
+        json!({ "Delete": { "user_id": other_id, "id": created["Create"]["reminder"]["id"] } }),
+    )
+    .await;
+    assert_rejected(status, &json, "NotFound");
+
+    let (status, json) = post(
+        &client,
+        REMINDER_URI,
+        json!({ "Acknowledge": { "user_id": user_id, "id": 42 } }),
+    )
+    .await;
+    assert_rejected(status, &json, "NotFound");
+    assert_eq!(
+        pending(&client, 42).await,
+        (Status::Ok, Some(json!({ "error": "NotFound" })))
+    );
+}
+
+#[tokio::test]
+async fn invalid_reminders() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let habit_id = create_habit(&client, user_id, "Gym").await;
+
+    let (status, json) = post(
+        &client,
+        REMINDER_URI,
+        json!({ "Create": {
+            "user_id": user_id,
+            "habit_id": habit_id,
+            "time_of_day": "8:30pm",
+            "channel": "poll"
+        } }),
+    )
+    .await;
+    assert_eq!(status, Status::BadRequest);
+    assert!(json["error"].as_str().unwrap().contains("8:30pm"));
+
+    // Email is only offered with a mail server.
+    let json = create_reminder(&client, user_id, habit_id, "email").await;
+    assert_eq!(
+        json["error"],
+        json!("\"email\" is not one of poll, webhook")
+    );
+}
+
+#[tokio::test]
+async fn settings() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let (status, json) = post(
+        &client,
+        REMINDER_URI,
+        json!({ "Settings": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        json["Settings"]["settings"],
+        json!({
+            "user_id": user_id,
+            "timezone": "UTC",
+            "email": null,
+            "quiet_start": null,
+            "quiet_end": null,
+            "digest_channel": null
+        })
+    );
+
+    let update = |timezone: &str, quiet_end: Option<&str>| {
+        json!({ "UpdateSettings": {
+            "user_id": user_id,
+            "timezone": timezone,
+            "email": "justin@example.com",
+            "quiet_start": "22:00",
+            "quiet_end": quiet_end
+        } })
+    };
+
+    let (status, _) = post(&client, REMINDER_URI, update("Mars/Olympus", Some("07:00"))).await;
+    assert_eq!(status, Status::BadRequest);
+    let (status, _) = post(&client, REMINDER_URI, update("Australia/Sydney", None)).await;
+    assert_eq!(status, Status::BadRequest);
+
+    let (status, json) = post(
+        &client,
+        REMINDER_URI,
+        update("Australia/Sydney", Some("07:00")),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    let (_, retrieved) = post(
+        &client,
+        REMINDER_URI,
+        json!({ "Settings": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(
+        retrieved["Settings"]["settings"],
+        json["UpdateSettings"]["settings"]
+    );
+    assert_eq!(
+        retrieved["Settings"]["settings"]["quiet_end"],
+        json!("07:00")
+    );
+}
+
+#[tokio::test]
+async fn email_reminders_need_an_address() {
+    let client = client_with(|config| config.reminders.smtp = Some(Default::default())).await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let habit_id = create_habit(&client, user_id, "Gym").await;
+
+    let json = create_reminder(&client, user_id, habit_id, "email").await;
+    assert_eq!(
+        json["error"],
+        json!("Set an email address with UpdateSettings first")
+    );
+
+    let (status, _) = post(
+        &client,
+        REMINDER_URI,
+        json!({ "UpdateSettings": {
+            "user_id": user_id,
+            "timezone": "UTC",
+            "email": "justin@example.com"
+        } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+
+    let json = create_reminder(&client, user_id, habit_id, "email").await;
+    assert_eq!(json["Create"]["reminder"]["channel"], json!("email"));
+}
+
+#[tokio::test]
+async fn reminders_can_be_disabled() {
+    let client = client_with(|config| config.features.reminders = false).await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    assert_eq!(pending(&client, user_id).await.0, Status::NotFound);
+}
@@ -0,0 +1,257 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{assert_rejected, client, client_with, create_user, post};
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::{json, Value};
+
+const TAG_URI: &str = "/mindless/api/tag";
+const TASK_URI: &str = "/mindless/api/task";
+
+/// Record client work on the 1st and 2nd of September 2020 and return the task with its instances.
+async fn client_work(client: &Client, user_id: i64) -> Value {
+    let (status, json) = post(
+        client,
+        TASK_URI,
+        json!({ "InsertAll": { "tasks": [[
+            { "id": 0, "user_id": user_id, "name": "Client Work" },
+            [
+                { "id": 0, "task_id": 0, "start": "2020-09-01T10:00:00", "end": "2020-09-01T11:00:00" },
+                { "id": 0, "task_id": 0, "start": "2020-09-02T14:00:00", "end": "2020-09-02T14:30:00" }
+            ]
+        ]] } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+
+    json["InsertAll"]["tasks"][0].clone()
+}
+
+async fn create_tag(client: &Client, user_id: i64, name: &str) -> i64 {
+    let (status, json) = post(
+        client,
+        TAG_URI,
+        json!({ "Create": { "user_id": user_id, "name": name } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+
+    json["Create"]["tag"]["id"]
+        .as_i64()
+        .expect("Created tag has an id.")
+}
+
+async fn get(client: &Client, uri: String) -> (Status, String) {
+    let response = client.get(uri).dispatch().await;
+    let status = response.status();
+    let body = response.into_string().await.unwrap_or_default();
+
+    (status, body)
+}
+
+#[tokio::test]
+async fn create_rename_and_delete_tags() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let other_id = create_user(&client, "other", "Other").await;
+
+    let (status, json) = post(
+        &client,
+        TAG_URI,
+        json!({ "Create": { "user_id": user_id, "name": " Billable " } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+    let billable = json["Create"]["tag"].clone();
+    assert_eq!(billable["name"], json!("billable"));
+
+    let (status, json) = post(
+        &client,
+        TAG_URI,
+        json!({ "Create": { "user_id": user_id, "name": "billable" } }),
+    )
+    .await;
+    assert_rejected(status, &json, "AlreadyExists");
+
+    // Names are unique per user only.
+    create_tag(&client, other_id, "billable").await;
+    let outside_id = create_tag(&client, user_id, "outside").await;
+    let rename = |user_id: i64, name: &str| json!({ "Rename": { "user_id": user_id, "id": outside_id, "name": name } });
+    let (status, json) = post(&client, TAG_URI, rename(user_id, "billable")).await;
+    assert_rejected(status, &json, "AlreadyExists");
+    let (status, json) = post(&client, TAG_URI, rename(other_id, "mine")).await;
+    assert_rejected(status, &json, "NotFound");
+    let (status, json) = post(&client, TAG_URI, rename(user_id, "Outdoor")).await;
+    assert_eq!(status, Status::Ok, "{}", json);
+    let outdoor = json["Rename"]["tag"].clone();
+    assert_eq!(outdoor["name"], json!("outdoor"));
+
+    let (status, json) = post(
+        &client,
+        TAG_URI,
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json["RetrieveAll"]["tags"], json!([billable, outdoor]));
+
+    let delete = json!({ "Delete": { "user_id": user_id, "id": outside_id } });
+    let (status, _) = post(&client, TAG_URI, delete.clone()).await;
+    assert_eq!(status, Status::Ok);
+    let (status, json) = post(&client, TAG_URI, delete).await;
+    assert_rejected(status, &json, "NotFound");
+}
+
+#[tokio::test]
+async fn tag_tasks_and_instances() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let other_id = create_user(&client, "other", "Other").await;
+    let task = client_work(&client, user_id).await;
+    let task_id = task[0]["id"].clone();
+    let meeting_id = task[1][0]["id"].clone();
+    let billable_id = create_tag(&client, user_id, "billable").await;
+    let outdoor_id = create_tag(&client, user_id, "outdoor").await;
+
+    let (status, json) = post(
+        &client,
+        TAG_URI,
+        json!({ "TagTask": { "user_id": user_id, "id": billable_id, "task_id": task_id } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+    assert_eq!(json["TagTask"]["task"]["tags"], json!(["billable"]));
+
+    let (status, json) = post(
+        &client,
+        TAG_URI,
+        json!({ "TagInstance": { "user_id": user_id, "id": outdoor_id, "instance_id": meeting_id } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+    assert_eq!(json["TagInstance"]["instance"]["id"], meeting_id);
+    assert_eq!(json["TagInstance"]["instance"]["tags"], json!(["outdoor"]));
+
+    // Neither the tag nor the task belong to the other user.
+    let (status, json) = post(
+        &client,
+        TAG_URI,
+        json!({ "TagTask": { "user_id": other_id, "id": billable_id, "task_id": task_id } }),
+    )
+    .await;
+    assert_rejected(status, &json, "NotFound");
+    let other_tag_id = create_tag(&client, other_id, "mine").await;
+    let (status, json) = post(
+        &client,
+        TAG_URI,
+        json!({ "TagInstance": { "user_id": other_id, "id": other_tag_id, "instance_id": meeting_id } }),
+    )
+    .await;
+    assert_rejected(status, &json, "NotFound");
+
+    let retrieve = |tag: Value| json!({ "RetrieveAll": { "user_id": user_id, "tag": tag } });
+    let (status, json) = post(&client, TASK_URI, retrieve(Value::Null)).await;
+    assert_eq!(status, Status::Ok);
+    let tasks = &json["RetrieveAll"]["tasks"];
+    assert_eq!(tasks[0][0]["tags"], json!(["billable"]));
+    assert_eq!(tasks[0][1][0]["tags"], json!(["outdoor"]));
+    assert_eq!(tasks[0][1][1]["tags"], json!([]));
+
+    // Instances have the tags of their task.
+    let (_, json) = post(&client, TASK_URI, retrieve(json!("billable"))).await;
+    assert_eq!(
+        json["RetrieveAll"]["tasks"][0][1]
+            .as_array()
+            .expect("Instances are a list.")
+            .len(),
+        2
+    );
+    let (_, json) = post(&client, TASK_URI, retrieve(json!("outdoor"))).await;
+    let instances = &json["RetrieveAll"]["tasks"][0][1];
+    assert_eq!(
+        instances.as_array().expect("Instances are a list.").len(),
+        1
+    );
+    assert_eq!(instances[0]["id"], meeting_id);
+
+    let (status, json) = post(&client, TASK_URI, retrieve(json!("unknown"))).await;
+    assert_rejected(status, &json, "NotFound");
+
+    let (status, json) = post(
+        &client,
+        TAG_URI,
+        json!({ "UntagTask": { "user_id": user_id, "id": billable_id, "task_id": task_id } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json["UntagTask"]["task"]["tags"], json!([]));
+    let (_, json) = post(&client, TASK_URI, retrieve(json!("billable"))).await;
+    assert_eq!(json["RetrieveAll"]["tasks"], json!([]));
+}
+
+#[tokio::test]
+async fn charts_and_digests_by_tag() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let task = client_work(&client, user_id).await;
+    let outdoor_id = create_tag(&client, user_id, "outdoor").await;
+    let (status, _) = post(
+        &client,
+        TAG_URI,
+        json!({ "TagInstance": { "user_id": user_id, "id": outdoor_id, "instance_id": task[1][1]["id"] } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+
+    let range = "from=2020-09-01&to=2020-09-30";
+    let uri = format!(
+        "/mindless/api/chart/{}/hours.svg?{}&tag=outdoor",
+        user_id, range
+    );
+    let (status, svg) = get(&client, uri).await;
+    assert_eq!(status, Status::Ok);
+    assert!(
+        svg.contains("<title>14:00 to 15:00: 0h30m</title>"),
+        "{}",
+        svg
+    );
+    assert!(!svg.contains("<title>10:00 to 11:00"), "{}", svg);
+
+    let uri = format!(
+        "/mindless/api/chart/{}/weekly.svg?{}&tag=unknown",
+        user_id, range
+    );
+    let (status, body) = get(&client, uri).await;
+    let json: Value = serde_json::from_str(&body).expect("Rejections are json.");
+    assert_rejected(status, &json, "NotFound");
+    let uri = format!(
+        "/mindless/api/chart/{}/heatmap.svg?{}&tag=not%20valid",
+        user_id, range
+    );
+    assert_eq!(get(&client, uri).await.0, Status::BadRequest);
+
+    let uri = format!(
+        "/mindless/api/digest/{}?week=2020-09-01&tag=outdoor",
+        user_id
+    );
+    let (status, body) = get(&client, uri).await;
+    assert_eq!(status, Status::Ok, "{}", body);
+    let digest: Value = serde_json::from_str(&body).expect("Digest is json.");
+    assert_eq!(digest["seconds"], json!(30 * 60));
+}
+
+#[tokio::test]
+async fn tags_can_be_disabled() {
+    let client = client_with(|config| config.features.tags = false).await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let response = client
+        .post(TAG_URI)
+        .header(ContentType::JSON)
+        .body(json!({ "RetrieveAll": { "user_id": user_id } }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
@@ -0,0 +1,140 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{assert_rejected, client, create_user, post};
+use rocket::http::Status;
+use serde_json::{json, Value};
+
+const TASK_URI: &str = "/mindless/api/task";
+
+/// A task with its instances as sent by the app, before anything has an id.
+fn exercise(user_id: i64) -> Value {
+    json!([
+        { "id": 0, "user_id": user_id, "name": "Exercise" },
+        [
+            { "id": 0, "task_id": 0, "start": "2020-09-01T10:00:00", "end": "2020-09-01T11:00:00" },
+            { "id": 0, "task_id": 0, "start": "2020-09-02T10:00:00", "end": "2020-09-02T10:30:00" }
+        ]
+    ])
+}
+
+#[tokio::test]
+async fn insert_and_retrieve_tasks() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let (status, inserted) = post(
+        &client,
+        TASK_URI,
+        json!({ "InsertAll": { "tasks": [exercise(user_id)] } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+
+    let tasks = inserted["InsertAll"]["tasks"]
+        .as_array()
+        .expect("Inserted tasks are a list.");
+    assert_eq!(tasks.len(), 1);
+    let task_id = tasks[0][0]["id"].as_i64().expect("Task has an id.");
+    assert_eq!(
+        tasks[0][0],
+        json!({ "id": task_id, "user_id": user_id, "name": "Exercise", "tags": [] })
+    );
+    let instances = tasks[0][1].as_array().expect("Instances are a list.");
+    assert_eq!(instances.len(), 2);
+    for instance in instances {
+        assert_ne!(instance["id"], json!(0));
+        assert_eq!(instance["task_id"], json!(task_id));
+        assert_eq!(instance["tags"], json!([]));
+    }
+
+    let (status, retrieved) = post(
+        &client,
+        TASK_URI,
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        retrieved["RetrieveAll"]["tasks"],
+        inserted["InsertAll"]["tasks"]
+    );
+}
+
+#[tokio::test]
+async fn insert_all_is_idempotent() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let request = json!({ "InsertAll": { "tasks": [exercise(user_id)] } });
+    let (_, first) = post(&client, TASK_URI, request.clone()).await;
+    let (status, second) = post(&client, TASK_URI, request).await;
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(first, second);
+}
+
+#[tokio::test]
+async fn retrieve_tasks_of_user_without_tasks() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    let (status, json) = post(
+        &client,
+        TASK_URI,
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json, json!({ "RetrieveAll": { "tasks": [] } }));
+}
+
+#[tokio::test]
+async fn retrieve_tasks_of_unknown_user() {
+    let client = client().await;
+
+    let (status, json) = post(
+        &client,
+        TASK_URI,
+        json!({ "RetrieveAll": { "user_id": 42 } }),
+    )
+    .await;
+    assert_rejected(status, &json, "NotFound");
+}
+
+#[tokio::test]
+async fn rename_task() {
+    let client = client().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let other_id = create_user(&client, "other", "Other").await;
+    let (_, inserted) = post(
+        &client,
+        TASK_URI,
+        json!({ "InsertAll": { "tasks": [exercise(user_id)] } }),
+    )
+    .await;
+    let task_id = inserted["InsertAll"]["tasks"][0][0]["id"].clone();
+
+    let rename = |user_id: i64, name: &str| json!({ "RenameTask": { "user_id": user_id, "task_id": task_id, "name": name } });
+    let (status, json) = post(&client, TASK_URI, rename(user_id, "Workout")).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        json["RenameTask"]["task"],
+        json!({ "id": task_id, "user_id": user_id, "name": "Workout", "tags": [] })
+    );
+
+    // Only the owner can rename it.
+    let (status, json) = post(&client, TASK_URI, rename(other_id, "Mine")).await;
+    assert_rejected(status, &json, "NotFound");
+
+    let (_, json) = post(
+        &client,
+        TASK_URI,
+        json!({ "InsertAll": { "tasks": [[{ "id": 0, "user_id": user_id, "name": "Reading" }, []]] } }),
+    )
+    .await;
+    assert_eq!(json["InsertAll"]["tasks"][0][0]["name"], json!("Reading"));
+    let (status, json) = post(&client, TASK_URI, rename(user_id, "Reading")).await;
+    assert_rejected(status, &json, "AlreadyExists");
+}
@@ -0,0 +1,114 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{assert_rejected, client, create_user, post};
+use rocket::http::{ContentType, Status};
+use serde_json::json;
+
+const USER_URI: &str = "/mindless/api/user";
+
+#[tokio::test]
+async fn create_user_and_login() {
+    let client = client().await;
+
+    let (status, json) = post(
+        &client,
+        USER_URI,
+        json!({ "Create": { "username": "justin", "name": "Justin" } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    let id = json["Create"]["user"]["id"]
+        .as_i64()
+        .expect("User has an id.");
+    assert_eq!(
+        json,
+        json!({ "Create": { "user": { "id": id, "username": "justin", "name": "Justin" } } })
+    );
+
+    let (status, json) = post(
+        &client,
+        USER_URI,
+        json!({ "Login": { "username": "justin" } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        json,
+        json!({ "Login": { "user": { "id": id, "username": "justin", "name": "Justin" } } })
+    );
+}
+
+#[tokio::test]
+async fn create_duplicate_user() {
+    let client = client().await;
+    create_user(&client, "justin", "Justin").await;
+
+    let (status, json) = post(
+        &client,
+        USER_URI,
+        json!({ "Create": { "username": "justin", "name": "Someone else" } }),
+    )
+    .await;
+    assert_rejected(status, &json, "AlreadyExists");
+}
+
+#[tokio::test]
+async fn login_unknown_user() {
+    let client = client().await;
+
+    let (status, json) = post(
+        &client,
+        USER_URI,
+        json!({ "Login": { "username": "nobody" } }),
+    )
+    .await;
+    assert_rejected(status, &json, "NotFound");
+}
+
+#[tokio::test]
+async fn update_user() {
+    let client = client().await;
+    let id = create_user(&client, "justin", "Justin").await;
+
+    let user = json!({ "id": id, "username": "james", "name": "James" });
+    let (status, json) = post(&client, USER_URI, json!({ "Update": { "user": user } })).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json, json!({ "Update": { "user": user } }));
+
+    let (status, json) = post(
+        &client,
+        USER_URI,
+        json!({ "Login": { "username": "james" } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json, json!({ "Login": { "user": user } }));
+}
+
+#[tokio::test]
+async fn delete_user() {
+    let client = client().await;
+    let id = create_user(&client, "justin", "Justin").await;
+
+    let (status, json) = post(&client, USER_URI, json!({ "Delete": { "id": id } })).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json, json!("Delete"));
+
+    let (status, json) = post(&client, USER_URI, json!({ "Delete": { "id": id } })).await;
+    assert_rejected(status, &json, "NotFound");
+}
+
+#[tokio::test]
+async fn reject_unknown_request() {
+    let client = client().await;
+
+    let response = client
+        .post(USER_URI)
+        .header(ContentType::JSON)
+        .body(json!({ "Explode": {} }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
@@ -0,0 +1,424 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{assert_rejected, client_with, create_user, post};
+use endpoint::signing::Signer;
+use endpoint::webhook::{EVENT_HEADER, SIGNATURE_HEADER};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const WEBHOOK_URI: &str = "/mindless/api/webhook";
+
+/// A post received by the stand-in server.
+#[derive(Debug, Clone)]
+struct Received {
+    event: String,
+    signature: String,
+    body: String,
+}
+
+/// Stands in for whatever a user points their webhook at.
+struct StandIn {
+    url: String,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+
+impl StandIn {
+    /// Start a server which responds with the given statuses in order and then with 200.
+    fn start(statuses: Vec<u16>) -> StandIn {
+        StandIn::serve(statuses, None)
+    }
+
+    /// Start a server which always redirects to `location`.
+    fn redirecting(location: &str) -> StandIn {
+        StandIn::serve(vec![302; 10], Some(location.to_string()))
+    }
+
+    fn serve(statuses: Vec<u16>, location: Option<String>) -> StandIn {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let statuses = Arc::new(Mutex::new(statuses.into_iter()));
+
+        let log = received.clone();
+        let make_service = make_service_fn(move |_| {
+            let log = log.clone();
+            let statuses = statuses.clone();
+            let location = location.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let log = log.clone();
+                    let statuses = statuses.clone();
+                    let location = location.clone();
+                    async move {
+                        let header = |name: &str| {
+                            request
+                                .headers()
+                                .get(name)
+                                .and_then(|value| value.to_str().ok())
+                                .unwrap_or_default()
+                                .to_string()
+                        };
+                        let event = header(EVENT_HEADER);
+                        let signature = header(SIGNATURE_HEADER);
+                        let body = hyper::body::to_bytes(request.into_body()).await?;
+
+                        log.lock().unwrap().push(Received {
+                            event,
+                            signature,
+                            body: String::from_utf8_lossy(&body).into_owned(),
+                        });
+                        let status = statuses.lock().unwrap().next().unwrap_or(200);
+
+                        let mut response = Response::builder().status(status);
+                        if let Some(location) = location {
+                            response = response.header("Location", location);
+                        }
+                        Ok::<_, hyper::Error>(
+                            response.body(Body::empty()).expect("Valid response."),
+                        )
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let url = format!("http://{}/hook", server.local_addr());
+        tokio::spawn(server);
+
+        StandIn { url, received }
+    }
+
+    fn received(&self) -> Vec<Received> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+/// A client which retries quickly so the tests don't wait on the backoff, and may post to the
+/// stand ins on this machine.
+async fn client_with_quick_retries() -> Client {
+    client_with(|config| {
+        config.webhooks.backoff_ms = 10;
+        config.webhooks.allowed_hosts = vec!["127.0.0.1".to_string(), "localhost".to_string()];
+    })
+    .await
+}
+
+/// Register a webhook and return it along with its secret.
+async fn register(client: &Client, user_id: i64, url: &str, events: Value) -> (Value, String) {
+    let (status, json) = post(
+        client,
+        WEBHOOK_URI,
+        json!({ "Register": { "user_id": user_id, "url": url, "events": events } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+
+    let secret = json["Register"]["secret"]
+        .as_str()
+        .expect("Secret is a string.")
+        .to_string();
+
+    (json["Register"]["webhook"].clone(), secret)
+}
+
+/// Wait until the delivery log of a webhook has at least `count` attempts.
+async fn deliveries(client: &Client, user_id: i64, webhook: &Value, count: usize) -> Vec<Value> {
+    let request = json!({ "Deliveries": { "user_id": user_id, "id": webhook["id"] } });
+    for _ in 0..200 {
+        let (status, json) = post(client, WEBHOOK_URI, request.clone()).await;
+        assert_eq!(status, Status::Ok, "{}", json);
+
+        let deliveries = json["Deliveries"]["deliveries"]
+            .as_array()
+            .expect("Deliveries are a list.")
+            .clone();
+        if deliveries.len() >= count {
+            return deliveries;
+        }
+
+        tokio::time::delay_for(Duration::from_millis(20)).await;
+    }
+
+    panic!("Expected {} deliveries to {}", count, webhook);
+}
+
+/// Kinds of the events delivered, sorted since deliveries run concurrently.
+fn kinds(deliveries: &[Value]) -> Vec<String> {
+    let mut kinds: Vec<String> = deliveries
+        .iter()
+        .map(|delivery| delivery["event"].as_str().expect("Has a kind.").to_string())
+        .collect();
+    kinds.sort();
+    kinds
+}
+
+async fn create_habit(client: &Client, user_id: i64, path: &str) {
+    let (status, json) = post(
+        client,
+        "/mindless/api/habit",
+        json!({ "Create": { "user_id": user_id, "path": path, "repeat_period_sec": 24 * 60 * 60 } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok, "{}", json);
+}
+
+#[tokio::test]
+async fn completed_habits_are_signed_and_delivered() {
+    let stand_in = StandIn::start(Vec::new());
+    let client = client_with_quick_retries().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let (webhook, secret) = register(&client, user_id, &stand_in.url, json!([])).await;
+    assert_eq!(webhook["events"], json!([]));
+    create_habit(&client, user_id, "Gym").await;
+
+    let mark = json!({ "Mark": { "user_id": user_id, "path": "Gym" } });
+    let (status, marked) = post(&client, "/mindless/api/habit", mark.clone()).await;
+    assert_eq!(status, Status::Ok);
+    // Already marked so there is nothing to deliver.
+    let (_, json) = post(&client, "/mindless/api/habit", mark).await;
+    assert_eq!(json["Mark"]["marked"], json!(false));
+
+    let log = deliveries(&client, user_id, &webhook, 1).await;
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0]["event"], json!("HabitCompleted"));
+    assert_eq!(log[0]["attempt"], json!(1));
+    assert_eq!(log[0]["status_code"], json!(200));
+    assert_eq!(log[0]["error"], json!(null));
+
+    let received = stand_in.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].event, "HabitCompleted");
+    assert_eq!(received[0].body, log[0]["payload"].as_str().unwrap());
+    let signature = received[0]
+        .signature
+        .strip_prefix("sha256=")
+        .expect("Signature names its algorithm.");
+    assert!(Signer::new(&secret).verify(received[0].body.as_bytes(), signature));
+    assert!(!Signer::new("some other secret").verify(received[0].body.as_bytes(), signature));
+
+    let payload: Value = serde_json::from_str(&received[0].body).expect("Payload is json.");
+    assert_eq!(payload["webhook_id"], webhook["id"]);
+    assert_eq!(payload["user_id"], json!(user_id));
+    assert_eq!(
+        payload["event"]["HabitCompleted"]["habit"],
+        marked["Mark"]["habit"]
+    );
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried() {
+    let stand_in = StandIn::start(vec![500, 503]);
+    let client = client_with_quick_retries().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let (webhook, _) = register(&client, user_id, &stand_in.url, json!([])).await;
+    create_habit(&client, user_id, "Gym").await;
+
+    post(
+        &client,
+        "/mindless/api/habit",
+        json!({ "Mark": { "user_id": user_id, "path": "Gym" } }),
+    )
+    .await;
+
+    let log = deliveries(&client, user_id, &webhook, 3).await;
+    let attempts: Vec<(Value, Value)> = log
+        .iter()
+        .map(|delivery| (delivery["attempt"].clone(), delivery["status_code"].clone()))
+        .collect();
+    assert_eq!(
+        attempts,
+        vec![
+            (json!(1), json!(500)),
+            (json!(2), json!(503)),
+            (json!(3), json!(200)),
+        ]
+    );
+    assert!(log[0]["error"].is_string());
+    assert_eq!(log[2]["error"], json!(null));
+    // Every attempt posts the same body.
+    assert!(log
+        .iter()
+        .all(|delivery| delivery["payload"] == log[0]["payload"]));
+    assert_eq!(stand_in.received().len(), 3);
+}
+
+#[tokio::test]
+async fn events_are_filtered() {
+    let stand_in = StandIn::start(Vec::new());
+    let client = client_with_quick_retries().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let (timers, _) = register(
+        &client,
+        user_id,
+        &stand_in.url,
+        json!(["TimerStarted", "TimerEnded"]),
+    )
+    .await;
+    let (everything, _) = register(&client, user_id, &stand_in.url, json!([])).await;
+
+    let upload = json!({ "InsertAll": { "tasks": [[
+        { "id": 0, "user_id": user_id, "name": "Exercise" },
+        [{ "id": 0, "task_id": 0, "start": "2020-09-01T10:00:00", "end": "2020-09-01T11:00:00" }]
+    ]] } });
+    let (status, json) = post(&client, "/mindless/api/task", upload.clone()).await;
+    assert_eq!(status, Status::Ok, "{}", json);
+    let task_id = json["InsertAll"]["tasks"][0][0]["id"].clone();
+    // Uploading the same instances again creates nothing.
+    let (status, _) = post(&client, "/mindless/api/task", upload).await;
+    assert_eq!(status, Status::Ok);
+
+    for request in &["StartTimer", "StopTimer"] {
+        let (status, json) = post(
+            &client,
+            "/mindless/api/task",
+            json!({ *request: { "user_id": user_id, "task_id": task_id } }),
+        )
+        .await;
+        assert_eq!(status, Status::Ok, "{}", json);
+    }
+
+    let log = deliveries(&client, user_id, &timers, 2).await;
+    assert_eq!(kinds(&log), vec!["TimerEnded", "TimerStarted"]);
+    let log = deliveries(&client, user_id, &everything, 4).await;
+    assert_eq!(
+        kinds(&log),
+        vec![
+            "InstanceCreated",
+            "InstanceCreated",
+            "TimerEnded",
+            "TimerStarted"
+        ]
+    );
+    assert_eq!(stand_in.received().len(), 6);
+}
+
+#[tokio::test]
+async fn redirects_are_not_followed() {
+    let target = StandIn::start(Vec::new());
+    let redirect = StandIn::redirecting(&target.url);
+    let client = client_with_quick_retries().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let (webhook, _) = register(&client, user_id, &redirect.url, json!([])).await;
+    create_habit(&client, user_id, "Gym").await;
+
+    post(
+        &client,
+        "/mindless/api/habit",
+        json!({ "Mark": { "user_id": user_id, "path": "Gym" } }),
+    )
+    .await;
+
+    let log = deliveries(&client, user_id, &webhook, 1).await;
+    assert_eq!(log[0]["status_code"], json!(302));
+    assert!(log[0]["error"].is_string());
+    assert!(!redirect.received().is_empty());
+    assert!(target.received().is_empty());
+}
+
+#[tokio::test]
+async fn private_hosts_are_rejected() {
+    let client = client_with(|config| {
+        config.webhooks.allowed_hosts = vec!["192.168.1.20".to_string()];
+    })
+    .await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+
+    for url in &[
+        "http://127.0.0.1:8000/mindless/api/user",
+        "http://10.0.0.1/hook",
+        "http://169.254.169.254/latest/meta-data",
+        "http://[::1]/hook",
+        "http://[::ffff:127.0.0.1]/hook",
+        "http://0.0.0.0/hook",
+    ] {
+        let (status, json) = post(
+            &client,
+            WEBHOOK_URI,
+            json!({ "Register": { "user_id": user_id, "url": url, "events": [] } }),
+        )
+        .await;
+        assert_eq!(status, Status::BadRequest, "{}: {}", url, json);
+    }
+
+    // Unless they are allowed.
+    register(&client, user_id, "http://192.168.1.20/hook", json!([])).await;
+}
+
+#[tokio::test]
+async fn register_and_manage_webhooks() {
+    let client = client_with_quick_retries().await;
+    let user_id = create_user(&client, "justin", "Justin").await;
+    let other_id = create_user(&client, "other", "Other").await;
+
+    let register_request = |url: &str, events: Value| json!({ "Register": { "user_id": user_id, "url": url, "events": events } });
+    let (status, json) = post(
+        &client,
+        WEBHOOK_URI,
+        register_request("ftp://localhost/hook", json!([])),
+    )
+    .await;
+    assert_eq!(status, Status::BadRequest);
+    assert!(json["error"].is_string());
+    let (status, _) = post(
+        &client,
+        WEBHOOK_URI,
+        register_request("http://localhost/hook", json!(["Nonsense"])),
+    )
+    .await;
+    assert_eq!(status, Status::BadRequest);
+
+    let (webhook, secret) = register(
+        &client,
+        user_id,
+        "http://localhost/hook",
+        json!(["HabitMissed"]),
+    )
+    .await;
+    assert!(!secret.is_empty());
+    // The secret is only shown when registering.
+    assert_eq!(webhook.get("secret"), None);
+    assert_eq!(webhook["events"], json!(["HabitMissed"]));
+
+    let (status, json) = post(
+        &client,
+        WEBHOOK_URI,
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json["RetrieveAll"]["webhooks"], json!([webhook.clone()]));
+
+    // Other users can't see or delete it.
+    for request in &["Deliveries", "Delete"] {
+        let (status, json) = post(
+            &client,
+            WEBHOOK_URI,
+            json!({ *request: { "user_id": other_id, "id": webhook["id"] } }),
+        )
+        .await;
+        assert_rejected(status, &json, "NotFound");
+    }
+
+    let (status, json) = post(
+        &client,
+        WEBHOOK_URI,
+        json!({ "Delete": { "user_id": user_id, "id": webhook["id"] } }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(json["Delete"]["webhook"], webhook);
+
+    let (_, json) = post(
+        &client,
+        WEBHOOK_URI,
+        json!({ "RetrieveAll": { "user_id": user_id } }),
+    )
+    .await;
+    assert_eq!(json["RetrieveAll"]["webhooks"], json!([]));
+}
@@ -0,0 +1,170 @@
+//! Talks to the `/mindless/api` endpoints.
+//!
+//! The types mirror the json of the server so the instances we upload are indistinguishable from
+//! the ones the app uploads.
+use anyhow::{bail, Context};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Task {
+    /// Zero until the server assigns an id.
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Instance {
+    /// Zero until the server assigns an id.
+    pub id: i64,
+    pub task_id: i64,
+
+    /// UTC.
+    pub start: NaiveDateTime,
+
+    /// UTC.
+    pub end: NaiveDateTime,
+}
+
+#[derive(Serialize, Debug)]
+enum UserRequest<'a> {
+    Login { username: &'a str },
+}
+
+#[derive(Deserialize, Debug)]
+enum UserResponse {
+    Login { user: User },
+}
+
+#[derive(Serialize, Debug)]
+enum TaskRequest {
+    RetrieveAll { user_id: i64 },
+    InsertAll { tasks: Vec<(Task, Vec<Instance>)> },
+}
+
+#[derive(Deserialize, Debug)]
+enum TaskResponse {
+    RetrieveAll { tasks: Vec<(Task, Vec<Instance>)> },
+    InsertAll { tasks: Vec<(Task, Vec<Instance>)> },
+}
+
+/// The error body of a failed request.
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    error: String,
+}
+
+pub struct Client {
+    http: reqwest::Client,
+
+    /// E.g. `http://localhost:8000`.
+    server: String,
+}
+
+impl Client {
+    pub fn new(server: &str) -> Client {
+        Client {
+            http: reqwest::Client::new(),
+            server: server.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub async fn login(&self, username: &str) -> anyhow::Result<User> {
+        match self.post("user", &UserRequest::Login { username }).await? {
+            UserResponse::Login { user } => Ok(user),
+        }
+    }
+
+    /// Every task of the user with their instances.
+    pub async fn retrieve_all(&self, user_id: i64) -> anyhow::Result<Vec<(Task, Vec<Instance>)>> {
+        match self
+            .post("task", &TaskRequest::RetrieveAll { user_id })
+            .await?
+        {
+            TaskResponse::RetrieveAll { tasks } => Ok(tasks),
+            TaskResponse::InsertAll { .. } => bail!("Unexpected response to RetrieveAll"),
+        }
+    }
+
+    /// Upload tasks with their instances. Instances the server already has are not duplicated.
+    pub async fn insert_all(
+        &self,
+        tasks: Vec<(Task, Vec<Instance>)>,
+    ) -> anyhow::Result<Vec<(Task, Vec<Instance>)>> {
+        match self.post("task", &TaskRequest::InsertAll { tasks }).await? {
+            TaskResponse::InsertAll { tasks } => Ok(tasks),
+            TaskResponse::RetrieveAll { .. } => bail!("Unexpected response to InsertAll"),
+        }
+    }
+
+    async fn post<Q: Serialize, R: for<'de> Deserialize<'de>>(
+        &self,
+        endpoint: &str,
+        request: &Q,
+    ) -> anyhow::Result<R> {
+        let url = format!("{}/mindless/api/{}", self.server, endpoint);
+        let response = self
+            .http
+            .post(&url)
+            .json(request)
+            .send()
+            .await
+            .with_context(|| format!("Could not reach {}", url))?;
+
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            let reason = serde_json::from_str::<ErrorResponse>(&body)
+                .map(|error| error.error)
+                .unwrap_or(body);
+            bail!("{} responded with {}: {}", url, status, reason);
+        }
+
+        serde_json::from_str(&body).with_context(|| format!("Unexpected response from {}", url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_all_matches_the_server() {
+        let start = NaiveDateTime::from_timestamp(1_598_954_400, 0);
+        let request = TaskRequest::InsertAll {
+            tasks: vec![(
+                Task {
+                    id: 0,
+                    user_id: 1,
+                    name: "Deep Work".to_string(),
+                },
+                vec![Instance {
+                    id: 0,
+                    task_id: 0,
+                    start,
+                    end: start + chrono::Duration::hours(1),
+                }],
+            )],
+        };
+
+        assert_eq!(
+            serde_json::to_value(&request).expect("Can serialize."),
+            serde_json::json!({
+                "InsertAll": {
+                    "tasks": [[
+                        { "id": 0, "user_id": 1, "name": "Deep Work" },
+                        [{ "id": 0, "task_id": 0, "start": "2020-09-01T10:00:00", "end": "2020-09-01T11:00:00" }]
+                    ]]
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! Track time from the terminal.
+//!
+//! `mindless start <task>` starts a timer and `mindless stop` uploads it to the server as an
+//! instance, just like the app does. Stopped timers are kept until the server has them so nothing
+//! is lost while offline.
+
+// Talking to the server.
+mod client;
+// What is remembered between runs.
+mod state;
+// Reading what the user typed about time.
+mod summary;
+
+use anyhow::{bail, Context};
+use chrono::{Duration, Local, NaiveDateTime, TimeZone, Timelike, Utc};
+use client::{Client, Instance, Task};
+use mindless_core::duration;
+use mindless_core::period::Period;
+use state::{Login, Session, State, Timer};
+use std::collections::BTreeMap;
+use std::path::Path;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "mindless", about = "Track time from the terminal.")]
+enum Command {
+    /// Log in so timers are uploaded as this user.
+    Login {
+        username: String,
+
+        /// The mindless server.
+        #[structopt(long, default_value = "http://localhost:8000")]
+        server: String,
+    },
+
+    /// Start a timer for a task.
+    Start { task: String },
+
+    /// Stop the running timer and upload it.
+    Stop,
+
+    /// Show the running timer and how many timers are waiting to be uploaded.
+    Status,
+
+    /// List what was tracked.
+    Log {
+        /// A date like 2020-09-01 or how long ago like 3d, 12h or 30m.
+        #[structopt(long, default_value = "7d")]
+        since: String,
+    },
+
+    /// Show how long was spent on each task today.
+    Today,
+}
+
+#[tokio::main]
+async fn main() {
+    let command = Command::from_args();
+
+    if let Err(e) = run(command).await {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(command: Command) -> anyhow::Result<()> {
+    let path = State::path()?;
+    let mut state = State::load(&path)?;
+
+    match command {
+        Command::Login { username, server } => {
+            let user = Client::new(&server)
+                .login(&username)
+                .await
+                .with_context(|| format!("Could not log in as \"{}\"", username))?;
+            state.login = Some(Login {
+                server,
+                user_id: user.id,
+                username: user.username,
+            });
+            state.save(&path)?;
+            println!("Logged in as \"{}\"", username);
+        }
+        Command::Start { task } => {
+            if let Some(timer) = &state.timer {
+                bail!(
+                    "\"{}\" is already running, stop it first with `mindless stop`",
+                    timer.task
+                );
+            }
+
+            state.timer = Some(Timer {
+                task: task.clone(),
+                start: now(),
+            });
+            state.save(&path)?;
+            println!("Started \"{}\"", task);
+        }
+        Command::Stop => {
+            let timer = state.timer.take().context("No timer is running")?;
+            let session = Session {
+                task: timer.task,
+                start: timer.start,
+                end: now(),
+            };
+            println!(
+                "Stopped \"{}\" after {}",
+                session.task,
+                duration::format(session.end - session.start)
+            );
+
+            state.pending.push(session);
+            state.save(&path)?;
+            flush(&mut state, &path).await;
+        }
+        Command::Status => {
+            match &state.timer {
+                Some(timer) => println!(
+                    "\"{}\" running for {}",
+                    timer.task,
+                    duration::format(now() - timer.start)
+                ),
+                None => println!("No timer is running"),
+            }
+
+            if !state.pending.is_empty() {
+                println!("{} timers waiting to be uploaded", state.pending.len());
+            }
+        }
+        Command::Log { since } => {
+            let since = summary::parse_since(&since, &Local::now())?;
+            flush(&mut state, &path).await;
+
+            let mut sessions: Vec<Session> = uploaded(&state)
+                .await?
+                .into_iter()
+                .chain(state.pending.iter().cloned())
+                .filter(|session| session.end > since)
+                .collect();
+            sessions.sort_by_key(|session| session.start);
+
+            for session in sessions {
+                let start = Local.from_utc_datetime(&session.start);
+                let end = Local.from_utc_datetime(&session.end);
+                println!(
+                    "{} {}-{} {:>7} {}",
+                    start.format("%Y-%m-%d"),
+                    start.format("%H:%M"),
+                    end.format("%H:%M"),
+                    duration::format(session.end - session.start),
+                    session.task
+                );
+            }
+        }
+        Command::Today => {
+            flush(&mut state, &path).await;
+
+            let today = Period::day(&Local::today().naive_local(), &Local);
+            let running = state.timer.as_ref().map(|timer| Session {
+                task: timer.task.clone(),
+                start: timer.start,
+                end: now(),
+            });
+
+            let mut totals = BTreeMap::new();
+            for session in uploaded(&state)
+                .await?
+                .into_iter()
+                .chain(state.pending.iter().cloned())
+                .chain(running)
+            {
+                let time = Period::new(session.start, session.end)
+                    .intersection(&today)
+                    .duration();
+                if time > Duration::zero() {
+                    *totals.entry(session.task).or_insert_with(Duration::zero) += time;
+                }
+            }
+
+            let total = totals
+                .values()
+                .fold(Duration::zero(), |total, time| total + *time);
+            for (task, time) in totals {
+                println!("{:>7} {}", duration::format(time), task);
+            }
+            println!("{:>7} Total", duration::format(total));
+        }
+    }
+
+    Ok(())
+}
+
+/// Now in UTC, at the second precision the server keeps.
+fn now() -> NaiveDateTime {
+    Utc::now()
+        .naive_utc()
+        .with_nanosecond(0)
+        .expect("Zero nanoseconds is valid.")
+}
+
+/// Upload the pending timers, keeping them for next time if the server can't be reached.
+async fn flush(state: &mut State, path: &Path) {
+    if state.pending.is_empty() {
+        return;
+    }
+
+    match upload(state).await {
+        Ok(()) => {
+            state.pending.clear();
+            if let Err(e) = state.save(path) {
+                eprintln!("Warning: {:#}", e);
+            }
+        }
+        Err(e) => eprintln!(
+            "Warning: {:#}, {} timers will be uploaded later",
+            e,
+            state.pending.len()
+        ),
+    }
+}
+
+async fn upload(state: &State) -> anyhow::Result<()> {
+    let login = logged_in(state)?;
+
+    // One task per name with all of its instances, the server assigns the ids.
+    let mut tasks: BTreeMap<&str, Vec<Instance>> = BTreeMap::new();
+    for session in &state.pending {
+        tasks
+            .entry(session.task.as_str())
+            .or_default()
+            .push(Instance {
+                id: 0,
+                task_id: 0,
+                start: session.start,
+                end: session.end,
+            });
+    }
+
+    let tasks = tasks
+        .into_iter()
+        .map(|(name, instances)| {
+            let task = Task {
+                id: 0,
+                user_id: login.user_id,
+                name: name.to_string(),
+            };
+            (task, instances)
+        })
+        .collect();
+
+    Client::new(&login.server).insert_all(tasks).await?;
+    Ok(())
+}
+
+/// Every instance the server has, as sessions.
+async fn uploaded(state: &State) -> anyhow::Result<Vec<Session>> {
+    let login = logged_in(state)?;
+    let tasks = Client::new(&login.server)
+        .retrieve_all(login.user_id)
+        .await?;
+
+    Ok(tasks
+        .into_iter()
+        .flat_map(|(task, instances)| {
+            instances.into_iter().map(move |instance| Session {
+                task: task.name.clone(),
+                start: instance.start,
+                end: instance.end,
+            })
+        })
+        .collect())
+}
+
+fn logged_in(state: &State) -> anyhow::Result<&Login> {
+    state
+        .login
+        .as_ref()
+        .context("Not logged in, run `mindless login <username>` first")
+}
@@ -0,0 +1,112 @@
+//! What the CLI remembers between runs.
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding where the state is kept.
+const STATE_PATH_ENV: &str = "MINDLESS_STATE";
+
+/// The running timer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Timer {
+    pub task: String,
+
+    /// UTC.
+    pub start: NaiveDateTime,
+}
+
+/// A stopped timer which has not been uploaded yet.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Session {
+    pub task: String,
+
+    /// UTC.
+    pub start: NaiveDateTime,
+
+    /// UTC.
+    pub end: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Login {
+    pub server: String,
+    pub user_id: i64,
+    pub username: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct State {
+    pub login: Option<Login>,
+    pub timer: Option<Timer>,
+
+    /// Oldest first.
+    pub pending: Vec<Session>,
+}
+
+impl State {
+    /// `MINDLESS_STATE` or `mindless/state.json` in the user's data directory.
+    pub fn path() -> anyhow::Result<PathBuf> {
+        if let Some(path) = std::env::var_os(STATE_PATH_ENV) {
+            return Ok(PathBuf::from(path));
+        }
+
+        let data =
+            dirs::data_dir().context("Could not find a data directory, set MINDLESS_STATE")?;
+        Ok(data.join("mindless").join("state.json"))
+    }
+
+    /// Load the state, starting afresh if there is none yet.
+    pub fn load(path: &Path) -> anyhow::Result<State> {
+        if !path.exists() {
+            return Ok(State::default());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        serde_json::from_str(&contents).with_context(|| format!("{} is corrupt", path.display()))
+    }
+
+    /// Save the state. A crash while saving leaves the previous state intact.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&temporary, path)
+            .with_context(|| format!("Could not write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir()
+            .join(format!("mindless-cli-{}", std::process::id()))
+            .join("state.json");
+        assert_eq!(
+            State::load(&path).expect("Missing state is empty."),
+            State::default()
+        );
+
+        let state = State {
+            login: None,
+            timer: Some(Timer {
+                task: "Deep Work".to_string(),
+                start: NaiveDateTime::from_timestamp(0, 0),
+            }),
+            pending: Vec::new(),
+        };
+        state.save(&path).expect("Can save.");
+        assert_eq!(State::load(&path).expect("Can load."), state);
+
+        std::fs::remove_dir_all(path.parent().expect("Has a parent.")).expect("Can clean up.");
+    }
+}
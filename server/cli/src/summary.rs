@@ -0,0 +1,46 @@
+//! Reading what the user typed about time.
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone};
+use mindless_core::error::{Error, Result};
+use mindless_core::{duration, period};
+
+/// Parse when `log --since` starts, in UTC.
+///
+/// Either a local date, e.g. `2020-09-01`, or how long ago, e.g. `3d`, `12h` or `30m`.
+pub fn parse_since<Tz: TimeZone>(since: &str, now: &DateTime<Tz>) -> Result<NaiveDateTime> {
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        return Ok(period::start_of_day(&date, &now.timezone()));
+    }
+
+    now.naive_utc()
+        .checked_sub_signed(duration::parse(since)?)
+        .ok_or_else(|| Error::InvalidDuration(since.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn time(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2020, 9, 1).and_hms(hour, minute, 0)
+    }
+
+    #[test]
+    fn since() {
+        let now = Utc.from_utc_datetime(&time(12, 0));
+
+        assert_eq!(parse_since("2h", &now), Ok(time(10, 0)));
+        assert_eq!(parse_since("30m", &now), Ok(time(11, 30)));
+        assert_eq!(
+            parse_since("1d", &now),
+            Ok(NaiveDate::from_ymd(2020, 8, 31).and_hms(12, 0, 0))
+        );
+        assert_eq!(parse_since("2020-09-01", &now), Ok(time(0, 0)));
+        assert!(parse_since("yesterday", &now).is_err());
+        assert!(parse_since("d", &now).is_err());
+        assert_eq!(
+            parse_since("99999999d", &now),
+            Err(Error::InvalidDuration("99999999d".to_string()))
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Reading and writing durations the way people type them, e.g. `1h30m`.
+use crate::error::{Error, Result};
+use chrono::Duration;
+
+/// Format a duration as hours and minutes, e.g. `1h05m`. Negative durations are `0h00m`.
+pub fn format(duration: Duration) -> String {
+    let minutes = duration.num_minutes().max(0);
+    format!("{}h{:02}m", minutes / 60, minutes % 60)
+}
+
+/// Parse a duration made of days, hours, minutes and seconds, e.g. `3d`, `12h` or `1h30m`.
+pub fn parse(duration: &str) -> Result<Duration> {
+    let invalid = || Error::InvalidDuration(duration.to_string());
+
+    // Count seconds so overflow is caught instead of panicking.
+    let mut total: i64 = 0;
+    let mut units = 0;
+    let mut amount = String::new();
+    for c in duration.trim().chars() {
+        if c.is_ascii_digit() {
+            amount.push(c);
+            continue;
+        }
+
+        let value: i64 = amount.parse().map_err(|_| invalid())?;
+        amount.clear();
+        units += 1;
+        let unit = match c {
+            'd' => 24 * 60 * 60,
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        total = value
+            .checked_mul(unit)
+            .and_then(|seconds| total.checked_add(seconds))
+            .ok_or_else(invalid)?;
+    }
+
+    // Every amount needs a unit and there must be at least one.
+    // Durations only go up to i64::MAX milliseconds.
+    if !amount.is_empty() || units == 0 || total > i64::MAX / 1000 {
+        return Err(invalid());
+    }
+
+    Ok(Duration::seconds(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn format_examples() {
+        assert_eq!(format(Duration::minutes(65)), "1h05m");
+        assert_eq!(format(Duration::seconds(59)), "0h00m");
+        assert_eq!(format(Duration::hours(-1)), "0h00m");
+        assert_eq!(format(Duration::days(2)), "48h00m");
+    }
+
+    #[test]
+    fn parse_examples() {
+        assert_eq!(parse("3d"), Ok(Duration::days(3)));
+        assert_eq!(parse("12h"), Ok(Duration::hours(12)));
+        assert_eq!(parse("1h30m"), Ok(Duration::minutes(90)));
+        assert_eq!(parse("0m"), Ok(Duration::zero()));
+        assert_eq!(parse(" 45s "), Ok(Duration::seconds(45)));
+
+        for invalid in &[
+            "",
+            "d",
+            "12",
+            "1h30",
+            "1w",
+            "one hour",
+            "-1h",
+            "9999999999999d",
+            "9223372036854775807s",
+            "9223372036854775807s1s",
+            "99999999999999999999s",
+        ] {
+            assert_eq!(
+                parse(invalid),
+                Err(Error::InvalidDuration(invalid.to_string())),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn parse_reads_format(minutes in 0i64..100_000) {
+            let formatted = format(Duration::minutes(minutes));
+            prop_assert_eq!(parse(&formatted), Ok(Duration::minutes(minutes)));
+        }
+    }
+}
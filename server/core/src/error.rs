@@ -0,0 +1,67 @@
+use std::error;
+use std::fmt;
+// Change the alias to use our custom error.
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    // A duration which isn't like `1h30m`.
+    InvalidDuration(String),
+
+    // A habit path which isn't like `Health/Exercise`.
+    InvalidPath(String),
+
+    // A goal comparison, unit or period which doesn't exist.
+    InvalidGoal(String),
+
+    // A tag name which is empty, too long or has whitespace or commas in it.
+    InvalidTag(String),
+
+    // A habit repeat period in seconds which is zero, negative or longer than a year.
+    InvalidRepeat(i64),
+}
+
+impl Error {
+    /// A short name for the kind of error. Useful for grouping errors, e.g. in metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::InvalidDuration(_) => "InvalidDuration",
+            Error::InvalidPath(_) => "InvalidPath",
+            Error::InvalidGoal(_) => "InvalidGoal",
+            Error::InvalidTag(_) => "InvalidTag",
+            Error::InvalidRepeat(_) => "InvalidRepeat",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidDuration(duration) => write!(
+                f,
+                "\"{}\" is not a duration like 3d, 12h, 30m or 1h30m",
+                duration
+            ),
+            Error::InvalidPath(path) => {
+                write!(f, "\"{}\" is not a habit path like Health/Exercise", path)
+            }
+            Error::InvalidGoal(value) => write!(
+                f,
+                "\"{}\" is not at_least, at_most, seconds, times, day, week or month",
+                value
+            ),
+            Error::InvalidTag(name) => write!(
+                f,
+                "\"{}\" is not a tag like billable, without whitespace or commas",
+                name
+            ),
+            Error::InvalidRepeat(seconds) => write!(
+                f,
+                "{} is not a number of seconds between 1 and a year to repeat over",
+                seconds
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {}
@@ -0,0 +1,427 @@
+//! Goals for a task or habit over calendar periods, e.g. at least 5h of Deep Work per week.
+//!
+//! Periods are local days, weeks starting on Monday or calendar months. Time goals count the
+//! time tracked without counting overlapping instances twice, count goals count instances or
+//! completions.
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::duration;
+use crate::error::{Error, Result};
+use crate::period::{merge, start_of_day, Period};
+
+/// Whether a goal is to do at least or at most its amount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    AtLeast,
+    AtMost,
+}
+
+/// What a goal counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    /// Time tracked.
+    Seconds,
+
+    /// Instances of a task or completions of a habit.
+    Times,
+}
+
+/// The periods a goal starts over in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cadence {
+    Day,
+    Week,
+    Month,
+}
+
+impl Comparison {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Comparison::AtLeast => "at_least",
+            Comparison::AtMost => "at_most",
+        }
+    }
+}
+
+impl Unit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Unit::Seconds => "seconds",
+            Unit::Times => "times",
+        }
+    }
+}
+
+impl Cadence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Cadence::Day => "day",
+            Cadence::Week => "week",
+            Cadence::Month => "month",
+        }
+    }
+
+    /// The first day of the period containing `date`.
+    pub fn start(&self, date: &NaiveDate) -> NaiveDate {
+        match self {
+            Cadence::Day => *date,
+            Cadence::Week => {
+                *date - Duration::days(i64::from(date.weekday().num_days_from_monday()))
+            }
+            Cadence::Month => NaiveDate::from_ymd(date.year(), date.month(), 1),
+        }
+    }
+
+    /// The first day of the period after the one starting on `start`, None past the last date.
+    pub fn next(&self, start: &NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Cadence::Day => start.succ_opt(),
+            Cadence::Week => start.checked_add_signed(Duration::days(7)),
+            Cadence::Month if start.month() == 12 => {
+                NaiveDate::from_ymd_opt(start.year() + 1, 1, 1)
+            }
+            Cadence::Month => NaiveDate::from_ymd_opt(start.year(), start.month() + 1, 1),
+        }
+    }
+
+    /// The longest a period can be, e.g. 31 days for a month.
+    pub fn longest(&self) -> Duration {
+        match self {
+            Cadence::Day => Duration::days(1),
+            Cadence::Week => Duration::days(7),
+            Cadence::Month => Duration::days(31),
+        }
+    }
+
+    /// The first days of the periods overlapping the days from `from` to `to`, in order. Only
+    /// periods which end before the last date are included.
+    pub fn starts(&self, from: &NaiveDate, to: &NaiveDate) -> Vec<NaiveDate> {
+        let mut starts = Vec::new();
+        let mut start = self.start(from);
+        while start <= *to {
+            let next = match self.next(&start) {
+                Some(next) => next,
+                None => break,
+            };
+            starts.push(start);
+            start = next;
+        }
+
+        starts
+    }
+}
+
+macro_rules! parse_as_str {
+    ($type:ty, $($variant:expr),+) => {
+        impl FromStr for $type {
+            type Err = Error;
+
+            fn from_str(value: &str) -> Result<Self> {
+                [$($variant),+]
+                    .iter()
+                    .find(|variant| variant.as_str() == value)
+                    .copied()
+                    .ok_or_else(|| Error::InvalidGoal(value.to_string()))
+            }
+        }
+
+        impl fmt::Display for $type {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+    };
+}
+
+parse_as_str!(Comparison, Comparison::AtLeast, Comparison::AtMost);
+parse_as_str!(Unit, Unit::Seconds, Unit::Times);
+parse_as_str!(Cadence, Cadence::Day, Cadence::Week, Cadence::Month);
+
+/// What a goal aims for, e.g. at least 18000 seconds per week.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Target {
+    pub comparison: Comparison,
+    pub amount: i64,
+    pub unit: Unit,
+    pub cadence: Cadence,
+}
+
+impl Target {
+    /// Whether `actual` meets the target. A period in progress may still miss an `at_least`
+    /// target it meets now or meet one it misses.
+    pub fn is_met(&self, actual: i64) -> bool {
+        match self.comparison {
+            Comparison::AtLeast => actual >= self.amount,
+            Comparison::AtMost => actual <= self.amount,
+        }
+    }
+
+    /// The amount the way people write it, e.g. `5h00m` or `3 times`.
+    pub fn format_amount(amount: i64, unit: Unit) -> String {
+        match unit {
+            // Durations only go up to i64::MAX milliseconds.
+            Unit::Seconds => {
+                duration::format(Duration::seconds(amount.max(0).min(i64::MAX / 1000)))
+            }
+            Unit::Times if amount == 1 => "once".to_string(),
+            Unit::Times => format!("{} times", amount),
+        }
+    }
+
+    /// A description of a goal for `name`, e.g. `At least 5h00m of Deep Work per week`.
+    pub fn describe(&self, name: &str) -> String {
+        let comparison = match self.comparison {
+            Comparison::AtLeast => "At least",
+            Comparison::AtMost => "At most",
+        };
+        let amount = Target::format_amount(self.amount, self.unit);
+        match self.unit {
+            Unit::Seconds => format!("{} {} of {} per {}", comparison, amount, name, self.cadence),
+            Unit::Times => format!(
+                "{} {} {} per {}",
+                name,
+                comparison.to_lowercase(),
+                amount,
+                self.cadence
+            ),
+        }
+    }
+}
+
+/// How a goal did in one of its periods.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Progress {
+    /// The first and last local day of the period.
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+
+    /// Seconds tracked or times done within the period.
+    pub actual: i64,
+
+    pub met: bool,
+}
+
+impl Progress {
+    /// None if the period doesn't end before the last date.
+    pub fn new(target: &Target, start: &NaiveDate, actual: i64) -> Option<Progress> {
+        Some(Progress {
+            start: *start,
+            end: target.cadence.next(start)?.pred(),
+            actual,
+            met: target.is_met(actual),
+        })
+    }
+}
+
+/// The UTC bounds of the period starting on the local day `start`, None if it doesn't end before
+/// the last date.
+pub fn bounds<Tz: TimeZone>(cadence: Cadence, start: &NaiveDate, timezone: &Tz) -> Option<Period> {
+    Some(Period::new(
+        start_of_day(start, timezone),
+        start_of_day(&cadence.next(start)?, timezone),
+    ))
+}
+
+/// How much of `unit` the periods add up to within `bounds`: the time spent without counting
+/// overlapping time twice, or how many periods start within `bounds`.
+pub fn measure(unit: Unit, periods: &[Period], bounds: &Period) -> i64 {
+    match unit {
+        Unit::Seconds => {
+            let within: Vec<Period> = periods
+                .iter()
+                .map(|period| period.intersection(bounds))
+                .collect();
+            merge(&within)
+                .iter()
+                .map(|period| period.duration().num_seconds())
+                .sum()
+        }
+        Unit::Times => count(
+            &periods
+                .iter()
+                .map(|period| period.start)
+                .collect::<Vec<_>>(),
+            bounds,
+        ),
+    }
+}
+
+/// How many times are within `bounds`, e.g. completions of a habit.
+pub fn count(times: &[NaiveDateTime], bounds: &Period) -> i64 {
+    times
+        .iter()
+        .filter(|time| bounds.start <= **time && **time < bounds.end)
+        .count() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::naive::MAX_DATE;
+    use chrono_tz::Australia::Sydney;
+    use chrono_tz::UTC;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(year, month, day)
+    }
+
+    fn time(date: NaiveDate, hour: u32, minute: u32) -> NaiveDateTime {
+        date.and_hms(hour, minute, 0)
+    }
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!("at_least".parse(), Ok(Comparison::AtLeast));
+        assert_eq!("at_most".parse(), Ok(Comparison::AtMost));
+        assert_eq!("times".parse(), Ok(Unit::Times));
+        assert_eq!("month".parse(), Ok(Cadence::Month));
+        assert_eq!(
+            "fortnight".parse::<Cadence>(),
+            Err(Error::InvalidGoal("fortnight".to_string()))
+        );
+        assert_eq!(Cadence::Week.to_string(), "week");
+        assert_eq!(
+            serde_json::to_string(&Comparison::AtLeast).unwrap(),
+            "\"at_least\""
+        );
+    }
+
+    #[test]
+    fn period_starts() {
+        // A Wednesday.
+        let wednesday = date(2020, 10, 14);
+        assert_eq!(Cadence::Day.start(&wednesday), wednesday);
+        assert_eq!(Cadence::Week.start(&wednesday), date(2020, 10, 12));
+        assert_eq!(Cadence::Month.start(&wednesday), date(2020, 10, 1));
+
+        assert_eq!(
+            Cadence::Week.next(&date(2020, 12, 28)),
+            Some(date(2021, 1, 4))
+        );
+        assert_eq!(
+            Cadence::Month.next(&date(2020, 12, 1)),
+            Some(date(2021, 1, 1))
+        );
+        assert_eq!(
+            Cadence::Month.next(&date(2020, 2, 1)),
+            Some(date(2020, 3, 1))
+        );
+
+        assert_eq!(
+            Cadence::Week.starts(&wednesday, &date(2020, 10, 26)),
+            vec![date(2020, 10, 12), date(2020, 10, 19), date(2020, 10, 26)]
+        );
+        assert_eq!(
+            Cadence::Month.starts(&date(2020, 11, 30), &date(2021, 1, 1)),
+            vec![date(2020, 11, 1), date(2020, 12, 1), date(2021, 1, 1)]
+        );
+        assert!(Cadence::Day
+            .starts(&wednesday, &date(2020, 10, 13))
+            .is_empty());
+    }
+
+    #[test]
+    fn periods_end_before_the_last_date() {
+        assert_eq!(Cadence::Day.next(&MAX_DATE), None);
+        assert_eq!(Cadence::Week.next(&Cadence::Week.start(&MAX_DATE)), None);
+        assert_eq!(Cadence::Month.next(&Cadence::Month.start(&MAX_DATE)), None);
+        assert!(Cadence::Week.starts(&MAX_DATE, &MAX_DATE).is_empty());
+        assert_eq!(
+            Cadence::Day.starts(&MAX_DATE.pred(), &MAX_DATE),
+            vec![MAX_DATE.pred()]
+        );
+        assert_eq!(bounds(Cadence::Month, &MAX_DATE, &UTC), None);
+
+        let target = Target {
+            comparison: Comparison::AtLeast,
+            amount: 1,
+            unit: Unit::Times,
+            cadence: Cadence::Day,
+        };
+        assert_eq!(Progress::new(&target, &MAX_DATE, 1), None);
+    }
+
+    #[test]
+    fn bounds_are_local() {
+        // Sydney is 11 hours ahead in summer.
+        let start = date(2020, 11, 2);
+        assert_eq!(
+            bounds(Cadence::Week, &start, &Sydney),
+            Some(Period::new(
+                time(date(2020, 11, 1), 13, 0),
+                time(date(2020, 11, 8), 13, 0)
+            ))
+        );
+        assert_eq!(
+            bounds(Cadence::Day, &start, &UTC),
+            Some(Period::new(
+                time(start, 0, 0),
+                time(date(2020, 11, 3), 0, 0)
+            ))
+        );
+    }
+
+    #[test]
+    fn measure_time_and_times() {
+        let day = date(2020, 10, 14);
+        let bounds = bounds(Cadence::Day, &day, &UTC).expect("Ends.");
+        let periods = vec![
+            // Started the day before, only the hour after midnight counts.
+            Period::new(time(day.pred(), 23, 0), time(day, 1, 0)),
+            // Overlaps the next one, which is not counted twice.
+            Period::new(time(day, 9, 0), time(day, 10, 0)),
+            Period::new(time(day, 9, 30), time(day, 10, 30)),
+            Period::new(time(day.succ(), 9, 0), time(day.succ(), 10, 0)),
+        ];
+
+        assert_eq!(
+            measure(Unit::Seconds, &periods, &bounds),
+            2 * 3600 + 30 * 60
+        );
+        assert_eq!(measure(Unit::Times, &periods, &bounds), 2);
+        assert_eq!(measure(Unit::Seconds, &[], &bounds), 0);
+    }
+
+    #[test]
+    fn targets() {
+        let at_least = Target {
+            comparison: Comparison::AtLeast,
+            amount: 5 * 3600,
+            unit: Unit::Seconds,
+            cadence: Cadence::Week,
+        };
+        assert!(at_least.is_met(5 * 3600));
+        assert!(!at_least.is_met(5 * 3600 - 1));
+        assert_eq!(
+            at_least.describe("Deep Work"),
+            "At least 5h00m of Deep Work per week"
+        );
+
+        let at_most = Target {
+            comparison: Comparison::AtMost,
+            amount: 1,
+            unit: Unit::Times,
+            cadence: Cadence::Day,
+        };
+        assert!(at_most.is_met(0));
+        assert!(!at_most.is_met(2));
+        assert_eq!(at_most.describe("Social"), "Social at most once per day");
+
+        // Goals stored before amounts were capped still describe themselves.
+        assert_eq!(
+            Target::format_amount(i64::MAX, Unit::Seconds),
+            duration::format(Duration::milliseconds(i64::MAX))
+        );
+        assert_eq!(Cadence::Month.longest(), Duration::days(31));
+
+        let progress = Progress::new(&at_least, &date(2020, 10, 12), 3600).expect("Ends.");
+        assert_eq!(progress.end, date(2020, 10, 18));
+        assert!(!progress.met);
+    }
+}
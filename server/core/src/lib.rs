@@ -0,0 +1,25 @@
+//! Pure domain logic shared by the server and its clients.
+//!
+//! Nothing here does any I/O so it can be used anywhere, e.g. the api, the database and the CLI
+//! all agree on what a day or a streak is.
+
+#[deny(clippy::all)]
+pub mod error;
+
+#[deny(clippy::all)]
+pub mod duration;
+
+#[deny(clippy::all)]
+pub mod path;
+
+#[deny(clippy::all)]
+pub mod period;
+
+#[deny(clippy::all)]
+pub mod reminder;
+
+#[deny(clippy::all)]
+pub mod goal;
+
+#[deny(clippy::all)]
+pub mod tag;
@@ -0,0 +1,152 @@
+//! Paths naming a habit within a user's habit tree, e.g. `Health/Exercise`.
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// Separates a habit from the habit it is grouped under.
+pub const SEPARATOR: char = '/';
+
+/// The names of a habit and every habit it is grouped under, top level first.
+///
+/// Serialized as a string like `Health/Exercise`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct HabitPath {
+    /// Never empty, none of them are empty or contain the separator.
+    names: Vec<String>,
+}
+
+impl HabitPath {
+    /// Top level first.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// The name of the habit itself.
+    pub fn name(&self) -> &str {
+        self.names.last().expect("A path is never empty.")
+    }
+
+    /// The path of the habit this is grouped under. None for top level habits.
+    pub fn parent(&self) -> Option<HabitPath> {
+        match self.names.len() {
+            1 => None,
+            length => Some(HabitPath {
+                names: self.names[..length - 1].to_vec(),
+            }),
+        }
+    }
+
+    /// The path of a habit grouped under this one.
+    pub fn child(&self, name: &str) -> Result<HabitPath> {
+        let mut names = self.names.clone();
+        names.push(parse_name(name).ok_or_else(|| Error::InvalidPath(name.to_string()))?);
+        Ok(HabitPath { names })
+    }
+}
+
+/// A name without surrounding whitespace, None if it is empty or contains the separator.
+fn parse_name(name: &str) -> Option<String> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(SEPARATOR) {
+        return None;
+    }
+
+    Some(name.to_string())
+}
+
+impl FromStr for HabitPath {
+    type Err = Error;
+
+    /// Names are trimmed, e.g. ` Health / Exercise ` is `Health/Exercise`.
+    fn from_str(path: &str) -> Result<HabitPath> {
+        let names = path
+            .split(SEPARATOR)
+            .map(parse_name)
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Error::InvalidPath(path.to_string()))?;
+
+        Ok(HabitPath { names })
+    }
+}
+
+impl TryFrom<String> for HabitPath {
+    type Error = Error;
+
+    fn try_from(path: String) -> Result<HabitPath> {
+        path.parse()
+    }
+}
+
+impl From<HabitPath> for String {
+    fn from(path: HabitPath) -> String {
+        path.to_string()
+    }
+}
+
+impl fmt::Display for HabitPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.names.join(&SEPARATOR.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn parse_examples() {
+        let path: HabitPath = " Health / Cold Shower ".parse().expect("Is valid.");
+        assert_eq!(path.names(), &["Health", "Cold Shower"]);
+        assert_eq!(path.name(), "Cold Shower");
+        assert_eq!(path.to_string(), "Health/Cold Shower");
+        assert_eq!(path.parent(), Some("Health".parse().expect("Is valid.")));
+        assert_eq!(path.parent().and_then(|parent| parent.parent()), None);
+
+        for invalid in &["", "/", "Health/", "/Health", "Health//Exercise", " "] {
+            assert_eq!(
+                invalid.parse::<HabitPath>(),
+                Err(Error::InvalidPath(invalid.to_string())),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn children() {
+        let health: HabitPath = "Health".parse().expect("Is valid.");
+
+        assert_eq!(
+            health.child("Exercise"),
+            "Health/Exercise".parse::<HabitPath>()
+        );
+        assert!(health.child("Exercise/Run").is_err());
+    }
+
+    #[test]
+    fn serialize_as_a_string() {
+        let path: HabitPath = "Health/Exercise".parse().expect("Is valid.");
+        let json = serde_json::to_string(&path).expect("Can serialize.");
+
+        assert_eq!(json, "\"Health/Exercise\"");
+        assert_eq!(
+            serde_json::from_str::<HabitPath>(&json).expect("Can deserialize."),
+            path
+        );
+        assert!(serde_json::from_str::<HabitPath>("\"Health//Exercise\"").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn display_round_trips(names in prop::collection::vec("[A-Za-z][A-Za-z ]{0,8}[A-Za-z]", 1..4)) {
+            let path: HabitPath = names.join("/").parse().expect("Is valid.");
+
+            prop_assert_eq!(path.names(), &names[..]);
+            prop_assert_eq!(path.to_string().parse::<HabitPath>(), Ok(path));
+        }
+    }
+}
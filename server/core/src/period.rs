@@ -0,0 +1,535 @@
+//! Pure functions over periods of time.
+//!
+//! Times are naive UTC, as in the database. Everything which depends on what day it is for the
+//! user, e.g. daily totals and streaks, takes the user's timezone so days start at their local
+//! midnight even across daylight saving changes.
+use chrono::{Duration, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::error::{Error, Result};
+
+/// The longest a habit can repeat over, a leap year.
+pub const MAX_REPEAT_SEC: i64 = 366 * 24 * 60 * 60;
+
+/// The half open period `[start, end)` in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Period {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+impl Period {
+    /// A period ending before it starts is treated as empty.
+    pub fn new(start: NaiveDateTime, end: NaiveDateTime) -> Period {
+        Period {
+            start,
+            end: end.max(start),
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Whether both periods share some time. Periods which only touch do not overlap.
+    pub fn overlaps(&self, other: &Period) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    /// The time both periods share, empty if they do not overlap.
+    pub fn intersection(&self, other: &Period) -> Period {
+        Period::new(self.start.max(other.start), self.end.min(other.end))
+    }
+
+    /// The period covering a local day.
+    pub fn day<Tz: TimeZone>(date: &NaiveDate, timezone: &Tz) -> Period {
+        Period::new(
+            start_of_day(date, timezone),
+            start_of_day(&date.succ(), timezone),
+        )
+    }
+}
+
+/// Merge overlapping and touching periods so time spent is not counted twice.
+///
+/// Returns disjoint, non-empty periods sorted by start.
+pub fn merge(periods: &[Period]) -> Vec<Period> {
+    let mut sorted: Vec<Period> = periods
+        .iter()
+        .filter(|period| !period.is_empty())
+        .copied()
+        .collect();
+    sorted.sort();
+
+    let mut merged: Vec<Period> = Vec::with_capacity(sorted.len());
+    for period in sorted {
+        match merged.last_mut() {
+            Some(last) if period.start <= last.end => last.end = last.end.max(period.end),
+            _ => merged.push(period),
+        }
+    }
+
+    merged
+}
+
+/// The period of a habit which contains `now`.
+///
+/// A repeating habit's periods follow each other from when it was created, e.g. a daily habit
+/// created at midnight gets one period per UTC day. A habit which does not repeat has a single
+/// period starting when it was created. Times before the habit was created belong to its first
+/// period. Fails when the period would end after the last time there is.
+pub fn repeat_period(
+    created_at: &NaiveDateTime,
+    repeat_period_sec: Option<i64>,
+    now: &NaiveDateTime,
+) -> Result<Period> {
+    let repeat = match repeat_period_sec {
+        Some(seconds) if seconds > 0 => seconds,
+        _ => return Ok(Period::new(*created_at, chrono::naive::MAX_DATETIME)),
+    };
+    let invalid = || Error::InvalidRepeat(repeat);
+
+    // Durations only go up to i64::MAX milliseconds.
+    if repeat > i64::MAX / 1000 {
+        return Err(invalid());
+    }
+    let elapsed = (*now - *created_at).num_seconds().max(0);
+    let start = created_at
+        .checked_add_signed(Duration::seconds(elapsed - elapsed % repeat))
+        .ok_or_else(invalid)?;
+    let end = start
+        .checked_add_signed(Duration::seconds(repeat))
+        .ok_or_else(invalid)?;
+
+    Ok(Period::new(start, end))
+}
+
+/// Check a habit repeats over more than nothing and at most `MAX_REPEAT_SEC`. Not repeating is
+/// fine.
+pub fn check_repeat(repeat_period_sec: Option<i64>) -> Result<()> {
+    match repeat_period_sec {
+        Some(seconds) if seconds <= 0 || seconds > MAX_REPEAT_SEC => {
+            Err(Error::InvalidRepeat(seconds))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The local date of a UTC time in `timezone`.
+pub fn local_date<Tz: TimeZone>(time: &NaiveDateTime, timezone: &Tz) -> NaiveDate {
+    timezone.from_utc_datetime(time).date().naive_local()
+}
+
+/// The UTC time at which `date` starts in `timezone`.
+///
+/// A few timezones skip midnight when daylight saving starts, their day starts at the first
+/// valid hour instead.
+pub fn start_of_day<Tz: TimeZone>(date: &NaiveDate, timezone: &Tz) -> NaiveDateTime {
+    for hour in 0..24 {
+        if let Some(start) = timezone
+            .from_local_datetime(&date.and_hms(hour, 0, 0))
+            .earliest()
+        {
+            return start.naive_utc();
+        }
+    }
+
+    unreachable!("Every day has a valid hour.")
+}
+
+/// Split a period at local midnights, returning the time spent on each local day in order.
+pub fn split_days<Tz: TimeZone>(period: &Period, timezone: &Tz) -> Vec<(NaiveDate, Duration)> {
+    let mut days = Vec::new();
+    let mut start = period.start;
+    let mut date = local_date(&start, timezone);
+
+    while start < period.end {
+        let next = date.succ();
+        let end = start_of_day(&next, timezone).min(period.end);
+
+        days.push((date, end - start));
+        start = end;
+        date = next;
+    }
+
+    days
+}
+
+/// Total time spent on each local day, without counting overlapping time twice.
+pub fn daily_totals<Tz: TimeZone>(
+    periods: &[Period],
+    timezone: &Tz,
+) -> BTreeMap<NaiveDate, Duration> {
+    let mut totals = BTreeMap::new();
+    for period in merge(periods) {
+        for (date, duration) in split_days(&period, timezone) {
+            *totals.entry(date).or_insert_with(Duration::zero) += duration;
+        }
+    }
+
+    totals
+}
+
+/// Total time spent in each local hour of the day, e.g. the first is between midnight and 1am,
+/// without counting overlapping time twice.
+pub fn hourly_totals<Tz: TimeZone>(periods: &[Period], timezone: &Tz) -> [Duration; 24] {
+    let mut totals = [Duration::zero(); 24];
+    for period in merge(periods) {
+        let mut start = period.start;
+        while start < period.end {
+            let local = timezone.from_utc_datetime(&start).naive_local();
+            // Offsets are whole minutes so the next local hour is the same distance away in UTC.
+            let into_hour = i64::from(local.minute() * 60 + local.second());
+            let end = (start + Duration::seconds(60 * 60 - into_hour)).min(period.end);
+
+            totals[local.hour() as usize] += end - start;
+            start = end;
+        }
+    }
+
+    totals
+}
+
+/// The number of consecutive days ending today which are in `days`.
+///
+/// A streak is kept alive until the end of today so it counts from yesterday if today is not in
+/// `days` yet.
+pub fn streak(days: &BTreeSet<NaiveDate>, today: &NaiveDate) -> u32 {
+    let mut day = if days.contains(today) {
+        *today
+    } else {
+        today.pred()
+    };
+
+    let mut streak = 0;
+    while days.contains(&day) {
+        streak += 1;
+        day = day.pred();
+    }
+
+    streak
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Tz;
+    use proptest::prelude::*;
+
+    fn time(date: (i32, u32, u32), hms: (u32, u32, u32)) -> NaiveDateTime {
+        NaiveDate::from_ymd(date.0, date.1, date.2).and_hms(hms.0, hms.1, hms.2)
+    }
+
+    /// Timezones with daylight saving, half hour offsets, southern hemisphere and 45 minute
+    /// offsets.
+    fn timezones() -> impl Strategy<Value = Tz> {
+        prop::sample::select(vec![
+            Tz::UTC,
+            Tz::Australia__Sydney,
+            Tz::America__New_York,
+            Tz::Europe__London,
+            Tz::Asia__Kolkata,
+            Tz::America__Sao_Paulo,
+            Tz::Pacific__Chatham,
+            Tz::Asia__Beirut,
+        ])
+    }
+
+    /// Times between 2000 and 2040 at second precision.
+    fn times() -> impl Strategy<Value = NaiveDateTime> {
+        (946_684_800i64..2_208_988_800)
+            .prop_map(|seconds| NaiveDateTime::from_timestamp(seconds, 0))
+    }
+
+    /// Periods lasting up to three days.
+    fn periods() -> impl Strategy<Value = Period> {
+        (times(), 0i64..3 * 24 * 60 * 60)
+            .prop_map(|(start, seconds)| Period::new(start, start + Duration::seconds(seconds)))
+    }
+
+    #[test]
+    fn sydney_daylight_saving_starts() {
+        let sydney = Tz::Australia__Sydney;
+        let day = NaiveDate::from_ymd(2020, 10, 4);
+        let period = Period::new(
+            start_of_day(&day, &sydney),
+            start_of_day(&day.succ(), &sydney),
+        );
+
+        assert_eq!(
+            split_days(&period, &sydney),
+            vec![(day, Duration::hours(23))]
+        );
+    }
+
+    #[test]
+    fn new_york_daylight_saving_ends() {
+        let new_york = Tz::America__New_York;
+        let day = NaiveDate::from_ymd(2020, 11, 1);
+        let period = Period::new(
+            start_of_day(&day, &new_york),
+            start_of_day(&day.succ(), &new_york),
+        );
+
+        assert_eq!(
+            split_days(&period, &new_york),
+            vec![(day, Duration::hours(25))]
+        );
+    }
+
+    #[test]
+    fn late_night_belongs_to_the_local_day() {
+        // 11pm in Sydney on the 1st is still 1pm on the 1st in UTC, 9am on the 1st in New York.
+        let period = Period::new(
+            time((2020, 9, 1), (13, 0, 0)),
+            time((2020, 9, 1), (14, 0, 0)),
+        );
+
+        assert_eq!(
+            split_days(&period, &Tz::Australia__Sydney),
+            vec![(NaiveDate::from_ymd(2020, 9, 1), Duration::hours(1))]
+        );
+
+        // Past midnight in Sydney.
+        let period = Period::new(
+            time((2020, 9, 1), (13, 30, 0)),
+            time((2020, 9, 1), (14, 30, 0)),
+        );
+        assert_eq!(
+            split_days(&period, &Tz::Australia__Sydney),
+            vec![
+                (NaiveDate::from_ymd(2020, 9, 1), Duration::minutes(30)),
+                (NaiveDate::from_ymd(2020, 9, 2), Duration::minutes(30)),
+            ]
+        );
+    }
+
+    #[test]
+    fn hours_are_local() {
+        // 9:30 to 11:00 in Kolkata, five and a half hours ahead of UTC.
+        let period = Period::new(
+            time((2020, 9, 1), (4, 0, 0)),
+            time((2020, 9, 1), (5, 30, 0)),
+        );
+        let totals = hourly_totals(&[period, period], &Tz::Asia__Kolkata);
+
+        assert_eq!(totals[9], Duration::minutes(30));
+        assert_eq!(totals[10], Duration::hours(1));
+        assert_eq!(
+            totals.iter().fold(Duration::zero(), |a, b| a + *b),
+            Duration::minutes(90)
+        );
+    }
+
+    #[test]
+    fn intersections() {
+        let morning = Period::new(
+            time((2020, 9, 1), (8, 0, 0)),
+            time((2020, 9, 1), (12, 0, 0)),
+        );
+        let lunch = Period::new(
+            time((2020, 9, 1), (11, 0, 0)),
+            time((2020, 9, 1), (13, 0, 0)),
+        );
+        let evening = Period::new(
+            time((2020, 9, 1), (18, 0, 0)),
+            time((2020, 9, 1), (20, 0, 0)),
+        );
+
+        assert_eq!(morning.intersection(&lunch).duration(), Duration::hours(1));
+        assert!(morning.intersection(&evening).is_empty());
+        assert_eq!(
+            Period::day(&NaiveDate::from_ymd(2020, 9, 1), &Tz::UTC).intersection(&lunch),
+            lunch
+        );
+    }
+
+    #[test]
+    fn repeat_period_examples() {
+        let created_at = time((2020, 9, 1), (0, 0, 0));
+        let day = Some(24 * 60 * 60);
+
+        assert_eq!(
+            repeat_period(&created_at, day, &time((2020, 9, 3), (18, 30, 0))),
+            Ok(Period::new(
+                time((2020, 9, 3), (0, 0, 0)),
+                time((2020, 9, 4), (0, 0, 0))
+            ))
+        );
+        assert_eq!(
+            repeat_period(&created_at, day, &time((2020, 8, 1), (0, 0, 0))),
+            Ok(Period::new(created_at, time((2020, 9, 2), (0, 0, 0))))
+        );
+        assert_eq!(
+            repeat_period(&created_at, None, &time((2030, 1, 1), (0, 0, 0))).map(|p| p.start),
+            Ok(created_at)
+        );
+
+        for huge in &[i64::MAX / 1000, i64::MAX] {
+            assert_eq!(
+                repeat_period(&created_at, Some(*huge), &created_at),
+                Err(Error::InvalidRepeat(*huge))
+            );
+        }
+    }
+
+    #[test]
+    fn repeats_are_checked() {
+        assert_eq!(check_repeat(None), Ok(()));
+        assert_eq!(check_repeat(Some(1)), Ok(()));
+        assert_eq!(check_repeat(Some(MAX_REPEAT_SEC)), Ok(()));
+        for invalid in &[0, -1, MAX_REPEAT_SEC + 1, i64::MAX] {
+            assert_eq!(
+                check_repeat(Some(*invalid)),
+                Err(Error::InvalidRepeat(*invalid))
+            );
+        }
+    }
+
+    #[test]
+    fn streak_examples() {
+        let today = NaiveDate::from_ymd(2020, 9, 10);
+        let days: BTreeSet<_> = (1..=3).map(|ago| today - Duration::days(ago)).collect();
+
+        // Today is not done yet, the streak is still alive.
+        assert_eq!(streak(&days, &today), 3);
+
+        let mut done_today = days.clone();
+        done_today.insert(today);
+        assert_eq!(streak(&done_today, &today), 4);
+
+        // Missed yesterday.
+        assert_eq!(streak(&days, &today.succ().succ()), 0);
+    }
+
+    proptest! {
+        #[test]
+        fn overlap_is_symmetric(a in periods(), b in periods()) {
+            prop_assert_eq!(a.overlaps(&b), b.overlaps(&a));
+        }
+
+        #[test]
+        fn touching_periods_do_not_overlap(a in periods(), seconds in 0i64..86_400) {
+            let b = Period::new(a.end, a.end + Duration::seconds(seconds));
+            prop_assert!(!a.overlaps(&b));
+        }
+
+        #[test]
+        fn merged_periods_are_disjoint(periods in prop::collection::vec(periods(), 0..10)) {
+            let merged = merge(&periods);
+
+            for pair in merged.windows(2) {
+                prop_assert!(pair[0].end < pair[1].start);
+            }
+
+            // Every period is covered by exactly one merged period.
+            for period in periods.iter().filter(|period| !period.is_empty()) {
+                let covering = merged
+                    .iter()
+                    .filter(|merged| merged.start <= period.start && period.end <= merged.end)
+                    .count();
+                prop_assert_eq!(covering, 1);
+            }
+
+            let total: Duration = periods.iter().map(Period::duration).fold(Duration::zero(), |a, b| a + b);
+            let merged_total = merged.iter().map(Period::duration).fold(Duration::zero(), |a, b| a + b);
+            prop_assert!(merged_total <= total);
+        }
+
+        #[test]
+        fn split_days_adds_up(period in periods(), timezone in timezones()) {
+            let days = split_days(&period, &timezone);
+
+            let total = days.iter().fold(Duration::zero(), |total, (_, duration)| total + *duration);
+            prop_assert_eq!(total, period.duration());
+
+            if let Some((first, _)) = days.first() {
+                prop_assert_eq!(*first, local_date(&period.start, &timezone));
+            }
+
+            for (_, duration) in &days {
+                prop_assert!(*duration > Duration::zero());
+                prop_assert!(*duration <= Duration::hours(25));
+            }
+
+            for pair in days.windows(2) {
+                prop_assert_eq!(pair[0].0.succ(), pair[1].0);
+            }
+        }
+
+        #[test]
+        fn days_start_on_their_own_date(time in times(), timezone in timezones()) {
+            let date = local_date(&time, &timezone);
+            let start = start_of_day(&date, &timezone);
+
+            prop_assert!(start <= time);
+            prop_assert_eq!(local_date(&start, &timezone), date);
+            prop_assert!(time < start_of_day(&date.succ(), &timezone));
+        }
+
+        #[test]
+        fn daily_totals_do_not_count_overlaps_twice(
+            periods in prop::collection::vec(periods(), 0..10),
+            timezone in timezones()
+        ) {
+            let totals = daily_totals(&periods, &timezone);
+            let total = totals.values().fold(Duration::zero(), |a, b| a + *b);
+            let merged = merge(&periods).iter().map(Period::duration).fold(Duration::zero(), |a, b| a + b);
+
+            prop_assert_eq!(total, merged);
+        }
+
+        #[test]
+        fn hourly_totals_add_up(
+            periods in prop::collection::vec(periods(), 0..10),
+            timezone in timezones()
+        ) {
+            let totals = hourly_totals(&periods, &timezone);
+            let total = totals.iter().fold(Duration::zero(), |a, b| a + *b);
+            let merged = merge(&periods).iter().map(Period::duration).fold(Duration::zero(), |a, b| a + b);
+
+            prop_assert_eq!(total, merged);
+        }
+
+        #[test]
+        fn repeat_period_contains_now(
+            created_at in times(),
+            now in times(),
+            repeat in 1i64..30 * 24 * 60 * 60
+        ) {
+            let period = repeat_period(&created_at, Some(repeat), &now).expect("Repeats fit.");
+
+            prop_assert_eq!(period.duration(), Duration::seconds(repeat));
+            prop_assert_eq!((period.start - created_at).num_seconds() % repeat, 0);
+            if created_at <= now {
+                prop_assert!(period.start <= now && now < period.end);
+            } else {
+                prop_assert_eq!(period.start, created_at);
+            }
+        }
+
+        #[test]
+        fn streak_counts_the_latest_run(
+            today in times().prop_map(|time| time.date()),
+            run in 0u32..60,
+            gap in 2i64..10,
+            older in 0u32..60,
+            done_today in any::<bool>()
+        ) {
+            let latest = if done_today { today } else { today.pred() };
+            let mut days: BTreeSet<NaiveDate> =
+                (0..run).map(|ago| latest - Duration::days(ago.into())).collect();
+
+            // An older run separated by at least one missed day, besides today, doesn't count.
+            let older_end = today - Duration::days(i64::from(run) + gap);
+            days.extend((0..older).map(|ago| older_end - Duration::days(ago.into())));
+
+            prop_assert_eq!(streak(&days, &today), run);
+        }
+    }
+}
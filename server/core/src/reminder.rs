@@ -0,0 +1,231 @@
+//! When reminders are due.
+//!
+//! Reminders are set for a local time of day and never go off during the user's quiet hours.
+//! One which would is held back until the quiet hours end.
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::period::local_date;
+
+/// Local times of day in which reminders are held back, e.g. at night.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn new(start: NaiveTime, end: NaiveTime) -> QuietHours {
+        QuietHours { start, end }
+    }
+
+    /// Whether a local time is in the quiet hours. They wrap past midnight when they end before
+    /// they start, e.g. from 22:00 to 07:00.
+    pub fn contains(&self, time: &NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= *time && *time < self.end
+        } else {
+            self.start <= *time || *time < self.end
+        }
+    }
+}
+
+/// The UTC time of a local date and time.
+///
+/// Times skipped when daylight saving starts go off once the clocks have moved forward. There is
+/// none when the timezone skips more than two hours, which no timezone does, or past the last
+/// date there is.
+pub fn local_to_utc<Tz: TimeZone>(
+    date: &NaiveDate,
+    time: &NaiveTime,
+    timezone: &Tz,
+) -> Option<NaiveDateTime> {
+    let local = date.and_time(*time);
+    (0..=120).step_by(15).find_map(|minutes| {
+        let local = local.checked_add_signed(Duration::minutes(minutes))?;
+        timezone
+            .from_local_datetime(&local)
+            .earliest()
+            .map(|utc| utc.naive_utc())
+    })
+}
+
+/// When a reminder set for `at` goes off on a local date, after holding it back for quiet hours.
+pub fn goes_off<Tz: TimeZone>(
+    date: &NaiveDate,
+    at: &NaiveTime,
+    quiet: Option<&QuietHours>,
+    timezone: &Tz,
+) -> Option<NaiveDateTime> {
+    match quiet {
+        Some(quiet) if quiet.contains(at) => {
+            // Quiet hours past midnight end the next day.
+            let date = if quiet.start > quiet.end && *at >= quiet.start {
+                date.succ_opt()?
+            } else {
+                *date
+            };
+
+            local_to_utc(&date, &quiet.end, timezone)
+        }
+        _ => local_to_utc(date, at, timezone),
+    }
+}
+
+/// The latest time in `(since, now]` at which a reminder set for `at` goes off, if any.
+pub fn due<Tz: TimeZone>(
+    at: &NaiveTime,
+    quiet: Option<&QuietHours>,
+    timezone: &Tz,
+    since: &NaiveDateTime,
+    now: &NaiveDateTime,
+) -> Option<NaiveDateTime> {
+    if since >= now {
+        return None;
+    }
+
+    // Held back reminders of the day before can go off today.
+    let mut date = local_date(now, timezone);
+    let since_date = local_date(since, timezone);
+    let first = since_date.pred_opt().unwrap_or(since_date);
+    while date >= first {
+        if let Some(time) = goes_off(&date, at, quiet, timezone) {
+            if *since < time && time <= *now {
+                return Some(time);
+            }
+        }
+        date = date.pred_opt()?;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Tz;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms(hour, minute, 0)
+    }
+
+    fn utc(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2020, 10, day).and_hms(hour, minute, 0)
+    }
+
+    #[test]
+    fn quiet_hours_wrap_past_midnight() {
+        let night = QuietHours::new(time(22, 0), time(7, 0));
+        assert!(night.contains(&time(23, 30)));
+        assert!(night.contains(&time(0, 0)));
+        assert!(night.contains(&time(6, 59)));
+        assert!(!night.contains(&time(7, 0)));
+        assert!(!night.contains(&time(12, 0)));
+
+        let lunch = QuietHours::new(time(12, 0), time(13, 0));
+        assert!(lunch.contains(&time(12, 30)));
+        assert!(!lunch.contains(&time(13, 0)));
+        assert!(!lunch.contains(&time(11, 59)));
+    }
+
+    #[test]
+    fn due_once_a_day() {
+        let at = time(9, 0);
+
+        assert_eq!(
+            due(&at, None, &Tz::UTC, &utc(19, 8, 0), &utc(19, 9, 0)),
+            Some(utc(19, 9, 0))
+        );
+        assert_eq!(
+            due(&at, None, &Tz::UTC, &utc(19, 9, 0), &utc(19, 10, 0)),
+            None
+        );
+        // The latest of several days.
+        assert_eq!(
+            due(&at, None, &Tz::UTC, &utc(15, 0, 0), &utc(19, 10, 0)),
+            Some(utc(19, 9, 0))
+        );
+        assert_eq!(
+            due(&at, None, &Tz::UTC, &utc(19, 10, 0), &utc(19, 10, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn due_in_local_time() {
+        // 09:00 in Sydney is 22:00 UTC the day before in October.
+        let sydney = Tz::Australia__Sydney;
+        assert_eq!(
+            due(&time(9, 0), None, &sydney, &utc(18, 12, 0), &utc(19, 0, 0)),
+            Some(utc(18, 22, 0))
+        );
+    }
+
+    #[test]
+    fn held_back_during_quiet_hours() {
+        let night = QuietHours::new(time(22, 0), time(7, 0));
+
+        // Set for 23:00, goes off at 07:00 the next day.
+        assert_eq!(
+            goes_off(
+                &NaiveDate::from_ymd(2020, 10, 18),
+                &time(23, 0),
+                Some(&night),
+                &Tz::UTC
+            ),
+            Some(utc(19, 7, 0))
+        );
+        assert_eq!(
+            due(
+                &time(23, 0),
+                Some(&night),
+                &Tz::UTC,
+                &utc(18, 22, 0),
+                &utc(19, 6, 0)
+            ),
+            None
+        );
+        assert_eq!(
+            due(
+                &time(23, 0),
+                Some(&night),
+                &Tz::UTC,
+                &utc(19, 6, 0),
+                &utc(19, 8, 0)
+            ),
+            Some(utc(19, 7, 0))
+        );
+        // Set for 06:00, goes off at 07:00 the same day.
+        assert_eq!(
+            due(
+                &time(6, 0),
+                Some(&night),
+                &Tz::UTC,
+                &utc(19, 5, 0),
+                &utc(19, 8, 0)
+            ),
+            Some(utc(19, 7, 0))
+        );
+    }
+
+    #[test]
+    fn skipped_local_times_go_off_after_the_change() {
+        // Clocks in Sydney go from 02:00 to 03:00 on the 4th of October 2020.
+        let sydney = Tz::Australia__Sydney;
+        let date = NaiveDate::from_ymd(2020, 10, 4);
+
+        assert!(local_to_utc(&date, &time(2, 30), &sydney).is_some());
+        assert_eq!(
+            local_to_utc(&date, &time(2, 30), &sydney),
+            local_to_utc(&date, &time(3, 0), &sydney)
+        );
+    }
+
+    #[test]
+    fn held_back_past_the_last_date() {
+        let night = QuietHours::new(time(22, 0), time(7, 0));
+        let last = chrono::naive::MAX_DATE;
+
+        assert_eq!(goes_off(&last, &time(23, 0), Some(&night), &Tz::UTC), None);
+    }
+}
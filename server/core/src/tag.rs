@@ -0,0 +1,93 @@
+//! Names of tags which cut across tasks, e.g. `billable` or `with-family`.
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// The longest a tag name can be, in characters.
+pub const MAX_LENGTH: usize = 64;
+
+/// A lowercase name without whitespace or commas, so tags can be listed like `billable,outdoor`.
+///
+/// Serialized as a string.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TagName {
+    name: String,
+}
+
+impl TagName {
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+}
+
+impl FromStr for TagName {
+    type Err = Error;
+
+    /// Names are trimmed and lowercased, e.g. ` Billable ` is `billable`.
+    fn from_str(name: &str) -> Result<TagName> {
+        let trimmed = name.trim();
+        if trimmed.is_empty()
+            || trimmed.chars().count() > MAX_LENGTH
+            || trimmed.contains(|c: char| c.is_whitespace() || c == ',')
+        {
+            return Err(Error::InvalidTag(name.to_string()));
+        }
+
+        Ok(TagName {
+            name: trimmed.to_lowercase(),
+        })
+    }
+}
+
+impl TryFrom<String> for TagName {
+    type Error = Error;
+
+    fn try_from(name: String) -> Result<TagName> {
+        name.parse()
+    }
+}
+
+impl From<TagName> for String {
+    fn from(name: TagName) -> String {
+        name.name
+    }
+}
+
+impl fmt::Display for TagName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_examples() {
+        let name: TagName = " With-Family ".parse().expect("Is valid.");
+        assert_eq!(name.as_str(), "with-family");
+        assert_eq!(name.to_string(), "with-family");
+        assert_eq!("outdoor".parse(), Ok(TagName::from_str("OUTDOOR").unwrap()));
+
+        let long = "a".repeat(MAX_LENGTH + 1);
+        for invalid in &["", " ", "with family", "billable,outdoor", long.as_str()] {
+            assert_eq!(
+                invalid.parse::<TagName>(),
+                Err(Error::InvalidTag(invalid.to_string())),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let name: TagName = serde_json::from_str("\"Billable\"").expect("Is valid.");
+        assert_eq!(serde_json::to_string(&name).unwrap(), "\"billable\"");
+        assert!(serde_json::from_str::<TagName>("\"not valid\"").is_err());
+    }
+}
@@ -0,0 +1,110 @@
+-- PostgreSQL version of the SQLite schema. Keep the two in sync.
+
+-- List of users!
+CREATE TABLE IF NOT EXISTS users (
+  id BIGSERIAL PRIMARY KEY,
+
+  -- Username
+  username TEXT NOT NULL,
+
+  -- The name of the user.
+  name TEXT NOT NULL,
+
+  -- Ensure the usernames are unique.
+  CONSTRAINT unique_username UNIQUE(username)
+);
+
+-- Representation of a task
+CREATE TABLE IF NOT EXISTS tasks (
+  id BIGSERIAL NOT NULL PRIMARY KEY,
+  parent_id BIGINT,
+  user_id BIGINT NOT NULL,
+
+  -- Name of this task.
+  name TEXT NOT NULL,
+
+  FOREIGN KEY(user_id) REFERENCES users(id),
+  FOREIGN KEY(parent_id) REFERENCES tasks(id),
+
+  -- Ensure the task and username pair is unique.
+  CONSTRAINT unique_task_username UNIQUE(user_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS instances (
+  id BIGSERIAL NOT NULL PRIMARY KEY,
+  task_id BIGINT NOT NULL,
+
+  -- The time this period started.
+  start TIMESTAMP NOT NULL,
+
+  -- The time this period completed.
+  "end" TIMESTAMP NOT NULL,
+
+  FOREIGN KEY(task_id) REFERENCES tasks(id),
+
+  -- Ensure the task and username pair is unique.
+  CONSTRAINT unique_instance UNIQUE(task_id, start, "end")
+);
+
+-- Representation of a habit
+CREATE TABLE IF NOT EXISTS habit (
+  id BIGSERIAL PRIMARY KEY,
+  parent_id BIGINT,
+  user_id BIGINT NOT NULL,
+
+  -- Name of this habit.
+  name TEXT NOT NULL,
+
+  -- Time this habit was created.
+  created_at TIMESTAMP NOT NULL,
+
+  -- Repeat period is in seconds.
+  repeat_period_sec BIGINT,
+
+  -- Optional set of notes.
+  notes TEXT,
+
+  FOREIGN KEY(user_id) REFERENCES users(id),
+  FOREIGN KEY(parent_id) REFERENCES habit(id),
+
+  -- Ensure the habit and username pair is unique.
+  CONSTRAINT unique_habit_username UNIQUE(user_id, parent_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS instance (
+  id BIGSERIAL PRIMARY KEY,
+  habit_id BIGINT NOT NULL,
+
+  -- Creation time.
+  created_at TIMESTAMP,
+
+  -- The minimum amount of time to have been spent in this instance until we can mark it as complete.
+  target_duration BIGINT,
+
+  -- Whether or not this habit has been completed.
+  completed BOOLEAN NOT NULL,
+
+  -- Optional notes to add to this instance.
+  notes TEXT,
+
+  FOREIGN KEY(habit_id) REFERENCES habit(id)
+);
+
+-- The elapsed period represents time spent on a habit instance.
+CREATE TABLE IF NOT EXISTS elapsed_period (
+  id BIGSERIAL PRIMARY KEY,
+
+  -- Which instance this period belongs to.
+  instance_id BIGINT NOT NULL,
+
+  -- The time this period started.
+  started_at TIMESTAMP,
+
+  -- The time this period completed.
+  ended_at TIMESTAMP,
+
+  -- Optional notes to add to this instance.
+  notes TEXT,
+
+  FOREIGN KEY(instance_id) REFERENCES instance(id)
+);
@@ -0,0 +1,15 @@
+-- Tokens behind the printed QR codes. Scanning one marks its habit as done.
+CREATE TABLE IF NOT EXISTS checkin_token (
+  id BIGSERIAL PRIMARY KEY,
+  user_id BIGINT NOT NULL,
+  habit_id BIGINT NOT NULL,
+
+  -- Time this token was issued.
+  created_at TIMESTAMP NOT NULL,
+
+  -- Time this token was revoked. Revoked tokens can't be used to check in.
+  revoked_at TIMESTAMP,
+
+  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+  FOREIGN KEY(habit_id) REFERENCES habit(id) ON DELETE CASCADE
+);
@@ -0,0 +1,53 @@
+-- Reminders of a habit, e.g. every day at 09:00 unless it was done.
+CREATE TABLE IF NOT EXISTS reminder (
+  id BIGSERIAL PRIMARY KEY,
+  habit_id BIGINT NOT NULL,
+
+  -- Local time of day to go off at in minutes after midnight.
+  time_of_day BIGINT NOT NULL,
+
+  -- Only go off if the habit was not done in its current period.
+  unless_done BOOLEAN NOT NULL,
+
+  -- How the reminder is delivered, e.g. `email`.
+  channel TEXT NOT NULL,
+
+  -- Time this reminder was created.
+  created_at TIMESTAMP NOT NULL,
+
+  -- The latest time this reminder was due and handled, missing until it first is.
+  last_due_at TIMESTAMP,
+
+  FOREIGN KEY(habit_id) REFERENCES habit(id) ON DELETE CASCADE
+);
+
+-- How and when a user wants to be reminded. Users without settings get the defaults.
+CREATE TABLE IF NOT EXISTS reminder_settings (
+  user_id BIGINT PRIMARY KEY,
+
+  -- IANA timezone reminder times are in, e.g. `Australia/Sydney`.
+  timezone TEXT NOT NULL,
+
+  -- Address email reminders are sent to.
+  email TEXT,
+
+  -- Local times in minutes after midnight between which reminders are held back.
+  quiet_start BIGINT,
+  quiet_end BIGINT,
+
+  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Reminders waiting for the app to fetch and acknowledge them.
+CREATE TABLE IF NOT EXISTS pending_reminder (
+  id BIGSERIAL PRIMARY KEY,
+  reminder_id BIGINT NOT NULL,
+
+  -- Time the reminder went off.
+  due_at TIMESTAMP NOT NULL,
+
+  FOREIGN KEY(reminder_id) REFERENCES reminder(id) ON DELETE CASCADE,
+
+  -- A reminder goes off once at a time.
+  CONSTRAINT unique_pending_reminder UNIQUE(reminder_id, due_at)
+);
@@ -0,0 +1,28 @@
+-- Goals for a task or a habit, e.g. at least 5h of Deep Work per week.
+CREATE TABLE IF NOT EXISTS goal (
+  id BIGSERIAL PRIMARY KEY,
+  user_id BIGINT NOT NULL,
+
+  -- The goal is for exactly one of a task or a habit.
+  task_id BIGINT,
+  habit_id BIGINT,
+
+  -- `at_least` or `at_most`.
+  comparison TEXT NOT NULL,
+
+  -- The amount to compare to, in `unit`.
+  amount BIGINT NOT NULL,
+
+  -- `seconds` tracked or `times` done.
+  unit TEXT NOT NULL,
+
+  -- The periods the goal starts over in, `day`, `week` or `month`.
+  cadence TEXT NOT NULL,
+
+  -- Time this goal was created.
+  created_at TIMESTAMP NOT NULL,
+
+  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+  FOREIGN KEY(task_id) REFERENCES tasks(id) ON DELETE CASCADE,
+  FOREIGN KEY(habit_id) REFERENCES habit(id) ON DELETE CASCADE
+);
@@ -0,0 +1,57 @@
+-- Timers which are running. Stopping one records an instance of its task.
+CREATE TABLE IF NOT EXISTS timer (
+  id INTEGER PRIMARY KEY,
+  task_id INTEGER NOT NULL,
+
+  -- Time this timer was started.
+  started_at DATETIME NOT NULL,
+
+  FOREIGN KEY(task_id) REFERENCES tasks(id) ON DELETE CASCADE,
+
+  -- A task has at most one running timer.
+  CONSTRAINT unique_timer_task UNIQUE(task_id)
+);
+
+-- Urls events of a user are posted to.
+CREATE TABLE IF NOT EXISTS webhook (
+  id INTEGER PRIMARY KEY,
+  user_id INTEGER NOT NULL,
+  url TEXT NOT NULL,
+
+  -- Key the payloads are signed with.
+  secret TEXT NOT NULL,
+
+  -- Comma separated kinds of events to post, empty for every event.
+  events TEXT NOT NULL,
+
+  -- Time this webhook was registered.
+  created_at DATETIME NOT NULL,
+
+  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Every attempt at posting an event to a webhook.
+CREATE TABLE IF NOT EXISTS webhook_delivery (
+  id INTEGER PRIMARY KEY,
+  webhook_id INTEGER NOT NULL,
+
+  -- Kind of the event, e.g. `HabitCompleted`.
+  event TEXT NOT NULL,
+
+  -- The json body which was posted.
+  payload TEXT NOT NULL,
+
+  -- Attempts of the same delivery count up from 1.
+  attempt INTEGER NOT NULL,
+
+  -- The response status, missing if there was no response.
+  status_code INTEGER,
+
+  -- Why the attempt failed, missing if it succeeded.
+  error TEXT,
+
+  -- Time of the attempt.
+  attempted_at DATETIME NOT NULL,
+
+  FOREIGN KEY(webhook_id) REFERENCES webhook(id) ON DELETE CASCADE
+);
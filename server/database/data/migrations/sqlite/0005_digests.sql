@@ -0,0 +1,2 @@
+-- Channel the weekly digest is delivered through, e.g. `email`. No digest is sent without one.
+ALTER TABLE reminder_settings ADD COLUMN digest_channel TEXT;
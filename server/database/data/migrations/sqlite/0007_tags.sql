@@ -0,0 +1,35 @@
+-- Tags of a user which cut across tasks, e.g. `billable`.
+CREATE TABLE IF NOT EXISTS tag (
+  id INTEGER PRIMARY KEY,
+  user_id INTEGER NOT NULL,
+
+  -- Lowercase, without whitespace or commas.
+  name TEXT NOT NULL,
+
+  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+
+  -- Tag names are unique per user.
+  CONSTRAINT unique_tag UNIQUE(user_id, name)
+);
+
+-- Tags of a task, which apply to all its instances.
+CREATE TABLE IF NOT EXISTS task_tag (
+  task_id INTEGER NOT NULL,
+  tag_id INTEGER NOT NULL,
+
+  FOREIGN KEY(task_id) REFERENCES tasks(id) ON DELETE CASCADE,
+  FOREIGN KEY(tag_id) REFERENCES tag(id) ON DELETE CASCADE,
+
+  CONSTRAINT unique_task_tag UNIQUE(task_id, tag_id)
+);
+
+-- Tags of a single instance, e.g. one session of a task which was billable.
+CREATE TABLE IF NOT EXISTS instance_tag (
+  instance_id INTEGER NOT NULL,
+  tag_id INTEGER NOT NULL,
+
+  FOREIGN KEY(instance_id) REFERENCES instances(id) ON DELETE CASCADE,
+  FOREIGN KEY(tag_id) REFERENCES tag(id) ON DELETE CASCADE,
+
+  CONSTRAINT unique_instance_tag UNIQUE(instance_id, tag_id)
+);
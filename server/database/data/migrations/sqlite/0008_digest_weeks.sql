@@ -0,0 +1,2 @@
+-- Midnight starting the local Monday of the latest week a digest was sent for, missing until one is.
+ALTER TABLE reminder_settings ADD COLUMN digest_week TIMESTAMP;
@@ -0,0 +1,58 @@
+//! Everything which differs between the database backends.
+//!
+//! The rest of the crate is written against these aliases and portable SQL, so the same
+//! `User`/`Task`/`Instance` API runs on either backend.
+
+#[cfg(all(feature = "sqlite", feature = "postgres"))]
+compile_error!("Enable exactly one of the `sqlite` and `postgres` features.");
+
+#[cfg(not(any(feature = "sqlite", feature = "postgres")))]
+compile_error!("Enable one of the `sqlite` or `postgres` features.");
+
+/// The sqlx database driver.
+#[cfg(feature = "sqlite")]
+pub type Db = sqlx::Sqlite;
+#[cfg(feature = "postgres")]
+pub type Db = sqlx::Postgres;
+
+/// A pool of connections to the database.
+pub type Pool = sqlx::Pool<Db>;
+
+/// Database uris of this backend start with one of these.
+#[cfg(feature = "sqlite")]
+pub const URI_SCHEMES: &[&str] = &["sqlite:"];
+#[cfg(feature = "postgres")]
+pub const URI_SCHEMES: &[&str] = &["postgres:", "postgresql:"];
+
+/// The error code for a unique constraint violation.
+///
+/// SQLITE_CONSTRAINT_UNIQUE, see https://sqlite.org/rescode.html#constraint_unique.
+#[cfg(feature = "sqlite")]
+pub const UNIQUE_VIOLATION_CODE: &str = "2067";
+/// unique_violation, see https://www.postgresql.org/docs/current/errcodes-appendix.html.
+#[cfg(feature = "postgres")]
+pub const UNIQUE_VIOLATION_CODE: &str = "23505";
+
+/// The schema migrations of this backend in the order they are applied.
+#[cfg(feature = "sqlite")]
+pub const MIGRATIONS: &[&str] = &[
+    include_str!("../data/migrations/sqlite/0001_initial.sql"),
+    include_str!("../data/migrations/sqlite/0002_checkin_tokens.sql"),
+    include_str!("../data/migrations/sqlite/0003_webhooks.sql"),
+    include_str!("../data/migrations/sqlite/0004_reminders.sql"),
+    include_str!("../data/migrations/sqlite/0005_digests.sql"),
+    include_str!("../data/migrations/sqlite/0006_goals.sql"),
+    include_str!("../data/migrations/sqlite/0007_tags.sql"),
+    include_str!("../data/migrations/sqlite/0008_digest_weeks.sql"),
+];
+#[cfg(feature = "postgres")]
+pub const MIGRATIONS: &[&str] = &[
+    include_str!("../data/migrations/postgres/0001_initial.sql"),
+    include_str!("../data/migrations/postgres/0002_checkin_tokens.sql"),
+    include_str!("../data/migrations/postgres/0003_webhooks.sql"),
+    include_str!("../data/migrations/postgres/0004_reminders.sql"),
+    include_str!("../data/migrations/postgres/0005_digests.sql"),
+    include_str!("../data/migrations/postgres/0006_goals.sql"),
+    include_str!("../data/migrations/postgres/0007_tags.sql"),
+    include_str!("../data/migrations/postgres/0008_digest_weeks.sql"),
+];
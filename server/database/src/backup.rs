@@ -0,0 +1,381 @@
+//! Online backups of the database.
+//!
+//! Backups are taken with `VACUUM INTO` which writes a consistent, compacted copy of the database
+//! while it keeps serving requests. Every backup is a standalone SQLite file named after the time
+//! it was taken so backups sort chronologically by name.
+use crate::connection::{Connection, JournalMode};
+use crate::error::{Error, Result};
+use crate::migration;
+use chrono::{Duration, NaiveDateTime};
+use sqlx::Row;
+use std::path::{Path, PathBuf};
+use tracing::instrument;
+
+/// Every backup file name starts with this prefix.
+const BACKUP_PREFIX: &str = "mindless-";
+
+/// Every backup file name ends with this extension.
+const BACKUP_EXTENSION: &str = ".db";
+
+/// Format of the time in a backup file name.
+const BACKUP_TIME_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// How many backups to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    /// Always keep this many of the latest backups regardless of their age.
+    pub keep_last: usize,
+
+    /// Delete backups older than this unless they are one of the latest `keep_last`.
+    pub max_age: Option<Duration>,
+}
+
+/// The name of a backup taken at `time`.
+pub fn backup_file_name(time: &NaiveDateTime) -> String {
+    format!(
+        "{}{}{}",
+        BACKUP_PREFIX,
+        time.format(BACKUP_TIME_FORMAT),
+        BACKUP_EXTENSION
+    )
+}
+
+/// Get the time a backup was taken from its file name. None if this is not a backup.
+fn backup_time(file_name: &str) -> Option<NaiveDateTime> {
+    let time = file_name
+        .strip_prefix(BACKUP_PREFIX)?
+        .strip_suffix(BACKUP_EXTENSION)?;
+
+    NaiveDateTime::parse_from_str(time, BACKUP_TIME_FORMAT).ok()
+}
+
+/// Write a consistent copy of the database into `directory`.
+///
+/// Returns the path of the new backup.
+#[instrument(level = "debug", skip(connection))]
+pub async fn snapshot(
+    connection: &Connection,
+    directory: &Path,
+    time: &NaiveDateTime,
+) -> Result<PathBuf> {
+    std::fs::create_dir_all(directory)?;
+
+    let path = directory.join(backup_file_name(time));
+    let destination = path
+        .to_str()
+        .ok_or_else(|| Error::InvalidBackup(format!("\"{}\" is not valid utf8", path.display())))?;
+
+    sqlx::query("VACUUM INTO ?")
+        .bind(destination)
+        .execute(connection.get_pool())
+        .await?;
+
+    Ok(path)
+}
+
+/// List the backups in `directory`, oldest first.
+pub fn list(directory: &Path) -> Result<Vec<(NaiveDateTime, PathBuf)>> {
+    let mut backups = Vec::new();
+
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        let time = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(backup_time);
+
+        if let Some(time) = time {
+            backups.push((time, path));
+        }
+    }
+
+    backups.sort();
+
+    Ok(backups)
+}
+
+/// Delete the backups in `directory` which fall outside of the retention rules.
+///
+/// Returns the deleted backups.
+#[instrument(level = "debug")]
+pub fn prune(directory: &Path, retention: &Retention, now: &NaiveDateTime) -> Result<Vec<PathBuf>> {
+    let backups = list(directory)?;
+    let protected = backups.len().saturating_sub(retention.keep_last);
+
+    let mut deleted = Vec::new();
+    for (time, path) in backups.into_iter().take(protected) {
+        let expired = match retention.max_age {
+            Some(max_age) => *now - time > max_age,
+            // Without a maximum age we only keep the latest backups.
+            None => true,
+        };
+
+        if expired {
+            std::fs::remove_file(&path)?;
+            deleted.push(path);
+        }
+    }
+
+    Ok(deleted)
+}
+
+/// Check a backup is a healthy database we know how to migrate.
+///
+/// Returns the schema version of the backup.
+#[instrument(level = "debug")]
+pub async fn validate(backup: &Path) -> Result<i64> {
+    if !backup.is_file() {
+        return Err(Error::InvalidBackup(format!(
+            "\"{}\" does not exist",
+            backup.display()
+        )));
+    }
+
+    let uri = format!("sqlite://{}?mode=ro", backup.display());
+    let connection = Connection::builder(&uri)
+        .max_connections(1)
+        // Don't convert the backup to WAL, it is opened read only.
+        .journal_mode(JournalMode::Delete)
+        .connect()
+        .await?;
+
+    let integrity: String = sqlx::query("PRAGMA integrity_check")
+        .fetch_one(connection.get_pool())
+        .await?
+        .try_get(0)?;
+
+    if integrity != "ok" {
+        return Err(Error::InvalidBackup(format!(
+            "integrity check failed: {}",
+            integrity
+        )));
+    }
+
+    let version = migration::version(&connection).await?;
+    if version < 1 || version > migration::LATEST_VERSION {
+        return Err(Error::InvalidBackup(format!(
+            "schema version {} is not between 1 and {}",
+            version,
+            migration::LATEST_VERSION
+        )));
+    }
+
+    Ok(version)
+}
+
+/// The path with a suffix appended to the file name, e.g. `habits.db-wal` for `habits.db`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+
+    PathBuf::from(path)
+}
+
+/// Replace the database at `destination` with `backup`.
+///
+/// The server must not be running. The current database is kept next to the destination with a
+/// `.before-restore` suffix, along with its write ahead log which may hold transactions that were
+/// not checkpointed yet.
+#[instrument(level = "debug")]
+pub async fn restore(backup: &Path, destination: &Path) -> Result<i64> {
+    let version = validate(backup).await?;
+
+    // Copy next to the destination first so that an interrupted copy leaves the current database
+    // untouched, and the copy can be renamed into place in one step.
+    let restoring = with_suffix(destination, ".restoring");
+    std::fs::copy(backup, &restoring)?;
+
+    let previous = with_suffix(destination, ".before-restore");
+    let logs = ["-wal", "-shm"];
+    if destination.exists() {
+        // Logs of an older restore would be replayed on top of the database we move aside.
+        for suffix in &logs {
+            let previous_log = with_suffix(&previous, suffix);
+            if previous_log.exists() {
+                std::fs::remove_file(previous_log)?;
+            }
+        }
+
+        std::fs::rename(destination, &previous)?;
+        for suffix in &logs {
+            let log = with_suffix(destination, suffix);
+            if log.exists() {
+                std::fs::rename(log, with_suffix(&previous, suffix))?;
+            }
+        }
+    }
+
+    // Logs without a database are stale and would be replayed on top of the restored database.
+    for suffix in &logs {
+        let log = with_suffix(destination, suffix);
+        if log.exists() {
+            std::fs::remove_file(log)?;
+        }
+    }
+
+    std::fs::rename(restoring, destination)?;
+
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::User;
+
+    /// A fresh directory for a test.
+    fn test_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("mindless-backup-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).expect("Can create test directory.");
+
+        directory
+    }
+
+    fn time(timestamp: i64) -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(timestamp, 0)
+    }
+
+    #[test]
+    fn file_name_round_trip() {
+        let now = time(1_600_000_000);
+        let name = backup_file_name(&now);
+
+        assert_eq!(name, "mindless-20200913T122640.db");
+        assert_eq!(backup_time(&name), Some(now));
+        assert_eq!(backup_time("habits.db"), None);
+    }
+
+    #[test]
+    fn prune_keeps_latest() {
+        let directory = test_directory("prune-latest");
+        for day in 0..5 {
+            std::fs::write(directory.join(backup_file_name(&time(day * 86400))), b"")
+                .expect("Can write backup.");
+        }
+        std::fs::write(directory.join("unrelated.db"), b"").expect("Can write file.");
+
+        let retention = Retention {
+            keep_last: 2,
+            max_age: None,
+        };
+        let deleted = prune(&directory, &retention, &time(5 * 86400)).expect("Can prune.");
+
+        assert_eq!(deleted.len(), 3);
+        let remaining: Vec<_> = list(&directory)
+            .expect("Can list.")
+            .into_iter()
+            .map(|(time, _)| time)
+            .collect();
+        assert_eq!(remaining, vec![time(3 * 86400), time(4 * 86400)]);
+        assert!(directory.join("unrelated.db").exists());
+
+        std::fs::remove_dir_all(directory).expect("Can clean up.");
+    }
+
+    #[test]
+    fn prune_by_age() {
+        let directory = test_directory("prune-age");
+        for day in 0..5 {
+            std::fs::write(directory.join(backup_file_name(&time(day * 86400))), b"")
+                .expect("Can write backup.");
+        }
+
+        let retention = Retention {
+            keep_last: 1,
+            max_age: Some(Duration::days(2)),
+        };
+        let deleted = prune(&directory, &retention, &time(5 * 86400)).expect("Can prune.");
+
+        // Days 0, 1 and 2 are more than two days old.
+        assert_eq!(deleted.len(), 3);
+        assert_eq!(list(&directory).expect("Can list.").len(), 2);
+
+        std::fs::remove_dir_all(directory).expect("Can clean up.");
+    }
+
+    #[tokio::test]
+    async fn snapshot_and_restore() {
+        let directory = test_directory("snapshot");
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        User::insert("username", "name", &connection)
+            .await
+            .expect("Should successfully insert.");
+
+        let backup = snapshot(&connection, &directory, &time(0))
+            .await
+            .expect("Can take a backup.");
+        assert_eq!(
+            validate(&backup).await.expect("Backup is valid."),
+            migration::LATEST_VERSION
+        );
+
+        let destination = directory.join("restored.db");
+        restore(&backup, &destination)
+            .await
+            .expect("Can restore the backup.");
+
+        let restored = Connection::connect(&format!("sqlite://{}", destination.display()))
+            .await
+            .expect("Should connect to the restored database.");
+        User::get("username", &restored)
+            .await
+            .expect("User is in the backup.");
+
+        std::fs::remove_dir_all(directory).expect("Can clean up.");
+    }
+
+    #[tokio::test]
+    async fn restore_keeps_the_write_ahead_log() {
+        let directory = test_directory("restore-wal");
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let backup = snapshot(&connection, &directory, &time(0))
+            .await
+            .expect("Can take a backup.");
+
+        let destination = directory.join("habits.db");
+        let read = |suffix: &str| std::fs::read(with_suffix(&destination, suffix)).ok();
+        std::fs::write(&destination, b"database").expect("Can write file.");
+        std::fs::write(with_suffix(&destination, "-wal"), b"wal").expect("Can write file.");
+        std::fs::write(with_suffix(&destination, "-shm"), b"shm").expect("Can write file.");
+        // Left over from an older restore.
+        std::fs::write(with_suffix(&destination, ".before-restore-wal"), b"old")
+            .expect("Can write file.");
+
+        restore(&backup, &destination)
+            .await
+            .expect("Can restore the backup.");
+
+        assert_eq!(read(".before-restore"), Some(b"database".to_vec()));
+        assert_eq!(read(".before-restore-wal"), Some(b"wal".to_vec()));
+        assert_eq!(read(".before-restore-shm"), Some(b"shm".to_vec()));
+        assert_eq!(read("-wal"), None);
+        assert_eq!(read("-shm"), None);
+        assert_eq!(read(".restoring"), None);
+        assert_eq!(read(""), std::fs::read(&backup).ok());
+
+        std::fs::remove_dir_all(directory).expect("Can clean up.");
+    }
+
+    #[tokio::test]
+    async fn reject_invalid_backup() {
+        let directory = test_directory("invalid");
+        let backup = directory.join(backup_file_name(&time(0)));
+        std::fs::write(&backup, b"not a database").expect("Can write file.");
+
+        assert!(validate(&backup).await.is_err());
+        assert_eq!(
+            validate(&directory.join("missing.db"))
+                .await
+                .expect_err("Backup does not exist."),
+            Error::InvalidBackup(String::new())
+        );
+
+        std::fs::remove_dir_all(directory).expect("Can clean up.");
+    }
+}
@@ -0,0 +1,254 @@
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::SqlId;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::Done;
+use sqlx::FromRow;
+use std::cmp::PartialEq;
+use tracing::instrument;
+
+/// This is a struct representing a check-in token, e.g. behind a printed QR code.
+///
+/// The token only identifies the habit. The api signs the id so tokens can't be guessed.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct CheckinToken {
+    /// The token id.
+    id: SqlId,
+
+    /// The user the habit belongs to.
+    user_id: SqlId,
+
+    /// The habit checking in marks as done.
+    habit_id: SqlId,
+
+    /// Time this token was issued.
+    created_at: NaiveDateTime,
+
+    /// Time this token was revoked.
+    revoked_at: Option<NaiveDateTime>,
+}
+
+impl CheckinToken {
+    pub fn new(
+        id: SqlId,
+        user_id: SqlId,
+        habit_id: SqlId,
+        created_at: NaiveDateTime,
+    ) -> CheckinToken {
+        CheckinToken {
+            id,
+            user_id,
+            habit_id,
+            created_at,
+            revoked_at: None,
+        }
+    }
+
+    pub fn get_id(&self) -> SqlId {
+        self.id
+    }
+
+    pub fn get_user_id(&self) -> SqlId {
+        self.user_id
+    }
+
+    pub fn get_habit_id(&self) -> SqlId {
+        self.habit_id
+    }
+
+    pub fn get_created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+
+    pub fn get_revoked_at(&self) -> Option<&NaiveDateTime> {
+        self.revoked_at.as_ref()
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+
+    /// Revoke this token as of `at`. Revoking it again keeps the first time.
+    pub fn set_revoked_at(&mut self, at: NaiveDateTime) {
+        self.revoked_at.get_or_insert(at);
+    }
+
+    /// Retrieve a token in the database by id.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn retrieve(id: SqlId, connection: &Connection) -> Result<CheckinToken> {
+        let token = sqlx::query_as!(
+            CheckinToken,
+            r#"
+                SELECT id AS "id!", user_id, habit_id, created_at, revoked_at
+                FROM checkin_token
+                WHERE id = ( $1 )
+            "#,
+            id
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(token)
+    }
+
+    /// Issue a token for a habit.
+    #[instrument(level = "debug", skip(created_at, connection))]
+    pub async fn insert(
+        user_id: SqlId,
+        habit_id: SqlId,
+        created_at: &NaiveDateTime,
+        connection: &Connection,
+    ) -> Result<CheckinToken> {
+        sqlx::query!(
+            r#"
+                INSERT INTO checkin_token ( user_id, habit_id, created_at )
+                VALUES ( $1, $2, $3 )
+            "#,
+            user_id,
+            habit_id,
+            created_at
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        // Find it again rather than relying on backend specific ways of getting the inserted id.
+        let token = sqlx::query_as!(
+            CheckinToken,
+            r#"
+                SELECT id AS "id!", user_id, habit_id, created_at, revoked_at
+                FROM checkin_token
+                WHERE
+                user_id = ( $1 )
+                AND
+                habit_id = ( $2 )
+                AND
+                created_at = ( $3 )
+                ORDER BY id DESC
+                LIMIT 1
+            "#,
+            user_id,
+            habit_id,
+            created_at
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(token)
+    }
+
+    /// Revoke this token as of `at`. Revoking it again keeps the first time.
+    #[instrument(level = "debug", skip(self, at, connection), fields(id = self.id))]
+    pub async fn revoke(&mut self, at: &NaiveDateTime, connection: &Connection) -> Result<()> {
+        let updated = sqlx::query!(
+            r#"
+                UPDATE checkin_token
+                SET revoked_at = COALESCE(revoked_at, $1)
+                WHERE id = ( $2 )
+            "#,
+            at,
+            self.id
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        if updated.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        self.set_revoked_at(*at);
+
+        Ok(())
+    }
+
+    /// Get all tokens of a user, including revoked ones.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_tokens(user_id: SqlId, connection: &Connection) -> Result<Vec<CheckinToken>> {
+        let tokens = sqlx::query_as!(
+            CheckinToken,
+            r#"
+                SELECT id AS "id!", user_id, habit_id, created_at, revoked_at
+                FROM checkin_token
+                WHERE user_id = ( $1 )
+                ORDER BY id
+            "#,
+            user_id
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::habit::Habit;
+    use crate::user::User;
+
+    #[tokio::test]
+    async fn issue_and_revoke() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let created_at = NaiveDateTime::from_timestamp(0, 0);
+        let habit = Habit::insert(
+            user.get_id(),
+            None,
+            "Cold Shower",
+            &created_at,
+            None,
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+
+        let mut first =
+            CheckinToken::insert(user.get_id(), habit.get_id(), &created_at, &connection)
+                .await
+                .expect("Should successfully insert.");
+        // Several tokens may point at the same habit, e.g. a reprinted code.
+        let second = CheckinToken::insert(user.get_id(), habit.get_id(), &created_at, &connection)
+            .await
+            .expect("Should successfully insert.");
+        assert_ne!(first.get_id(), second.get_id());
+        assert!(!first.is_revoked());
+
+        let revoked_at = NaiveDateTime::from_timestamp(60, 0);
+        first
+            .revoke(&revoked_at, &connection)
+            .await
+            .expect("Can revoke.");
+        first
+            .revoke(&NaiveDateTime::from_timestamp(120, 0), &connection)
+            .await
+            .expect("Can revoke again.");
+        assert_eq!(first.get_revoked_at(), Some(&revoked_at));
+        assert_eq!(
+            CheckinToken::retrieve(first.get_id(), &connection)
+                .await
+                .expect("Token exists."),
+            first
+        );
+
+        let id = first.get_id();
+        assert_eq!(
+            CheckinToken::get_tokens(user.get_id(), &connection)
+                .await
+                .expect("Can list."),
+            vec![first, second]
+        );
+
+        user.delete(&connection).await.expect("Can delete.");
+        assert_eq!(
+            CheckinToken::retrieve(id, &connection)
+                .await
+                .expect_err("Tokens are deleted with the user."),
+            Error::NotFound
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use crate::connection::Connection;
+use crate::error::Result;
+use crate::SqlId;
+use chrono::NaiveDateTime;
+use mindless_core::period::Period;
+use serde::{Deserialize, Serialize};
+use sqlx::Done;
+use sqlx::FromRow;
+use std::cmp::PartialEq;
+use tracing::instrument;
+
+/// This is a struct representing a habit being done, a row of the `instance` table.
+///
+/// Not to be confused with `Instance` which is time spent on a task.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct Completion {
+    /// The completion id.
+    id: SqlId,
+
+    /// The habit which was done.
+    habit_id: SqlId,
+
+    /// Time the habit was done.
+    created_at: NaiveDateTime,
+
+    /// Optional notes.
+    notes: Option<String>,
+}
+
+impl Completion {
+    pub fn new(id: SqlId, habit_id: SqlId, created_at: NaiveDateTime) -> Completion {
+        Completion {
+            id,
+            habit_id,
+            created_at,
+            notes: None,
+        }
+    }
+
+    pub fn get_id(&self) -> SqlId {
+        self.id
+    }
+
+    pub fn get_habit_id(&self) -> SqlId {
+        self.habit_id
+    }
+
+    pub fn get_created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+
+    pub fn get_notes(&self) -> Option<&str> {
+        self.notes.as_deref()
+    }
+
+    /// Record that a habit was done at `created_at` unless it was already done within `period`.
+    ///
+    /// The habit's row is locked while checking and inserting so concurrent requests can't both
+    /// insert, on PostgreSQL as well as on SQLite.
+    ///
+    /// Returns whether a completion was inserted.
+    #[instrument(level = "debug", skip(created_at, period, connection))]
+    pub async fn insert_once(
+        habit_id: SqlId,
+        created_at: &NaiveDateTime,
+        period: &Period,
+        connection: &Connection,
+    ) -> Result<bool> {
+        // Rolled back on drop if anything fails.
+        let mut transaction = connection.get_pool().begin().await?;
+
+        // Updating the habit holds its row lock until we commit. A concurrent mark waits here and
+        // then sees our completion.
+        sqlx::query!(
+            r#"
+                UPDATE habit
+                SET id = id
+                WHERE id = ( $1 )
+            "#,
+            habit_id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        let inserted = sqlx::query!(
+            r#"
+                INSERT INTO instance ( habit_id, created_at, completed )
+                SELECT $1, $2, TRUE
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM instance
+                    WHERE
+                    habit_id = ( $1 )
+                    AND
+                    completed
+                    AND
+                    created_at >= ( $3 )
+                    AND
+                    created_at < ( $4 )
+                )
+            "#,
+            habit_id,
+            created_at,
+            period.start,
+            period.end
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(inserted.rows_affected() > 0)
+    }
+
+    /// Delete the latest completion of a habit within `period`, the one `insert_once` recorded.
+    ///
+    /// Instances of the habit which were not completed are kept.
+    ///
+    /// Returns whether a completion was deleted.
+    #[instrument(level = "debug", skip(period, connection))]
+    pub async fn delete_latest_within(
+        habit_id: SqlId,
+        period: &Period,
+        connection: &Connection,
+    ) -> Result<bool> {
+        // Rolled back on drop if anything fails.
+        let mut transaction = connection.get_pool().begin().await?;
+
+        let latest = sqlx::query!(
+            r#"
+                SELECT id AS "id!" FROM instance
+                WHERE
+                habit_id = ( $1 )
+                AND
+                completed
+                AND
+                created_at >= ( $2 )
+                AND
+                created_at < ( $3 )
+                ORDER BY created_at DESC, id DESC
+                LIMIT 1
+            "#,
+            habit_id,
+            period.start,
+            period.end
+        )
+        .fetch_optional(&mut transaction)
+        .await?;
+
+        let id = match latest {
+            Some(latest) => latest.id,
+            None => return Ok(false),
+        };
+
+        // Completions may have time recorded against them.
+        sqlx::query!(
+            r#"
+                DELETE FROM elapsed_period
+                WHERE instance_id = ( $1 )
+            "#,
+            id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        let deleted = sqlx::query!(
+            r#"
+                DELETE FROM instance
+                WHERE id = ( $1 )
+            "#,
+            id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    /// Get the completions of a habit within `period`, oldest first.
+    #[instrument(level = "debug", skip(period, connection))]
+    pub async fn get_completions(
+        habit_id: SqlId,
+        period: &Period,
+        connection: &Connection,
+    ) -> Result<Vec<Completion>> {
+        let completions = sqlx::query_as!(
+            Completion,
+            r#"
+                SELECT id AS "id!", habit_id, created_at AS "created_at!", notes
+                FROM instance
+                WHERE
+                habit_id = ( $1 )
+                AND
+                completed
+                AND
+                created_at >= ( $2 )
+                AND
+                created_at < ( $3 )
+                ORDER BY created_at, id
+            "#,
+            habit_id,
+            period.start,
+            period.end
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(completions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::habit::Habit;
+    use crate::user::User;
+    use chrono::Duration;
+
+    fn time(hour: u32) -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd(2020, 9, 1).and_hms(hour, 0, 0)
+    }
+
+    #[tokio::test]
+    async fn insert_once_per_period() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let habit = Habit::insert(user.get_id(), None, "Read", &time(0), None, &connection)
+            .await
+            .expect("Should successfully insert.");
+        let morning = Period::new(time(0), time(12));
+        let afternoon = Period::new(time(12), time(0) + Duration::days(1));
+
+        assert!(
+            Completion::insert_once(habit.get_id(), &time(8), &morning, &connection)
+                .await
+                .expect("Can insert.")
+        );
+        assert!(
+            !Completion::insert_once(habit.get_id(), &time(9), &morning, &connection)
+                .await
+                .expect("Can insert."),
+            "Already done this morning."
+        );
+        assert!(
+            Completion::insert_once(habit.get_id(), &time(13), &afternoon, &connection)
+                .await
+                .expect("Can insert.")
+        );
+
+        let completions = Completion::get_completions(habit.get_id(), &morning, &connection)
+            .await
+            .expect("Can list.");
+        assert_eq!(completions.len(), 1);
+        assert_eq!(completions[0].get_created_at(), &time(8));
+
+        // An instance of the habit which was never completed.
+        sqlx::query(
+            "INSERT INTO instance ( habit_id, created_at, completed ) VALUES ( $1, $2, FALSE )",
+        )
+        .bind(habit.get_id())
+        .bind(time(10))
+        .execute(connection.get_pool())
+        .await
+        .expect("Can insert.");
+
+        assert!(
+            Completion::delete_latest_within(habit.get_id(), &morning, &connection)
+                .await
+                .expect("Can delete.")
+        );
+        assert!(
+            !Completion::delete_latest_within(habit.get_id(), &morning, &connection)
+                .await
+                .expect("Can delete.")
+        );
+        let remaining =
+            sqlx::query("SELECT id FROM instance WHERE habit_id = $1 AND NOT completed")
+                .bind(habit.get_id())
+                .fetch_all(connection.get_pool())
+                .await
+                .expect("Can list.");
+        assert_eq!(remaining.len(), 1, "Uncompleted instances are kept.");
+        assert_eq!(
+            Completion::get_completions(habit.get_id(), &afternoon, &connection)
+                .await
+                .expect("Can list.")
+                .len(),
+            1
+        );
+    }
+}
@@ -1,5 +1,11 @@
+use crate::backend::{Db, Pool};
 use crate::error::Result;
-use sqlx::SqlitePool;
+use serde::Deserialize;
+use sqlx::pool::PoolOptions;
+use sqlx::Executor;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// A simple database abstraction which contains the db info.
 ///
@@ -7,45 +13,415 @@ use sqlx::SqlitePool;
 /// of connection it is from the user.
 ///
 /// We use this as a dependency injection as opposed to using a global static.
+///
+/// Cloning is cheap, every clone shares the same pool.
+#[derive(Clone)]
 pub struct Connection {
-    pool: SqlitePool,
+    pool: Pool,
+
+    /// The file backing the database. None for in memory and PostgreSQL databases.
+    path: Option<PathBuf>,
 }
 
 impl Connection {
-    /// Connect to a Sqlite3 database.
+    /// Connect to a database with the production defaults.
+    ///
+    /// See `ConnectionBuilder` for the defaults.
     ///
     /// # Arguments
     ///
-    /// * `sqlite3_db_uri` - A sqlite3 datbase uri.
+    /// * `database_uri` - A sqlite3 or postgres database uri, depending on the enabled backend.
     ///
     /// # Examples
     /// ```
+    /// # #[cfg(feature = "sqlite")]
     /// #[tokio::main]
     /// # async fn main() {
     ///     let database = database::connection::Connection::connect("sqlite::memory:").await;
     /// #   assert!(database.is_ok(), "Should be able to connect to the in memory database.");
     /// # }
+    /// # #[cfg(feature = "postgres")]
+    /// # fn main() {}
     /// ```
-    pub async fn connect(sqlite3_db_uri: &str) -> Result<Self> {
-        Ok(Connection {
-            pool: SqlitePool::connect(sqlite3_db_uri).await?,
-        })
+    pub async fn connect(database_uri: &str) -> Result<Self> {
+        Connection::builder(database_uri).connect().await
+    }
+
+    /// Configure the pool and pragmas before connecting to a database.
+    ///
+    /// # Examples
+    /// ```
+    /// use database::connection::{Connection, JournalMode};
+    ///
+    /// # #[cfg(feature = "sqlite")]
+    /// #[tokio::main]
+    /// # async fn main() {
+    ///     let database = Connection::builder("sqlite::memory:")
+    ///         .max_connections(1)
+    ///         .journal_mode(JournalMode::Memory)
+    ///         .connect()
+    ///         .await;
+    /// #   assert!(database.is_ok(), "Should be able to connect to the in memory database.");
+    /// # }
+    /// # #[cfg(feature = "postgres")]
+    /// # fn main() {}
+    /// ```
+    pub fn builder(database_uri: &str) -> ConnectionBuilder {
+        ConnectionBuilder::new(database_uri)
     }
 
     /// Acquire a single connection to the database.
-    pub fn get_pool(&self) -> &SqlitePool {
+    pub fn get_pool(&self) -> &Pool {
         &self.pool
     }
 
+    /// The file backing the database. None for in memory and PostgreSQL databases.
+    pub fn get_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Check the database can execute a query.
+    pub async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(self.get_pool()).await?;
+
+        Ok(())
+    }
+
+    /// Reclaim the space of deleted rows.
+    pub async fn vacuum(&self) -> Result<()> {
+        sqlx::query("VACUUM").execute(self.get_pool()).await?;
+
+        Ok(())
+    }
+
     /// Connect to an in memory database also loading the schema.
-    #[cfg(test)]
+    #[cfg(all(any(test, feature = "test-util"), feature = "sqlite"))]
     pub async fn connect_temporary_with_schema() -> Result<Self> {
         let connection = Connection::connect("sqlite://").await?;
 
-        sqlx::query_file!("data/mindless.sql")
-            .execute(connection.get_pool())
+        crate::migration::run(&connection).await?;
+
+        Ok(connection)
+    }
+
+    /// Connect to a fresh schema in the database at `TEST_DATABASE_URL` also loading the schema.
+    ///
+    /// Every call gets its own schema so tests don't see each other's data.
+    #[cfg(all(any(test, feature = "test-util"), feature = "postgres"))]
+    pub async fn connect_temporary_with_schema() -> Result<Self> {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static NEXT_SCHEMA: AtomicU64 = AtomicU64::new(0);
+
+        let uri = std::env::var("TEST_DATABASE_URL")
+            .expect("`TEST_DATABASE_URL` must be set to run the tests against PostgreSQL.");
+        let schema = format!(
+            "test_{}_{}",
+            std::process::id(),
+            NEXT_SCHEMA.fetch_add(1, Ordering::Relaxed)
+        );
+
+        let setup = Connection::builder(&uri)
+            .max_connections(1)
+            .connect()
+            .await?;
+        let create_schema = format!(
+            "DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0};",
+            schema
+        );
+        setup.get_pool().execute(create_schema.as_str()).await?;
+
+        let connection = Connection::builder(&uri)
+            .search_path(&schema)
+            .connect()
             .await?;
 
+        crate::migration::run(&connection).await?;
+
         Ok(connection)
     }
 }
+
+/// SQLite journal mode. See https://sqlite.org/pragma.html#pragma_journal_mode.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off,
+}
+
+impl fmt::Display for JournalMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mode = match self {
+            JournalMode::Delete => "DELETE",
+            JournalMode::Truncate => "TRUNCATE",
+            JournalMode::Persist => "PERSIST",
+            JournalMode::Memory => "MEMORY",
+            JournalMode::Wal => "WAL",
+            JournalMode::Off => "OFF",
+        };
+
+        write!(f, "{}", mode)
+    }
+}
+
+/// SQLite synchronous level. See https://sqlite.org/pragma.html#pragma_synchronous.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+impl fmt::Display for Synchronous {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        };
+
+        write!(f, "{}", level)
+    }
+}
+
+/// Builds a `Connection` with a tuned pool.
+///
+/// The defaults suit a server with a few concurrent writers:
+/// * WAL journal so readers don't block the writer.
+/// * NORMAL synchronous which is safe in WAL mode.
+/// * A 5 second busy timeout instead of failing with "database is locked".
+/// * Foreign keys are enforced.
+///
+/// The pragmas only apply to SQLite. PostgreSQL always enforces foreign keys and waits on locks.
+#[derive(Debug, Clone)]
+pub struct ConnectionBuilder {
+    uri: String,
+    max_connections: u32,
+    journal_mode: JournalMode,
+    synchronous: Synchronous,
+    busy_timeout: Duration,
+    foreign_keys: bool,
+
+    /// PostgreSQL schema to use instead of `public`.
+    #[cfg(feature = "postgres")]
+    search_path: Option<String>,
+}
+
+impl ConnectionBuilder {
+    fn new(database_uri: &str) -> ConnectionBuilder {
+        ConnectionBuilder {
+            uri: database_uri.to_string(),
+            max_connections: 5,
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            busy_timeout: Duration::from_secs(5),
+            foreign_keys: true,
+            #[cfg(feature = "postgres")]
+            search_path: None,
+        }
+    }
+
+    /// Maximum number of connections in the pool.
+    pub fn max_connections(mut self, max_connections: u32) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    pub fn journal_mode(mut self, journal_mode: JournalMode) -> Self {
+        self.journal_mode = journal_mode;
+        self
+    }
+
+    pub fn synchronous(mut self, synchronous: Synchronous) -> Self {
+        self.synchronous = synchronous;
+        self
+    }
+
+    /// How long to wait for a lock before failing with "database is locked".
+    pub fn busy_timeout(mut self, busy_timeout: Duration) -> Self {
+        self.busy_timeout = busy_timeout;
+        self
+    }
+
+    pub fn foreign_keys(mut self, foreign_keys: bool) -> Self {
+        self.foreign_keys = foreign_keys;
+        self
+    }
+
+    /// Use the tables in `schema` instead of `public`.
+    #[cfg(feature = "postgres")]
+    pub fn search_path(mut self, schema: &str) -> Self {
+        self.search_path = Some(schema.to_string());
+        self
+    }
+
+    /// The statements executed on every new connection.
+    #[cfg(feature = "sqlite")]
+    fn pragmas(&self) -> String {
+        format!(
+            "PRAGMA journal_mode = {}; PRAGMA synchronous = {}; PRAGMA busy_timeout = {}; PRAGMA foreign_keys = {};",
+            self.journal_mode,
+            self.synchronous,
+            self.busy_timeout.as_millis(),
+            if self.foreign_keys { "ON" } else { "OFF" }
+        )
+    }
+
+    /// The statements executed on every new connection.
+    #[cfg(feature = "postgres")]
+    fn pragmas(&self) -> String {
+        match &self.search_path {
+            Some(schema) => format!("SET search_path TO {};", schema),
+            None => String::new(),
+        }
+    }
+
+    pub async fn connect(self) -> Result<Connection> {
+        let pragmas = self.pragmas();
+
+        let pool = PoolOptions::<Db>::new()
+            .max_connections(self.max_connections)
+            .after_connect(move |connection| {
+                let pragmas = pragmas.clone();
+                Box::pin(async move {
+                    if !pragmas.is_empty() {
+                        connection.execute(pragmas.as_str()).await?;
+                    }
+                    Ok(())
+                })
+            })
+            .connect(&self.uri)
+            .await?;
+
+        Ok(Connection {
+            pool,
+            path: database_file(&self.uri),
+        })
+    }
+}
+
+/// Extract the file path from a sqlite3 database uri.
+///
+/// E.g. `sqlite:data/habits.db?mode=rwc` is backed by `data/habits.db`.
+pub fn database_file(database_uri: &str) -> Option<PathBuf> {
+    if !database_uri.starts_with("sqlite:") {
+        return None;
+    }
+
+    let path = database_uri
+        .trim_start_matches("sqlite:")
+        .trim_start_matches("//")
+        .split('?')
+        .next()
+        .unwrap_or_default();
+
+    if path.is_empty() || path == ":memory:" {
+        None
+    } else {
+        Some(PathBuf::from(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn database_file_from_uri() {
+        assert_eq!(
+            database_file("sqlite:data/habits.db"),
+            Some(PathBuf::from("data/habits.db"))
+        );
+        assert_eq!(
+            database_file("sqlite:///tmp/habits.db?mode=rwc"),
+            Some(PathBuf::from("/tmp/habits.db"))
+        );
+        assert_eq!(database_file("sqlite::memory:"), None);
+        assert_eq!(database_file("sqlite://"), None);
+    }
+
+    #[cfg(feature = "sqlite")]
+    async fn pragma<T>(pragma: &str, connection: &Connection) -> T
+    where
+        T: for<'r> sqlx::Decode<'r, sqlx::Sqlite> + sqlx::Type<sqlx::Sqlite>,
+    {
+        use sqlx::Row;
+
+        sqlx::query(&format!("PRAGMA {}", pragma))
+            .fetch_one(connection.get_pool())
+            .await
+            .expect("Should read pragma.")
+            .try_get(0)
+            .expect("Pragma has a value.")
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn default_pragmas() {
+        assert_eq!(
+            Connection::builder("sqlite://").pragmas(),
+            "PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL; PRAGMA busy_timeout = 5000; PRAGMA foreign_keys = ON;"
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn production_defaults() {
+        let path =
+            std::env::temp_dir().join(format!("mindless-connection-{}.db", std::process::id()));
+        let uri = format!("sqlite://{}?mode=rwc", path.display());
+
+        let connection = Connection::connect(&uri).await.expect("Should connect");
+
+        assert_eq!(connection.get_path(), Some(path.as_path()));
+        assert_eq!(pragma::<String>("journal_mode", &connection).await, "wal");
+        // NORMAL
+        assert_eq!(pragma::<i32>("synchronous", &connection).await, 1);
+        assert_eq!(pragma::<i32>("busy_timeout", &connection).await, 5000);
+        assert_eq!(pragma::<i32>("foreign_keys", &connection).await, 1);
+
+        drop(connection);
+        for suffix in &["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn custom_pragmas() {
+        let connection = Connection::builder("sqlite://")
+            .max_connections(1)
+            .synchronous(Synchronous::Full)
+            .busy_timeout(Duration::from_millis(100))
+            .foreign_keys(false)
+            .connect()
+            .await
+            .expect("Should connect");
+
+        // FULL
+        assert_eq!(pragma::<i32>("synchronous", &connection).await, 2);
+        assert_eq!(pragma::<i32>("busy_timeout", &connection).await, 100);
+        assert_eq!(pragma::<i32>("foreign_keys", &connection).await, 0);
+    }
+
+    #[tokio::test]
+    async fn foreign_keys_are_enforced() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+
+        let result = crate::task::Task::insert(1234, "No such user", &connection).await;
+
+        assert!(matches!(
+            result.expect_err("User does not exist."),
+            crate::error::Error::UnknownSql(_)
+        ));
+    }
+}
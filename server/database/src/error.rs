@@ -1,11 +1,9 @@
+use crate::backend::UNIQUE_VIOLATION_CODE;
 use std::error;
 use std::fmt;
 // Change the alias to use our custom database error.
 pub type Result<T> = std::result::Result<T, Error>;
 
-/// The error code for constraint violated.
-const SQLITE_CONSTRAINT_UNIQUE_CODE: &str = "2067";
-
 #[derive(Debug)]
 pub enum Error {
     // Field already exists in SQL database.
@@ -16,6 +14,29 @@ pub enum Error {
 
     // Some sql error occurred.
     UnknownSql(sqlx::Error),
+
+    // A backup can't be restored.
+    InvalidBackup(String),
+
+    // Reading or writing a database file failed.
+    Io(std::io::Error),
+
+    // A stored habit whose periods can't be worked out, e.g. one repeating over millennia.
+    InvalidHabit(mindless_core::error::Error),
+}
+
+impl Error {
+    /// A short name for the kind of error. Useful for grouping errors, e.g. in metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::AlreadyExists => "AlreadyExists",
+            Error::NotFound => "NotFound",
+            Error::UnknownSql(_) => "UnknownSql",
+            Error::InvalidBackup(_) => "InvalidBackup",
+            Error::Io(_) => "Io",
+            Error::InvalidHabit(_) => "InvalidHabit",
+        }
+    }
 }
 
 impl PartialEq for Error {
@@ -24,7 +45,10 @@ impl PartialEq for Error {
         if let (AlreadyExists, AlreadyExists) |
                 (NotFound, NotFound) |
             // For the sake of simplicity, we treat all UnknownSql errors the same.
-            (UnknownSql(_), UnknownSql(_))
+            (UnknownSql(_), UnknownSql(_)) |
+            (InvalidBackup(_), InvalidBackup(_)) |
+            (Io(_), Io(_)) |
+            (InvalidHabit(_), InvalidHabit(_))
                 = (&self, &other) {
                     return true;
         }
@@ -41,6 +65,9 @@ impl fmt::Display for Error {
             Error::NotFound => write!(f, "NotFound"),
             Error::AlreadyExists => write!(f, "AlreadyExists"),
             Error::UnknownSql(ref e) => write!(f, "Unknown SQL error: \"{}\"", e),
+            Error::InvalidBackup(ref reason) => write!(f, "Invalid backup: {}", reason),
+            Error::Io(ref e) => write!(f, "IO error: \"{}\"", e),
+            Error::InvalidHabit(ref e) => write!(f, "Invalid habit: {}", e),
         }
     }
 }
@@ -52,18 +79,26 @@ impl error::Error for Error {
             // cast to the trait object `&error::Error`. This works because the
             // underlying type already implements the `Error` trait.
             Error::UnknownSql(ref e) => Some(e),
+            Error::Io(ref e) => Some(e),
+            Error::InvalidHabit(ref e) => Some(e),
             _ => None,
         }
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
 /// Implement forwarding conversion from sqlx error.
 impl From<sqlx::Error> for Error {
     fn from(err: sqlx::Error) -> Error {
         match &err {
             sqlx::error::Error::Database(e) => {
                 if let Some(code) = e.code() {
-                    if SQLITE_CONSTRAINT_UNIQUE_CODE == code {
+                    if UNIQUE_VIOLATION_CODE == code {
                         return Error::AlreadyExists;
                     }
                 }
@@ -0,0 +1,325 @@
+//! Deterministic test and demo data.
+//!
+//! A `FixtureBuilder` generates users with a tree of habits, their tasks and a history of
+//! instances. The same seed always generates the same data so tests, demos and bug reports can
+//! share it.
+//!
+//! ```no_run
+//! # async fn seed(connection: &database::connection::Connection) -> database::error::Result<()> {
+//! use database::fixture::FixtureBuilder;
+//!
+//! let fixture = FixtureBuilder::new(42).users(2).days(14).insert(connection).await?;
+//! assert_eq!(fixture.users.len(), 2);
+//! # Ok(())
+//! # }
+//! ```
+use crate::connection::Connection;
+use crate::error::Result;
+use crate::habit::Habit;
+use crate::instance::Instance;
+use crate::task::Task;
+use crate::user::User;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Weekday};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// Names of the generated users. Usernames are made unique with the index and seed.
+const NAMES: &[&str] = &["Justin", "Alex", "Sam", "Jordan", "Taylor", "Morgan"];
+
+/// Top level habits with the habits grouped under them. Every child is also a task.
+const HABITS: &[(&str, &[&str])] = &[
+    ("Health", &["Exercise", "Cold Shower", "Meditate"]),
+    ("Learning", &["Read", "Deep Work", "Journal"]),
+    ("Routine", &["Breakfast"]),
+];
+
+/// How a task typically fits into a day.
+struct Routine {
+    task: &'static str,
+
+    /// Chance of doing the task on a weekday for the most diligent user.
+    probability: f64,
+
+    /// The hour the task usually starts at.
+    hour: u32,
+
+    /// How long the task usually takes.
+    minutes: i64,
+}
+
+const ROUTINES: &[Routine] = &[
+    Routine {
+        task: "Exercise",
+        probability: 0.6,
+        hour: 7,
+        minutes: 45,
+    },
+    Routine {
+        task: "Cold Shower",
+        probability: 0.5,
+        hour: 8,
+        minutes: 5,
+    },
+    Routine {
+        task: "Meditate",
+        probability: 0.7,
+        hour: 6,
+        minutes: 15,
+    },
+    Routine {
+        task: "Read",
+        probability: 0.6,
+        hour: 21,
+        minutes: 30,
+    },
+    Routine {
+        task: "Deep Work",
+        probability: 0.8,
+        hour: 10,
+        minutes: 120,
+    },
+    Routine {
+        task: "Journal",
+        probability: 0.5,
+        hour: 22,
+        minutes: 10,
+    },
+    Routine {
+        task: "Breakfast",
+        probability: 0.9,
+        hour: 9,
+        minutes: 20,
+    },
+];
+
+/// Seconds in a day, the repeat period of every generated habit.
+const DAY_SEC: i64 = 24 * 60 * 60;
+
+/// Everything generated for a single user.
+#[derive(Debug)]
+pub struct UserFixture {
+    pub user: User,
+
+    /// Parents before their children.
+    pub habits: Vec<Habit>,
+
+    /// Every task with its instances, oldest first.
+    pub tasks: Vec<(Task, Vec<Instance>)>,
+}
+
+/// Everything generated by a `FixtureBuilder`.
+#[derive(Debug)]
+pub struct Fixture {
+    pub users: Vec<UserFixture>,
+}
+
+/// Generates a `Fixture` from a seed.
+#[derive(Debug, Clone)]
+pub struct FixtureBuilder {
+    seed: u64,
+    users: usize,
+    days: i64,
+    until: NaiveDate,
+}
+
+impl FixtureBuilder {
+    /// Three users with 30 days of history up to 2020-09-01.
+    ///
+    /// The end date is fixed so the data does not depend on when it is generated.
+    pub fn new(seed: u64) -> FixtureBuilder {
+        FixtureBuilder {
+            seed,
+            users: 3,
+            days: 30,
+            until: NaiveDate::from_ymd(2020, 9, 1),
+        }
+    }
+
+    /// Number of users to generate.
+    pub fn users(mut self, users: usize) -> FixtureBuilder {
+        self.users = users;
+        self
+    }
+
+    /// Number of days of history to generate.
+    pub fn days(mut self, days: i64) -> FixtureBuilder {
+        self.days = days;
+        self
+    }
+
+    /// The last day of history, exclusive.
+    pub fn until(mut self, until: NaiveDate) -> FixtureBuilder {
+        self.until = until;
+        self
+    }
+
+    /// Generate the data and insert it into the database.
+    pub async fn insert(self, connection: &Connection) -> Result<Fixture> {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        let first_day = self.until - Duration::days(self.days);
+        let created_at = first_day.and_hms(0, 0, 0);
+
+        let mut users = Vec::with_capacity(self.users);
+        for index in 0..self.users {
+            let name = NAMES[index % NAMES.len()];
+            let username = format!("{}{}-{}", name.to_lowercase(), index, self.seed);
+            let user = User::insert(&username, name, connection).await?;
+
+            let mut habits = Vec::new();
+            for (parent, children) in HABITS {
+                let parent =
+                    Habit::insert(user.get_id(), None, parent, &created_at, None, connection)
+                        .await?;
+                for child in children.iter() {
+                    habits.push(
+                        Habit::insert(
+                            user.get_id(),
+                            Some(parent.get_id()),
+                            child,
+                            &created_at,
+                            Some(DAY_SEC),
+                            connection,
+                        )
+                        .await?,
+                    );
+                }
+                habits.insert(habits.len() - children.len(), parent);
+            }
+
+            // Some users stick to their routines better than others.
+            let diligence = rng.gen_range(0.5, 1.0);
+
+            let mut tasks = Vec::with_capacity(ROUTINES.len());
+            for routine in ROUTINES {
+                let task = Task::insert(user.get_id(), routine.task, connection).await?;
+
+                let mut instances = Vec::new();
+                for day in 0..self.days {
+                    let date = first_day + Duration::days(day);
+                    let (start, end) = match generate_period(&mut rng, routine, diligence, date) {
+                        Some(period) => period,
+                        None => continue,
+                    };
+
+                    instances
+                        .push(Instance::insert(task.get_id(), &start, &end, connection).await?);
+                }
+
+                tasks.push((task, instances));
+            }
+
+            users.push(UserFixture {
+                user,
+                habits,
+                tasks,
+            });
+        }
+
+        Ok(Fixture { users })
+    }
+}
+
+/// Generate the period a task was done on a day, if it was done at all.
+fn generate_period<R: Rng>(
+    rng: &mut R,
+    routine: &Routine,
+    diligence: f64,
+    date: NaiveDate,
+) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    let weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+    let probability = if weekend {
+        routine.probability * diligence / 2.0
+    } else {
+        routine.probability * diligence
+    };
+
+    if !rng.gen_bool(probability) {
+        return None;
+    }
+
+    // Start within 45 minutes of the usual time and take between half and one and a half times
+    // as long as usual.
+    let start = date.and_hms(routine.hour, 0, 0) + Duration::minutes(rng.gen_range(-45, 45));
+    let minutes = rng.gen_range(routine.minutes / 2, routine.minutes * 3 / 2 + 1);
+
+    Some((start, start + Duration::minutes(minutes.max(1))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every generated instance as (task, start, end).
+    fn periods(fixture: &Fixture) -> Vec<(String, NaiveDateTime, NaiveDateTime)> {
+        fixture
+            .users
+            .iter()
+            .flat_map(|user| user.tasks.iter())
+            .flat_map(|(task, instances)| {
+                instances.iter().map(move |instance| {
+                    (
+                        task.get_name().to_string(),
+                        *instance.get_start(),
+                        *instance.get_end(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    async fn generate(builder: FixtureBuilder) -> Fixture {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+
+        builder.insert(&connection).await.expect("Can insert.")
+    }
+
+    #[tokio::test]
+    async fn same_seed_same_data() {
+        let first = generate(FixtureBuilder::new(7)).await;
+        let second = generate(FixtureBuilder::new(7)).await;
+        let other = generate(FixtureBuilder::new(8)).await;
+
+        assert!(!periods(&first).is_empty());
+        assert_eq!(periods(&first), periods(&second));
+        assert_ne!(periods(&first), periods(&other));
+    }
+
+    #[tokio::test]
+    async fn habits_form_a_tree() {
+        let fixture = generate(FixtureBuilder::new(0).users(1)).await;
+        let habits = &fixture.users[0].habits;
+
+        let roots: Vec<_> = habits
+            .iter()
+            .filter(|habit| habit.get_parent_id().is_none())
+            .collect();
+        assert_eq!(roots.len(), HABITS.len());
+
+        for (index, habit) in habits.iter().enumerate() {
+            if let Some(parent_id) = habit.get_parent_id() {
+                let parent = habits[..index]
+                    .iter()
+                    .position(|parent| parent.get_id() == parent_id);
+                assert!(parent.is_some(), "Parents come before their children.");
+                assert_eq!(habit.get_repeat_period_sec(), Some(DAY_SEC));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn instances_are_within_range() {
+        let until = NaiveDate::from_ymd(2020, 9, 1);
+        let fixture = generate(FixtureBuilder::new(1).users(2).days(7).until(until)).await;
+
+        assert_eq!(fixture.users.len(), 2);
+        for (_, start, end) in periods(&fixture) {
+            assert!(start < end);
+            // The earliest routine starts at 6am, less 45 minutes, on the first day.
+            assert!(start >= (until - Duration::days(7)).and_hms(5, 0, 0));
+            // `until` is exclusive, the last day of history is the day before.
+            assert!(end <= until.and_hms(0, 0, 0));
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::SqlId;
+use chrono::NaiveDateTime;
+use mindless_core::goal::Target;
+use serde::Serialize;
+use sqlx::Done;
+use sqlx::FromRow;
+use std::cmp::PartialEq;
+use tracing::instrument;
+
+/// This is a struct representing a goal for a task or a habit, e.g. at least 5h per week.
+#[derive(Debug, Clone, FromRow, Serialize, PartialEq)]
+pub struct Goal {
+    /// The goal id.
+    id: SqlId,
+
+    /// The user the goal belongs to.
+    user_id: SqlId,
+
+    /// The task whose time or instances count, if the goal is for a task.
+    task_id: Option<SqlId>,
+
+    /// The habit whose completions count, if the goal is for a habit.
+    habit_id: Option<SqlId>,
+
+    /// `at_least` or `at_most`.
+    comparison: String,
+
+    /// The amount to compare to, in `unit`.
+    amount: i64,
+
+    /// `seconds` tracked or `times` done.
+    unit: String,
+
+    /// The periods the goal starts over in, `day`, `week` or `month`.
+    cadence: String,
+
+    /// Time this goal was created.
+    created_at: NaiveDateTime,
+}
+
+impl Goal {
+    pub fn new(
+        id: SqlId,
+        user_id: SqlId,
+        task_id: Option<SqlId>,
+        habit_id: Option<SqlId>,
+        target: &Target,
+        created_at: NaiveDateTime,
+    ) -> Goal {
+        Goal {
+            id,
+            user_id,
+            task_id,
+            habit_id,
+            comparison: target.comparison.to_string(),
+            amount: target.amount,
+            unit: target.unit.to_string(),
+            cadence: target.cadence.to_string(),
+            created_at,
+        }
+    }
+
+    pub fn get_id(&self) -> SqlId {
+        self.id
+    }
+
+    pub fn get_user_id(&self) -> SqlId {
+        self.user_id
+    }
+
+    pub fn get_task_id(&self) -> Option<SqlId> {
+        self.task_id
+    }
+
+    pub fn get_habit_id(&self) -> Option<SqlId> {
+        self.habit_id
+    }
+
+    /// What the goal aims for. Fails if the stored comparison, unit or cadence is unknown.
+    pub fn get_target(&self) -> mindless_core::error::Result<Target> {
+        Ok(Target {
+            comparison: self.comparison.parse()?,
+            amount: self.amount,
+            unit: self.unit.parse()?,
+            cadence: self.cadence.parse()?,
+        })
+    }
+
+    pub fn get_created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+
+    /// Retrieve a goal in the database by id.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn retrieve(id: SqlId, connection: &Connection) -> Result<Goal> {
+        let goal = sqlx::query_as!(
+            Goal,
+            r#"
+                SELECT
+                id AS "id!", user_id, task_id, habit_id, comparison, amount, unit, cadence,
+                created_at
+                FROM goal
+                WHERE id = ( $1 )
+            "#,
+            id
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(goal)
+    }
+
+    /// Set a goal for a task or a habit of a user.
+    #[instrument(level = "debug", skip(created_at, connection))]
+    pub async fn insert(
+        user_id: SqlId,
+        task_id: Option<SqlId>,
+        habit_id: Option<SqlId>,
+        target: &Target,
+        created_at: &NaiveDateTime,
+        connection: &Connection,
+    ) -> Result<Goal> {
+        let (comparison, unit, cadence) = (
+            target.comparison.as_str(),
+            target.unit.as_str(),
+            target.cadence.as_str(),
+        );
+        sqlx::query!(
+            r#"
+                INSERT INTO goal
+                ( user_id, task_id, habit_id, comparison, amount, unit, cadence, created_at )
+                VALUES ( $1, $2, $3, $4, $5, $6, $7, $8 )
+            "#,
+            user_id,
+            task_id,
+            habit_id,
+            comparison,
+            target.amount,
+            unit,
+            cadence,
+            created_at
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        // Find it again rather than relying on backend specific ways of getting the inserted id.
+        let goal = sqlx::query_as!(
+            Goal,
+            r#"
+                SELECT
+                id AS "id!", user_id, task_id, habit_id, comparison, amount, unit, cadence,
+                created_at
+                FROM goal
+                WHERE user_id = ( $1 )
+                ORDER BY id DESC
+                LIMIT 1
+            "#,
+            user_id
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(goal)
+    }
+
+    /// Get the goals of a user, oldest first.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_goals(user_id: SqlId, connection: &Connection) -> Result<Vec<Goal>> {
+        let goals = sqlx::query_as!(
+            Goal,
+            r#"
+                SELECT
+                id AS "id!", user_id, task_id, habit_id, comparison, amount, unit, cadence,
+                created_at
+                FROM goal
+                WHERE user_id = ( $1 )
+                ORDER BY id
+            "#,
+            user_id
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(goals)
+    }
+
+    /// Delete this goal.
+    ///
+    /// This consumes self since it is invalid after deletion from the database.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn delete(self, connection: &Connection) -> Result<()> {
+        let deleted_row_count = sqlx::query!(
+            r#"
+                DELETE FROM goal
+                WHERE
+                id = ( $1 )
+                AND
+                user_id = ( $2 )
+            "#,
+            self.id,
+            self.user_id
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        if deleted_row_count.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::Task;
+    use crate::user::User;
+    use mindless_core::goal::{Cadence, Comparison, Unit};
+
+    #[tokio::test]
+    async fn insert_and_delete() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let task = Task::insert(user.get_id(), "Deep Work", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let target = Target {
+            comparison: Comparison::AtLeast,
+            amount: 5 * 3600,
+            unit: Unit::Seconds,
+            cadence: Cadence::Week,
+        };
+        let created_at = NaiveDateTime::from_timestamp(0, 0);
+
+        let goal = Goal::insert(
+            user.get_id(),
+            Some(task.get_id()),
+            None,
+            &target,
+            &created_at,
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+        assert_eq!(
+            goal,
+            Goal::new(
+                goal.get_id(),
+                user.get_id(),
+                Some(task.get_id()),
+                None,
+                &target,
+                created_at
+            )
+        );
+        assert_eq!(goal.get_target(), Ok(target));
+        assert_eq!(
+            Goal::retrieve(goal.get_id(), &connection)
+                .await
+                .expect("Goal exists."),
+            goal
+        );
+        assert_eq!(
+            Goal::get_goals(user.get_id(), &connection)
+                .await
+                .expect("Can list."),
+            vec![goal.clone()]
+        );
+
+        let id = goal.get_id();
+        goal.clone().delete(&connection).await.expect("Can delete.");
+        assert_eq!(
+            goal.delete(&connection)
+                .await
+                .expect_err("Already deleted."),
+            Error::NotFound
+        );
+        assert_eq!(
+            Goal::retrieve(id, &connection)
+                .await
+                .expect_err("Goal is deleted."),
+            Error::NotFound
+        );
+    }
+}
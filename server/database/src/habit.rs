@@ -0,0 +1,632 @@
+use crate::completion::Completion;
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::SqlId;
+use chrono::NaiveDateTime;
+use mindless_core::path::HabitPath;
+use mindless_core::period::{self, Period};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::cmp::PartialEq;
+use tracing::instrument;
+
+/// This is a struct representing a habit.
+///
+/// Habits form a tree per user, e.g. Exercise is a child of Health. Only the leaves are expected
+/// to repeat.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct Habit {
+    /// The habit id.
+    id: SqlId,
+
+    /// The habit this is grouped under. None for the top level habits.
+    parent_id: Option<SqlId>,
+
+    /// User Id.
+    user_id: SqlId,
+
+    /// Name.
+    name: String,
+
+    /// Time this habit was created.
+    created_at: NaiveDateTime,
+
+    /// How often this habit should be done in seconds. None if it does not repeat.
+    repeat_period_sec: Option<i64>,
+
+    /// Optional notes.
+    notes: Option<String>,
+}
+
+impl Habit {
+    pub fn new(
+        id: SqlId,
+        parent_id: Option<SqlId>,
+        user_id: SqlId,
+        name: String,
+        created_at: NaiveDateTime,
+        repeat_period_sec: Option<i64>,
+    ) -> Habit {
+        Habit {
+            id,
+            parent_id,
+            user_id,
+            name,
+            created_at,
+            repeat_period_sec,
+            notes: None,
+        }
+    }
+
+    pub fn get_id(&self) -> SqlId {
+        self.id
+    }
+
+    pub fn get_parent_id(&self) -> Option<SqlId> {
+        self.parent_id
+    }
+
+    pub fn get_user_id(&self) -> SqlId {
+        self.user_id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+
+    pub fn get_repeat_period_sec(&self) -> Option<i64> {
+        self.repeat_period_sec
+    }
+
+    pub fn get_notes(&self) -> Option<&str> {
+        self.notes.as_deref()
+    }
+
+    /// The period containing `now` in which this habit is either done or not.
+    pub fn get_period(&self, now: &NaiveDateTime) -> Result<Period> {
+        period::repeat_period(&self.created_at, self.repeat_period_sec, now)
+            .map_err(Error::InvalidHabit)
+    }
+
+    /// Retrieve a habit in the database by id.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn retrieve(id: SqlId, connection: &Connection) -> Result<Habit> {
+        let habit = sqlx::query_as!(
+            Habit,
+            r#"
+                SELECT id AS "id!", parent_id, user_id, name, created_at,
+                       repeat_period_sec AS "repeat_period_sec: i64", notes
+                FROM habit
+                WHERE id = ( $1 )
+            "#,
+            id
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(habit)
+    }
+
+    /// Find the latest habit of a user with this name and parent.
+    #[instrument(level = "debug", skip(name, connection))]
+    async fn find(
+        user_id: SqlId,
+        parent_id: Option<SqlId>,
+        name: &str,
+        connection: &Connection,
+    ) -> Result<Habit> {
+        let habit = sqlx::query_as!(
+            Habit,
+            r#"
+                SELECT id AS "id!", parent_id, user_id, name, created_at,
+                       repeat_period_sec AS "repeat_period_sec: i64", notes
+                FROM habit
+                WHERE
+                user_id = ( $1 )
+                AND
+                ( parent_id = ( $2 ) OR ( parent_id IS NULL AND $2 IS NULL ) )
+                AND
+                name = ( $3 )
+                ORDER BY id DESC
+                LIMIT 1
+            "#,
+            user_id,
+            parent_id,
+            name
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(habit)
+    }
+
+    /// Insert a habit into the database.
+    #[instrument(level = "debug", skip(name, connection))]
+    pub async fn insert(
+        user_id: SqlId,
+        parent_id: Option<SqlId>,
+        name: &str,
+        created_at: &NaiveDateTime,
+        repeat_period_sec: Option<i64>,
+        connection: &Connection,
+    ) -> Result<Habit> {
+        sqlx::query!(
+            r#"
+                INSERT INTO habit ( user_id, parent_id, name, created_at, repeat_period_sec )
+                VALUES ( $1, $2, $3, $4, $5 )
+            "#,
+            user_id,
+            parent_id,
+            name,
+            created_at,
+            repeat_period_sec
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        // Find it again rather than relying on backend specific ways of getting the inserted id.
+        Habit::find(user_id, parent_id, name, connection).await
+    }
+
+    /// Find a habit of a user by its path, e.g. `Health/Exercise`.
+    #[instrument(level = "debug", skip(connection), fields(path = %path))]
+    pub async fn get_by_path(
+        user_id: SqlId,
+        path: &HabitPath,
+        connection: &Connection,
+    ) -> Result<Habit> {
+        let mut parent_id = None;
+        let mut habit = Err(Error::NotFound);
+        for name in path.names() {
+            let found = Habit::find(user_id, parent_id, name, connection).await?;
+            parent_id = Some(found.id);
+            habit = Ok(found);
+        }
+
+        habit
+    }
+
+    /// Mark this habit as done at `now` unless it was already done in the same period.
+    ///
+    /// Returns whether the habit was marked.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn mark(&self, now: &NaiveDateTime, connection: &Connection) -> Result<bool> {
+        Completion::insert_once(self.id, now, &self.get_period(now)?, connection).await
+    }
+
+    /// Undo marking this habit as done in the period containing `now`.
+    ///
+    /// Returns whether the habit had been marked.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn unmark(&self, now: &NaiveDateTime, connection: &Connection) -> Result<bool> {
+        Completion::delete_latest_within(self.id, &self.get_period(now)?, connection).await
+    }
+
+    /// Get all habits of a user, parents before their children.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_habits(user_id: SqlId, connection: &Connection) -> Result<Vec<Habit>> {
+        let habits = sqlx::query_as!(
+            Habit,
+            r#"
+                SELECT id AS "id!", parent_id, user_id, name, created_at,
+                       repeat_period_sec AS "repeat_period_sec: i64", notes
+                FROM habit
+                WHERE user_id = ( $1 )
+                ORDER BY id
+            "#,
+            user_id
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(habits)
+    }
+
+    /// Delete this habit along with the habits grouped under it and everything they own.
+    ///
+    /// This consumes self since it is invalid after deletion from the database.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn delete(self, connection: &Connection) -> Result<()> {
+        let habits = Habit::get_habits(self.user_id, connection).await?;
+        let mut ids = subtree(&habits, self.id);
+        if ids.is_empty() {
+            return Err(Error::NotFound);
+        }
+        // Children go first so no habit is left pointing at a deleted parent.
+        ids.reverse();
+
+        // Rolled back on drop if anything fails.
+        let mut transaction = connection.get_pool().begin().await?;
+
+        for id in &ids {
+            sqlx::query!(
+                r#"
+                    DELETE FROM elapsed_period
+                    WHERE instance_id IN ( SELECT id FROM instance WHERE habit_id = ( $1 ) )
+                "#,
+                id
+            )
+            .execute(&mut transaction)
+            .await?;
+
+            sqlx::query!(
+                r#"
+                    DELETE FROM instance
+                    WHERE habit_id = ( $1 )
+                "#,
+                id
+            )
+            .execute(&mut transaction)
+            .await?;
+
+            sqlx::query!(
+                r#"
+                    DELETE FROM checkin_token
+                    WHERE habit_id = ( $1 )
+                "#,
+                id
+            )
+            .execute(&mut transaction)
+            .await?;
+
+            sqlx::query!(
+                r#"
+                    DELETE FROM goal
+                    WHERE habit_id = ( $1 )
+                "#,
+                id
+            )
+            .execute(&mut transaction)
+            .await?;
+
+            sqlx::query!(
+                r#"
+                    DELETE FROM pending_reminder
+                    WHERE reminder_id IN ( SELECT id FROM reminder WHERE habit_id = ( $1 ) )
+                "#,
+                id
+            )
+            .execute(&mut transaction)
+            .await?;
+
+            sqlx::query!(
+                r#"
+                    DELETE FROM reminder
+                    WHERE habit_id = ( $1 )
+                "#,
+                id
+            )
+            .execute(&mut transaction)
+            .await?;
+
+            let deleted_row_count = sqlx::query!(
+                r#"
+                    DELETE FROM habit
+                    WHERE
+                    id = ( $1 )
+                    AND
+                    user_id = ( $2 )
+                "#,
+                id,
+                self.user_id
+            )
+            .execute(&mut transaction)
+            .await?;
+
+            if deleted_row_count.rows_affected() == 0 {
+                return Err(Error::NotFound);
+            }
+        }
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+}
+
+/// The ids of the habit `id` and all habits grouped under it, parents before their children.
+///
+/// `habits` must be ordered parents first, like `Habit::get_habits` returns them. Empty if `id`
+/// is not one of `habits`.
+pub(crate) fn subtree(habits: &[Habit], id: SqlId) -> Vec<SqlId> {
+    let mut ids = Vec::new();
+    for habit in habits {
+        let under = habit
+            .parent_id
+            .map_or(false, |parent_id| ids.contains(&parent_id));
+        if habit.id == id || under {
+            ids.push(habit.id);
+        }
+    }
+
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::User;
+
+    const DAY_SEC: i64 = 24 * 60 * 60;
+
+    #[tokio::test]
+    async fn insert_hierarchy() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let created_at = NaiveDateTime::from_timestamp(0, 0);
+
+        let health = Habit::insert(
+            user.get_id(),
+            None,
+            "Health",
+            &created_at,
+            None,
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+        let exercise = Habit::insert(
+            user.get_id(),
+            Some(health.get_id()),
+            "Exercise",
+            &created_at,
+            Some(DAY_SEC),
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+
+        assert_eq!(health.get_parent_id(), None);
+        assert_eq!(exercise.get_parent_id(), Some(health.get_id()));
+        assert_eq!(exercise.get_repeat_period_sec(), Some(DAY_SEC));
+        assert_eq!(
+            Habit::retrieve(exercise.get_id(), &connection)
+                .await
+                .expect("Habit exists."),
+            exercise
+        );
+        assert_eq!(
+            Habit::get_habits(user.get_id(), &connection)
+                .await
+                .expect("Can list."),
+            vec![health, exercise]
+        );
+    }
+
+    #[tokio::test]
+    async fn find_by_path() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let created_at = NaiveDateTime::from_timestamp(0, 0);
+
+        let health = Habit::insert(
+            user.get_id(),
+            None,
+            "Health",
+            &created_at,
+            None,
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+        let exercise = Habit::insert(
+            user.get_id(),
+            Some(health.get_id()),
+            "Exercise",
+            &created_at,
+            None,
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+        // Same name at the top level.
+        Habit::insert(
+            user.get_id(),
+            None,
+            "Exercise",
+            &created_at,
+            None,
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+
+        let path = |path: &str| path.parse::<HabitPath>().expect("Is valid.");
+        assert_eq!(
+            Habit::get_by_path(user.get_id(), &path("Health/Exercise"), &connection)
+                .await
+                .expect("Habit exists."),
+            exercise
+        );
+        assert_eq!(
+            Habit::get_by_path(user.get_id(), &path("Health"), &connection)
+                .await
+                .expect("Habit exists."),
+            health
+        );
+        assert_eq!(
+            Habit::get_by_path(user.get_id(), &path("Health/Read"), &connection)
+                .await
+                .expect_err("Habit does not exist."),
+            Error::NotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn mark_once_per_period() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let created_at = NaiveDateTime::from_timestamp(0, 0);
+        let shower = Habit::insert(
+            user.get_id(),
+            None,
+            "Cold Shower",
+            &created_at,
+            Some(DAY_SEC),
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+
+        let morning = created_at + chrono::Duration::hours(8);
+        let evening = created_at + chrono::Duration::hours(20);
+        let tomorrow = created_at + chrono::Duration::hours(32);
+
+        assert!(shower.mark(&morning, &connection).await.expect("Can mark."));
+        assert!(!shower.mark(&evening, &connection).await.expect("Can mark."));
+        assert!(shower
+            .mark(&tomorrow, &connection)
+            .await
+            .expect("Can mark."));
+
+        assert!(shower
+            .unmark(&evening, &connection)
+            .await
+            .expect("Can unmark."));
+        assert!(!shower
+            .unmark(&evening, &connection)
+            .await
+            .expect("Can unmark."));
+
+        // Tomorrow is untouched.
+        assert_eq!(
+            Completion::get_completions(
+                shower.get_id(),
+                &shower.get_period(&tomorrow).expect("A day fits."),
+                &connection
+            )
+            .await
+            .expect("Can list.")
+            .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn fail_if_sibling_already_exists() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let created_at = NaiveDateTime::from_timestamp(0, 0);
+
+        let health = Habit::insert(
+            user.get_id(),
+            None,
+            "Health",
+            &created_at,
+            None,
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+        Habit::insert(
+            user.get_id(),
+            Some(health.get_id()),
+            "Exercise",
+            &created_at,
+            None,
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+
+        assert_eq!(
+            Habit::insert(
+                user.get_id(),
+                Some(health.get_id()),
+                "Exercise",
+                &created_at,
+                None,
+                &connection,
+            )
+            .await
+            .expect_err("Should have failed due to duplication."),
+            Error::AlreadyExists
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_subtree() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let created_at = NaiveDateTime::from_timestamp(0, 0);
+
+        let health = Habit::insert(
+            user.get_id(),
+            None,
+            "Health",
+            &created_at,
+            None,
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+        let exercise = Habit::insert(
+            user.get_id(),
+            Some(health.get_id()),
+            "Exercise",
+            &created_at,
+            Some(DAY_SEC),
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+        let reading = Habit::insert(
+            user.get_id(),
+            None,
+            "Reading",
+            &created_at,
+            Some(DAY_SEC),
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+        assert!(exercise
+            .mark(&created_at, &connection)
+            .await
+            .expect("Can mark."));
+
+        health
+            .clone()
+            .delete(&connection)
+            .await
+            .expect("Can delete.");
+
+        assert_eq!(
+            Habit::get_habits(user.get_id(), &connection)
+                .await
+                .expect("Can list."),
+            vec![reading]
+        );
+        assert_eq!(
+            health
+                .delete(&connection)
+                .await
+                .expect_err("Habit was deleted."),
+            Error::NotFound
+        );
+    }
+}
@@ -2,10 +2,12 @@ use crate::connection::Connection;
 use crate::error::{Error, Result};
 use crate::SqlId;
 use chrono::NaiveDateTime;
+use mindless_core::period::Period;
 use serde::{Deserialize, Serialize};
 use sqlx::Done;
 use sqlx::FromRow;
 use std::cmp::PartialEq;
+use tracing::instrument;
 
 /// This is a struct representing a task.
 ///
@@ -13,7 +15,7 @@ use std::cmp::PartialEq;
 ///
 /// We temporarily make this Serialize since we re-use this as the http rseponse. As the database
 /// structure and the http query starts to diverge we will create a separate struct.
-#[derive(Debug, FromRow, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
 pub struct Instance {
     /// Instance id.
     id: SqlId,
@@ -38,17 +40,38 @@ impl Instance {
         }
     }
 
+    pub fn get_id(&self) -> SqlId {
+        self.id
+    }
+
+    pub fn get_task_id(&self) -> SqlId {
+        self.task_id
+    }
+
+    pub fn get_start(&self) -> &NaiveDateTime {
+        &self.start
+    }
+
+    pub fn get_end(&self) -> &NaiveDateTime {
+        &self.end
+    }
+
+    pub fn get_period(&self) -> Period {
+        Period::new(self.start, self.end)
+    }
+
     pub fn set_task_id(&mut self, task_id: SqlId) {
         self.task_id = task_id
     }
 
     /// Retrieve a instance in the database by id.
+    #[instrument(level = "debug", skip(connection))]
     pub async fn retrieve(id: i64, connection: &Connection) -> Result<Instance> {
         let instance = sqlx::query_as!(
             Instance,
             r#"
-                SELECT id, task_id, start, end FROM instances
-                WHERE id = ( ? )
+                SELECT id, task_id, start, "end" FROM instances
+                WHERE id = ( $1 )
             "#,
             id
         )
@@ -59,17 +82,18 @@ impl Instance {
     }
 
     /// Find a instance in the database by id.
+    #[instrument(level = "debug", skip(self, connection), fields(task_id = self.task_id))]
     pub async fn find(&mut self, connection: &Connection) -> Result<()> {
         let instance = sqlx::query_as!(
             Instance,
             r#"
-                SELECT id, task_id, start, end FROM instances
+                SELECT id, task_id, start, "end" FROM instances
                 WHERE
-                task_id = ( ? )
+                task_id = ( $1 )
                 AND
-                start = ( ? )
+                start = ( $2 )
                 AND
-                end = ( ? )
+                "end" = ( $3 )
             "#,
             self.task_id,
             self.start,
@@ -84,13 +108,14 @@ impl Instance {
     }
 
     /// Insert a instance into the database
+    #[instrument(level = "debug", skip(self, connection), fields(task_id = self.task_id))]
     pub async fn try_insert(&mut self, connection: &Connection) -> Result<()> {
         // TODO: Match on error only.
         // Ignore whether or not insert succeeded
         let _result = sqlx::query!(
             r#"
-                INSERT INTO instances ( task_id, start, end )
-                VALUES ( ?, ?, ? )
+                INSERT INTO instances ( task_id, start, "end" )
+                VALUES ( $1, $2, $3 )
             "#,
             self.task_id,
             self.start,
@@ -103,16 +128,17 @@ impl Instance {
     }
 
     /// Insert a instance into the database
+    #[instrument(level = "debug", skip(start, end, connection))]
     pub async fn insert(
         task_id: SqlId,
         start: &NaiveDateTime,
         end: &NaiveDateTime,
         connection: &Connection,
     ) -> Result<Instance> {
-        let result = sqlx::query!(
+        sqlx::query!(
             r#"
-                INSERT INTO instances ( task_id, start, end )
-                VALUES ( ?, ?, ? )
+                INSERT INTO instances ( task_id, start, "end" )
+                VALUES ( $1, $2, $3 )
             "#,
             task_id,
             start,
@@ -121,24 +147,16 @@ impl Instance {
         .execute(connection.get_pool())
         .await?;
 
-        let last_rowid = result.last_insert_rowid();
-
-        let instance_result = sqlx::query_as!(
-            Instance,
-            r#"
-                    SELECT id, task_id, start, end
-                    FROM instances
-                    WHERE rowid= ( ? )
-                "#,
-            last_rowid
-        )
-        .fetch_one(connection.get_pool())
-        .await?;
+        // The instance is unique so find it again rather than relying on backend specific ways of
+        // getting the inserted id.
+        let mut instance = Instance::new(0, task_id, *start, *end);
+        instance.find(connection).await?;
 
-        Ok(instance_result)
+        Ok(instance)
     }
 
     /// Insert a vector of instances
+    #[instrument(level = "debug", skip(instances, connection), fields(count = instances.len()))]
     pub async fn try_insert_all(
         mut instances: Vec<Instance>,
         connection: &Connection,
@@ -153,42 +171,132 @@ impl Instance {
     /// Delete a instance from the database.
     ///
     /// This consumes self since it is invalid after deletion from the database.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
     pub async fn delete(self, connection: &Connection) -> Result<()> {
+        // Rolled back on drop if anything fails.
+        let mut transaction = connection.get_pool().begin().await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM instance_tag
+                WHERE instance_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
         //  We really only need the id but we use everything to be specific.
         let deleted_row_count = sqlx::query!(
             r#"
                    DELETE FROM instances
                    WHERE
-                   id = ( ? )
+                   id = ( $1 )
                    AND
-                   task_id = ( ? )
+                   task_id = ( $2 )
                    AND
-                   start = ( ? )
+                   start = ( $3 )
                    AND
-                   end = ( ? )
+                   "end" = ( $4 )
                "#,
             self.id,
             self.task_id,
             self.start,
             self.end
         )
-        .execute(connection.get_pool())
+        .execute(&mut transaction)
         .await?;
 
         if deleted_row_count.rows_affected() == 0 {
-            Err(Error::NotFound)
-        } else {
-            Ok(())
+            return Err(Error::NotFound);
         }
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    /// Move instances to another task, all of them or none.
+    ///
+    /// Fails with `AlreadyExists` if the task already has an instance with the same period as one
+    /// of them.
+    #[instrument(level = "debug", skip(instances, connection), fields(count = instances.len()))]
+    pub async fn reassign_all(
+        instances: &mut [Instance],
+        task_id: SqlId,
+        connection: &Connection,
+    ) -> Result<()> {
+        // Rolled back on drop if anything fails.
+        let mut transaction = connection.get_pool().begin().await?;
+
+        for instance in instances.iter() {
+            let updated = sqlx::query!(
+                r#"
+                    UPDATE instances
+                    SET task_id = ( $1 )
+                    WHERE id = ( $2 )
+                "#,
+                task_id,
+                instance.id
+            )
+            .execute(&mut transaction)
+            .await?;
+
+            if updated.rows_affected() == 0 {
+                return Err(Error::NotFound);
+            }
+        }
+
+        transaction.commit().await?;
+
+        for instance in instances.iter_mut() {
+            instance.task_id = task_id;
+        }
+
+        Ok(())
+    }
+
+    /// Change the period of this instance.
+    ///
+    /// Fails with `AlreadyExists` if the task already has an instance with the new period.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn update(
+        &mut self,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+        connection: &Connection,
+    ) -> Result<()> {
+        let updated = sqlx::query!(
+            r#"
+                UPDATE instances
+                SET start = ( $1 ), "end" = ( $2 )
+                WHERE id = ( $3 )
+            "#,
+            start,
+            end,
+            self.id
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        if updated.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        self.start = *start;
+        self.end = *end;
+
+        Ok(())
     }
 
     /// Get all tasks for a user.
+    #[instrument(level = "debug", skip(connection))]
     pub async fn get_instances(task_id: SqlId, connection: &Connection) -> Result<Vec<Instance>> {
         let instances = sqlx::query_as!(
             Instance,
             r#"
-                 SELECT id, task_id, start, end FROM instances
-                 WHERE task_id = ( ? )
+                 SELECT id, task_id, start, "end" FROM instances
+                 WHERE task_id = ( $1 )
              "#,
             task_id
         )
@@ -261,4 +369,256 @@ mod test {
         assert_eq!(instances[0], instance1);
         assert_eq!(instances[1], instance2);
     }
+
+    #[tokio::test]
+    async fn reassign_instance() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+
+        let task = create_test_task(USERNAME, NAME, TASK_NAME, &connection).await;
+        let other = Task::insert(task.get_user_id(), "Gym", &connection)
+            .await
+            .expect("Should successfully insert.");
+
+        let time = |timestamp| NaiveDateTime::from_timestamp(timestamp, 0);
+        let instance = Instance::insert(task.get_id(), &time(1), &time(2), &connection)
+            .await
+            .expect("Should successfully insert.");
+        let other_instance = Instance::insert(other.get_id(), &time(1), &time(2), &connection)
+            .await
+            .expect("Should successfully insert.");
+        let later = Instance::insert(other.get_id(), &time(3), &time(4), &connection)
+            .await
+            .expect("Should successfully insert.");
+
+        // `task` has an instance with the same period so nothing moves.
+        let mut instances = vec![later.clone(), other_instance];
+        assert_eq!(
+            Instance::reassign_all(&mut instances, task.get_id(), &connection)
+                .await
+                .expect_err("Period is taken."),
+            Error::AlreadyExists
+        );
+        assert_eq!(
+            Instance::retrieve(later.get_id(), &connection)
+                .await
+                .expect("Instance exists."),
+            later
+        );
+
+        let mut instances = vec![instance];
+        Instance::reassign_all(&mut instances, other.get_id(), &connection)
+            .await
+            .expect("Can reassign.");
+        assert_eq!(
+            Instance::retrieve(instances[0].get_id(), &connection)
+                .await
+                .expect("Instance exists."),
+            instances[0]
+        );
+        assert!(Instance::get_instances(task.get_id(), &connection)
+            .await
+            .expect("Can list.")
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn update_instance() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+
+        let task = create_test_task(USERNAME, NAME, TASK_NAME, &connection).await;
+
+        let mut instance = Instance::insert(
+            task.get_id(),
+            &NaiveDateTime::from_timestamp(1, 0),
+            &NaiveDateTime::from_timestamp(2, 0),
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+        Instance::insert(
+            task.get_id(),
+            &NaiveDateTime::from_timestamp(5, 0),
+            &NaiveDateTime::from_timestamp(6, 0),
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+
+        instance
+            .update(
+                &NaiveDateTime::from_timestamp(3, 0),
+                &NaiveDateTime::from_timestamp(4, 0),
+                &connection,
+            )
+            .await
+            .expect("Can update.");
+        assert_eq!(
+            Instance::retrieve(instance.get_id(), &connection)
+                .await
+                .expect("Instance exists."),
+            instance
+        );
+        assert_eq!(
+            instance
+                .update(
+                    &NaiveDateTime::from_timestamp(5, 0),
+                    &NaiveDateTime::from_timestamp(6, 0),
+                    &connection,
+                )
+                .await
+                .expect_err("Periods are unique per task."),
+            Error::AlreadyExists
+        );
+    }
+}
+
+/// Properties which must hold for any instances, checked against a temporary database.
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use crate::task::Task;
+    use crate::user::User;
+    use chrono::Duration;
+    use mindless_core::period;
+    use proptest::prelude::*;
+    use std::collections::BTreeSet;
+
+    /// Every case gets a fresh database so keep the number of cases down.
+    const CASES: u32 = 32;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Runtime::new()
+            .expect("Can create a runtime.")
+            .block_on(future)
+    }
+
+    async fn create_task(connection: &Connection) -> Task {
+        let user = User::insert("test_username", "test_name", connection)
+            .await
+            .expect("Should successfully insert.");
+
+        Task::insert(user.get_id(), "Exercise", connection)
+            .await
+            .expect("Should successfully insert.")
+    }
+
+    /// Periods between 2000 and 2040 at second precision lasting up to a day.
+    fn periods() -> impl Strategy<Value = (NaiveDateTime, NaiveDateTime)> {
+        (946_684_800i64..2_208_988_800, 0i64..24 * 60 * 60).prop_map(|(start, seconds)| {
+            let start = NaiveDateTime::from_timestamp(start, 0);
+            (start, start + Duration::seconds(seconds))
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(CASES))]
+
+        #[test]
+        fn insert_find_delete_round_trip((start, end) in periods()) {
+            block_on(async {
+                let connection = Connection::connect_temporary_with_schema()
+                    .await
+                    .expect("Should connect");
+                let task = create_task(&connection).await;
+
+                let instance = Instance::insert(task.get_id(), &start, &end, &connection)
+                    .await
+                    .expect("Should successfully insert.");
+                assert_eq!(
+                    Instance::retrieve(instance.get_id(), &connection)
+                        .await
+                        .expect("Instance exists."),
+                    instance
+                );
+
+                let mut found = Instance::new(0, task.get_id(), start, end);
+                found.find(&connection).await.expect("Instance exists.");
+                assert_eq!(found, instance);
+
+                let id = instance.get_id();
+                instance.delete(&connection).await.expect("Can delete.");
+                assert_eq!(
+                    Instance::retrieve(id, &connection)
+                        .await
+                        .expect_err("Instance was deleted."),
+                    Error::NotFound
+                );
+            });
+        }
+
+        #[test]
+        fn try_insert_is_idempotent(spans in prop::collection::vec(periods(), 0..8)) {
+            block_on(async {
+                let connection = Connection::connect_temporary_with_schema()
+                    .await
+                    .expect("Should connect");
+                let task = create_task(&connection).await;
+                let instances = || {
+                    spans
+                        .iter()
+                        .map(|(start, end)| Instance::new(0, task.get_id(), *start, *end))
+                        .collect::<Vec<_>>()
+                };
+
+                let first = Instance::try_insert_all(instances(), &connection)
+                    .await
+                    .expect("Can insert.");
+                let second = Instance::try_insert_all(instances(), &connection)
+                    .await
+                    .expect("Can insert again.");
+                assert_eq!(first, second);
+
+                let distinct: BTreeSet<_> = spans.iter().collect();
+                assert_eq!(
+                    Instance::get_instances(task.get_id(), &connection)
+                        .await
+                        .expect("Can list.")
+                        .len(),
+                    distinct.len()
+                );
+            });
+        }
+
+        #[test]
+        fn overlapping_instances_are_counted_once(
+            (start, end) in periods(),
+            offset in 0i64..24 * 60 * 60
+        ) {
+            block_on(async {
+                let connection = Connection::connect_temporary_with_schema()
+                    .await
+                    .expect("Should connect");
+                let task = create_task(&connection).await;
+
+                // The database keeps overlapping instances, e.g. when two devices tracked the same
+                // session.
+                let shifted = (start + Duration::seconds(offset), end + Duration::seconds(offset));
+                let instances = Instance::try_insert_all(
+                    vec![
+                        Instance::new(0, task.get_id(), start, end),
+                        Instance::new(0, task.get_id(), shifted.0, shifted.1),
+                    ],
+                    &connection,
+                )
+                .await
+                .expect("Can insert.");
+
+                let periods: Vec<_> = instances.iter().map(Instance::get_period).collect();
+                let totals = period::daily_totals(&periods, &chrono::Utc);
+                let total = totals.values().fold(Duration::zero(), |a, b| a + *b);
+
+                // The union of the two periods.
+                let union = if shifted.0 <= end {
+                    shifted.1 - start
+                } else {
+                    (end - start) + (shifted.1 - shifted.0)
+                };
+                assert_eq!(total, union);
+            });
+        }
+    }
 }
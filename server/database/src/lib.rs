@@ -1,9 +1,18 @@
 type SqlId = i64;
 
+#[deny(clippy::all)]
+pub mod backend;
+
 #[deny(clippy::all)]
 pub mod connection;
 #[deny(clippy::all)]
 pub mod error;
+#[deny(clippy::all)]
+pub mod migration;
+// Backups use `VACUUM INTO`. PostgreSQL has `pg_dump` for this.
+#[cfg(feature = "sqlite")]
+#[deny(clippy::all)]
+pub mod backup;
 
 // SQLx clippy errors.
 #[deny(clippy::all)]
@@ -17,3 +26,50 @@ pub mod task;
 // SQLx clippy errors.
 #[deny(clippy::all)]
 pub mod instance;
+
+// SQLx clippy errors.
+#[deny(clippy::all)]
+pub mod habit;
+
+// SQLx clippy errors.
+#[deny(clippy::all)]
+pub mod completion;
+
+// SQLx clippy errors.
+#[deny(clippy::all)]
+pub mod checkin;
+
+// SQLx clippy errors.
+#[deny(clippy::all)]
+pub mod timer;
+
+// SQLx clippy errors.
+#[deny(clippy::all)]
+pub mod webhook;
+
+// SQLx clippy errors.
+#[deny(clippy::all)]
+pub mod reminder;
+
+// SQLx clippy errors.
+#[deny(clippy::all)]
+pub mod goal;
+
+// SQLx clippy errors.
+#[deny(clippy::all)]
+pub mod tag;
+
+// SQLx clippy errors.
+#[deny(clippy::all)]
+pub mod stats;
+
+#[deny(clippy::all)]
+pub mod store;
+
+#[deny(clippy::all)]
+pub mod memory;
+
+// Generated data for tests and demos.
+#[cfg(feature = "test-util")]
+#[deny(clippy::all)]
+pub mod fixture;
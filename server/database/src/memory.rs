@@ -0,0 +1,1104 @@
+//! A store keeping everything in memory.
+//!
+//! This follows the unique constraints of the schema so it can stand in for a database in tests.
+//! Foreign keys are not checked.
+use crate::checkin::CheckinToken;
+use crate::completion::Completion;
+use crate::error::{Error, Result};
+use crate::goal::Goal;
+use crate::habit::{subtree, Habit};
+use crate::instance::Instance;
+use crate::reminder::{PendingReminder, Reminder, ReminderSettings};
+use crate::store::{
+    CheckinStore, GoalStore, HabitStore, InstanceStore, ReminderStore, TagStore, TaskStore,
+    TimerStore, UserStore, WebhookStore,
+};
+use crate::tag::Tag;
+use crate::task::Task;
+use crate::timer::Timer;
+use crate::user::User;
+use crate::webhook::{Delivery, Webhook};
+use crate::SqlId;
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use mindless_core::goal::Target;
+use mindless_core::path::HabitPath;
+use mindless_core::period::Period;
+use mindless_core::tag::TagName;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Default)]
+struct Tables {
+    /// The last id handed out, shared between tables.
+    last_id: SqlId,
+
+    users: BTreeMap<SqlId, User>,
+    tasks: BTreeMap<SqlId, Task>,
+    instances: BTreeMap<SqlId, Instance>,
+    habits: BTreeMap<SqlId, Habit>,
+    completions: BTreeMap<SqlId, Completion>,
+    checkin_tokens: BTreeMap<SqlId, CheckinToken>,
+    timers: BTreeMap<SqlId, Timer>,
+    webhooks: BTreeMap<SqlId, Webhook>,
+    deliveries: BTreeMap<SqlId, Delivery>,
+    reminders: BTreeMap<SqlId, Reminder>,
+    pending_reminders: BTreeMap<SqlId, PendingReminder>,
+    goals: BTreeMap<SqlId, Goal>,
+    tags: BTreeMap<SqlId, Tag>,
+
+    /// Task and tag ids.
+    task_tags: BTreeSet<(SqlId, SqlId)>,
+
+    /// Instance and tag ids.
+    instance_tags: BTreeSet<(SqlId, SqlId)>,
+
+    /// Keyed by user id.
+    reminder_settings: BTreeMap<SqlId, ReminderSettings>,
+
+    /// Monday of the latest week a digest was sent for, keyed by user id.
+    digest_weeks: BTreeMap<SqlId, NaiveDate>,
+}
+
+impl Tables {
+    fn next_id(&mut self) -> SqlId {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn find_task(&self, user_id: SqlId, name: &str) -> Option<&Task> {
+        self.tasks
+            .values()
+            .find(|task| task.get_user_id() == user_id && task.get_name() == name)
+    }
+
+    fn find_instance(
+        &self,
+        task_id: SqlId,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+    ) -> Option<&Instance> {
+        self.instances.values().find(|instance| {
+            instance.get_task_id() == task_id
+                && instance.get_start() == start
+                && instance.get_end() == end
+        })
+    }
+
+    fn insert_task(&mut self, user_id: SqlId, name: &str) -> Result<Task> {
+        if self.find_task(user_id, name).is_some() {
+            return Err(Error::AlreadyExists);
+        }
+
+        let task = Task::new(self.next_id(), user_id, name.to_string());
+        self.tasks.insert(task.get_id(), task.clone());
+
+        Ok(task)
+    }
+
+    fn insert_instance(
+        &mut self,
+        task_id: SqlId,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+    ) -> Result<Instance> {
+        if self.find_instance(task_id, start, end).is_some() {
+            return Err(Error::AlreadyExists);
+        }
+
+        let instance = Instance::new(self.next_id(), task_id, *start, *end);
+        self.instances.insert(instance.get_id(), instance.clone());
+
+        Ok(instance)
+    }
+
+    fn find_habit(&self, user_id: SqlId, parent_id: Option<SqlId>, name: &str) -> Option<&Habit> {
+        self.habits.values().find(|habit| {
+            habit.get_user_id() == user_id
+                && habit.get_parent_id() == parent_id
+                && habit.get_name() == name
+        })
+    }
+
+    fn completions_within<'a>(
+        &'a self,
+        habit_id: SqlId,
+        period: &'a Period,
+    ) -> impl Iterator<Item = &'a Completion> {
+        self.completions.values().filter(move |completion| {
+            completion.get_habit_id() == habit_id
+                && period.start <= *completion.get_created_at()
+                && *completion.get_created_at() < period.end
+        })
+    }
+
+    fn delete_task(&mut self, id: SqlId) {
+        self.goals.retain(|_, goal| goal.get_task_id() != Some(id));
+        self.timers.retain(|_, timer| timer.get_task_id() != id);
+        self.task_tags.retain(|(task_id, _)| *task_id != id);
+        let instances = &self.instances;
+        self.instance_tags.retain(|(instance_id, _)| {
+            instances
+                .get(instance_id)
+                .map_or(true, |instance| instance.get_task_id() != id)
+        });
+        self.instances
+            .retain(|_, instance| instance.get_task_id() != id);
+        self.tasks.remove(&id);
+    }
+
+    /// The tags attached to something, ordered by what they are attached to then by name.
+    fn tags_of(&self, user_id: SqlId, links: &BTreeSet<(SqlId, SqlId)>) -> Vec<(SqlId, Tag)> {
+        let mut tags: Vec<(SqlId, Tag)> = links
+            .iter()
+            .filter_map(|(id, tag_id)| self.tags.get(tag_id).map(|tag| (*id, tag.clone())))
+            .filter(|(_, tag)| tag.get_user_id() == user_id)
+            .collect();
+        tags.sort_by(|(a, a_tag), (b, b_tag)| (a, a_tag.get_name()).cmp(&(b, b_tag.get_name())));
+
+        tags
+    }
+}
+
+/// Everything a database holds, in memory.
+#[derive(Default)]
+pub struct MemoryStore {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryStore {
+    fn lock(&self) -> MutexGuard<'_, Tables> {
+        // The tables are never left half updated so they are still usable after a panic.
+        self.tables
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryStore {
+    async fn insert_user(&self, username: &str, name: &str) -> Result<User> {
+        let mut tables = self.lock();
+        if tables
+            .users
+            .values()
+            .any(|user| user.get_username() == username)
+        {
+            return Err(Error::AlreadyExists);
+        }
+
+        let user = User::new(tables.next_id(), username.to_string(), name.to_string());
+        tables.users.insert(user.get_id(), user.clone());
+
+        Ok(user)
+    }
+
+    async fn retrieve_user(&self, id: SqlId) -> Result<User> {
+        self.lock().users.get(&id).cloned().ok_or(Error::NotFound)
+    }
+
+    async fn get_user(&self, username: &str) -> Result<User> {
+        self.lock()
+            .users
+            .values()
+            .find(|user| user.get_username() == username)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn update_user(&self, user: &User) -> Result<()> {
+        let mut tables = self.lock();
+        if tables.users.values().any(|other| {
+            other.get_id() != user.get_id() && other.get_username() == user.get_username()
+        }) {
+            return Err(Error::AlreadyExists);
+        }
+
+        // Like an sql UPDATE, updating a missing user does nothing.
+        if let Some(existing) = tables.users.get_mut(&user.get_id()) {
+            *existing = user.clone();
+        }
+
+        Ok(())
+    }
+
+    async fn delete_user(&self, user: User) -> Result<()> {
+        let mut tables = self.lock();
+        if tables.users.get(&user.get_id()) != Some(&user) {
+            return Err(Error::NotFound);
+        }
+
+        let tasks: Vec<SqlId> = tables
+            .tasks
+            .values()
+            .filter(|task| task.get_user_id() == user.get_id())
+            .map(Task::get_id)
+            .collect();
+        for task in tasks {
+            tables.delete_task(task);
+        }
+
+        let habits: Vec<SqlId> = tables
+            .habits
+            .values()
+            .filter(|habit| habit.get_user_id() == user.get_id())
+            .map(Habit::get_id)
+            .collect();
+        tables
+            .completions
+            .retain(|_, completion| !habits.contains(&completion.get_habit_id()));
+        let reminders: Vec<SqlId> = tables
+            .reminders
+            .values()
+            .filter(|reminder| habits.contains(&reminder.get_habit_id()))
+            .map(Reminder::get_id)
+            .collect();
+        tables
+            .pending_reminders
+            .retain(|_, pending| !reminders.contains(&pending.get_reminder_id()));
+        tables
+            .reminders
+            .retain(|_, reminder| !habits.contains(&reminder.get_habit_id()));
+        tables.reminder_settings.remove(&user.get_id());
+        tables.digest_weeks.remove(&user.get_id());
+        tables
+            .goals
+            .retain(|_, goal| goal.get_user_id() != user.get_id());
+        let tags: Vec<SqlId> = tables
+            .tags
+            .values()
+            .filter(|tag| tag.get_user_id() == user.get_id())
+            .map(Tag::get_id)
+            .collect();
+        tables
+            .task_tags
+            .retain(|(_, tag_id)| !tags.contains(tag_id));
+        tables
+            .instance_tags
+            .retain(|(_, tag_id)| !tags.contains(tag_id));
+        tables
+            .tags
+            .retain(|_, tag| tag.get_user_id() != user.get_id());
+        tables
+            .habits
+            .retain(|_, habit| habit.get_user_id() != user.get_id());
+        tables
+            .checkin_tokens
+            .retain(|_, token| token.get_user_id() != user.get_id());
+
+        let webhooks: Vec<SqlId> = tables
+            .webhooks
+            .values()
+            .filter(|webhook| webhook.get_user_id() == user.get_id())
+            .map(Webhook::get_id)
+            .collect();
+        tables
+            .deliveries
+            .retain(|_, delivery| !webhooks.contains(&delivery.get_webhook_id()));
+        tables
+            .webhooks
+            .retain(|_, webhook| webhook.get_user_id() != user.get_id());
+        tables.users.remove(&user.get_id());
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TaskStore for MemoryStore {
+    async fn insert_task(&self, user_id: SqlId, name: &str) -> Result<Task> {
+        self.lock().insert_task(user_id, name)
+    }
+
+    async fn retrieve_task(&self, id: SqlId) -> Result<Task> {
+        self.lock().tasks.get(&id).cloned().ok_or(Error::NotFound)
+    }
+
+    async fn try_insert_task(&self, task: &mut Task) -> Result<bool> {
+        let mut tables = self.lock();
+        if let Some(existing) = tables.find_task(task.get_user_id(), task.get_name()) {
+            *task = existing.clone();
+            return Ok(false);
+        }
+        *task = tables.insert_task(task.get_user_id(), task.get_name())?;
+
+        Ok(true)
+    }
+
+    async fn get_tasks(&self, user: &User) -> Result<Vec<Task>> {
+        Ok(self
+            .lock()
+            .tasks
+            .values()
+            .filter(|task| task.get_user_id() == user.get_id())
+            .cloned()
+            .collect())
+    }
+
+    async fn rename_task(&self, task: &mut Task, name: &str) -> Result<()> {
+        let mut tables = self.lock();
+        if !tables.tasks.contains_key(&task.get_id()) {
+            return Err(Error::NotFound);
+        }
+        if tables.find_task(task.get_user_id(), name).is_some() {
+            return Err(Error::AlreadyExists);
+        }
+
+        *task = Task::new(task.get_id(), task.get_user_id(), name.to_string());
+        tables.tasks.insert(task.get_id(), task.clone());
+
+        Ok(())
+    }
+
+    async fn delete_task(&self, task: Task) -> Result<()> {
+        let mut tables = self.lock();
+        if tables.tasks.get(&task.get_id()) != Some(&task) {
+            return Err(Error::NotFound);
+        }
+
+        tables.delete_task(task.get_id());
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl InstanceStore for MemoryStore {
+    async fn insert_instance(
+        &self,
+        task_id: SqlId,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+    ) -> Result<Instance> {
+        self.lock().insert_instance(task_id, start, end)
+    }
+
+    async fn retrieve_instance(&self, id: SqlId) -> Result<Instance> {
+        self.lock()
+            .instances
+            .get(&id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn try_insert_instances(&self, instances: Vec<Instance>) -> Result<Vec<Instance>> {
+        let mut tables = self.lock();
+        let mut result = Vec::with_capacity(instances.len());
+        for instance in instances {
+            let (task_id, start, end) = (
+                instance.get_task_id(),
+                instance.get_start(),
+                instance.get_end(),
+            );
+            result.push(match tables.find_instance(task_id, start, end) {
+                Some(existing) => existing.clone(),
+                None => tables.insert_instance(task_id, start, end)?,
+            });
+        }
+
+        Ok(result)
+    }
+
+    async fn get_instances(&self, task_id: SqlId) -> Result<Vec<Instance>> {
+        Ok(self
+            .lock()
+            .instances
+            .values()
+            .filter(|instance| instance.get_task_id() == task_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn update_instance(
+        &self,
+        instance: &mut Instance,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+    ) -> Result<()> {
+        let mut tables = self.lock();
+        if !tables.instances.contains_key(&instance.get_id()) {
+            return Err(Error::NotFound);
+        }
+        if let Some(existing) = tables.find_instance(instance.get_task_id(), start, end) {
+            if existing.get_id() != instance.get_id() {
+                return Err(Error::AlreadyExists);
+            }
+        }
+
+        let updated = Instance::new(instance.get_id(), instance.get_task_id(), *start, *end);
+        tables.instances.insert(updated.get_id(), updated.clone());
+        *instance = updated;
+
+        Ok(())
+    }
+
+    async fn delete_instance(&self, instance: Instance) -> Result<()> {
+        let mut tables = self.lock();
+        if tables.instances.get(&instance.get_id()) != Some(&instance) {
+            return Err(Error::NotFound);
+        }
+
+        tables
+            .instance_tags
+            .retain(|(instance_id, _)| *instance_id != instance.get_id());
+        tables.instances.remove(&instance.get_id());
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl HabitStore for MemoryStore {
+    async fn insert_habit(
+        &self,
+        user_id: SqlId,
+        parent_id: Option<SqlId>,
+        name: &str,
+        created_at: &NaiveDateTime,
+        repeat_period_sec: Option<i64>,
+    ) -> Result<Habit> {
+        let mut tables = self.lock();
+        if tables.find_habit(user_id, parent_id, name).is_some() {
+            return Err(Error::AlreadyExists);
+        }
+
+        let habit = Habit::new(
+            tables.next_id(),
+            parent_id,
+            user_id,
+            name.to_string(),
+            *created_at,
+            repeat_period_sec,
+        );
+        tables.habits.insert(habit.get_id(), habit.clone());
+
+        Ok(habit)
+    }
+
+    async fn retrieve_habit(&self, id: SqlId) -> Result<Habit> {
+        self.lock().habits.get(&id).cloned().ok_or(Error::NotFound)
+    }
+
+    async fn get_habit(&self, user_id: SqlId, path: &HabitPath) -> Result<Habit> {
+        let tables = self.lock();
+        let mut habit: Option<&Habit> = None;
+        for name in path.names() {
+            let parent_id = habit.map(Habit::get_id);
+            habit = Some(
+                tables
+                    .find_habit(user_id, parent_id, name)
+                    .ok_or(Error::NotFound)?,
+            );
+        }
+
+        habit.cloned().ok_or(Error::NotFound)
+    }
+
+    async fn get_habits(&self, user_id: SqlId) -> Result<Vec<Habit>> {
+        Ok(self
+            .lock()
+            .habits
+            .values()
+            .filter(|habit| habit.get_user_id() == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn mark_habit(&self, habit: &Habit, now: &NaiveDateTime) -> Result<bool> {
+        let mut tables = self.lock();
+        let period = habit.get_period(now)?;
+        if tables
+            .completions_within(habit.get_id(), &period)
+            .next()
+            .is_some()
+        {
+            return Ok(false);
+        }
+
+        let completion = Completion::new(tables.next_id(), habit.get_id(), *now);
+        tables.completions.insert(completion.get_id(), completion);
+
+        Ok(true)
+    }
+
+    async fn unmark_habit(&self, habit: &Habit, now: &NaiveDateTime) -> Result<bool> {
+        let mut tables = self.lock();
+        let period = habit.get_period(now)?;
+        let latest = tables
+            .completions_within(habit.get_id(), &period)
+            .max_by_key(|completion| (*completion.get_created_at(), completion.get_id()))
+            .map(Completion::get_id);
+
+        Ok(match latest {
+            Some(id) => tables.completions.remove(&id).is_some(),
+            None => false,
+        })
+    }
+
+    async fn get_completions(&self, habit_id: SqlId, period: &Period) -> Result<Vec<Completion>> {
+        let mut completions: Vec<Completion> = self
+            .lock()
+            .completions_within(habit_id, period)
+            .cloned()
+            .collect();
+        completions.sort_by_key(|completion| (*completion.get_created_at(), completion.get_id()));
+
+        Ok(completions)
+    }
+
+    async fn delete_habit(&self, habit: Habit) -> Result<()> {
+        let mut tables = self.lock();
+        if tables.habits.get(&habit.get_id()) != Some(&habit) {
+            return Err(Error::NotFound);
+        }
+
+        let habits: Vec<Habit> = tables
+            .habits
+            .values()
+            .filter(|other| other.get_user_id() == habit.get_user_id())
+            .cloned()
+            .collect();
+        let ids = subtree(&habits, habit.get_id());
+        tables
+            .completions
+            .retain(|_, completion| !ids.contains(&completion.get_habit_id()));
+        tables
+            .checkin_tokens
+            .retain(|_, token| !ids.contains(&token.get_habit_id()));
+        tables.goals.retain(|_, goal| {
+            goal.get_habit_id()
+                .map_or(true, |habit_id| !ids.contains(&habit_id))
+        });
+        let reminders: Vec<SqlId> = tables
+            .reminders
+            .values()
+            .filter(|reminder| ids.contains(&reminder.get_habit_id()))
+            .map(Reminder::get_id)
+            .collect();
+        tables
+            .pending_reminders
+            .retain(|_, pending| !reminders.contains(&pending.get_reminder_id()));
+        tables
+            .reminders
+            .retain(|_, reminder| !ids.contains(&reminder.get_habit_id()));
+        tables.habits.retain(|id, _| !ids.contains(id));
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CheckinStore for MemoryStore {
+    async fn insert_checkin_token(
+        &self,
+        user_id: SqlId,
+        habit_id: SqlId,
+        created_at: &NaiveDateTime,
+    ) -> Result<CheckinToken> {
+        let mut tables = self.lock();
+        let token = CheckinToken::new(tables.next_id(), user_id, habit_id, *created_at);
+        tables.checkin_tokens.insert(token.get_id(), token.clone());
+
+        Ok(token)
+    }
+
+    async fn retrieve_checkin_token(&self, id: SqlId) -> Result<CheckinToken> {
+        self.lock()
+            .checkin_tokens
+            .get(&id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn get_checkin_tokens(&self, user_id: SqlId) -> Result<Vec<CheckinToken>> {
+        Ok(self
+            .lock()
+            .checkin_tokens
+            .values()
+            .filter(|token| token.get_user_id() == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn revoke_checkin_token(
+        &self,
+        token: &mut CheckinToken,
+        at: &NaiveDateTime,
+    ) -> Result<()> {
+        let mut tables = self.lock();
+        let existing = tables
+            .checkin_tokens
+            .get_mut(&token.get_id())
+            .ok_or(Error::NotFound)?;
+        existing.set_revoked_at(*at);
+        *token = existing.clone();
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TimerStore for MemoryStore {
+    async fn start_timer(&self, task_id: SqlId, started_at: &NaiveDateTime) -> Result<Timer> {
+        let mut tables = self.lock();
+        if tables
+            .timers
+            .values()
+            .any(|timer| timer.get_task_id() == task_id)
+        {
+            return Err(Error::AlreadyExists);
+        }
+
+        let timer = Timer::new(tables.next_id(), task_id, *started_at);
+        tables.timers.insert(timer.get_id(), timer.clone());
+
+        Ok(timer)
+    }
+
+    async fn retrieve_timer(&self, task_id: SqlId) -> Result<Timer> {
+        self.lock()
+            .timers
+            .values()
+            .find(|timer| timer.get_task_id() == task_id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn get_timers(&self, user_id: SqlId) -> Result<Vec<Timer>> {
+        let tables = self.lock();
+        Ok(tables
+            .timers
+            .values()
+            .filter(|timer| {
+                tables
+                    .tasks
+                    .get(&timer.get_task_id())
+                    .map_or(false, |task| task.get_user_id() == user_id)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn stop_timer(&self, timer: Timer, ended_at: &NaiveDateTime) -> Result<Instance> {
+        let mut tables = self.lock();
+        if tables.timers.get(&timer.get_id()) != Some(&timer) {
+            return Err(Error::NotFound);
+        }
+
+        let instance =
+            tables.insert_instance(timer.get_task_id(), timer.get_started_at(), ended_at)?;
+        tables.timers.remove(&timer.get_id());
+
+        Ok(instance)
+    }
+}
+
+#[async_trait]
+impl WebhookStore for MemoryStore {
+    async fn insert_webhook(
+        &self,
+        user_id: SqlId,
+        url: &str,
+        secret: &str,
+        events: &[String],
+        created_at: &NaiveDateTime,
+    ) -> Result<Webhook> {
+        let mut tables = self.lock();
+        let webhook = Webhook::new(
+            tables.next_id(),
+            user_id,
+            url.to_string(),
+            secret.to_string(),
+            events,
+            *created_at,
+        );
+        tables.webhooks.insert(webhook.get_id(), webhook.clone());
+
+        Ok(webhook)
+    }
+
+    async fn retrieve_webhook(&self, id: SqlId) -> Result<Webhook> {
+        self.lock()
+            .webhooks
+            .get(&id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn get_webhooks(&self, user_id: SqlId) -> Result<Vec<Webhook>> {
+        Ok(self
+            .lock()
+            .webhooks
+            .values()
+            .filter(|webhook| webhook.get_user_id() == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_all_webhooks(&self) -> Result<Vec<Webhook>> {
+        Ok(self.lock().webhooks.values().cloned().collect())
+    }
+
+    async fn delete_webhook(&self, webhook: Webhook) -> Result<()> {
+        let mut tables = self.lock();
+        if tables.webhooks.remove(&webhook.get_id()).is_none() {
+            return Err(Error::NotFound);
+        }
+        tables
+            .deliveries
+            .retain(|_, delivery| delivery.get_webhook_id() != webhook.get_id());
+
+        Ok(())
+    }
+
+    async fn insert_delivery(&self, delivery: &mut Delivery) -> Result<()> {
+        let mut tables = self.lock();
+        delivery.set_id(tables.next_id());
+        tables
+            .deliveries
+            .insert(delivery.get_id(), delivery.clone());
+
+        Ok(())
+    }
+
+    async fn get_deliveries(&self, webhook_id: SqlId) -> Result<Vec<Delivery>> {
+        Ok(self
+            .lock()
+            .deliveries
+            .values()
+            .filter(|delivery| delivery.get_webhook_id() == webhook_id)
+            .cloned()
+            .collect())
+    }
+    async fn delete_deliveries_before(&self, before: &NaiveDateTime) -> Result<u64> {
+        let mut tables = self.lock();
+        let count = tables.deliveries.len();
+        tables
+            .deliveries
+            .retain(|_, delivery| delivery.get_attempted_at() >= before);
+
+        Ok((count - tables.deliveries.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl ReminderStore for MemoryStore {
+    async fn insert_reminder(
+        &self,
+        habit_id: SqlId,
+        time_of_day: &NaiveTime,
+        unless_done: bool,
+        channel: &str,
+        created_at: &NaiveDateTime,
+    ) -> Result<Reminder> {
+        let mut tables = self.lock();
+        let reminder = Reminder::new(
+            tables.next_id(),
+            habit_id,
+            time_of_day,
+            unless_done,
+            channel.to_string(),
+            *created_at,
+        );
+        tables.reminders.insert(reminder.get_id(), reminder.clone());
+
+        Ok(reminder)
+    }
+
+    async fn retrieve_reminder(&self, id: SqlId) -> Result<Reminder> {
+        self.lock()
+            .reminders
+            .get(&id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn get_reminders(&self, user_id: SqlId) -> Result<Vec<Reminder>> {
+        let tables = self.lock();
+        Ok(tables
+            .reminders
+            .values()
+            .filter(|reminder| {
+                tables
+                    .habits
+                    .get(&reminder.get_habit_id())
+                    .map_or(false, |habit| habit.get_user_id() == user_id)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn get_all_reminders(&self) -> Result<Vec<Reminder>> {
+        Ok(self.lock().reminders.values().cloned().collect())
+    }
+
+    async fn update_reminder_last_due_at(&self, reminder: &Reminder) -> Result<()> {
+        let mut tables = self.lock();
+        match tables.reminders.get_mut(&reminder.get_id()) {
+            Some(existing) => {
+                if let Some(last_due_at) = reminder.get_last_due_at() {
+                    existing.set_last_due_at(*last_due_at);
+                }
+
+                Ok(())
+            }
+            None => Err(Error::NotFound),
+        }
+    }
+
+    async fn delete_reminder(&self, reminder: Reminder) -> Result<()> {
+        let mut tables = self.lock();
+        if tables.reminders.remove(&reminder.get_id()).is_none() {
+            return Err(Error::NotFound);
+        }
+        tables
+            .pending_reminders
+            .retain(|_, pending| pending.get_reminder_id() != reminder.get_id());
+
+        Ok(())
+    }
+
+    async fn retrieve_reminder_settings(&self, user_id: SqlId) -> Result<ReminderSettings> {
+        Ok(self
+            .lock()
+            .reminder_settings
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_else(|| ReminderSettings::new(user_id)))
+    }
+
+    async fn update_reminder_settings(&self, settings: &ReminderSettings) -> Result<()> {
+        self.lock()
+            .reminder_settings
+            .insert(settings.get_user_id(), settings.clone());
+
+        Ok(())
+    }
+
+    async fn get_all_reminder_settings(&self) -> Result<Vec<ReminderSettings>> {
+        Ok(self.lock().reminder_settings.values().cloned().collect())
+    }
+
+    async fn retrieve_digest_week(&self, user_id: SqlId) -> Result<Option<NaiveDate>> {
+        Ok(self.lock().digest_weeks.get(&user_id).cloned())
+    }
+
+    async fn update_digest_week(&self, user_id: SqlId, week: &NaiveDate) -> Result<()> {
+        let mut tables = self.lock();
+        if !tables.reminder_settings.contains_key(&user_id) {
+            return Err(Error::NotFound);
+        }
+        tables.digest_weeks.insert(user_id, *week);
+
+        Ok(())
+    }
+
+    async fn insert_pending_reminder(
+        &self,
+        reminder: &Reminder,
+        due_at: &NaiveDateTime,
+    ) -> Result<PendingReminder> {
+        let mut tables = self.lock();
+        if tables.pending_reminders.values().any(|pending| {
+            pending.get_reminder_id() == reminder.get_id() && pending.get_due_at() == due_at
+        }) {
+            return Err(Error::AlreadyExists);
+        }
+
+        let pending = PendingReminder::new(
+            tables.next_id(),
+            reminder.get_id(),
+            reminder.get_habit_id(),
+            *due_at,
+        );
+        tables
+            .pending_reminders
+            .insert(pending.get_id(), pending.clone());
+
+        Ok(pending)
+    }
+
+    async fn retrieve_pending_reminder(&self, id: SqlId) -> Result<PendingReminder> {
+        self.lock()
+            .pending_reminders
+            .get(&id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn get_pending_reminders(&self, user_id: SqlId) -> Result<Vec<PendingReminder>> {
+        let tables = self.lock();
+        let mut pending: Vec<PendingReminder> = tables
+            .pending_reminders
+            .values()
+            .filter(|pending| {
+                tables
+                    .habits
+                    .get(&pending.get_habit_id())
+                    .map_or(false, |habit| habit.get_user_id() == user_id)
+            })
+            .cloned()
+            .collect();
+        pending.sort_by_key(|pending| (*pending.get_due_at(), pending.get_id()));
+
+        Ok(pending)
+    }
+
+    async fn delete_pending_reminder(&self, pending: PendingReminder) -> Result<()> {
+        match self.lock().pending_reminders.remove(&pending.get_id()) {
+            Some(_) => Ok(()),
+            None => Err(Error::NotFound),
+        }
+    }
+}
+
+#[async_trait]
+impl GoalStore for MemoryStore {
+    async fn insert_goal(
+        &self,
+        user_id: SqlId,
+        task_id: Option<SqlId>,
+        habit_id: Option<SqlId>,
+        target: &Target,
+        created_at: &NaiveDateTime,
+    ) -> Result<Goal> {
+        let mut tables = self.lock();
+        let goal = Goal::new(
+            tables.next_id(),
+            user_id,
+            task_id,
+            habit_id,
+            target,
+            *created_at,
+        );
+        tables.goals.insert(goal.get_id(), goal.clone());
+
+        Ok(goal)
+    }
+
+    async fn retrieve_goal(&self, id: SqlId) -> Result<Goal> {
+        self.lock().goals.get(&id).cloned().ok_or(Error::NotFound)
+    }
+
+    async fn get_goals(&self, user_id: SqlId) -> Result<Vec<Goal>> {
+        Ok(self
+            .lock()
+            .goals
+            .values()
+            .filter(|goal| goal.get_user_id() == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_goal(&self, goal: Goal) -> Result<()> {
+        match self.lock().goals.remove(&goal.get_id()) {
+            Some(_) => Ok(()),
+            None => Err(Error::NotFound),
+        }
+    }
+}
+
+#[async_trait]
+impl TagStore for MemoryStore {
+    async fn insert_tag(&self, user_id: SqlId, name: &TagName) -> Result<Tag> {
+        let mut tables = self.lock();
+        if tables
+            .tags
+            .values()
+            .any(|tag| tag.get_user_id() == user_id && tag.get_name() == name.as_str())
+        {
+            return Err(Error::AlreadyExists);
+        }
+
+        let tag = Tag::new(tables.next_id(), user_id, name);
+        tables.tags.insert(tag.get_id(), tag.clone());
+
+        Ok(tag)
+    }
+
+    async fn retrieve_tag(&self, id: SqlId) -> Result<Tag> {
+        self.lock().tags.get(&id).cloned().ok_or(Error::NotFound)
+    }
+
+    async fn get_tags(&self, user_id: SqlId) -> Result<Vec<Tag>> {
+        let mut tags: Vec<Tag> = self
+            .lock()
+            .tags
+            .values()
+            .filter(|tag| tag.get_user_id() == user_id)
+            .cloned()
+            .collect();
+        tags.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+
+        Ok(tags)
+    }
+
+    async fn rename_tag(&self, tag: &mut Tag, name: &TagName) -> Result<()> {
+        let mut tables = self.lock();
+        if !tables.tags.contains_key(&tag.get_id()) {
+            return Err(Error::NotFound);
+        }
+        if tables.tags.values().any(|other| {
+            other.get_id() != tag.get_id()
+                && other.get_user_id() == tag.get_user_id()
+                && other.get_name() == name.as_str()
+        }) {
+            return Err(Error::AlreadyExists);
+        }
+
+        *tag = Tag::new(tag.get_id(), tag.get_user_id(), name);
+        tables.tags.insert(tag.get_id(), tag.clone());
+
+        Ok(())
+    }
+
+    async fn delete_tag(&self, tag: Tag) -> Result<()> {
+        let mut tables = self.lock();
+        if tables.tags.remove(&tag.get_id()).is_none() {
+            return Err(Error::NotFound);
+        }
+
+        tables
+            .task_tags
+            .retain(|(_, tag_id)| *tag_id != tag.get_id());
+        tables
+            .instance_tags
+            .retain(|(_, tag_id)| *tag_id != tag.get_id());
+
+        Ok(())
+    }
+
+    async fn tag_task(&self, tag: &Tag, task_id: SqlId) -> Result<bool> {
+        Ok(self.lock().task_tags.insert((task_id, tag.get_id())))
+    }
+
+    async fn untag_task(&self, tag: &Tag, task_id: SqlId) -> Result<bool> {
+        Ok(self.lock().task_tags.remove(&(task_id, tag.get_id())))
+    }
+
+    async fn tag_instance(&self, tag: &Tag, instance_id: SqlId) -> Result<bool> {
+        Ok(self
+            .lock()
+            .instance_tags
+            .insert((instance_id, tag.get_id())))
+    }
+
+    async fn untag_instance(&self, tag: &Tag, instance_id: SqlId) -> Result<bool> {
+        Ok(self
+            .lock()
+            .instance_tags
+            .remove(&(instance_id, tag.get_id())))
+    }
+
+    async fn get_task_tags(&self, user_id: SqlId) -> Result<Vec<(SqlId, Tag)>> {
+        let tables = self.lock();
+
+        Ok(tables.tags_of(user_id, &tables.task_tags))
+    }
+
+    async fn get_instance_tags(&self, user_id: SqlId) -> Result<Vec<(SqlId, Tag)>> {
+        let tables = self.lock();
+
+        Ok(tables.tags_of(user_id, &tables.instance_tags))
+    }
+}
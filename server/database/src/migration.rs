@@ -0,0 +1,124 @@
+//! Schema migrations.
+//!
+//! Every migration is a sql script in `data/migrations/<backend>` which is applied in order. The
+//! version of a database is the number of migrations applied to it. It is stored in SQLite's
+//! `user_version` or in the `schema_version` table on PostgreSQL.
+use crate::backend::MIGRATIONS;
+use crate::connection::Connection;
+use crate::error::Result;
+use sqlx::{Executor, Row};
+use tracing::instrument;
+
+/// The schema version of a database with every migration applied.
+pub const LATEST_VERSION: i64 = MIGRATIONS.len() as i64;
+
+/// Get the schema version of the database.
+#[cfg(feature = "sqlite")]
+#[instrument(level = "debug", skip(connection))]
+pub async fn version(connection: &Connection) -> Result<i64> {
+    let row = sqlx::query("PRAGMA user_version")
+        .fetch_one(connection.get_pool())
+        .await?;
+
+    Ok(row.try_get(0)?)
+}
+
+/// Get the schema version of the database.
+#[cfg(feature = "postgres")]
+#[instrument(level = "debug", skip(connection))]
+pub async fn version(connection: &Connection) -> Result<i64> {
+    connection
+        .get_pool()
+        .execute("CREATE TABLE IF NOT EXISTS schema_version ( version BIGINT NOT NULL )")
+        .await?;
+
+    let row = sqlx::query("SELECT COALESCE(MAX(version), 0) FROM schema_version")
+        .fetch_one(connection.get_pool())
+        .await?;
+
+    Ok(row.try_get(0)?)
+}
+
+/// The statement recording that the database is at `version`.
+#[cfg(feature = "sqlite")]
+fn set_version(version: i64) -> String {
+    // Pragmas can't be bound as parameters.
+    format!("PRAGMA user_version = {}", version)
+}
+
+/// The statement recording that the database is at `version`.
+#[cfg(feature = "postgres")]
+fn set_version(version: i64) -> String {
+    format!(
+        "INSERT INTO schema_version ( version ) VALUES ( {} )",
+        version
+    )
+}
+
+/// Apply every migration which has not been applied yet.
+///
+/// Each migration is applied in its own transaction along with the version bump so a failed
+/// migration leaves the database at the previous version.
+///
+/// Returns the number of migrations applied.
+#[instrument(level = "debug", skip(connection))]
+pub async fn run(connection: &Connection) -> Result<usize> {
+    let current = version(connection).await?;
+
+    let mut applied = 0;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let next_version = index as i64 + 1;
+        tracing::info!(version = next_version, "Applying migration");
+
+        let mut transaction = connection.get_pool().begin().await?;
+
+        // Executed without arguments so scripts may contain several statements.
+        transaction.execute(*migration).await?;
+        transaction
+            .execute(set_version(next_version).as_str())
+            .await?;
+
+        transaction.commit().await?;
+        applied += 1;
+    }
+
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn migrate_empty_database() {
+        let connection = Connection::connect("sqlite://")
+            .await
+            .expect("Should connect");
+
+        assert_eq!(version(&connection).await.expect("Has a version."), 0);
+
+        let applied = run(&connection).await.expect("Should migrate.");
+
+        assert_eq!(applied, MIGRATIONS.len());
+        assert_eq!(
+            version(&connection).await.expect("Has a version."),
+            LATEST_VERSION
+        );
+    }
+
+    #[tokio::test]
+    async fn migrate_is_idempotent() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+
+        let applied = run(&connection).await.expect("Should migrate.");
+
+        assert_eq!(applied, 0);
+        assert_eq!(
+            version(&connection).await.expect("Has a version."),
+            LATEST_VERSION
+        );
+    }
+}
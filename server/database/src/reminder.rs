@@ -0,0 +1,766 @@
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::SqlId;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use mindless_core::reminder::QuietHours;
+use serde::{Serialize, Serializer};
+use sqlx::Done;
+use sqlx::FromRow;
+use std::cmp::PartialEq;
+use tracing::instrument;
+
+/// Times of day are stored in minutes after midnight.
+fn to_minutes(time: &NaiveTime) -> i64 {
+    i64::from(time.num_seconds_from_midnight() / 60)
+}
+
+fn from_minutes(minutes: i64) -> NaiveTime {
+    NaiveTime::from_num_seconds_from_midnight((minutes.rem_euclid(24 * 60) * 60) as u32, 0)
+}
+
+/// Show times of day as `HH:MM` rather than as they are stored.
+fn serialize_minutes<S: Serializer>(
+    minutes: &i64,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(&from_minutes(*minutes).format("%H:%M"))
+}
+
+fn serialize_optional_minutes<S: Serializer>(
+    minutes: &Option<i64>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match minutes {
+        Some(minutes) => serialize_minutes(minutes, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// This is a struct representing when to remind a user of a habit.
+#[derive(Debug, Clone, FromRow, Serialize, PartialEq)]
+pub struct Reminder {
+    /// The reminder id.
+    id: SqlId,
+
+    /// The habit to remind of.
+    habit_id: SqlId,
+
+    /// Local time of day to go off at, in minutes after midnight.
+    #[serde(serialize_with = "serialize_minutes")]
+    time_of_day: i64,
+
+    /// Only go off if the habit was not done in its current period.
+    unless_done: bool,
+
+    /// How the reminder is delivered, e.g. `email`.
+    channel: String,
+
+    /// Time this reminder was created.
+    created_at: NaiveDateTime,
+
+    /// The latest time this reminder was due and handled.
+    last_due_at: Option<NaiveDateTime>,
+}
+
+impl Reminder {
+    pub fn new(
+        id: SqlId,
+        habit_id: SqlId,
+        time_of_day: &NaiveTime,
+        unless_done: bool,
+        channel: String,
+        created_at: NaiveDateTime,
+    ) -> Reminder {
+        Reminder {
+            id,
+            habit_id,
+            time_of_day: to_minutes(time_of_day),
+            unless_done,
+            channel,
+            created_at,
+            last_due_at: None,
+        }
+    }
+
+    pub fn get_id(&self) -> SqlId {
+        self.id
+    }
+
+    pub fn get_habit_id(&self) -> SqlId {
+        self.habit_id
+    }
+
+    pub fn get_time_of_day(&self) -> NaiveTime {
+        from_minutes(self.time_of_day)
+    }
+
+    pub fn get_unless_done(&self) -> bool {
+        self.unless_done
+    }
+
+    pub fn get_channel(&self) -> &str {
+        &self.channel
+    }
+
+    pub fn get_created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+
+    pub fn get_last_due_at(&self) -> Option<&NaiveDateTime> {
+        self.last_due_at.as_ref()
+    }
+
+    /// Record the latest time the reminder was due.
+    ///
+    /// This does not get comitted into the database until `update_last_due_at` is called.
+    pub fn set_last_due_at(&mut self, last_due_at: NaiveDateTime) {
+        self.last_due_at = Some(last_due_at);
+    }
+
+    /// Retrieve a reminder in the database by id.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn retrieve(id: SqlId, connection: &Connection) -> Result<Reminder> {
+        let reminder = sqlx::query_as!(
+            Reminder,
+            r#"
+                SELECT
+                id AS "id!", habit_id, time_of_day, unless_done, channel, created_at, last_due_at
+                FROM reminder
+                WHERE id = ( $1 )
+            "#,
+            id
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(reminder)
+    }
+
+    /// Add a reminder to a habit.
+    #[instrument(level = "debug", skip(time_of_day, channel, created_at, connection))]
+    pub async fn insert(
+        habit_id: SqlId,
+        time_of_day: &NaiveTime,
+        unless_done: bool,
+        channel: &str,
+        created_at: &NaiveDateTime,
+        connection: &Connection,
+    ) -> Result<Reminder> {
+        let minutes = to_minutes(time_of_day);
+        sqlx::query!(
+            r#"
+                INSERT INTO reminder ( habit_id, time_of_day, unless_done, channel, created_at )
+                VALUES ( $1, $2, $3, $4, $5 )
+            "#,
+            habit_id,
+            minutes,
+            unless_done,
+            channel,
+            created_at
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        // Find it again rather than relying on backend specific ways of getting the inserted id.
+        let reminder = sqlx::query_as!(
+            Reminder,
+            r#"
+                SELECT
+                id AS "id!", habit_id, time_of_day, unless_done, channel, created_at, last_due_at
+                FROM reminder
+                WHERE habit_id = ( $1 )
+                ORDER BY id DESC
+                LIMIT 1
+            "#,
+            habit_id
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(reminder)
+    }
+
+    /// Get the reminders of every habit of a user.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_reminders(user_id: SqlId, connection: &Connection) -> Result<Vec<Reminder>> {
+        let reminders = sqlx::query_as!(
+            Reminder,
+            r#"
+                SELECT
+                reminder.id AS "id!", reminder.habit_id, reminder.time_of_day,
+                reminder.unless_done, reminder.channel, reminder.created_at, reminder.last_due_at
+                FROM reminder
+                INNER JOIN habit ON reminder.habit_id = habit.id
+                WHERE habit.user_id = ( $1 )
+                ORDER BY reminder.id
+            "#,
+            user_id
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(reminders)
+    }
+
+    /// Get the reminders of every user.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_all(connection: &Connection) -> Result<Vec<Reminder>> {
+        let reminders = sqlx::query_as!(
+            Reminder,
+            r#"
+                SELECT
+                id AS "id!", habit_id, time_of_day, unless_done, channel, created_at, last_due_at
+                FROM reminder
+                ORDER BY id
+            "#
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(reminders)
+    }
+
+    /// Save the latest time the reminder was due.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn update_last_due_at(&self, connection: &Connection) -> Result<()> {
+        let updated = sqlx::query!(
+            r#"
+                UPDATE reminder
+                SET last_due_at = ( $1 )
+                WHERE id = ( $2 )
+            "#,
+            self.last_due_at,
+            self.id
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        if updated.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    /// Delete a reminder along with its pending deliveries.
+    ///
+    /// This consumes self since it is invalid after deletion from the database.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn delete(self, connection: &Connection) -> Result<()> {
+        // Rolled back on drop if anything fails.
+        let mut transaction = connection.get_pool().begin().await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM pending_reminder
+                WHERE reminder_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        let deleted = sqlx::query!(
+            r#"
+                DELETE FROM reminder
+                WHERE id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        if deleted.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+}
+
+/// This is a struct representing how and when a user wants to be reminded.
+#[derive(Debug, Clone, FromRow, Serialize, PartialEq)]
+pub struct ReminderSettings {
+    /// The user these settings belong to.
+    user_id: SqlId,
+
+    /// IANA timezone reminder times are in, e.g. `Australia/Sydney`.
+    timezone: String,
+
+    /// Address email reminders are sent to.
+    email: Option<String>,
+
+    /// Local time in minutes after midnight from which reminders are held back.
+    #[serde(serialize_with = "serialize_optional_minutes")]
+    quiet_start: Option<i64>,
+
+    /// Local time in minutes after midnight until which reminders are held back.
+    #[serde(serialize_with = "serialize_optional_minutes")]
+    quiet_end: Option<i64>,
+
+    /// How the weekly digest is delivered, e.g. `email`. None if the user doesn't want one.
+    digest_channel: Option<String>,
+}
+
+impl ReminderSettings {
+    /// The defaults of users who haven't changed their settings, UTC without quiet hours.
+    pub fn new(user_id: SqlId) -> ReminderSettings {
+        ReminderSettings {
+            user_id,
+            timezone: "UTC".to_string(),
+            email: None,
+            quiet_start: None,
+            quiet_end: None,
+            digest_channel: None,
+        }
+    }
+
+    pub fn get_user_id(&self) -> SqlId {
+        self.user_id
+    }
+
+    pub fn get_timezone(&self) -> &str {
+        &self.timezone
+    }
+
+    pub fn get_email(&self) -> Option<&str> {
+        self.email.as_deref()
+    }
+
+    pub fn get_quiet_hours(&self) -> Option<QuietHours> {
+        match (self.quiet_start, self.quiet_end) {
+            (Some(start), Some(end)) => {
+                Some(QuietHours::new(from_minutes(start), from_minutes(end)))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_digest_channel(&self) -> Option<&str> {
+        self.digest_channel.as_deref()
+    }
+
+    /// This does not get comitted into the database until update is called.
+    pub fn set_timezone(&mut self, timezone: String) {
+        self.timezone = timezone;
+    }
+
+    /// This does not get comitted into the database until update is called.
+    pub fn set_email(&mut self, email: Option<String>) {
+        self.email = email;
+    }
+
+    /// This does not get comitted into the database until update is called.
+    pub fn set_quiet_hours(&mut self, quiet_hours: Option<QuietHours>) {
+        self.quiet_start = quiet_hours.map(|quiet| to_minutes(&quiet.start));
+        self.quiet_end = quiet_hours.map(|quiet| to_minutes(&quiet.end));
+    }
+
+    /// This does not get comitted into the database until update is called.
+    pub fn set_digest_channel(&mut self, digest_channel: Option<String>) {
+        self.digest_channel = digest_channel;
+    }
+
+    /// Retrieve the settings of a user, the defaults if they haven't changed them.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn retrieve(user_id: SqlId, connection: &Connection) -> Result<ReminderSettings> {
+        let settings = sqlx::query_as!(
+            ReminderSettings,
+            r#"
+                SELECT user_id AS "user_id!", timezone, email, quiet_start, quiet_end, digest_channel
+                FROM reminder_settings
+                WHERE user_id = ( $1 )
+            "#,
+            user_id
+        )
+        .fetch_optional(connection.get_pool())
+        .await?;
+
+        Ok(settings.unwrap_or_else(|| ReminderSettings::new(user_id)))
+    }
+
+    /// Retrieve the settings of every user who changed them, e.g. to send digests.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_all(connection: &Connection) -> Result<Vec<ReminderSettings>> {
+        let settings = sqlx::query_as!(
+            ReminderSettings,
+            r#"
+                SELECT user_id AS "user_id!", timezone, email, quiet_start, quiet_end, digest_channel
+                FROM reminder_settings
+                ORDER BY user_id
+            "#
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(settings)
+    }
+
+    /// Save the settings.
+    #[instrument(level = "debug", skip(self, connection), fields(user_id = self.user_id))]
+    pub async fn update(&self, connection: &Connection) -> Result<()> {
+        sqlx::query!(
+            r#"
+                INSERT INTO reminder_settings
+                ( user_id, timezone, email, quiet_start, quiet_end, digest_channel )
+                VALUES ( $1, $2, $3, $4, $5, $6 )
+                ON CONFLICT ( user_id ) DO UPDATE SET
+                timezone = excluded.timezone,
+                email = excluded.email,
+                quiet_start = excluded.quiet_start,
+                quiet_end = excluded.quiet_end,
+                digest_channel = excluded.digest_channel
+            "#,
+            self.user_id,
+            self.timezone,
+            self.email,
+            self.quiet_start,
+            self.quiet_end,
+            self.digest_channel
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Retrieve the Monday of the latest week a user was sent a digest for, if any.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn retrieve_digest_week(
+        user_id: SqlId,
+        connection: &Connection,
+    ) -> Result<Option<NaiveDate>> {
+        let row = sqlx::query!(
+            r#"
+                SELECT digest_week
+                FROM reminder_settings
+                WHERE user_id = ( $1 )
+            "#,
+            user_id
+        )
+        .fetch_optional(connection.get_pool())
+        .await?;
+
+        Ok(row.and_then(|row| row.digest_week).map(|week| week.date()))
+    }
+
+    /// Save the Monday of the latest week a user was sent a digest for.
+    /// Fails with `NotFound` if the user has no settings, since only users with settings get digests.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn update_digest_week(
+        user_id: SqlId,
+        week: &NaiveDate,
+        connection: &Connection,
+    ) -> Result<()> {
+        let week = week.and_hms(0, 0, 0);
+        let updated = sqlx::query!(
+            r#"
+                UPDATE reminder_settings
+                SET digest_week = ( $1 )
+                WHERE user_id = ( $2 )
+            "#,
+            week,
+            user_id
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        if updated.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+}
+
+/// This is a struct representing a reminder which went off and waits for the app to fetch it.
+#[derive(Debug, Clone, FromRow, Serialize, PartialEq)]
+pub struct PendingReminder {
+    /// The pending reminder id.
+    id: SqlId,
+
+    /// The reminder which went off.
+    reminder_id: SqlId,
+
+    /// The habit to remind of.
+    habit_id: SqlId,
+
+    /// Time the reminder went off.
+    due_at: NaiveDateTime,
+}
+
+impl PendingReminder {
+    pub fn new(
+        id: SqlId,
+        reminder_id: SqlId,
+        habit_id: SqlId,
+        due_at: NaiveDateTime,
+    ) -> PendingReminder {
+        PendingReminder {
+            id,
+            reminder_id,
+            habit_id,
+            due_at,
+        }
+    }
+
+    pub fn get_id(&self) -> SqlId {
+        self.id
+    }
+
+    pub fn get_reminder_id(&self) -> SqlId {
+        self.reminder_id
+    }
+
+    pub fn get_habit_id(&self) -> SqlId {
+        self.habit_id
+    }
+
+    pub fn get_due_at(&self) -> &NaiveDateTime {
+        &self.due_at
+    }
+
+    /// Retrieve a pending reminder in the database by id.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn retrieve(id: SqlId, connection: &Connection) -> Result<PendingReminder> {
+        let pending = sqlx::query_as!(
+            PendingReminder,
+            r#"
+                SELECT
+                pending_reminder.id AS "id!", pending_reminder.reminder_id, reminder.habit_id,
+                pending_reminder.due_at
+                FROM pending_reminder
+                INNER JOIN reminder ON pending_reminder.reminder_id = reminder.id
+                WHERE pending_reminder.id = ( $1 )
+            "#,
+            id
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(pending)
+    }
+
+    /// Queue a reminder which went off. Fails with `AlreadyExists` if it is already queued.
+    #[instrument(level = "debug", skip(reminder, due_at, connection), fields(reminder_id = reminder.id))]
+    pub async fn insert(
+        reminder: &Reminder,
+        due_at: &NaiveDateTime,
+        connection: &Connection,
+    ) -> Result<PendingReminder> {
+        sqlx::query!(
+            r#"
+                INSERT INTO pending_reminder ( reminder_id, due_at )
+                VALUES ( $1, $2 )
+            "#,
+            reminder.id,
+            due_at
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        // A reminder goes off once at a time so find it again rather than relying on backend
+        // specific ways of getting the inserted id.
+        let row = sqlx::query!(
+            r#"
+                SELECT id AS "id!" FROM pending_reminder
+                WHERE
+                reminder_id = ( $1 )
+                AND
+                due_at = ( $2 )
+            "#,
+            reminder.id,
+            due_at
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(PendingReminder::new(
+            row.id,
+            reminder.id,
+            reminder.habit_id,
+            *due_at,
+        ))
+    }
+
+    /// Get the pending reminders of a user, oldest first.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_pending(
+        user_id: SqlId,
+        connection: &Connection,
+    ) -> Result<Vec<PendingReminder>> {
+        let pending = sqlx::query_as!(
+            PendingReminder,
+            r#"
+                SELECT
+                pending_reminder.id AS "id!", pending_reminder.reminder_id, reminder.habit_id,
+                pending_reminder.due_at
+                FROM pending_reminder
+                INNER JOIN reminder ON pending_reminder.reminder_id = reminder.id
+                INNER JOIN habit ON reminder.habit_id = habit.id
+                WHERE habit.user_id = ( $1 )
+                ORDER BY pending_reminder.due_at, pending_reminder.id
+            "#,
+            user_id
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(pending)
+    }
+
+    /// Remove a pending reminder once the app has shown it.
+    ///
+    /// This consumes self since it is invalid after deletion from the database.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn delete(self, connection: &Connection) -> Result<()> {
+        let deleted = sqlx::query!(
+            r#"
+                DELETE FROM pending_reminder
+                WHERE id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        if deleted.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::habit::Habit;
+    use crate::user::User;
+
+    #[tokio::test]
+    async fn reminders_and_pending() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let created_at = NaiveDateTime::from_timestamp(0, 0);
+        let habit = Habit::insert(
+            user.get_id(),
+            None,
+            "Read",
+            &created_at,
+            Some(86400),
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+
+        let nine = NaiveTime::from_hms(9, 0, 0);
+        let mut reminder = Reminder::insert(
+            habit.get_id(),
+            &nine,
+            true,
+            "poll",
+            &created_at,
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+        assert_eq!(reminder.get_time_of_day(), nine);
+        assert!(reminder.get_unless_done());
+        assert_eq!(reminder.get_last_due_at(), None);
+        assert_eq!(
+            serde_json::to_value(&reminder).expect("Can serialize.")["time_of_day"],
+            serde_json::json!("09:00")
+        );
+
+        let due_at = NaiveDateTime::from_timestamp(9 * 60 * 60, 0);
+        reminder.set_last_due_at(due_at);
+        reminder
+            .update_last_due_at(&connection)
+            .await
+            .expect("Can update.");
+        assert_eq!(
+            Reminder::get_reminders(user.get_id(), &connection)
+                .await
+                .expect("Can list."),
+            vec![reminder.clone()]
+        );
+
+        let pending = PendingReminder::insert(&reminder, &due_at, &connection)
+            .await
+            .expect("Can queue.");
+        assert_eq!(
+            PendingReminder::insert(&reminder, &due_at, &connection)
+                .await
+                .expect_err("Already queued."),
+            Error::AlreadyExists
+        );
+        assert_eq!(
+            PendingReminder::get_pending(user.get_id(), &connection)
+                .await
+                .expect("Can list."),
+            vec![pending.clone()]
+        );
+        assert_eq!(pending.get_habit_id(), habit.get_id());
+
+        let id = reminder.get_id();
+        reminder.delete(&connection).await.expect("Can delete.");
+        assert_eq!(
+            Reminder::retrieve(id, &connection)
+                .await
+                .expect_err("Reminder was deleted."),
+            Error::NotFound
+        );
+        assert!(PendingReminder::get_pending(user.get_id(), &connection)
+            .await
+            .expect("Can list.")
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn settings_default_until_updated() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+
+        let mut settings = ReminderSettings::retrieve(user.get_id(), &connection)
+            .await
+            .expect("Defaults.");
+        assert_eq!(settings, ReminderSettings::new(user.get_id()));
+        assert_eq!(settings.get_quiet_hours(), None);
+
+        let night = QuietHours::new(NaiveTime::from_hms(22, 0, 0), NaiveTime::from_hms(7, 0, 0));
+        settings.set_timezone("Australia/Sydney".to_string());
+        settings.set_email(Some("justin@example.com".to_string()));
+        settings.set_quiet_hours(Some(night));
+        settings.set_digest_channel(Some("email".to_string()));
+        settings.update(&connection).await.expect("Can save.");
+        // Saving again updates rather than inserts.
+        settings.update(&connection).await.expect("Can save.");
+
+        let saved = ReminderSettings::retrieve(user.get_id(), &connection)
+            .await
+            .expect("Saved.");
+        assert_eq!(saved, settings);
+        assert_eq!(saved.get_quiet_hours(), Some(night));
+        assert_eq!(
+            serde_json::to_value(&saved).expect("Can serialize.")["quiet_start"],
+            serde_json::json!("22:00")
+        );
+        assert_eq!(
+            ReminderSettings::get_all(&connection)
+                .await
+                .expect("Can list."),
+            vec![saved]
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! Aggregate statistics across all users.
+//!
+//! The counts are cheap queries meant to be polled, e.g. by the metrics endpoint. `usage` reads
+//! every instance and is meant for occasional reports such as `mindless-admin usage`.
+use crate::connection::Connection;
+use crate::error::Result;
+use crate::SqlId;
+use chrono::{Duration, NaiveDateTime};
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::instrument;
+
+/// How much a user uses the tracker.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Usage {
+    pub user_id: SqlId,
+    pub username: String,
+    pub tasks: i64,
+    pub instances: i64,
+
+    /// Total time of all instances. Overlapping instances are counted twice.
+    pub tracked_sec: i64,
+
+    /// The end of the latest instance.
+    pub last_active: Option<NaiveDateTime>,
+}
+
+/// Count the task timers which have been started but not stopped yet.
+#[instrument(level = "debug", skip(connection))]
+pub async fn count_active_timers(connection: &Connection) -> Result<i64> {
+    let result = sqlx::query!(
+        r#"
+            SELECT COUNT(*) AS "count!: i64" FROM timer
+        "#
+    )
+    .fetch_one(connection.get_pool())
+    .await?;
+
+    Ok(result.count)
+}
+
+/// Count the instances which started at or after `since`.
+#[instrument(level = "debug", skip(connection))]
+pub async fn count_instances_since(since: &NaiveDateTime, connection: &Connection) -> Result<i64> {
+    let result = sqlx::query!(
+        r#"
+            SELECT COUNT(*) AS "count!: i64" FROM instances
+            WHERE start >= ( $1 )
+        "#,
+        since
+    )
+    .fetch_one(connection.get_pool())
+    .await?;
+
+    Ok(result.count)
+}
+
+/// The usage of every user.
+///
+/// This loads every instance, don't poll it.
+#[instrument(level = "debug", skip(connection))]
+pub async fn usage(connection: &Connection) -> Result<Vec<Usage>> {
+    let users = sqlx::query!(
+        r#"
+            SELECT users.id AS "id!", users.username AS "username!", COUNT(tasks.id) AS "tasks!: i64"
+            FROM users
+            LEFT JOIN tasks ON tasks.user_id = users.id
+            GROUP BY users.id, users.username
+            ORDER BY users.id
+        "#
+    )
+    .fetch_all(connection.get_pool())
+    .await?;
+
+    // Durations are computed here since date arithmetic differs between the backends.
+    let instances = sqlx::query!(
+        r#"
+            SELECT tasks.user_id AS "user_id!", instances.start AS "start!", instances."end" AS "end!"
+            FROM instances
+            INNER JOIN tasks ON instances.task_id = tasks.id
+        "#
+    )
+    .fetch_all(connection.get_pool())
+    .await?;
+
+    let mut usage: Vec<Usage> = users
+        .into_iter()
+        .map(|user| Usage {
+            user_id: user.id,
+            username: user.username,
+            tasks: user.tasks,
+            instances: 0,
+            tracked_sec: 0,
+            last_active: None,
+        })
+        .collect();
+    let index: HashMap<SqlId, usize> = usage
+        .iter()
+        .enumerate()
+        .map(|(index, usage)| (usage.user_id, index))
+        .collect();
+
+    for instance in instances {
+        if let Some(&index) = index.get(&instance.user_id) {
+            let usage = &mut usage[index];
+            usage.instances += 1;
+            usage.tracked_sec += (instance.end - instance.start)
+                .max(Duration::zero())
+                .num_seconds();
+            usage.last_active = usage.last_active.max(Some(instance.end));
+        }
+    }
+
+    Ok(usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::Instance;
+    use crate::task::Task;
+    use crate::timer::Timer;
+    use crate::user::User;
+
+    #[tokio::test]
+    async fn count_instances() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+
+        let task = Task::insert(user.get_id(), "Exercise", &connection)
+            .await
+            .expect("Should successfully insert.");
+
+        for start in &[10, 100, 1000] {
+            Instance::insert(
+                task.get_id(),
+                &NaiveDateTime::from_timestamp(*start, 0),
+                &NaiveDateTime::from_timestamp(start + 5, 0),
+                &connection,
+            )
+            .await
+            .expect("Should successfully insert.");
+        }
+
+        let count = count_instances_since(&NaiveDateTime::from_timestamp(100, 0), &connection)
+            .await
+            .expect("Should count.");
+
+        assert_eq!(count, 2);
+    }
+
+    #[tokio::test]
+    async fn usage_per_user() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+
+        let active = User::insert("active", "name", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let idle = User::insert("idle", "name", &connection)
+            .await
+            .expect("Should successfully insert.");
+
+        for name in &["Exercise", "Read"] {
+            let task = Task::insert(active.get_id(), name, &connection)
+                .await
+                .expect("Should successfully insert.");
+            Instance::insert(
+                task.get_id(),
+                &NaiveDateTime::from_timestamp(0, 0),
+                &NaiveDateTime::from_timestamp(60, 0),
+                &connection,
+            )
+            .await
+            .expect("Should successfully insert.");
+        }
+
+        assert_eq!(
+            usage(&connection).await.expect("Should compute usage."),
+            vec![
+                Usage {
+                    user_id: active.get_id(),
+                    username: "active".to_string(),
+                    tasks: 2,
+                    instances: 2,
+                    tracked_sec: 120,
+                    last_active: Some(NaiveDateTime::from_timestamp(60, 0)),
+                },
+                Usage {
+                    user_id: idle.get_id(),
+                    username: "idle".to_string(),
+                    tasks: 0,
+                    instances: 0,
+                    tracked_sec: 0,
+                    last_active: None,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn count_timers() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+
+        assert_eq!(
+            count_active_timers(&connection)
+                .await
+                .expect("Should count."),
+            0
+        );
+
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let mut timers = Vec::new();
+        for name in &["Exercise", "Read"] {
+            let task = Task::insert(user.get_id(), name, &connection)
+                .await
+                .expect("Should successfully insert.");
+            timers.push(
+                Timer::start(
+                    task.get_id(),
+                    &NaiveDateTime::from_timestamp(0, 0),
+                    &connection,
+                )
+                .await
+                .expect("Can start."),
+            );
+        }
+        timers
+            .pop()
+            .expect("Started two timers.")
+            .stop(&NaiveDateTime::from_timestamp(10, 0), &connection)
+            .await
+            .expect("Can stop.");
+
+        assert_eq!(
+            count_active_timers(&connection)
+                .await
+                .expect("Should count."),
+            1
+        );
+    }
+}
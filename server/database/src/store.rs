@@ -0,0 +1,1513 @@
+//! Repository traits over the database.
+//!
+//! Code which only reads and writes users, tasks, instances and habits should depend on these traits
+//! rather than on `Connection` so it can run against the in memory store in tests.
+use crate::checkin::CheckinToken;
+use crate::completion::Completion;
+use crate::connection::Connection;
+use crate::error::Result;
+use crate::goal::Goal;
+use crate::habit::Habit;
+use crate::instance::Instance;
+use crate::reminder::{PendingReminder, Reminder, ReminderSettings};
+use crate::tag::Tag;
+use crate::task::Task;
+use crate::timer::Timer;
+use crate::user::User;
+use crate::webhook::{Delivery, Webhook};
+use crate::SqlId;
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use mindless_core::goal::Target;
+use mindless_core::path::HabitPath;
+use mindless_core::period::Period;
+use mindless_core::tag::TagName;
+
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    /// Insert a user. Fails with `AlreadyExists` if the username is taken.
+    async fn insert_user(&self, username: &str, name: &str) -> Result<User>;
+
+    /// Retrieve a user by id.
+    async fn retrieve_user(&self, id: SqlId) -> Result<User>;
+
+    /// Retrieve a user by username.
+    async fn get_user(&self, username: &str) -> Result<User>;
+
+    /// Save the username and name of a user.
+    async fn update_user(&self, user: &User) -> Result<()>;
+
+    /// Delete a user along with everything belonging to them.
+    async fn delete_user(&self, user: User) -> Result<()>;
+}
+
+#[async_trait]
+pub trait TaskStore: Send + Sync {
+    /// Insert a task. Fails with `AlreadyExists` if the user already has a task with this name.
+    async fn insert_task(&self, user_id: SqlId, name: &str) -> Result<Task>;
+
+    /// Retrieve a task by id.
+    async fn retrieve_task(&self, id: SqlId) -> Result<Task>;
+
+    /// Insert a task unless it already exists and set its id, returning whether it is new.
+    async fn try_insert_task(&self, task: &mut Task) -> Result<bool>;
+
+    /// Get all tasks of a user.
+    async fn get_tasks(&self, user: &User) -> Result<Vec<Task>>;
+
+    /// Rename a task. Fails with `AlreadyExists` if the user already has a task with this name.
+    async fn rename_task(&self, task: &mut Task, name: &str) -> Result<()>;
+
+    /// Delete a task along with its instances.
+    async fn delete_task(&self, task: Task) -> Result<()>;
+}
+
+#[async_trait]
+pub trait InstanceStore: Send + Sync {
+    /// Insert an instance. Fails with `AlreadyExists` if the task already has this period.
+    async fn insert_instance(
+        &self,
+        task_id: SqlId,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+    ) -> Result<Instance>;
+
+    /// Retrieve an instance by id.
+    async fn retrieve_instance(&self, id: SqlId) -> Result<Instance>;
+
+    /// Insert the instances which do not exist yet and return all of them with their ids.
+    async fn try_insert_instances(&self, instances: Vec<Instance>) -> Result<Vec<Instance>>;
+
+    /// Get all instances of a task.
+    async fn get_instances(&self, task_id: SqlId) -> Result<Vec<Instance>>;
+
+    /// Change the period of an instance. Fails with `AlreadyExists` if the task already has it.
+    async fn update_instance(
+        &self,
+        instance: &mut Instance,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+    ) -> Result<()>;
+
+    /// Delete an instance.
+    async fn delete_instance(&self, instance: Instance) -> Result<()>;
+}
+
+#[async_trait]
+pub trait HabitStore: Send + Sync {
+    /// Insert a habit. Fails with `AlreadyExists` if the parent already has a habit with this name.
+    async fn insert_habit(
+        &self,
+        user_id: SqlId,
+        parent_id: Option<SqlId>,
+        name: &str,
+        created_at: &NaiveDateTime,
+        repeat_period_sec: Option<i64>,
+    ) -> Result<Habit>;
+
+    /// Retrieve a habit by id.
+    async fn retrieve_habit(&self, id: SqlId) -> Result<Habit>;
+
+    /// Find a habit of a user by its path.
+    async fn get_habit(&self, user_id: SqlId, path: &HabitPath) -> Result<Habit>;
+
+    /// Get all habits of a user, parents before their children.
+    async fn get_habits(&self, user_id: SqlId) -> Result<Vec<Habit>>;
+
+    /// Mark a habit as done at `now` unless it was already done in the same period.
+    ///
+    /// Returns whether the habit was marked.
+    async fn mark_habit(&self, habit: &Habit, now: &NaiveDateTime) -> Result<bool>;
+
+    /// Undo marking a habit as done in the period containing `now`.
+    ///
+    /// Returns whether the habit had been marked.
+    async fn unmark_habit(&self, habit: &Habit, now: &NaiveDateTime) -> Result<bool>;
+
+    /// Get the completions of a habit within `period`, oldest first.
+    async fn get_completions(&self, habit_id: SqlId, period: &Period) -> Result<Vec<Completion>>;
+
+    /// Delete a habit along with the habits grouped under it.
+    async fn delete_habit(&self, habit: Habit) -> Result<()>;
+}
+
+#[async_trait]
+pub trait CheckinStore: Send + Sync {
+    /// Issue a check-in token for a habit.
+    async fn insert_checkin_token(
+        &self,
+        user_id: SqlId,
+        habit_id: SqlId,
+        created_at: &NaiveDateTime,
+    ) -> Result<CheckinToken>;
+
+    /// Retrieve a check-in token by id.
+    async fn retrieve_checkin_token(&self, id: SqlId) -> Result<CheckinToken>;
+
+    /// Get all check-in tokens of a user, including revoked ones.
+    async fn get_checkin_tokens(&self, user_id: SqlId) -> Result<Vec<CheckinToken>>;
+
+    /// Revoke a check-in token as of `at`. Revoking it again keeps the first time.
+    async fn revoke_checkin_token(
+        &self,
+        token: &mut CheckinToken,
+        at: &NaiveDateTime,
+    ) -> Result<()>;
+}
+
+#[async_trait]
+pub trait TimerStore: Send + Sync {
+    /// Start a timer for a task. Fails with `AlreadyExists` if one is already running.
+    async fn start_timer(&self, task_id: SqlId, started_at: &NaiveDateTime) -> Result<Timer>;
+
+    /// Retrieve the running timer of a task.
+    async fn retrieve_timer(&self, task_id: SqlId) -> Result<Timer>;
+
+    /// Get the running timers of a user.
+    async fn get_timers(&self, user_id: SqlId) -> Result<Vec<Timer>>;
+
+    /// Stop a timer and record the time as an instance of its task.
+    async fn stop_timer(&self, timer: Timer, ended_at: &NaiveDateTime) -> Result<Instance>;
+}
+
+#[async_trait]
+pub trait WebhookStore: Send + Sync {
+    /// Register a webhook for the events of a user. No events means every event.
+    async fn insert_webhook(
+        &self,
+        user_id: SqlId,
+        url: &str,
+        secret: &str,
+        events: &[String],
+        created_at: &NaiveDateTime,
+    ) -> Result<Webhook>;
+
+    /// Retrieve a webhook by id.
+    async fn retrieve_webhook(&self, id: SqlId) -> Result<Webhook>;
+
+    /// Get the webhooks of a user.
+    async fn get_webhooks(&self, user_id: SqlId) -> Result<Vec<Webhook>>;
+
+    /// Get the webhooks of every user.
+    async fn get_all_webhooks(&self) -> Result<Vec<Webhook>>;
+
+    /// Delete a webhook along with its deliveries.
+    async fn delete_webhook(&self, webhook: Webhook) -> Result<()>;
+
+    /// Add an attempt to the delivery log and set its id.
+    async fn insert_delivery(&self, delivery: &mut Delivery) -> Result<()>;
+
+    /// Get the delivery log of a webhook, oldest first.
+    async fn get_deliveries(&self, webhook_id: SqlId) -> Result<Vec<Delivery>>;
+
+    /// Delete the attempts made before a time from every delivery log, returning how many.
+    async fn delete_deliveries_before(&self, before: &NaiveDateTime) -> Result<u64>;
+}
+
+#[async_trait]
+pub trait ReminderStore: Send + Sync {
+    /// Add a reminder to a habit.
+    async fn insert_reminder(
+        &self,
+        habit_id: SqlId,
+        time_of_day: &NaiveTime,
+        unless_done: bool,
+        channel: &str,
+        created_at: &NaiveDateTime,
+    ) -> Result<Reminder>;
+
+    /// Retrieve a reminder by id.
+    async fn retrieve_reminder(&self, id: SqlId) -> Result<Reminder>;
+
+    /// Get the reminders of every habit of a user.
+    async fn get_reminders(&self, user_id: SqlId) -> Result<Vec<Reminder>>;
+
+    /// Get the reminders of every user.
+    async fn get_all_reminders(&self) -> Result<Vec<Reminder>>;
+
+    /// Save the latest time a reminder was due.
+    async fn update_reminder_last_due_at(&self, reminder: &Reminder) -> Result<()>;
+
+    /// Delete a reminder along with its pending deliveries.
+    async fn delete_reminder(&self, reminder: Reminder) -> Result<()>;
+
+    /// Retrieve the reminder settings of a user, the defaults if they haven't changed them.
+    async fn retrieve_reminder_settings(&self, user_id: SqlId) -> Result<ReminderSettings>;
+
+    /// Save the reminder settings of a user.
+    async fn update_reminder_settings(&self, settings: &ReminderSettings) -> Result<()>;
+
+    /// Get the reminder settings of every user who changed them.
+    async fn get_all_reminder_settings(&self) -> Result<Vec<ReminderSettings>>;
+
+    /// Retrieve the Monday of the latest week a user was sent a digest for, if any.
+    async fn retrieve_digest_week(&self, user_id: SqlId) -> Result<Option<NaiveDate>>;
+
+    /// Save the Monday of the latest week a user was sent a digest for.
+    /// Fails with `NotFound` if the user has no reminder settings.
+    async fn update_digest_week(&self, user_id: SqlId, week: &NaiveDate) -> Result<()>;
+
+    /// Queue a reminder which went off. Fails with `AlreadyExists` if it is already queued.
+    async fn insert_pending_reminder(
+        &self,
+        reminder: &Reminder,
+        due_at: &NaiveDateTime,
+    ) -> Result<PendingReminder>;
+
+    /// Retrieve a pending reminder by id.
+    async fn retrieve_pending_reminder(&self, id: SqlId) -> Result<PendingReminder>;
+
+    /// Get the pending reminders of a user, oldest first.
+    async fn get_pending_reminders(&self, user_id: SqlId) -> Result<Vec<PendingReminder>>;
+
+    /// Remove a pending reminder once the app has shown it.
+    async fn delete_pending_reminder(&self, pending: PendingReminder) -> Result<()>;
+}
+
+#[async_trait]
+pub trait GoalStore: Send + Sync {
+    /// Set a goal for a task or a habit of a user.
+    async fn insert_goal(
+        &self,
+        user_id: SqlId,
+        task_id: Option<SqlId>,
+        habit_id: Option<SqlId>,
+        target: &Target,
+        created_at: &NaiveDateTime,
+    ) -> Result<Goal>;
+
+    /// Retrieve a goal by id.
+    async fn retrieve_goal(&self, id: SqlId) -> Result<Goal>;
+
+    /// Get the goals of a user, oldest first.
+    async fn get_goals(&self, user_id: SqlId) -> Result<Vec<Goal>>;
+
+    /// Delete a goal.
+    async fn delete_goal(&self, goal: Goal) -> Result<()>;
+}
+
+#[async_trait]
+pub trait TagStore: Send + Sync {
+    /// Insert a tag. Fails with `AlreadyExists` if the user already has a tag with this name.
+    async fn insert_tag(&self, user_id: SqlId, name: &TagName) -> Result<Tag>;
+
+    /// Retrieve a tag by id.
+    async fn retrieve_tag(&self, id: SqlId) -> Result<Tag>;
+
+    /// Get the tags of a user by name.
+    async fn get_tags(&self, user_id: SqlId) -> Result<Vec<Tag>>;
+
+    /// Rename a tag. Fails with `AlreadyExists` if the user already has a tag with this name.
+    async fn rename_tag(&self, tag: &mut Tag, name: &TagName) -> Result<()>;
+
+    /// Delete a tag and remove it from everything it is attached to.
+    async fn delete_tag(&self, tag: Tag) -> Result<()>;
+
+    /// Attach a tag to a task. Returns whether it wasn't attached already.
+    async fn tag_task(&self, tag: &Tag, task_id: SqlId) -> Result<bool>;
+
+    /// Remove a tag from a task. Returns whether it was attached.
+    async fn untag_task(&self, tag: &Tag, task_id: SqlId) -> Result<bool>;
+
+    /// Attach a tag to a single instance. Returns whether it wasn't attached already.
+    async fn tag_instance(&self, tag: &Tag, instance_id: SqlId) -> Result<bool>;
+
+    /// Remove a tag from a single instance. Returns whether it was attached.
+    async fn untag_instance(&self, tag: &Tag, instance_id: SqlId) -> Result<bool>;
+
+    /// The tags on the tasks of a user, as task ids with their tags ordered by name.
+    async fn get_task_tags(&self, user_id: SqlId) -> Result<Vec<(SqlId, Tag)>>;
+
+    /// The tags on single instances of a user, as instance ids with their tags ordered by name.
+    async fn get_instance_tags(&self, user_id: SqlId) -> Result<Vec<(SqlId, Tag)>>;
+}
+
+/// Everything the api needs from a database.
+pub trait Store:
+    UserStore
+    + TaskStore
+    + InstanceStore
+    + HabitStore
+    + CheckinStore
+    + TimerStore
+    + WebhookStore
+    + ReminderStore
+    + GoalStore
+    + TagStore
+{
+}
+
+impl<T> Store for T where
+    T: UserStore
+        + TaskStore
+        + InstanceStore
+        + HabitStore
+        + CheckinStore
+        + TimerStore
+        + WebhookStore
+        + ReminderStore
+        + GoalStore
+        + TagStore
+{
+}
+
+#[async_trait]
+impl UserStore for Connection {
+    async fn insert_user(&self, username: &str, name: &str) -> Result<User> {
+        User::insert(username, name, self).await
+    }
+
+    async fn retrieve_user(&self, id: SqlId) -> Result<User> {
+        User::retrieve(id, self).await
+    }
+
+    async fn get_user(&self, username: &str) -> Result<User> {
+        User::get(username, self).await
+    }
+
+    async fn update_user(&self, user: &User) -> Result<()> {
+        user.clone().update(self).await
+    }
+
+    async fn delete_user(&self, user: User) -> Result<()> {
+        user.delete(self).await
+    }
+}
+
+#[async_trait]
+impl TaskStore for Connection {
+    async fn insert_task(&self, user_id: SqlId, name: &str) -> Result<Task> {
+        Task::insert(user_id, name, self).await
+    }
+
+    async fn retrieve_task(&self, id: SqlId) -> Result<Task> {
+        Task::retrieve(id, self).await
+    }
+
+    async fn try_insert_task(&self, task: &mut Task) -> Result<bool> {
+        task.try_insert(self).await
+    }
+
+    async fn get_tasks(&self, user: &User) -> Result<Vec<Task>> {
+        Task::get_tasks(user, self).await
+    }
+
+    async fn rename_task(&self, task: &mut Task, name: &str) -> Result<()> {
+        task.rename(name, self).await
+    }
+
+    async fn delete_task(&self, task: Task) -> Result<()> {
+        task.delete(self).await
+    }
+}
+
+#[async_trait]
+impl InstanceStore for Connection {
+    async fn insert_instance(
+        &self,
+        task_id: SqlId,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+    ) -> Result<Instance> {
+        Instance::insert(task_id, start, end, self).await
+    }
+
+    async fn retrieve_instance(&self, id: SqlId) -> Result<Instance> {
+        Instance::retrieve(id, self).await
+    }
+
+    async fn try_insert_instances(&self, instances: Vec<Instance>) -> Result<Vec<Instance>> {
+        Instance::try_insert_all(instances, self).await
+    }
+
+    async fn get_instances(&self, task_id: SqlId) -> Result<Vec<Instance>> {
+        Instance::get_instances(task_id, self).await
+    }
+
+    async fn update_instance(
+        &self,
+        instance: &mut Instance,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+    ) -> Result<()> {
+        instance.update(start, end, self).await
+    }
+
+    async fn delete_instance(&self, instance: Instance) -> Result<()> {
+        instance.delete(self).await
+    }
+}
+
+#[async_trait]
+impl HabitStore for Connection {
+    async fn insert_habit(
+        &self,
+        user_id: SqlId,
+        parent_id: Option<SqlId>,
+        name: &str,
+        created_at: &NaiveDateTime,
+        repeat_period_sec: Option<i64>,
+    ) -> Result<Habit> {
+        Habit::insert(
+            user_id,
+            parent_id,
+            name,
+            created_at,
+            repeat_period_sec,
+            self,
+        )
+        .await
+    }
+
+    async fn retrieve_habit(&self, id: SqlId) -> Result<Habit> {
+        Habit::retrieve(id, self).await
+    }
+
+    async fn get_habit(&self, user_id: SqlId, path: &HabitPath) -> Result<Habit> {
+        Habit::get_by_path(user_id, path, self).await
+    }
+
+    async fn get_habits(&self, user_id: SqlId) -> Result<Vec<Habit>> {
+        Habit::get_habits(user_id, self).await
+    }
+
+    async fn mark_habit(&self, habit: &Habit, now: &NaiveDateTime) -> Result<bool> {
+        habit.mark(now, self).await
+    }
+
+    async fn unmark_habit(&self, habit: &Habit, now: &NaiveDateTime) -> Result<bool> {
+        habit.unmark(now, self).await
+    }
+
+    async fn get_completions(&self, habit_id: SqlId, period: &Period) -> Result<Vec<Completion>> {
+        Completion::get_completions(habit_id, period, self).await
+    }
+
+    async fn delete_habit(&self, habit: Habit) -> Result<()> {
+        habit.delete(self).await
+    }
+}
+
+#[async_trait]
+impl CheckinStore for Connection {
+    async fn insert_checkin_token(
+        &self,
+        user_id: SqlId,
+        habit_id: SqlId,
+        created_at: &NaiveDateTime,
+    ) -> Result<CheckinToken> {
+        CheckinToken::insert(user_id, habit_id, created_at, self).await
+    }
+
+    async fn retrieve_checkin_token(&self, id: SqlId) -> Result<CheckinToken> {
+        CheckinToken::retrieve(id, self).await
+    }
+
+    async fn get_checkin_tokens(&self, user_id: SqlId) -> Result<Vec<CheckinToken>> {
+        CheckinToken::get_tokens(user_id, self).await
+    }
+
+    async fn revoke_checkin_token(
+        &self,
+        token: &mut CheckinToken,
+        at: &NaiveDateTime,
+    ) -> Result<()> {
+        token.revoke(at, self).await
+    }
+}
+
+#[async_trait]
+impl TimerStore for Connection {
+    async fn start_timer(&self, task_id: SqlId, started_at: &NaiveDateTime) -> Result<Timer> {
+        Timer::start(task_id, started_at, self).await
+    }
+
+    async fn retrieve_timer(&self, task_id: SqlId) -> Result<Timer> {
+        Timer::retrieve(task_id, self).await
+    }
+
+    async fn get_timers(&self, user_id: SqlId) -> Result<Vec<Timer>> {
+        Timer::get_timers(user_id, self).await
+    }
+
+    async fn stop_timer(&self, timer: Timer, ended_at: &NaiveDateTime) -> Result<Instance> {
+        timer.stop(ended_at, self).await
+    }
+}
+
+#[async_trait]
+impl WebhookStore for Connection {
+    async fn insert_webhook(
+        &self,
+        user_id: SqlId,
+        url: &str,
+        secret: &str,
+        events: &[String],
+        created_at: &NaiveDateTime,
+    ) -> Result<Webhook> {
+        Webhook::insert(user_id, url, secret, events, created_at, self).await
+    }
+
+    async fn retrieve_webhook(&self, id: SqlId) -> Result<Webhook> {
+        Webhook::retrieve(id, self).await
+    }
+
+    async fn get_webhooks(&self, user_id: SqlId) -> Result<Vec<Webhook>> {
+        Webhook::get_webhooks(user_id, self).await
+    }
+
+    async fn get_all_webhooks(&self) -> Result<Vec<Webhook>> {
+        Webhook::get_all(self).await
+    }
+
+    async fn delete_webhook(&self, webhook: Webhook) -> Result<()> {
+        webhook.delete(self).await
+    }
+
+    async fn insert_delivery(&self, delivery: &mut Delivery) -> Result<()> {
+        delivery.insert(self).await
+    }
+
+    async fn get_deliveries(&self, webhook_id: SqlId) -> Result<Vec<Delivery>> {
+        Delivery::get_deliveries(webhook_id, self).await
+    }
+
+    async fn delete_deliveries_before(&self, before: &NaiveDateTime) -> Result<u64> {
+        Delivery::delete_before(before, self).await
+    }
+}
+
+#[async_trait]
+impl ReminderStore for Connection {
+    async fn insert_reminder(
+        &self,
+        habit_id: SqlId,
+        time_of_day: &NaiveTime,
+        unless_done: bool,
+        channel: &str,
+        created_at: &NaiveDateTime,
+    ) -> Result<Reminder> {
+        Reminder::insert(
+            habit_id,
+            time_of_day,
+            unless_done,
+            channel,
+            created_at,
+            self,
+        )
+        .await
+    }
+
+    async fn retrieve_reminder(&self, id: SqlId) -> Result<Reminder> {
+        Reminder::retrieve(id, self).await
+    }
+
+    async fn get_reminders(&self, user_id: SqlId) -> Result<Vec<Reminder>> {
+        Reminder::get_reminders(user_id, self).await
+    }
+
+    async fn get_all_reminders(&self) -> Result<Vec<Reminder>> {
+        Reminder::get_all(self).await
+    }
+
+    async fn update_reminder_last_due_at(&self, reminder: &Reminder) -> Result<()> {
+        reminder.update_last_due_at(self).await
+    }
+
+    async fn delete_reminder(&self, reminder: Reminder) -> Result<()> {
+        reminder.delete(self).await
+    }
+
+    async fn retrieve_reminder_settings(&self, user_id: SqlId) -> Result<ReminderSettings> {
+        ReminderSettings::retrieve(user_id, self).await
+    }
+
+    async fn update_reminder_settings(&self, settings: &ReminderSettings) -> Result<()> {
+        settings.update(self).await
+    }
+
+    async fn get_all_reminder_settings(&self) -> Result<Vec<ReminderSettings>> {
+        ReminderSettings::get_all(self).await
+    }
+
+    async fn retrieve_digest_week(&self, user_id: SqlId) -> Result<Option<NaiveDate>> {
+        ReminderSettings::retrieve_digest_week(user_id, self).await
+    }
+
+    async fn update_digest_week(&self, user_id: SqlId, week: &NaiveDate) -> Result<()> {
+        ReminderSettings::update_digest_week(user_id, week, self).await
+    }
+
+    async fn insert_pending_reminder(
+        &self,
+        reminder: &Reminder,
+        due_at: &NaiveDateTime,
+    ) -> Result<PendingReminder> {
+        PendingReminder::insert(reminder, due_at, self).await
+    }
+
+    async fn retrieve_pending_reminder(&self, id: SqlId) -> Result<PendingReminder> {
+        PendingReminder::retrieve(id, self).await
+    }
+
+    async fn get_pending_reminders(&self, user_id: SqlId) -> Result<Vec<PendingReminder>> {
+        PendingReminder::get_pending(user_id, self).await
+    }
+
+    async fn delete_pending_reminder(&self, pending: PendingReminder) -> Result<()> {
+        pending.delete(self).await
+    }
+}
+
+#[async_trait]
+impl GoalStore for Connection {
+    async fn insert_goal(
+        &self,
+        user_id: SqlId,
+        task_id: Option<SqlId>,
+        habit_id: Option<SqlId>,
+        target: &Target,
+        created_at: &NaiveDateTime,
+    ) -> Result<Goal> {
+        Goal::insert(user_id, task_id, habit_id, target, created_at, self).await
+    }
+
+    async fn retrieve_goal(&self, id: SqlId) -> Result<Goal> {
+        Goal::retrieve(id, self).await
+    }
+
+    async fn get_goals(&self, user_id: SqlId) -> Result<Vec<Goal>> {
+        Goal::get_goals(user_id, self).await
+    }
+
+    async fn delete_goal(&self, goal: Goal) -> Result<()> {
+        goal.delete(self).await
+    }
+}
+
+#[async_trait]
+impl TagStore for Connection {
+    async fn insert_tag(&self, user_id: SqlId, name: &TagName) -> Result<Tag> {
+        Tag::insert(user_id, name, self).await
+    }
+
+    async fn retrieve_tag(&self, id: SqlId) -> Result<Tag> {
+        Tag::retrieve(id, self).await
+    }
+
+    async fn get_tags(&self, user_id: SqlId) -> Result<Vec<Tag>> {
+        Tag::get_tags(user_id, self).await
+    }
+
+    async fn rename_tag(&self, tag: &mut Tag, name: &TagName) -> Result<()> {
+        tag.rename(name, self).await
+    }
+
+    async fn delete_tag(&self, tag: Tag) -> Result<()> {
+        tag.delete(self).await
+    }
+
+    async fn tag_task(&self, tag: &Tag, task_id: SqlId) -> Result<bool> {
+        tag.tag_task(task_id, self).await
+    }
+
+    async fn untag_task(&self, tag: &Tag, task_id: SqlId) -> Result<bool> {
+        tag.untag_task(task_id, self).await
+    }
+
+    async fn tag_instance(&self, tag: &Tag, instance_id: SqlId) -> Result<bool> {
+        tag.tag_instance(instance_id, self).await
+    }
+
+    async fn untag_instance(&self, tag: &Tag, instance_id: SqlId) -> Result<bool> {
+        tag.untag_instance(instance_id, self).await
+    }
+
+    async fn get_task_tags(&self, user_id: SqlId) -> Result<Vec<(SqlId, Tag)>> {
+        Tag::get_task_tags(user_id, self).await
+    }
+
+    async fn get_instance_tags(&self, user_id: SqlId) -> Result<Vec<(SqlId, Tag)>> {
+        Tag::get_instance_tags(user_id, self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::memory::MemoryStore;
+    use mindless_core::goal::{Cadence, Comparison, Unit};
+
+    fn time(timestamp: i64) -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(timestamp, 0)
+    }
+
+    /// Behaviour every store must share.
+    async fn check_store(store: &dyn Store) {
+        let mut user = store
+            .insert_user("username", "name")
+            .await
+            .expect("Should successfully insert.");
+        assert_eq!(
+            store
+                .insert_user("username", "other")
+                .await
+                .expect_err("Username is taken."),
+            Error::AlreadyExists
+        );
+        assert_eq!(
+            store.get_user("username").await.expect("User exists."),
+            user
+        );
+
+        user.set_name("renamed".to_string());
+        store.update_user(&user).await.expect("Can update.");
+        assert_eq!(
+            store
+                .retrieve_user(user.get_id())
+                .await
+                .expect("User exists.")
+                .get_name(),
+            "renamed"
+        );
+
+        let task = store
+            .insert_task(user.get_id(), "Exercise")
+            .await
+            .expect("Should successfully insert.");
+        let mut again = Task::new(0, user.get_id(), "Exercise".to_string());
+        assert!(!store
+            .try_insert_task(&mut again)
+            .await
+            .expect("Existing tasks are ignored."));
+        assert_eq!(again, task);
+        assert_eq!(
+            store.get_tasks(&user).await.expect("Can list."),
+            vec![task.clone()]
+        );
+
+        let mut other = store
+            .insert_task(user.get_id(), "Reading")
+            .await
+            .expect("Should successfully insert.");
+        assert_eq!(
+            store
+                .rename_task(&mut other, "Exercise")
+                .await
+                .expect_err("Names are unique per user."),
+            Error::AlreadyExists
+        );
+        store
+            .rename_task(&mut other, "Books")
+            .await
+            .expect("Can rename.");
+        assert_eq!(other.get_name(), "Books");
+        assert_eq!(
+            store
+                .retrieve_task(other.get_id())
+                .await
+                .expect("Task exists."),
+            other
+        );
+        store.delete_task(other).await.expect("Can delete.");
+
+        let instance = store
+            .insert_instance(task.get_id(), &time(1), &time(2))
+            .await
+            .expect("Should successfully insert.");
+        let instances = store
+            .try_insert_instances(vec![
+                Instance::new(0, task.get_id(), time(1), time(2)),
+                Instance::new(0, task.get_id(), time(3), time(4)),
+            ])
+            .await
+            .expect("Existing instances are ignored.");
+        assert_eq!(instances[0], instance);
+        assert_eq!(
+            store
+                .get_instances(task.get_id())
+                .await
+                .expect("Can list.")
+                .len(),
+            2
+        );
+
+        let mut moved = instances[1].clone();
+        assert_eq!(
+            store
+                .update_instance(&mut moved, &time(1), &time(2))
+                .await
+                .expect_err("Periods are unique per task."),
+            Error::AlreadyExists
+        );
+        store
+            .update_instance(&mut moved, &time(3), &time(5))
+            .await
+            .expect("Can update.");
+        assert_eq!(
+            store
+                .retrieve_instance(moved.get_id())
+                .await
+                .expect("Instance exists."),
+            moved
+        );
+
+        store
+            .delete_instance(instance.clone())
+            .await
+            .expect("Can delete.");
+        assert_eq!(
+            store
+                .retrieve_instance(instance.get_id())
+                .await
+                .expect_err("Instance was deleted."),
+            Error::NotFound
+        );
+
+        let health = store
+            .insert_habit(user.get_id(), None, "Health", &time(0), None)
+            .await
+            .expect("Should successfully insert.");
+        let shower = store
+            .insert_habit(
+                user.get_id(),
+                Some(health.get_id()),
+                "Cold Shower",
+                &time(0),
+                Some(10),
+            )
+            .await
+            .expect("Should successfully insert.");
+        assert_eq!(
+            store
+                .insert_habit(user.get_id(), None, "Health", &time(0), None)
+                .await
+                .expect_err("Sibling already exists."),
+            Error::AlreadyExists
+        );
+        let path = "Health/Cold Shower".parse().expect("Is valid.");
+        assert_eq!(
+            store
+                .get_habit(user.get_id(), &path)
+                .await
+                .expect("Habit exists."),
+            shower
+        );
+        assert_eq!(
+            store.get_habits(user.get_id()).await.expect("Can list."),
+            vec![health, shower.clone()]
+        );
+
+        assert_eq!(
+            store
+                .retrieve_habit(shower.get_id())
+                .await
+                .expect("Habit exists."),
+            shower
+        );
+
+        let mut token = store
+            .insert_checkin_token(user.get_id(), shower.get_id(), &time(0))
+            .await
+            .expect("Should successfully insert.");
+        store
+            .revoke_checkin_token(&mut token, &time(3))
+            .await
+            .expect("Can revoke.");
+        store
+            .revoke_checkin_token(&mut token, &time(4))
+            .await
+            .expect("Can revoke again.");
+        assert_eq!(token.get_revoked_at(), Some(&time(3)));
+        assert_eq!(
+            store
+                .retrieve_checkin_token(token.get_id())
+                .await
+                .expect("Token exists."),
+            token
+        );
+        assert_eq!(
+            store
+                .get_checkin_tokens(user.get_id())
+                .await
+                .expect("Can list."),
+            vec![token.clone()]
+        );
+
+        assert!(store
+            .mark_habit(&shower, &time(1))
+            .await
+            .expect("Can mark."));
+        assert!(!store
+            .mark_habit(&shower, &time(9))
+            .await
+            .expect("Can mark."));
+        assert!(store
+            .mark_habit(&shower, &time(10))
+            .await
+            .expect("Can mark."));
+        assert!(store
+            .unmark_habit(&shower, &time(5))
+            .await
+            .expect("Can unmark."));
+        assert!(!store
+            .unmark_habit(&shower, &time(5))
+            .await
+            .expect("Can unmark."));
+        let completions = store
+            .get_completions(shower.get_id(), &Period::new(time(0), time(20)))
+            .await
+            .expect("Can list.");
+        assert_eq!(completions.len(), 1);
+        assert_eq!(completions[0].get_created_at(), &time(10));
+
+        let chores = store
+            .insert_habit(user.get_id(), None, "Chores", &time(0), None)
+            .await
+            .expect("Should successfully insert.");
+        let dishes = store
+            .insert_habit(
+                user.get_id(),
+                Some(chores.get_id()),
+                "Dishes",
+                &time(0),
+                Some(10),
+            )
+            .await
+            .expect("Should successfully insert.");
+        assert!(store
+            .mark_habit(&dishes, &time(1))
+            .await
+            .expect("Can mark."));
+        let dishes_goal = store
+            .insert_goal(
+                user.get_id(),
+                None,
+                Some(dishes.get_id()),
+                &Target {
+                    comparison: Comparison::AtLeast,
+                    amount: 3,
+                    unit: Unit::Times,
+                    cadence: Cadence::Week,
+                },
+                &time(0),
+            )
+            .await
+            .expect("Should successfully insert.");
+        store
+            .delete_habit(chores.clone())
+            .await
+            .expect("Can delete.");
+        assert_eq!(
+            store
+                .retrieve_habit(dishes.get_id())
+                .await
+                .expect_err("Children are deleted with their parent."),
+            Error::NotFound
+        );
+        assert!(store
+            .get_completions(dishes.get_id(), &Period::new(time(0), time(20)))
+            .await
+            .expect("Can list.")
+            .is_empty());
+        assert_eq!(
+            store
+                .delete_habit(chores)
+                .await
+                .expect_err("Habit was deleted."),
+            Error::NotFound
+        );
+        assert_eq!(
+            store
+                .retrieve_goal(dishes_goal.get_id())
+                .await
+                .expect_err("Goals are deleted with their habit."),
+            Error::NotFound
+        );
+
+        let target = Target {
+            comparison: Comparison::AtMost,
+            amount: 3600,
+            unit: Unit::Seconds,
+            cadence: Cadence::Day,
+        };
+        let goal = store
+            .insert_goal(user.get_id(), Some(task.get_id()), None, &target, &time(0))
+            .await
+            .expect("Should successfully insert.");
+        assert_eq!(goal.get_target(), Ok(target));
+        assert_eq!(
+            store
+                .retrieve_goal(goal.get_id())
+                .await
+                .expect("Goal exists."),
+            goal
+        );
+        let social = store
+            .insert_task(user.get_id(), "Social")
+            .await
+            .expect("Should successfully insert.");
+        let social_goal = store
+            .insert_goal(
+                user.get_id(),
+                Some(social.get_id()),
+                None,
+                &target,
+                &time(0),
+            )
+            .await
+            .expect("Should successfully insert.");
+        assert_eq!(
+            store.get_goals(user.get_id()).await.expect("Can list."),
+            vec![goal.clone(), social_goal]
+        );
+        store.delete_task(social).await.expect("Can delete.");
+        assert_eq!(
+            store.get_goals(user.get_id()).await.expect("Can list."),
+            vec![goal.clone()]
+        );
+        let removed = store
+            .insert_goal(user.get_id(), Some(task.get_id()), None, &target, &time(0))
+            .await
+            .expect("Should successfully insert.");
+        store
+            .delete_goal(removed.clone())
+            .await
+            .expect("Can delete.");
+        assert_eq!(
+            store
+                .delete_goal(removed)
+                .await
+                .expect_err("Goal was deleted."),
+            Error::NotFound
+        );
+
+        let name = |name: &str| -> TagName { name.parse().expect("Is a valid tag.") };
+        let billable = store
+            .insert_tag(user.get_id(), &name("billable"))
+            .await
+            .expect("Should successfully insert.");
+        assert_eq!(
+            store
+                .insert_tag(user.get_id(), &name("Billable"))
+                .await
+                .expect_err("Names are unique per user."),
+            Error::AlreadyExists
+        );
+        let mut outdoor = store
+            .insert_tag(user.get_id(), &name("outside"))
+            .await
+            .expect("Should successfully insert.");
+        assert_eq!(
+            store
+                .rename_tag(&mut outdoor, &name("billable"))
+                .await
+                .expect_err("Names are unique per user."),
+            Error::AlreadyExists
+        );
+        store
+            .rename_tag(&mut outdoor, &name("outdoor"))
+            .await
+            .expect("Can rename.");
+        assert_eq!(
+            store
+                .retrieve_tag(outdoor.get_id())
+                .await
+                .expect("Tag exists."),
+            outdoor
+        );
+        assert_eq!(
+            store.get_tags(user.get_id()).await.expect("Can list."),
+            vec![billable.clone(), outdoor.clone()]
+        );
+
+        assert!(store
+            .tag_task(&billable, task.get_id())
+            .await
+            .expect("Can tag."));
+        assert!(!store
+            .tag_task(&billable, task.get_id())
+            .await
+            .expect("Can tag."));
+        assert!(store
+            .tag_instance(&outdoor, moved.get_id())
+            .await
+            .expect("Can tag."));
+        assert_eq!(
+            store.get_task_tags(user.get_id()).await.expect("Can list."),
+            vec![(task.get_id(), billable.clone())]
+        );
+        assert_eq!(
+            store
+                .get_instance_tags(user.get_id())
+                .await
+                .expect("Can list."),
+            vec![(moved.get_id(), outdoor.clone())]
+        );
+        assert!(store
+            .untag_instance(&outdoor, moved.get_id())
+            .await
+            .expect("Can untag."));
+        assert!(!store
+            .untag_instance(&outdoor, moved.get_id())
+            .await
+            .expect("Can untag."));
+
+        let errands = store
+            .insert_task(user.get_id(), "Errands")
+            .await
+            .expect("Should successfully insert.");
+        let errand = store
+            .insert_instance(errands.get_id(), &time(1), &time(2))
+            .await
+            .expect("Should successfully insert.");
+        store
+            .tag_task(&outdoor, errands.get_id())
+            .await
+            .expect("Can tag.");
+        store
+            .tag_instance(&billable, errand.get_id())
+            .await
+            .expect("Can tag.");
+        store.delete_task(errands).await.expect("Can delete.");
+        assert_eq!(
+            store.get_task_tags(user.get_id()).await.expect("Can list."),
+            vec![(task.get_id(), billable.clone())]
+        );
+        assert!(store
+            .get_instance_tags(user.get_id())
+            .await
+            .expect("Can list.")
+            .is_empty());
+
+        store
+            .tag_instance(&outdoor, moved.get_id())
+            .await
+            .expect("Can tag.");
+        store
+            .delete_tag(outdoor.clone())
+            .await
+            .expect("Can delete.");
+        assert_eq!(
+            store
+                .delete_tag(outdoor)
+                .await
+                .expect_err("Tag was deleted."),
+            Error::NotFound
+        );
+        assert!(store
+            .get_instance_tags(user.get_id())
+            .await
+            .expect("Can list.")
+            .is_empty());
+
+        let timer = store
+            .start_timer(task.get_id(), &time(5))
+            .await
+            .expect("Can start.");
+        assert_eq!(
+            store
+                .start_timer(task.get_id(), &time(6))
+                .await
+                .expect_err("Timer is already running."),
+            Error::AlreadyExists
+        );
+        assert_eq!(
+            store.get_timers(user.get_id()).await.expect("Can list."),
+            vec![timer.clone()]
+        );
+        let timed = store.stop_timer(timer, &time(8)).await.expect("Can stop.");
+        assert_eq!(timed.get_period(), Period::new(time(5), time(8)));
+        assert_eq!(
+            store
+                .retrieve_timer(task.get_id())
+                .await
+                .expect_err("Timer was stopped."),
+            Error::NotFound
+        );
+        store
+            .start_timer(task.get_id(), &time(9))
+            .await
+            .expect("Can start again.");
+
+        let webhook = store
+            .insert_webhook(
+                user.get_id(),
+                "http://localhost/hook",
+                "secret",
+                &["TimerStarted".to_string()],
+                &time(0),
+            )
+            .await
+            .expect("Should successfully insert.");
+        assert_eq!(
+            store
+                .retrieve_webhook(webhook.get_id())
+                .await
+                .expect("Webhook exists."),
+            webhook
+        );
+        assert_eq!(
+            store.get_webhooks(user.get_id()).await.expect("Can list."),
+            vec![webhook.clone()]
+        );
+        assert_eq!(
+            store.get_all_webhooks().await.expect("Can list."),
+            vec![webhook.clone()]
+        );
+        let mut delivery = Delivery::new(
+            0,
+            webhook.get_id(),
+            "TimerStarted".to_string(),
+            "{}".to_string(),
+            1,
+            time(1),
+        );
+        delivery.set_status_code(200);
+        store
+            .insert_delivery(&mut delivery)
+            .await
+            .expect("Can log.");
+        assert_eq!(
+            store
+                .get_deliveries(webhook.get_id())
+                .await
+                .expect("Can list."),
+            vec![delivery]
+        );
+        assert_eq!(
+            store
+                .delete_deliveries_before(&time(1))
+                .await
+                .expect("Can prune."),
+            0
+        );
+        assert_eq!(
+            store
+                .delete_deliveries_before(&time(2))
+                .await
+                .expect("Can prune."),
+            1
+        );
+        assert!(store
+            .get_deliveries(webhook.get_id())
+            .await
+            .expect("Can list.")
+            .is_empty());
+
+        let nine = NaiveTime::from_hms(9, 0, 0);
+        let mut reminder = store
+            .insert_reminder(shower.get_id(), &nine, true, "poll", &time(0))
+            .await
+            .expect("Should successfully insert.");
+        assert_eq!(reminder.get_time_of_day(), nine);
+        reminder.set_last_due_at(time(4));
+        store
+            .update_reminder_last_due_at(&reminder)
+            .await
+            .expect("Can update.");
+        assert_eq!(
+            store
+                .retrieve_reminder(reminder.get_id())
+                .await
+                .expect("Reminder exists."),
+            reminder
+        );
+        assert_eq!(
+            store.get_reminders(user.get_id()).await.expect("Can list."),
+            vec![reminder.clone()]
+        );
+        assert_eq!(
+            store.get_all_reminders().await.expect("Can list."),
+            vec![reminder.clone()]
+        );
+
+        let pending = store
+            .insert_pending_reminder(&reminder, &time(4))
+            .await
+            .expect("Can queue.");
+        assert_eq!(
+            store
+                .insert_pending_reminder(&reminder, &time(4))
+                .await
+                .expect_err("Already queued."),
+            Error::AlreadyExists
+        );
+        assert_eq!(pending.get_habit_id(), shower.get_id());
+        assert_eq!(
+            store
+                .get_pending_reminders(user.get_id())
+                .await
+                .expect("Can list."),
+            vec![pending.clone()]
+        );
+        store
+            .delete_pending_reminder(pending.clone())
+            .await
+            .expect("Can acknowledge.");
+        assert_eq!(
+            store
+                .retrieve_pending_reminder(pending.get_id())
+                .await
+                .expect_err("Pending reminder was acknowledged."),
+            Error::NotFound
+        );
+        let pending = store
+            .insert_pending_reminder(&reminder, &time(5))
+            .await
+            .expect("Can queue.");
+
+        let mut settings = store
+            .retrieve_reminder_settings(user.get_id())
+            .await
+            .expect("Defaults.");
+        assert_eq!(settings, ReminderSettings::new(user.get_id()));
+        let week = NaiveDate::from_ymd(2020, 10, 12);
+        assert_eq!(
+            store
+                .update_digest_week(user.get_id(), &week)
+                .await
+                .expect_err("Only users with settings get digests."),
+            Error::NotFound
+        );
+        settings.set_email(Some("name@example.com".to_string()));
+        store
+            .update_reminder_settings(&settings)
+            .await
+            .expect("Can save.");
+        assert_eq!(
+            store
+                .retrieve_digest_week(user.get_id())
+                .await
+                .expect("Can retrieve."),
+            None
+        );
+        store
+            .update_digest_week(user.get_id(), &week)
+            .await
+            .expect("Can save.");
+        // Saving the settings again keeps the week.
+        store
+            .update_reminder_settings(&settings)
+            .await
+            .expect("Can save.");
+        assert_eq!(
+            store
+                .retrieve_digest_week(user.get_id())
+                .await
+                .expect("Saved."),
+            Some(week)
+        );
+        assert_eq!(
+            store
+                .retrieve_reminder_settings(user.get_id())
+                .await
+                .expect("Saved."),
+            settings
+        );
+        assert_eq!(
+            store.get_all_reminder_settings().await.expect("Can list."),
+            vec![settings]
+        );
+
+        let id = user.get_id();
+        store.delete_user(user).await.expect("Can delete.");
+        assert_eq!(
+            store
+                .retrieve_reminder(reminder.get_id())
+                .await
+                .expect_err("Reminder was deleted with the user."),
+            Error::NotFound
+        );
+        assert_eq!(
+            store
+                .retrieve_pending_reminder(pending.get_id())
+                .await
+                .expect_err("Pending reminder was deleted with the user."),
+            Error::NotFound
+        );
+        assert_eq!(
+            store
+                .retrieve_reminder_settings(id)
+                .await
+                .expect("Defaults."),
+            ReminderSettings::new(id)
+        );
+        assert_eq!(
+            store.retrieve_digest_week(id).await.expect("Can retrieve."),
+            None
+        );
+        assert_eq!(
+            store
+                .retrieve_timer(task.get_id())
+                .await
+                .expect_err("Timer was deleted with the user."),
+            Error::NotFound
+        );
+        assert_eq!(
+            store
+                .retrieve_webhook(webhook.get_id())
+                .await
+                .expect_err("Webhook was deleted with the user."),
+            Error::NotFound
+        );
+        assert_eq!(
+            store
+                .retrieve_task(task.get_id())
+                .await
+                .expect_err("Task was deleted with the user."),
+            Error::NotFound
+        );
+        assert_eq!(
+            store
+                .get_habit(id, &path)
+                .await
+                .expect_err("Habit was deleted with the user."),
+            Error::NotFound
+        );
+        assert_eq!(
+            store
+                .retrieve_checkin_token(token.get_id())
+                .await
+                .expect_err("Token was deleted with the user."),
+            Error::NotFound
+        );
+        assert_eq!(
+            store
+                .retrieve_goal(goal.get_id())
+                .await
+                .expect_err("Goal was deleted with the user."),
+            Error::NotFound
+        );
+        assert_eq!(
+            store
+                .retrieve_tag(billable.get_id())
+                .await
+                .expect_err("Tag was deleted with the user."),
+            Error::NotFound
+        );
+        assert!(store.get_task_tags(id).await.expect("Can list.").is_empty());
+        assert_eq!(
+            store
+                .retrieve_user(id)
+                .await
+                .expect_err("User was deleted."),
+            Error::NotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn connection_store() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+
+        check_store(&connection).await;
+    }
+
+    #[tokio::test]
+    async fn memory_store() {
+        check_store(&MemoryStore::default()).await;
+    }
+}
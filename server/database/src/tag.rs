@@ -0,0 +1,519 @@
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::SqlId;
+use mindless_core::tag::TagName;
+use serde::Serialize;
+use sqlx::Done;
+use sqlx::FromRow;
+use std::cmp::PartialEq;
+use tracing::instrument;
+
+/// This is a struct representing a tag of a user, e.g. `billable`.
+///
+/// Tags are attached to tasks, which tags all their instances, or to single instances.
+#[derive(Debug, Clone, FromRow, Serialize, PartialEq)]
+pub struct Tag {
+    /// The tag id.
+    id: SqlId,
+
+    /// The user the tag belongs to.
+    user_id: SqlId,
+
+    /// Lowercase, without whitespace or commas.
+    name: String,
+}
+
+impl Tag {
+    pub fn new(id: SqlId, user_id: SqlId, name: &TagName) -> Tag {
+        Tag {
+            id,
+            user_id,
+            name: name.to_string(),
+        }
+    }
+
+    pub fn get_id(&self) -> SqlId {
+        self.id
+    }
+
+    pub fn get_user_id(&self) -> SqlId {
+        self.user_id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Retrieve a tag in the database by id.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn retrieve(id: SqlId, connection: &Connection) -> Result<Tag> {
+        let tag = sqlx::query_as!(
+            Tag,
+            r#"
+                SELECT id AS "id!", user_id, name FROM tag
+                WHERE id = ( $1 )
+            "#,
+            id
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(tag)
+    }
+
+    /// Insert a tag for a user.
+    ///
+    /// Fails with `AlreadyExists` if the user already has a tag with this name.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn insert(user_id: SqlId, name: &TagName, connection: &Connection) -> Result<Tag> {
+        let name = name.as_str();
+        sqlx::query!(
+            r#"
+                INSERT INTO tag ( user_id, name )
+                VALUES ( $1, $2 )
+            "#,
+            user_id,
+            name
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        // The name is unique per user so find it again rather than relying on backend specific
+        // ways of getting the inserted id.
+        let tag = sqlx::query_as!(
+            Tag,
+            r#"
+                SELECT id AS "id!", user_id, name FROM tag
+                WHERE
+                user_id = ( $1 )
+                AND
+                name = ( $2 )
+            "#,
+            user_id,
+            name
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(tag)
+    }
+
+    /// Get the tags of a user by name.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_tags(user_id: SqlId, connection: &Connection) -> Result<Vec<Tag>> {
+        let tags = sqlx::query_as!(
+            Tag,
+            r#"
+                SELECT id AS "id!", user_id, name FROM tag
+                WHERE user_id = ( $1 )
+                ORDER BY name
+            "#,
+            user_id
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(tags)
+    }
+
+    /// Rename a tag, which renames it on everything it is attached to.
+    ///
+    /// Fails with `AlreadyExists` if the user already has a tag with this name.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn rename(&mut self, name: &TagName, connection: &Connection) -> Result<()> {
+        let name = name.as_str();
+        let updated = sqlx::query!(
+            r#"
+                UPDATE tag
+                SET name = ( $1 )
+                WHERE
+                id = ( $2 )
+                AND
+                user_id = ( $3 )
+            "#,
+            name,
+            self.id,
+            self.user_id
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        if updated.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        self.name = name.to_string();
+
+        Ok(())
+    }
+
+    /// Delete this tag and remove it from everything it is attached to.
+    ///
+    /// This consumes self since it is invalid after deletion from the database.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn delete(self, connection: &Connection) -> Result<()> {
+        // Rolled back on drop if anything fails.
+        let mut transaction = connection.get_pool().begin().await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM task_tag
+                WHERE tag_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM instance_tag
+                WHERE tag_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        let deleted_row_count = sqlx::query!(
+            r#"
+                DELETE FROM tag
+                WHERE
+                id = ( $1 )
+                AND
+                user_id = ( $2 )
+            "#,
+            self.id,
+            self.user_id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        if deleted_row_count.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    /// Attach this tag to a task unless it already is.
+    ///
+    /// Returns whether the tag was attached.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn tag_task(&self, task_id: SqlId, connection: &Connection) -> Result<bool> {
+        let inserted = sqlx::query!(
+            r#"
+                INSERT INTO task_tag ( task_id, tag_id )
+                SELECT $1, $2
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM task_tag
+                    WHERE
+                    task_id = ( $1 )
+                    AND
+                    tag_id = ( $2 )
+                )
+            "#,
+            task_id,
+            self.id
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        Ok(inserted.rows_affected() > 0)
+    }
+
+    /// Remove this tag from a task.
+    ///
+    /// Returns whether the task had the tag.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn untag_task(&self, task_id: SqlId, connection: &Connection) -> Result<bool> {
+        let deleted = sqlx::query!(
+            r#"
+                DELETE FROM task_tag
+                WHERE
+                task_id = ( $1 )
+                AND
+                tag_id = ( $2 )
+            "#,
+            task_id,
+            self.id
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    /// Attach this tag to a single instance unless it already is.
+    ///
+    /// Returns whether the tag was attached.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn tag_instance(&self, instance_id: SqlId, connection: &Connection) -> Result<bool> {
+        let inserted = sqlx::query!(
+            r#"
+                INSERT INTO instance_tag ( instance_id, tag_id )
+                SELECT $1, $2
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM instance_tag
+                    WHERE
+                    instance_id = ( $1 )
+                    AND
+                    tag_id = ( $2 )
+                )
+            "#,
+            instance_id,
+            self.id
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        Ok(inserted.rows_affected() > 0)
+    }
+
+    /// Remove this tag from a single instance. Tags of the instance's task are not affected.
+    ///
+    /// Returns whether the instance had the tag.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn untag_instance(
+        &self,
+        instance_id: SqlId,
+        connection: &Connection,
+    ) -> Result<bool> {
+        let deleted = sqlx::query!(
+            r#"
+                DELETE FROM instance_tag
+                WHERE
+                instance_id = ( $1 )
+                AND
+                tag_id = ( $2 )
+            "#,
+            instance_id,
+            self.id
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    /// The tags attached to the tasks of a user, as task ids with their tags ordered by name.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_task_tags(
+        user_id: SqlId,
+        connection: &Connection,
+    ) -> Result<Vec<(SqlId, Tag)>> {
+        let rows = sqlx::query!(
+            r#"
+                SELECT
+                task_tag.task_id AS "task_id!", tag.id AS "id!", tag.user_id AS "user_id!",
+                tag.name AS "name!"
+                FROM task_tag
+                INNER JOIN tag ON task_tag.tag_id = tag.id
+                WHERE tag.user_id = ( $1 )
+                ORDER BY task_tag.task_id, tag.name
+            "#,
+            user_id
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                (
+                    row.task_id,
+                    Tag {
+                        id: row.id,
+                        user_id: row.user_id,
+                        name: row.name,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// The tags attached to single instances of a user, as instance ids with their tags ordered
+    /// by name. Tags of the instances' tasks are not included.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_instance_tags(
+        user_id: SqlId,
+        connection: &Connection,
+    ) -> Result<Vec<(SqlId, Tag)>> {
+        let rows = sqlx::query!(
+            r#"
+                SELECT
+                instance_tag.instance_id AS "instance_id!", tag.id AS "id!",
+                tag.user_id AS "user_id!", tag.name AS "name!"
+                FROM instance_tag
+                INNER JOIN tag ON instance_tag.tag_id = tag.id
+                WHERE tag.user_id = ( $1 )
+                ORDER BY instance_tag.instance_id, tag.name
+            "#,
+            user_id
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                (
+                    row.instance_id,
+                    Tag {
+                        id: row.id,
+                        user_id: row.user_id,
+                        name: row.name,
+                    },
+                )
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::Instance;
+    use crate::task::Task;
+    use crate::user::User;
+    use chrono::NaiveDateTime;
+
+    fn name(name: &str) -> TagName {
+        name.parse().expect("Is a valid tag.")
+    }
+
+    #[tokio::test]
+    async fn insert_rename_and_delete() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+
+        let billable = Tag::insert(user.get_id(), &name("billable"), &connection)
+            .await
+            .expect("Should successfully insert.");
+        assert_eq!(
+            billable,
+            Tag::new(billable.get_id(), user.get_id(), &name("billable"))
+        );
+        assert_eq!(
+            Tag::insert(user.get_id(), &name("billable"), &connection)
+                .await
+                .expect_err("Should have failed due to duplication."),
+            Error::AlreadyExists
+        );
+
+        let mut outdoor = Tag::insert(user.get_id(), &name("outside"), &connection)
+            .await
+            .expect("Should successfully insert.");
+        assert_eq!(
+            outdoor
+                .rename(&name("billable"), &connection)
+                .await
+                .expect_err("Name is taken."),
+            Error::AlreadyExists
+        );
+        outdoor
+            .rename(&name("outdoor"), &connection)
+            .await
+            .expect("Can rename.");
+        assert_eq!(
+            Tag::retrieve(outdoor.get_id(), &connection)
+                .await
+                .expect("Tag exists."),
+            outdoor
+        );
+        assert_eq!(
+            Tag::get_tags(user.get_id(), &connection)
+                .await
+                .expect("Can list."),
+            vec![billable.clone(), outdoor.clone()]
+        );
+
+        let id = outdoor.get_id();
+        outdoor
+            .clone()
+            .delete(&connection)
+            .await
+            .expect("Can delete.");
+        assert_eq!(
+            outdoor
+                .delete(&connection)
+                .await
+                .expect_err("Already deleted."),
+            Error::NotFound
+        );
+        assert_eq!(
+            Tag::retrieve(id, &connection)
+                .await
+                .expect_err("Tag is deleted."),
+            Error::NotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn tag_tasks_and_instances() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let task = Task::insert(user.get_id(), "Client Work", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let instance = Instance::insert(
+            task.get_id(),
+            &NaiveDateTime::from_timestamp(0, 0),
+            &NaiveDateTime::from_timestamp(3600, 0),
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+        let billable = Tag::insert(user.get_id(), &name("billable"), &connection)
+            .await
+            .expect("Should successfully insert.");
+        let outdoor = Tag::insert(user.get_id(), &name("outdoor"), &connection)
+            .await
+            .expect("Should successfully insert.");
+
+        assert!(billable.tag_task(task.get_id(), &connection).await.unwrap());
+        assert!(!billable.tag_task(task.get_id(), &connection).await.unwrap());
+        assert!(outdoor
+            .tag_instance(instance.get_id(), &connection)
+            .await
+            .unwrap());
+        assert_eq!(
+            Tag::get_task_tags(user.get_id(), &connection)
+                .await
+                .expect("Can list."),
+            vec![(task.get_id(), billable.clone())]
+        );
+        assert_eq!(
+            Tag::get_instance_tags(user.get_id(), &connection)
+                .await
+                .expect("Can list."),
+            vec![(instance.get_id(), outdoor.clone())]
+        );
+
+        assert!(billable
+            .untag_task(task.get_id(), &connection)
+            .await
+            .unwrap());
+        assert!(!billable
+            .untag_task(task.get_id(), &connection)
+            .await
+            .unwrap());
+
+        // Deleting a tag removes it from instances.
+        outdoor.delete(&connection).await.expect("Can delete.");
+        assert!(Tag::get_instance_tags(user.get_id(), &connection)
+            .await
+            .expect("Can list.")
+            .is_empty());
+    }
+}
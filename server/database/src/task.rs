@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use sqlx::Done;
 use sqlx::FromRow;
 use std::cmp::PartialEq;
+use tracing::instrument;
 
 use crate::SqlId;
 
@@ -14,7 +15,7 @@ use crate::SqlId;
 ///
 /// We temporarily make this Serialize since we re-use this as the http rseponse. As the database
 /// structure and the http query starts to diverge we will create a separate struct.
-#[derive(Debug, FromRow, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
 pub struct Task {
     /// The task id.
     id: SqlId,
@@ -35,13 +36,22 @@ impl Task {
         self.id
     }
 
+    pub fn get_user_id(&self) -> SqlId {
+        self.user_id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
     /// Retrieve a task in the database by id.
+    #[instrument(level = "debug", skip(connection))]
     pub async fn retrieve(id: i64, connection: &Connection) -> Result<Task> {
         let task = sqlx::query_as!(
             Task,
             r#"
                 SELECT id, user_id, name FROM tasks
-                WHERE id = ( ? )
+                WHERE id = ( $1 )
             "#,
             id
         )
@@ -52,15 +62,16 @@ impl Task {
     }
 
     /// Find a task in the database by id.
+    #[instrument(level = "debug", skip(self, connection), fields(user_id = self.user_id))]
     pub async fn find(&mut self, connection: &Connection) -> Result<()> {
         let task = sqlx::query_as!(
             Task,
             r#"
                 SELECT id, user_id, name FROM tasks
                 WHERE
-                user_id = ( ? )
+                user_id = ( $1 )
                 AND
-                name = ( ? )
+                name = ( $2 )
             "#,
             self.user_id,
             self.name
@@ -74,58 +85,51 @@ impl Task {
     }
 
     /// Insert a task into the database
-    pub async fn insert(&mut self, connection: &Connection) -> Result<()> {
-        let result = sqlx::query!(
+    #[instrument(level = "debug", skip(name, connection))]
+    pub async fn insert(user_id: SqlId, name: &str, connection: &Connection) -> Result<Task> {
+        sqlx::query!(
             r#"
                 INSERT INTO tasks ( user_id, name )
-                VALUES ( ?, ? )
+                VALUES ( $1, $2 )
             "#,
-            self.user_id,
-            self.name
+            user_id,
+            name
         )
         .execute(connection.get_pool())
         .await?;
 
-        let last_rowid = result.last_insert_rowid();
-
-        let task_result = sqlx::query_as!(
-            Task,
-            r#"
-                SELECT id, user_id, name
-                FROM tasks
-                WHERE rowid= ( ? )
-            "#,
-            last_rowid
-        )
-        .fetch_one(connection.get_pool())
-        .await?;
+        // The task is unique per user so find it again rather than relying on backend specific
+        // ways of getting the inserted id.
+        let mut task = Task::new(0, user_id, name.to_string());
+        task.find(connection).await?;
 
-        self.id = task_result.id;
-
-        Ok(())
+        Ok(task)
     }
 
-    /// Try Insert a task into the database
-    pub async fn try_insert(&mut self, connection: &Connection) -> Result<()> {
+    /// Try Insert a task into the database, returning whether it is new.
+    #[instrument(level = "debug", skip(self, connection), fields(user_id = self.user_id))]
+    pub async fn try_insert(&mut self, connection: &Connection) -> Result<bool> {
         // TODO: Match on the error sepcifically.
         // If this fails with already found, ignore and proceed.
-        let _result = sqlx::query!(
+        let result = sqlx::query!(
             r#"
                 INSERT INTO tasks ( user_id, name )
-                VALUES ( ?, ? )
+                VALUES ( $1, $2 )
             "#,
             self.user_id,
             self.name
         )
         .execute(connection.get_pool())
         .await;
+        let inserted = result.map_or(false, |done| done.rows_affected() == 1);
 
         self.find(connection).await?;
 
-        Ok(())
+        Ok(inserted)
     }
 
     /// Insert a vector of tasks
+    #[instrument(level = "debug", skip(tasks, connection), fields(count = tasks.len()))]
     pub async fn try_insert_all(tasks: &mut Vec<Task>, connection: &Connection) -> Result<()> {
         for task in tasks.iter_mut() {
             task.try_insert(connection).await?;
@@ -134,36 +138,330 @@ impl Task {
         Ok(())
     }
 
-    /// Delete a user from the database.
+    /// Delete a task from the database along with its instances.
     ///
     /// This consumes self since it is invalid after deletion from the database.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
     pub async fn delete(self, connection: &Connection) -> Result<()> {
+        // Rolled back on drop if anything fails.
+        let mut transaction = connection.get_pool().begin().await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM goal
+                WHERE task_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM task_tag
+                WHERE task_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM instance_tag
+                WHERE instance_id IN ( SELECT id FROM instances WHERE task_id = ( $1 ) )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM timer
+                WHERE task_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM instances
+                WHERE task_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
         //  We really only need the id but we use everything to be specific.
         let deleted_row_count = sqlx::query!(
             r#"
                 DELETE FROM tasks
                 WHERE
-                id = ( ? )
+                id = ( $1 )
                 AND
-                user_id = ( ? )
+                user_id = ( $2 )
                 AND
-                name = ( ? )
+                name = ( $3 )
             "#,
             self.id,
             self.user_id,
             self.name
         )
-        .execute(connection.get_pool())
+        .execute(&mut transaction)
         .await?;
 
         if deleted_row_count.rows_affected() == 0 {
-            Err(Error::NotFound)
-        } else {
-            Ok(())
+            return Err(Error::NotFound);
+        }
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    /// Rename a task.
+    ///
+    /// Fails with `AlreadyExists` if the user already has a task with this name.
+    #[instrument(level = "debug", skip(self, name, connection), fields(id = self.id))]
+    pub async fn rename(&mut self, name: &str, connection: &Connection) -> Result<()> {
+        let updated = sqlx::query!(
+            r#"
+                UPDATE tasks
+                SET name = ( $1 )
+                WHERE id = ( $2 )
+            "#,
+            name,
+            self.id
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        if updated.rows_affected() == 0 {
+            return Err(Error::NotFound);
         }
+
+        self.name = name.to_string();
+
+        Ok(())
+    }
+
+    /// Move every instance of this task to `into` and delete this task.
+    ///
+    /// Instances `into` already has are dropped rather than duplicated. Everything else pointing at
+    /// this task moves to `into` as well: its child tasks, its goals, a running timer unless `into`
+    /// has one and its tags, which go on the moved instances so they don't spread to the instances
+    /// of `into`. When `into` is somewhere under this task it moves up to take its place. Both tasks
+    /// should belong to the same user.
+    ///
+    /// Returns the number of instances moved.
+    #[instrument(level = "debug", skip(self, into, connection), fields(id = self.id, into = into.id))]
+    pub async fn merge_into(self, into: &Task, connection: &Connection) -> Result<u64> {
+        // Rolled back on drop if anything fails.
+        let mut transaction = connection.get_pool().begin().await?;
+
+        // Tags of the instances which are dropped below.
+        sqlx::query!(
+            r#"
+                DELETE FROM instance_tag
+                WHERE instance_id IN (
+                    SELECT id FROM instances
+                    WHERE
+                    task_id = ( $1 )
+                    AND
+                    EXISTS (
+                        SELECT 1 FROM instances AS existing
+                        WHERE
+                        existing.task_id = ( $2 )
+                        AND
+                        existing.start = instances.start
+                        AND
+                        existing."end" = instances."end"
+                    )
+                )
+            "#,
+            self.id,
+            into.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM instances
+                WHERE
+                task_id = ( $1 )
+                AND
+                EXISTS (
+                    SELECT 1 FROM instances AS existing
+                    WHERE
+                    existing.task_id = ( $2 )
+                    AND
+                    existing.start = instances.start
+                    AND
+                    existing."end" = instances."end"
+                )
+            "#,
+            self.id,
+            into.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        // The tags of this task stay on its instances, unless `into` has them already.
+        sqlx::query!(
+            r#"
+                INSERT INTO instance_tag ( instance_id, tag_id )
+                SELECT instances.id, task_tag.tag_id
+                FROM instances
+                INNER JOIN task_tag ON task_tag.task_id = instances.task_id
+                WHERE
+                instances.task_id = ( $1 )
+                AND
+                NOT EXISTS (
+                    SELECT 1 FROM task_tag AS kept
+                    WHERE
+                    kept.task_id = ( $2 )
+                    AND
+                    kept.tag_id = task_tag.tag_id
+                )
+                AND
+                NOT EXISTS (
+                    SELECT 1 FROM instance_tag AS existing
+                    WHERE
+                    existing.instance_id = instances.id
+                    AND
+                    existing.tag_id = task_tag.tag_id
+                )
+            "#,
+            self.id,
+            into.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM task_tag
+                WHERE task_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        let moved = sqlx::query!(
+            r#"
+                UPDATE instances
+                SET task_id = ( $1 )
+                WHERE task_id = ( $2 )
+            "#,
+            into.id,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                UPDATE goal
+                SET task_id = ( $1 )
+                WHERE task_id = ( $2 )
+            "#,
+            into.id,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        // A task has at most one running timer.
+        sqlx::query!(
+            r#"
+                DELETE FROM timer
+                WHERE
+                task_id = ( $1 )
+                AND
+                EXISTS ( SELECT 1 FROM timer AS running WHERE running.task_id = ( $2 ) )
+            "#,
+            self.id,
+            into.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                UPDATE timer
+                SET task_id = ( $1 )
+                WHERE task_id = ( $2 )
+            "#,
+            into.id,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        // If `into` is under this task it takes its place, so it doesn't end up under one of the
+        // children moving to it.
+        sqlx::query!(
+            r#"
+                UPDATE tasks
+                SET parent_id = ( SELECT parent_id FROM tasks WHERE id = ( $1 ) )
+                WHERE
+                id = ( $2 )
+                AND
+                id IN (
+                    WITH RECURSIVE descendants ( id ) AS (
+                        SELECT id FROM tasks WHERE parent_id = ( $1 )
+                        UNION ALL
+                        SELECT tasks.id FROM tasks
+                        INNER JOIN descendants ON tasks.parent_id = descendants.id
+                    )
+                    SELECT id FROM descendants
+                )
+            "#,
+            self.id,
+            into.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                UPDATE tasks
+                SET parent_id = ( $1 )
+                WHERE parent_id = ( $2 )
+            "#,
+            into.id,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        let deleted = sqlx::query!(
+            r#"
+                DELETE FROM tasks
+                WHERE id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        if deleted.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        transaction.commit().await?;
+
+        Ok(moved.rows_affected())
     }
 
     /// Get all tasks for a user.
+    #[instrument(level = "debug", skip(user, connection), fields(user_id = user.get_id()))]
     pub async fn get_tasks(user: &User, connection: &Connection) -> Result<Vec<Task>> {
         let user_id = user.get_id();
 
@@ -171,7 +469,7 @@ impl Task {
             Task,
             r#"
                 SELECT id, user_id, name FROM tasks
-                WHERE user_id = ( ? )
+                WHERE user_id = ( $1 )
             "#,
             user_id
         )
@@ -254,4 +552,252 @@ mod tests {
         assert_eq!(tasks[0], task1);
         assert_eq!(tasks[1], task2);
     }
+
+    #[tokio::test]
+    async fn rename_task() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+
+        let user = create_test_user(USERNAME, NAME, &connection).await;
+        let mut task = Task::insert(user.get_id(), TASK_NAME, &connection)
+            .await
+            .expect("Should successfully insert.");
+        Task::insert(user.get_id(), "Taken", &connection)
+            .await
+            .expect("Should successfully insert.");
+
+        task.rename("Gym", &connection).await.expect("Can rename.");
+        assert_eq!(
+            Task::retrieve(task.get_id(), &connection)
+                .await
+                .expect("Task exists."),
+            task
+        );
+        assert_eq!(
+            task.rename("Taken", &connection)
+                .await
+                .expect_err("Name is taken."),
+            Error::AlreadyExists
+        );
+    }
+
+    #[tokio::test]
+    async fn merge_tasks() {
+        use crate::instance::Instance;
+        use chrono::NaiveDateTime;
+
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+
+        let user = create_test_user(USERNAME, NAME, &connection).await;
+        let from = Task::insert(user.get_id(), "Gym", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let into = Task::insert(user.get_id(), TASK_NAME, &connection)
+            .await
+            .expect("Should successfully insert.");
+
+        let time = |timestamp| NaiveDateTime::from_timestamp(timestamp, 0);
+        for task in &[&from, &into] {
+            Instance::insert(task.get_id(), &time(1), &time(2), &connection)
+                .await
+                .expect("Should successfully insert.");
+        }
+        Instance::insert(from.get_id(), &time(3), &time(4), &connection)
+            .await
+            .expect("Should successfully insert.");
+
+        let from_id = from.get_id();
+        assert_eq!(
+            from.merge_into(&into, &connection)
+                .await
+                .expect("Can merge."),
+            1
+        );
+
+        assert_eq!(
+            Task::retrieve(from_id, &connection)
+                .await
+                .expect_err("Merged task was deleted."),
+            Error::NotFound
+        );
+        assert_eq!(
+            Instance::get_instances(into.get_id(), &connection)
+                .await
+                .expect("Can list.")
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn merge_tasks_moves_children_goals_and_tags() {
+        use crate::goal::Goal;
+        use crate::instance::Instance;
+        use crate::tag::Tag;
+        use crate::timer::Timer;
+        use chrono::NaiveDateTime;
+        use mindless_core::goal::{Cadence, Comparison, Target, Unit};
+        use sqlx::Row;
+
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+
+        let user = create_test_user(USERNAME, NAME, &connection).await;
+        let insert = |name: &'static str| Task::insert(user.get_id(), name, &connection);
+        let from = insert("Gym").await.expect("Should successfully insert.");
+        let into = insert(TASK_NAME)
+            .await
+            .expect("Should successfully insert.");
+        let child = insert("Squats").await.expect("Should successfully insert.");
+
+        // The task model has no parent so set it directly.
+        let set_parent = |id: SqlId, parent_id: SqlId| {
+            sqlx::query("UPDATE tasks SET parent_id = $1 WHERE id = $2")
+                .bind(parent_id)
+                .bind(id)
+                .execute(connection.get_pool())
+        };
+        set_parent(child.get_id(), from.get_id())
+            .await
+            .expect("Can set the parent.");
+        set_parent(into.get_id(), from.get_id())
+            .await
+            .expect("Can set the parent.");
+
+        let time = |timestamp| NaiveDateTime::from_timestamp(timestamp, 0);
+        let instance = Instance::insert(from.get_id(), &time(1), &time(2), &connection)
+            .await
+            .expect("Should successfully insert.");
+        let target = Target {
+            comparison: Comparison::AtLeast,
+            amount: 3,
+            unit: Unit::Times,
+            cadence: Cadence::Week,
+        };
+        let goal = Goal::insert(
+            user.get_id(),
+            Some(from.get_id()),
+            None,
+            &target,
+            &time(0),
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+        let tag = Tag::insert(
+            user.get_id(),
+            &"outdoor".parse().expect("Valid tag."),
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+        tag.tag_task(from.get_id(), &connection)
+            .await
+            .expect("Can tag.");
+        Timer::start(from.get_id(), &time(3), &connection)
+            .await
+            .expect("Can start.");
+
+        from.merge_into(&into, &connection)
+            .await
+            .expect("Can merge.");
+
+        let parent_of = |id: SqlId| {
+            sqlx::query("SELECT parent_id FROM tasks WHERE id = $1")
+                .bind(id)
+                .fetch_one(connection.get_pool())
+        };
+        let parent: Option<SqlId> = parent_of(child.get_id())
+            .await
+            .expect("Child still exists.")
+            .get(0);
+        assert_eq!(parent, Some(into.get_id()));
+        let parent: Option<SqlId> = parent_of(into.get_id())
+            .await
+            .expect("Task still exists.")
+            .get(0);
+        assert_eq!(parent, None);
+
+        assert_eq!(
+            Goal::retrieve(goal.get_id(), &connection)
+                .await
+                .expect("Goal was kept.")
+                .get_task_id(),
+            Some(into.get_id())
+        );
+        assert_eq!(
+            Timer::retrieve(into.get_id(), &connection)
+                .await
+                .expect("Timer was kept.")
+                .get_started_at(),
+            &time(3)
+        );
+        // The tag stays on the moved instance without spreading to the other instances of `into`.
+        assert!(Tag::get_task_tags(user.get_id(), &connection)
+            .await
+            .expect("Can list.")
+            .is_empty());
+        assert_eq!(
+            Tag::get_instance_tags(user.get_id(), &connection)
+                .await
+                .expect("Can list."),
+            vec![(instance.get_id(), tag)]
+        );
+    }
+
+    #[tokio::test]
+    async fn merge_tasks_into_a_grandchild() {
+        use sqlx::Row;
+
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+
+        let user = create_test_user(USERNAME, NAME, &connection).await;
+        let insert = |name: &'static str| Task::insert(user.get_id(), name, &connection);
+        let parent = insert("Health").await.expect("Should successfully insert.");
+        let from = insert("Gym").await.expect("Should successfully insert.");
+        let child = insert("Weights")
+            .await
+            .expect("Should successfully insert.");
+        let into = insert("Squats").await.expect("Should successfully insert.");
+
+        // Health > Gym > Weights > Squats
+        let set_parent = |id: SqlId, parent_id: SqlId| {
+            sqlx::query("UPDATE tasks SET parent_id = $1 WHERE id = $2")
+                .bind(parent_id)
+                .bind(id)
+                .execute(connection.get_pool())
+        };
+        for (task, above) in &[(&from, &parent), (&child, &from), (&into, &child)] {
+            set_parent(task.get_id(), above.get_id())
+                .await
+                .expect("Can set the parent.");
+        }
+
+        from.merge_into(&into, &connection)
+            .await
+            .expect("Can merge.");
+
+        // Health > Squats > Weights, without a cycle.
+        let parent_of = |id: SqlId| {
+            sqlx::query("SELECT parent_id FROM tasks WHERE id = $1")
+                .bind(id)
+                .fetch_one(connection.get_pool())
+        };
+        let into_parent: Option<SqlId> = parent_of(into.get_id())
+            .await
+            .expect("Task still exists.")
+            .get(0);
+        assert_eq!(into_parent, Some(parent.get_id()));
+        let child_parent: Option<SqlId> = parent_of(child.get_id())
+            .await
+            .expect("Child still exists.")
+            .get(0);
+        assert_eq!(child_parent, Some(into.get_id()));
+    }
 }
@@ -0,0 +1,210 @@
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::instance::Instance;
+use crate::SqlId;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::Done;
+use sqlx::FromRow;
+use std::cmp::PartialEq;
+use tracing::instrument;
+
+/// This is a struct representing a running timer of a task.
+///
+/// Stopping it turns it into an instance of the task.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct Timer {
+    /// The timer id.
+    id: SqlId,
+
+    /// The task being timed.
+    task_id: SqlId,
+
+    /// Time the timer was started.
+    started_at: NaiveDateTime,
+}
+
+impl Timer {
+    pub fn new(id: SqlId, task_id: SqlId, started_at: NaiveDateTime) -> Timer {
+        Timer {
+            id,
+            task_id,
+            started_at,
+        }
+    }
+
+    pub fn get_id(&self) -> SqlId {
+        self.id
+    }
+
+    pub fn get_task_id(&self) -> SqlId {
+        self.task_id
+    }
+
+    pub fn get_started_at(&self) -> &NaiveDateTime {
+        &self.started_at
+    }
+
+    /// Retrieve the running timer of a task.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn retrieve(task_id: SqlId, connection: &Connection) -> Result<Timer> {
+        let timer = sqlx::query_as!(
+            Timer,
+            r#"
+                SELECT id AS "id!", task_id, started_at
+                FROM timer
+                WHERE task_id = ( $1 )
+            "#,
+            task_id
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(timer)
+    }
+
+    /// Start a timer for a task. Fails with `AlreadyExists` if one is already running.
+    #[instrument(level = "debug", skip(started_at, connection))]
+    pub async fn start(
+        task_id: SqlId,
+        started_at: &NaiveDateTime,
+        connection: &Connection,
+    ) -> Result<Timer> {
+        sqlx::query!(
+            r#"
+                INSERT INTO timer ( task_id, started_at )
+                VALUES ( $1, $2 )
+            "#,
+            task_id,
+            started_at
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        // A task has one timer so find it again rather than relying on backend specific ways of
+        // getting the inserted id.
+        Timer::retrieve(task_id, connection).await
+    }
+
+    /// Stop the timer and record the time as an instance of its task.
+    ///
+    /// This consumes self since it is invalid after deletion from the database.
+    #[instrument(level = "debug", skip(self, ended_at, connection), fields(id = self.id))]
+    pub async fn stop(self, ended_at: &NaiveDateTime, connection: &Connection) -> Result<Instance> {
+        // Rolled back on drop if anything fails.
+        let mut transaction = connection.get_pool().begin().await?;
+
+        let deleted = sqlx::query!(
+            r#"
+                DELETE FROM timer
+                WHERE id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        if deleted.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        sqlx::query!(
+            r#"
+                INSERT INTO instances ( task_id, start, "end" )
+                VALUES ( $1, $2, $3 )
+            "#,
+            self.task_id,
+            self.started_at,
+            ended_at
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        let mut instance = Instance::new(0, self.task_id, self.started_at, *ended_at);
+        instance.find(connection).await?;
+
+        Ok(instance)
+    }
+
+    /// Get the running timers of a user.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_timers(user_id: SqlId, connection: &Connection) -> Result<Vec<Timer>> {
+        let timers = sqlx::query_as!(
+            Timer,
+            r#"
+                SELECT timer.id AS "id!", timer.task_id, timer.started_at
+                FROM timer
+                INNER JOIN tasks ON timer.task_id = tasks.id
+                WHERE tasks.user_id = ( $1 )
+                ORDER BY timer.id
+            "#,
+            user_id
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(timers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::Task;
+    use crate::user::User;
+
+    #[tokio::test]
+    async fn start_and_stop() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let task = Task::insert(user.get_id(), "Exercise", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let started_at = NaiveDateTime::from_timestamp(0, 0);
+
+        let timer = Timer::start(task.get_id(), &started_at, &connection)
+            .await
+            .expect("Can start.");
+        assert_eq!(
+            Timer::start(task.get_id(), &started_at, &connection)
+                .await
+                .expect_err("Timer is already running."),
+            Error::AlreadyExists
+        );
+        assert_eq!(
+            Timer::get_timers(user.get_id(), &connection)
+                .await
+                .expect("Can list."),
+            vec![timer.clone()]
+        );
+
+        let ended_at = NaiveDateTime::from_timestamp(60, 0);
+        let instance = timer
+            .clone()
+            .stop(&ended_at, &connection)
+            .await
+            .expect("Can stop.");
+        assert_eq!(instance.get_task_id(), task.get_id());
+        assert_eq!(instance.get_start(), &started_at);
+        assert_eq!(instance.get_end(), &ended_at);
+        assert_eq!(
+            timer
+                .stop(&ended_at, &connection)
+                .await
+                .expect_err("Timer was stopped."),
+            Error::NotFound
+        );
+        assert_eq!(
+            Timer::retrieve(task.get_id(), &connection)
+                .await
+                .expect_err("Timer was stopped."),
+            Error::NotFound
+        );
+    }
+}
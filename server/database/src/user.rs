@@ -2,6 +2,7 @@ use crate::connection::Connection;
 use crate::error::{Error, Result};
 use serde::{Deserialize, Serialize};
 use sqlx::Done;
+use tracing::instrument;
 
 /// This is a struct representing a user.
 ///
@@ -9,7 +10,7 @@ use sqlx::Done;
 ///
 /// We temporarily make this Serialize since we re-use this as the http rseponse. As the database
 /// structure and the http query starts to diverge we will create a separate struct.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct User {
     /// The user id.
     id: i64,
@@ -30,12 +31,21 @@ impl User {
         self.id
     }
 
+    pub fn get_username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
     /// Retrieve a user in the database by id.
+    #[instrument(level = "debug", skip(connection))]
     pub async fn retrieve(id: i64, connection: &Connection) -> Result<User> {
         let done = sqlx::query!(
             r#"
                 SELECT username, name FROM users
-                WHERE id = ( ? )
+                WHERE id = ( $1 )
             "#,
             id
         )
@@ -50,11 +60,12 @@ impl User {
     }
 
     /// Insert a user in the database
+    #[instrument(level = "debug", skip(username, name, connection))]
     pub async fn insert(username: &str, name: &str, connection: &Connection) -> Result<User> {
         sqlx::query!(
             r#"
                 INSERT INTO users ( username, name )
-                VALUES ( ?, ? )
+                VALUES ( $1, $2 )
             "#,
             username,
             name
@@ -67,31 +78,207 @@ impl User {
 
     /// Delete a user from the database.
     ///
+    /// This also deletes everything belonging to the user since foreign keys are enforced.
+    ///
     /// This consumes self since it is invalid after deletion from the database.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
     pub async fn delete(self, connection: &Connection) -> Result<()> {
+        // Rolled back on drop if anything fails.
+        let mut transaction = connection.get_pool().begin().await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM goal
+                WHERE user_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM instance_tag
+                WHERE tag_id IN ( SELECT id FROM tag WHERE user_id = ( $1 ) )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM task_tag
+                WHERE tag_id IN ( SELECT id FROM tag WHERE user_id = ( $1 ) )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM tag
+                WHERE user_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM timer
+                WHERE task_id IN ( SELECT id FROM tasks WHERE user_id = ( $1 ) )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM instances
+                WHERE task_id IN ( SELECT id FROM tasks WHERE user_id = ( $1 ) )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM tasks
+                WHERE user_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM elapsed_period
+                WHERE instance_id IN (
+                    SELECT instance.id FROM instance
+                    INNER JOIN habit ON instance.habit_id = habit.id
+                    WHERE habit.user_id = ( $1 )
+                )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM instance
+                WHERE habit_id IN ( SELECT id FROM habit WHERE user_id = ( $1 ) )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM checkin_token
+                WHERE user_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM webhook_delivery
+                WHERE webhook_id IN ( SELECT id FROM webhook WHERE user_id = ( $1 ) )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM webhook
+                WHERE user_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM pending_reminder
+                WHERE reminder_id IN (
+                    SELECT reminder.id FROM reminder
+                    INNER JOIN habit ON reminder.habit_id = habit.id
+                    WHERE habit.user_id = ( $1 )
+                )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM reminder
+                WHERE habit_id IN ( SELECT id FROM habit WHERE user_id = ( $1 ) )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM reminder_settings
+                WHERE user_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM habit
+                WHERE user_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
         //  We really only need the id but we use everything to be specific.
         let deleted_row_count = sqlx::query!(
             r#"
                 DELETE FROM users
                 WHERE
-                id = ( ? )
+                id = ( $1 )
                 AND
-                username = ( ? )
+                username = ( $2 )
                 AND
-                name = ( ? )
+                name = ( $3 )
             "#,
             self.id,
             self.username,
             self.name
         )
-        .execute(connection.get_pool())
+        .execute(&mut transaction)
         .await?;
 
         if deleted_row_count.rows_affected() == 0 {
-            Err(Error::NotFound)
-        } else {
-            Ok(())
+            return Err(Error::NotFound);
         }
+
+        transaction.commit().await?;
+
+        Ok(())
     }
 
     /// Set the name to a new value.
@@ -108,14 +295,15 @@ impl User {
         self.username = username;
     }
 
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
     pub async fn update(&mut self, connection: &Connection) -> Result<()> {
         sqlx::query!(
             r#"
                 UPDATE users
-                SET username = ( ? ),
-                    name = ( ? )
+                SET username = ( $1 ),
+                    name = ( $2 )
                 WHERE
-                id = ( ? )
+                id = ( $3 )
             "#,
             self.username,
             self.name,
@@ -127,24 +315,35 @@ impl User {
         Ok(())
     }
 
+    /// Get every user.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_users(connection: &Connection) -> Result<Vec<User>> {
+        let users = sqlx::query_as!(
+            User,
+            r#"
+                SELECT id AS "id!", username, name FROM users
+                ORDER BY id
+            "#
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(users)
+    }
+
     /// Get the user given a username. This user must exist.
+    #[instrument(level = "debug", skip(username, connection))]
     pub async fn get(username: &str, connection: &Connection) -> Result<User> {
         let result = sqlx::query!(
             r#"
-    SELECT id, name FROM users WHERE username=?
+    SELECT id AS "id!", name FROM users WHERE username=$1
             "#,
             username
         )
         .fetch_one(connection.get_pool())
         .await?;
 
-        let id = result
-            .id
-            .expect("Should exists since this field is NON NULL.");
-
-        let name = result.name;
-
-        Ok(User::new(id, username.to_string(), name))
+        Ok(User::new(result.id, username.to_string(), result.name))
     }
 }
 
@@ -207,6 +406,48 @@ mod tests {
             .expect("Can delete newly added user.");
     }
 
+    #[tokio::test]
+    async fn delete_user_with_tasks() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+
+        let user = User::insert("JustinUserName", "Justin", &connection)
+            .await
+            .expect("Should successfully insert. ");
+
+        let task = crate::task::Task::insert(user.get_id(), "Exercise", &connection)
+            .await
+            .expect("Should successfully insert.");
+
+        crate::instance::Instance::insert(
+            task.get_id(),
+            &chrono::NaiveDateTime::from_timestamp(1, 0),
+            &chrono::NaiveDateTime::from_timestamp(2, 0),
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+
+        let id = user.get_id();
+        user.delete(&connection)
+            .await
+            .expect("Deletes the user along with their tasks.");
+
+        assert_eq!(
+            crate::task::Task::retrieve(task.get_id(), &connection)
+                .await
+                .expect_err("Task was deleted with the user."),
+            crate::error::Error::NotFound
+        );
+        assert_eq!(
+            User::retrieve(id, &connection)
+                .await
+                .expect_err("User was deleted."),
+            crate::error::Error::NotFound
+        );
+    }
+
     #[tokio::test]
     async fn fail_delete_non_existent_user() {
         let connection = Connection::connect_temporary_with_schema()
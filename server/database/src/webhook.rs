@@ -0,0 +1,500 @@
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::SqlId;
+use chrono::NaiveDateTime;
+use serde::{Serialize, Serializer};
+use sqlx::Done;
+use sqlx::FromRow;
+use std::cmp::PartialEq;
+use tracing::instrument;
+
+/// This is a struct representing a url the events of a user are posted to.
+#[derive(Debug, Clone, FromRow, Serialize, PartialEq)]
+pub struct Webhook {
+    /// The webhook id.
+    id: SqlId,
+
+    /// The user whose events are posted.
+    user_id: SqlId,
+
+    /// Where events are posted to.
+    url: String,
+
+    /// Key the payloads are signed with. Only shown when the webhook is registered.
+    #[serde(skip_serializing)]
+    secret: String,
+
+    /// Comma separated kinds of events to post, empty for every event.
+    #[serde(serialize_with = "serialize_events")]
+    events: String,
+
+    /// Time this webhook was registered.
+    created_at: NaiveDateTime,
+}
+
+/// Show the events as a list rather than as they are stored.
+fn serialize_events<S: Serializer>(
+    events: &str,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_seq(split_events(events))
+}
+
+fn split_events(events: &str) -> impl Iterator<Item = &str> {
+    events.split(',').filter(|event| !event.is_empty())
+}
+
+impl Webhook {
+    pub fn new(
+        id: SqlId,
+        user_id: SqlId,
+        url: String,
+        secret: String,
+        events: &[String],
+        created_at: NaiveDateTime,
+    ) -> Webhook {
+        Webhook {
+            id,
+            user_id,
+            url,
+            secret,
+            events: events.join(","),
+            created_at,
+        }
+    }
+
+    pub fn get_id(&self) -> SqlId {
+        self.id
+    }
+
+    pub fn get_user_id(&self) -> SqlId {
+        self.user_id
+    }
+
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn get_secret(&self) -> &str {
+        &self.secret
+    }
+
+    /// The kinds of events to post, empty for every event.
+    pub fn get_events(&self) -> Vec<&str> {
+        split_events(&self.events).collect()
+    }
+
+    pub fn get_created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+
+    /// Whether events of this kind are posted.
+    pub fn subscribes_to(&self, event: &str) -> bool {
+        self.events.is_empty() || split_events(&self.events).any(|kind| kind == event)
+    }
+
+    /// Retrieve a webhook in the database by id.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn retrieve(id: SqlId, connection: &Connection) -> Result<Webhook> {
+        let webhook = sqlx::query_as!(
+            Webhook,
+            r#"
+                SELECT id AS "id!", user_id, url, secret, events, created_at
+                FROM webhook
+                WHERE id = ( $1 )
+            "#,
+            id
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(webhook)
+    }
+
+    /// Register a webhook for the events of a user.
+    #[instrument(level = "debug", skip(url, secret, created_at, connection))]
+    pub async fn insert(
+        user_id: SqlId,
+        url: &str,
+        secret: &str,
+        events: &[String],
+        created_at: &NaiveDateTime,
+        connection: &Connection,
+    ) -> Result<Webhook> {
+        let events = events.join(",");
+        sqlx::query!(
+            r#"
+                INSERT INTO webhook ( user_id, url, secret, events, created_at )
+                VALUES ( $1, $2, $3, $4, $5 )
+            "#,
+            user_id,
+            url,
+            secret,
+            events,
+            created_at
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        // Find it again rather than relying on backend specific ways of getting the inserted id.
+        let webhook = sqlx::query_as!(
+            Webhook,
+            r#"
+                SELECT id AS "id!", user_id, url, secret, events, created_at
+                FROM webhook
+                WHERE
+                user_id = ( $1 )
+                AND
+                secret = ( $2 )
+                ORDER BY id DESC
+                LIMIT 1
+            "#,
+            user_id,
+            secret
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        Ok(webhook)
+    }
+
+    /// Get the webhooks of a user.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_webhooks(user_id: SqlId, connection: &Connection) -> Result<Vec<Webhook>> {
+        let webhooks = sqlx::query_as!(
+            Webhook,
+            r#"
+                SELECT id AS "id!", user_id, url, secret, events, created_at
+                FROM webhook
+                WHERE user_id = ( $1 )
+                ORDER BY id
+            "#,
+            user_id
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    /// Get the webhooks of every user.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_all(connection: &Connection) -> Result<Vec<Webhook>> {
+        let webhooks = sqlx::query_as!(
+            Webhook,
+            r#"
+                SELECT id AS "id!", user_id, url, secret, events, created_at
+                FROM webhook
+                ORDER BY id
+            "#
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    /// Delete a webhook along with its deliveries.
+    ///
+    /// This consumes self since it is invalid after deletion from the database.
+    #[instrument(level = "debug", skip(self, connection), fields(id = self.id))]
+    pub async fn delete(self, connection: &Connection) -> Result<()> {
+        // Rolled back on drop if anything fails.
+        let mut transaction = connection.get_pool().begin().await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM webhook_delivery
+                WHERE webhook_id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        let deleted = sqlx::query!(
+            r#"
+                DELETE FROM webhook
+                WHERE id = ( $1 )
+            "#,
+            self.id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        if deleted.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+}
+
+/// This is a struct representing an attempt at posting an event to a webhook.
+#[derive(Debug, Clone, FromRow, Serialize, PartialEq)]
+pub struct Delivery {
+    /// The delivery id.
+    id: SqlId,
+
+    /// The webhook posted to.
+    webhook_id: SqlId,
+
+    /// Kind of the event.
+    event: String,
+
+    /// The json body which was posted.
+    payload: String,
+
+    /// Attempts of the same delivery count up from 1.
+    attempt: i64,
+
+    /// The response status, missing if there was no response.
+    status_code: Option<i64>,
+
+    /// Why the attempt failed, missing if it succeeded.
+    error: Option<String>,
+
+    /// Time of the attempt.
+    attempted_at: NaiveDateTime,
+}
+
+impl Delivery {
+    pub fn new(
+        id: SqlId,
+        webhook_id: SqlId,
+        event: String,
+        payload: String,
+        attempt: i64,
+        attempted_at: NaiveDateTime,
+    ) -> Delivery {
+        Delivery {
+            id,
+            webhook_id,
+            event,
+            payload,
+            attempt,
+            status_code: None,
+            error: None,
+            attempted_at,
+        }
+    }
+
+    pub fn get_id(&self) -> SqlId {
+        self.id
+    }
+
+    pub fn get_webhook_id(&self) -> SqlId {
+        self.webhook_id
+    }
+
+    pub fn get_event(&self) -> &str {
+        &self.event
+    }
+
+    pub fn get_payload(&self) -> &str {
+        &self.payload
+    }
+
+    pub fn get_attempt(&self) -> i64 {
+        self.attempt
+    }
+
+    pub fn get_status_code(&self) -> Option<i64> {
+        self.status_code
+    }
+
+    pub fn get_error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn get_attempted_at(&self) -> &NaiveDateTime {
+        &self.attempted_at
+    }
+
+    pub fn set_id(&mut self, id: SqlId) {
+        self.id = id;
+    }
+
+    /// Record the response. Anything but a success status is an error.
+    pub fn set_status_code(&mut self, status_code: u16) {
+        self.status_code = Some(i64::from(status_code));
+        if !(200..300).contains(&status_code) {
+            self.error = Some(format!("Unexpected status {}", status_code));
+        }
+    }
+
+    /// Record why the attempt failed.
+    pub fn set_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+
+    /// Whether the webhook received the event.
+    pub fn is_delivered(&self) -> bool {
+        self.error.is_none() && self.status_code.is_some()
+    }
+
+    /// Add this attempt to the delivery log and set its id.
+    #[instrument(level = "debug", skip(self, connection), fields(webhook_id = self.webhook_id))]
+    pub async fn insert(&mut self, connection: &Connection) -> Result<()> {
+        sqlx::query!(
+            r#"
+                INSERT INTO webhook_delivery
+                ( webhook_id, event, payload, attempt, status_code, error, attempted_at )
+                VALUES ( $1, $2, $3, $4, $5, $6, $7 )
+            "#,
+            self.webhook_id,
+            self.event,
+            self.payload,
+            self.attempt,
+            self.status_code,
+            self.error,
+            self.attempted_at
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        // Find it again rather than relying on backend specific ways of getting the inserted id.
+        let row = sqlx::query!(
+            r#"
+                SELECT id AS "id!" FROM webhook_delivery
+                WHERE webhook_id = ( $1 )
+                ORDER BY id DESC
+                LIMIT 1
+            "#,
+            self.webhook_id
+        )
+        .fetch_one(connection.get_pool())
+        .await?;
+
+        self.id = row.id;
+
+        Ok(())
+    }
+
+    /// Get the delivery log of a webhook, oldest first.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn get_deliveries(
+        webhook_id: SqlId,
+        connection: &Connection,
+    ) -> Result<Vec<Delivery>> {
+        let deliveries = sqlx::query_as!(
+            Delivery,
+            r#"
+                SELECT
+                id AS "id!", webhook_id, event, payload, attempt, status_code, error, attempted_at
+                FROM webhook_delivery
+                WHERE webhook_id = ( $1 )
+                ORDER BY id
+            "#,
+            webhook_id
+        )
+        .fetch_all(connection.get_pool())
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    /// Delete the attempts made before a time from every delivery log, returning how many.
+    #[instrument(level = "debug", skip(connection))]
+    pub async fn delete_before(before: &NaiveDateTime, connection: &Connection) -> Result<u64> {
+        let deleted = sqlx::query!(
+            r#"
+                DELETE FROM webhook_delivery
+                WHERE attempted_at < ( $1 )
+            "#,
+            before
+        )
+        .execute(connection.get_pool())
+        .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::User;
+
+    #[tokio::test]
+    async fn register_and_log_deliveries() {
+        let connection = Connection::connect_temporary_with_schema()
+            .await
+            .expect("Should connect");
+        let user = User::insert("test_username", "test_name", &connection)
+            .await
+            .expect("Should successfully insert.");
+        let created_at = NaiveDateTime::from_timestamp(0, 0);
+
+        let webhook = Webhook::insert(
+            user.get_id(),
+            "http://localhost:9000/hook",
+            "secret",
+            &["HabitCompleted".to_string(), "HabitMissed".to_string()],
+            &created_at,
+            &connection,
+        )
+        .await
+        .expect("Should successfully insert.");
+        assert!(webhook.subscribes_to("HabitMissed"));
+        assert!(!webhook.subscribes_to("TimerStarted"));
+        assert_eq!(
+            Webhook::retrieve(webhook.get_id(), &connection)
+                .await
+                .expect("Webhook exists."),
+            webhook
+        );
+
+        let mut delivery = Delivery::new(
+            0,
+            webhook.get_id(),
+            "HabitMissed".to_string(),
+            "{}".to_string(),
+            1,
+            created_at,
+        );
+        delivery.set_status_code(500);
+        delivery.insert(&connection).await.expect("Can log.");
+        assert!(!delivery.is_delivered());
+        assert_eq!(
+            Delivery::get_deliveries(webhook.get_id(), &connection)
+                .await
+                .expect("Can list."),
+            vec![delivery]
+        );
+
+        let id = webhook.get_id();
+        webhook.delete(&connection).await.expect("Can delete.");
+        assert_eq!(
+            Webhook::retrieve(id, &connection)
+                .await
+                .expect_err("Webhook was deleted."),
+            Error::NotFound
+        );
+        assert!(Delivery::get_deliveries(id, &connection)
+            .await
+            .expect("Can list.")
+            .is_empty());
+    }
+
+    #[test]
+    fn every_event_without_a_filter() {
+        let webhook = Webhook::new(
+            1,
+            1,
+            "http://localhost".to_string(),
+            "secret".to_string(),
+            &[],
+            NaiveDateTime::from_timestamp(0, 0),
+        );
+
+        assert!(webhook.subscribes_to("TimerStarted"));
+        assert!(webhook.get_events().is_empty());
+        assert_eq!(
+            serde_json::to_value(&webhook).expect("Can serialize.")["events"],
+            serde_json::json!([])
+        );
+    }
+}
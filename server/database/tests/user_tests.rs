@@ -1,5 +1,59 @@
-#[test]
-fn test() {
-    // Verify tests work!
-    assert_eq!(2 + 2, 4);
+//! Use the crate the way the api does, through its public interface only.
+#![cfg(feature = "sqlite")]
+
+use database::connection::{Connection, JournalMode};
+use database::error::Error;
+use database::migration;
+use database::store::{Store, TaskStore, UserStore};
+
+async fn connect() -> Connection {
+    // A single connection since every connection to `sqlite::memory:` opens a different database.
+    let connection = Connection::builder("sqlite::memory:")
+        .max_connections(1)
+        .journal_mode(JournalMode::Memory)
+        .connect()
+        .await
+        .expect("Should connect");
+    migration::run(&connection)
+        .await
+        .expect("Should migrate an empty database.");
+
+    connection
+}
+
+#[tokio::test]
+async fn user_lifecycle() {
+    let connection = connect().await;
+    let store: &dyn Store = &connection;
+
+    let mut user = store
+        .insert_user("justin", "Justin")
+        .await
+        .expect("Should successfully insert.");
+    store
+        .insert_task(user.get_id(), "Exercise")
+        .await
+        .expect("Should successfully insert.");
+
+    user.set_username("james".to_string());
+    store.update_user(&user).await.expect("Can update.");
+    assert_eq!(
+        store
+            .get_user("justin")
+            .await
+            .expect_err("Username changed."),
+        Error::NotFound
+    );
+
+    let user = store.get_user("james").await.expect("User exists.");
+    assert_eq!(store.get_tasks(&user).await.expect("Can list.").len(), 1);
+
+    store.delete_user(user).await.expect("Can delete.");
+    assert_eq!(
+        store
+            .get_user("james")
+            .await
+            .expect_err("User was deleted."),
+        Error::NotFound
+    );
 }